@@ -0,0 +1,158 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use feed_bouncer_database::{
+    CommitSummary, Database, FeedId, FIRST_RETRY_DELAY, UPDATE_RETRY_ATTEMPTS,
+};
+use tokio::time::Instant;
+
+/// The state of the daemon, rewritten after each update
+struct Status {
+    path: PathBuf,
+    started_at: DateTime<Utc>,
+    last_update: Option<CommitSummary>,
+}
+
+impl Status {
+    fn write(&self, state: &str, next_update_at: Option<DateTime<Utc>>) {
+        let status = serde_json::json!({
+            "pid": std::process::id(),
+            "state": state,
+            "started_at": self.started_at,
+            "next_update_at": next_update_at,
+            "last_update": self.last_update,
+        });
+        let status = serde_json::to_string_pretty(&status).unwrap();
+        if let Err(e) = std::fs::write(&self.path, status) {
+            eprintln!("WARN: could not write {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// Resolves on SIGTERM or Ctrl-C
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+            Err(e) => {
+                eprintln!("WARN: could not listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Updates the feeds in the interval of the config, retrying failed feeds
+/// with the same backoff as the server, until a shutdown signal arrives.
+/// Downloads get cancelled by the signal, commits always finish, and the
+/// storage is saved before returning.
+pub async fn run(mut db: Database, status_path: PathBuf) {
+    let interval = Duration::from_secs(60 * db.config().updates.interval_mins.max(1));
+    let mut status = Status {
+        path: status_path,
+        started_at: Utc::now(),
+        last_update: None,
+    };
+    status.write("starting", None);
+    println!(
+        "Updating every {} minutes, status in {:?}",
+        interval.as_secs() / 60,
+        status.path
+    );
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    'daemon: loop {
+        let next_update = Instant::now() + interval;
+        let next_update_at = Utc::now() + chrono::Duration::from_std(interval).unwrap();
+        db.import().await;
+
+        // All feeds first, then the ones that failed
+        let mut retry: Option<Vec<FeedId>> = None;
+        let mut delay = FIRST_RETRY_DELAY;
+        for attempt in 0..=UPDATE_RETRY_ATTEMPTS {
+            let task = match &retry {
+                None => db.update_feeds_task(),
+                Some(feed_ids) => db.retry_feeds_task(feed_ids),
+            };
+            status.write("updating", None);
+            let results = tokio::select! {
+                results = task.run() => results,
+                _ = &mut shutdown => break 'daemon,
+            };
+            let failed = results.unavailable_feeds();
+            let summary = db.commit_from(results).await;
+            println!("Update: {}", summary.summary());
+            let moved = if retry.is_none() {
+                db.move_to_cold_storage()
+            } else {
+                Ok(0)
+            };
+            match moved {
+                Ok(0) => db.save(),
+                Ok(_) => db.save_shrunk(),
+                Err(e) => {
+                    // Items of other feeds may have moved before the error
+                    eprintln!("WARN: could not move items to cold storage: {}", e);
+                    db.save_shrunk();
+                }
+            }
+            status.last_update = Some(summary);
+            status.write("waiting", Some(next_update_at));
+
+            if failed.is_empty() {
+                break;
+            }
+            if attempt == UPDATE_RETRY_ATTEMPTS {
+                eprintln!(
+                    "WARN: {} feeds still failed after {} retries",
+                    failed.len(),
+                    UPDATE_RETRY_ATTEMPTS
+                );
+                db.escalate_fetch_failures(&failed);
+                break;
+            }
+            // The next update fetches them anyway
+            if Instant::now() + delay >= next_update {
+                break;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = &mut shutdown => break 'daemon,
+            }
+            delay *= 2;
+            retry = Some(failed);
+        }
+
+        let archive = db.archive_task();
+        if !archive.is_empty() {
+            let result = tokio::select! {
+                result = archive.run() => result,
+                _ = &mut shutdown => break 'daemon,
+            };
+            db.commit_archive(result);
+            db.save();
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep_until(next_update) => {}
+            _ = &mut shutdown => break 'daemon,
+        }
+    }
+
+    println!("Shutting down");
+    db.save();
+    status.write("stopped", None);
+}
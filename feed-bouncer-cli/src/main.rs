@@ -1,8 +1,11 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+mod daemon;
+
+use clap::{Parser, Subcommand};
 use feed_bouncer_database::Database;
 use feed_bouncer_database::Error as DbError;
+use feed_bouncer_database::{ItemOrder, ItemQuery, OpmlGrouping, UserDataExport};
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -11,35 +14,288 @@ struct Opts {
     #[clap(short, long)]
     recent: bool,
 
+    /// Print the summary of the update as JSON
+    #[clap(long)]
+    json: bool,
+
     #[clap(short, long)]
     storage_path: Option<PathBuf>,
+
+    /// Open the storage without locking it or saving any changes
+    #[clap(long)]
+    read_only: bool,
+
+    /// Write all subscriptions to an OPML file
+    #[clap(long)]
+    export_opml: Option<PathBuf>,
+
+    /// Folders of the OPML export: flat, tags or primary_tag
+    #[clap(long, default_value = "flat")]
+    opml_grouping: OpmlGrouping,
+
+    /// Rebuild the full-text search index
+    #[clap(long)]
+    reindex: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+/// Syncs the read state with another instance through a shared file,
+/// imports feeds or checks them. Runs instead of an update.
+#[derive(Subcommand)]
+enum Command {
+    /// Add the read state of this storage to the file, creating it if needed
+    Push { file: PathBuf },
+    /// Mark the items read that are read in the file
+    Pull { file: PathBuf },
+    /// Subscribe to the feeds of an OPML file
+    ImportOpml {
+        file: PathBuf,
+        /// Tags added to all imported feeds
+        #[clap(long)]
+        tag: Vec<String>,
+        /// Tag the feeds with the names of their folders
+        #[clap(long)]
+        folder_tags: bool,
+        /// Treat the file as the source of truth: update the outlines and
+        /// folders of existing feeds and report the feeds it no longer has
+        #[clap(long)]
+        resync: bool,
+        /// With `--resync`, tag the feeds missing from the file
+        /// `removed_from_opml`
+        #[clap(long, requires = "resync")]
+        tag_removed: bool,
+    },
+    /// Subscribe to a local directory, each markdown file in it is an item
+    AddDirectory { path: PathBuf },
+    /// Check that the feed urls respond, without downloading any items
+    Probe {
+        /// Also list the feeds that respond fine
+        #[clap(long)]
+        all: bool,
+    },
+    /// Rebuild the caches and repair the id counters after the storage was
+    /// edited by hand
+    Fsck,
+    /// Drop redundant headers and legacy fields and rewrite all feed files
+    Compact,
+    /// Record tag synonyms and replace all synonyms among the tags of the
+    /// feeds by their canonical tags
+    MergeTags {
+        /// Pairs of `synonym=tag`, like `golang=go`
+        synonyms: Vec<String>,
+    },
+    /// Keep updating the feeds in the interval of the config, like the
+    /// server does, until SIGTERM or Ctrl-C
+    Daemon {
+        /// Where the pid and the result of the last update get written,
+        /// `daemon_status.json` in the storage path by default
+        #[clap(long)]
+        status_file: Option<PathBuf>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<(), DbError> {
     let opts = Opts::parse();
 
-    let mut db = Database::init(opts.storage_path);
+    let mut db = if opts.read_only {
+        Database::init_read_only(opts.storage_path)?
+    } else {
+        Database::init(opts.storage_path)?
+    };
+    if opts.reindex {
+        db.reindex();
+        println!("Rebuilt search index");
+    }
+    match &opts.command {
+        Some(Command::Push { file }) => {
+            let mut export = db.export_user_data();
+            if file.exists() {
+                export.merge(UserDataExport::read_from(file)?)?;
+            }
+            export.write_to(file)?;
+            println!(
+                "Pushed read state of {} feeds to {:?}",
+                export.feeds.len(),
+                file
+            );
+            return Ok(());
+        }
+        Some(Command::Pull { file }) => {
+            let report = db.merge_user_data(UserDataExport::read_from(file)?)?;
+            db.save_user_data();
+            println!(
+                "Pulled read state from {:?}: {} items marked read, {} moved to the trash, {} unknown feeds",
+                file, report.marked_read, report.hidden, report.unknown_feeds
+            );
+            return Ok(());
+        }
+        Some(Command::ImportOpml {
+            file,
+            tag,
+            folder_tags,
+            resync,
+            tag_removed,
+        }) => {
+            let report = if *resync {
+                db.resync_from_opml(file, tag, *folder_tags, *tag_removed)?
+            } else {
+                db.import_from_opml(file, tag, *folder_tags)?
+            };
+            db.save();
+            println!("Imported {:?}: {}", file, report.summary());
+            for name in &report.updated {
+                println!("  updated {}", name);
+            }
+            for name in &report.removed {
+                println!("  removed {}", name);
+            }
+            for problem in &report.skipped {
+                println!("  skipped {}: {}", problem.outline, problem.reason);
+            }
+            for problem in &report.blocked {
+                println!("  blocked {}: {}", problem.outline, problem.reason);
+            }
+            for problem in &report.errors {
+                println!("  error in {}: {}", problem.outline, problem.reason);
+            }
+            return Ok(());
+        }
+        Some(Command::AddDirectory { path }) => {
+            let path = std::fs::canonicalize(path)?;
+            let feed_id = db.add_directory_feed(&path);
+            db.save();
+            println!("Subscribed to {:?} as feed {}", path, feed_id);
+            return Ok(());
+        }
+        Some(Command::Probe { all }) => {
+            let results = db.probe_task().run().await;
+            let failed = results.iter().filter(|result| !result.is_ok()).count();
+            for result in &results {
+                if result.is_ok() && result.redirected_to.is_none() && !all {
+                    continue;
+                }
+                let status = match (&result.error, result.status) {
+                    (Some(error), _) => error.clone(),
+                    (None, Some(status)) => status.to_string(),
+                    (None, None) => "no response".to_owned(),
+                };
+                println!("[{}] {}: {}", result.feed_name, result.url, status);
+                if let Some(location) = &result.redirected_to {
+                    let kind = if result.permanent_redirect {
+                        "permanently"
+                    } else {
+                        "temporarily"
+                    };
+                    println!("  {} redirected to {}", kind, location);
+                }
+            }
+            println!("Probed {} feeds, {} failed", results.len(), failed);
+            return Ok(());
+        }
+        Some(Command::Fsck) => {
+            let report = db.fsck();
+            for (feed_id, old, new) in &report.item_counters {
+                println!("  item counter of {}: {} -> {}", feed_id, old, new);
+            }
+            for (feed_id, old, new) in &report.header_counters {
+                println!("  header counter of {}: {} -> {}", feed_id, old, new);
+            }
+            for (feed_id, old, new) in &report.renumbered_items {
+                println!("  duplicate item {} of {} is now {}", old, feed_id, new);
+            }
+            if !report.is_clean() {
+                db.save();
+            }
+            println!("{}", report.summary());
+            return Ok(());
+        }
+        Some(Command::Compact) => {
+            println!("{}", db.compact().summary());
+            return Ok(());
+        }
+        Some(Command::MergeTags { synonyms }) => {
+            for pair in synonyms {
+                let (synonym, canonical) = match pair.split_once('=') {
+                    Some((synonym, canonical)) => (synonym.trim(), canonical.trim()),
+                    None => {
+                        eprintln!("ERROR: expected synonym=tag, got {:?}", pair);
+                        std::process::exit(1);
+                    }
+                };
+                if let Err(e) = db.add_tag_synonym(synonym, canonical) {
+                    eprintln!("ERROR: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            let changed = db.merge_tag_synonyms();
+            if let Err(e) = db.save_atomic_shrunk() {
+                eprintln!("ERROR: could not save the tag synonyms: {}", e);
+                std::process::exit(1);
+            }
+            for (synonym, canonical) in db.tag_synonyms().iter() {
+                println!("  {} -> {}", synonym, canonical);
+            }
+            println!("Merged tag synonyms, {} feeds changed", changed);
+            return Ok(());
+        }
+        Some(Command::Daemon { status_file }) => {
+            if opts.read_only {
+                eprintln!("ERROR: the daemon can't run on a read-only storage");
+                std::process::exit(1);
+            }
+            let status_file = status_file
+                .clone()
+                .unwrap_or_else(|| db.storage_path().join("daemon_status.json"));
+            daemon::run(db, status_file).await;
+            return Ok(());
+        }
+        None => {}
+    }
     db.import().await;
     let tasks = db.update_feeds_task();
     let results = tasks.run().await;
-    db.commit_from(results).await;
+    let summary = db.commit_from(results).await;
     db.save();
 
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&summary).unwrap());
+    } else {
+        for feed in &summary.feeds {
+            println!(
+                "  [{}] {} new, {} updated items",
+                feed.name, feed.new_items, feed.updated_items
+            );
+        }
+        println!("Update: {}", summary.summary());
+    }
+
     if opts.recent {
         println!();
         println!("Recent updates:");
-        let mut feeds = db.get_items_ordered_by_time();
-        feeds.reverse();
-        let feeds = &feeds[0..(feeds.len().min(10))];
-        for (_feed_id, feed, item) in feeds {
+        let query = ItemQuery::new()
+            .order(ItemOrder::NewestFirst)
+            .hidden(false)
+            .limit(10);
+        for (_feed_id, feed, item) in db.query_items(&query) {
             println!(
                 "  [{}] {}",
                 feed.display_name(),
-                item.display_title_without_prefixes(&feed).unwrap_or("???")
+                item.display_title_without_prefixes(feed).unwrap_or("???")
             );
         }
     }
 
+    if let Some(path) = &opts.export_opml {
+        let opml = db
+            .export_opml(opts.opml_grouping)
+            .to_string()
+            .map_err(DbError::Opml)?;
+        std::fs::write(path, opml)?;
+        println!("Exported subscriptions to {:?}", path);
+    }
+
     Ok(())
 }
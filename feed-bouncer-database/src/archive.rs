@@ -0,0 +1,393 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    content::ItemContent,
+    database::{Database, FeedId},
+};
+
+/// Number of articles downloaded per archive run, so a newly flagged feed
+/// with a long history gets archived over several runs
+#[cfg(feature = "fetch")]
+const ARCHIVE_ITEMS_PER_RUN: usize = 20;
+/// Number of articles downloaded per feed and archive run, so sites don't
+/// get flooded
+#[cfg(feature = "fetch")]
+const ARCHIVE_ITEMS_PER_FEED: usize = 5;
+
+fn archive_path(storage_path: &Path) -> PathBuf {
+    storage_path.join("archive")
+}
+
+fn item_path(storage_path: &Path, feed_id: &FeedId, item_id: usize) -> PathBuf {
+    archive_path(storage_path)
+        .join(feed_id)
+        .join(item_id.to_string())
+        .with_extension("html")
+}
+
+fn read_article(storage_path: &Path, feed_id: &FeedId, item_id: usize) -> Option<String> {
+    let path = item_path(storage_path, feed_id, item_id);
+    match std::fs::read_to_string(&path) {
+        Ok(html) => Some(html),
+        Err(e) => {
+            eprintln!("WARN: could not read archived article {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Writes to a new file first, so a failed write leaves the old one
+fn write_article(
+    storage_path: &Path,
+    feed_id: &FeedId,
+    item_id: usize,
+    html: &str,
+) -> std::io::Result<()> {
+    let path = item_path(storage_path, feed_id, item_id);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    let new_path = path.with_extension("new.html");
+    std::fs::write(&new_path, html)?;
+    std::fs::rename(new_path, path)
+}
+
+/// The items with an archived copy of their linked article, stored as
+/// `archive/<feed_id>/<item_id>.html`. Feeds with the `archive` flag keep
+/// them for offline reading, feeds with the `full_content` setting show
+/// them as the content of their items.
+#[derive(Default)]
+pub struct ArchiveIndex {
+    items: BTreeMap<FeedId, BTreeSet<usize>>,
+    /// Articles that could not be downloaded, which are not tried again
+    /// until a restart so they don't hold up the others
+    failed: BTreeSet<(FeedId, usize)>,
+}
+
+impl ArchiveIndex {
+    pub fn open_or_default(storage_path: &Path) -> Self {
+        let mut index = Self::default();
+        let feeds = match std::fs::read_dir(archive_path(storage_path)) {
+            Ok(feeds) => feeds,
+            Err(_) => return index,
+        };
+        for feed in feeds.flatten() {
+            let feed_id = feed.file_name().to_string_lossy().into_owned();
+            let items = match std::fs::read_dir(feed.path()) {
+                Ok(items) => items,
+                Err(_) => continue,
+            };
+            for item in items.flatten() {
+                let path = item.path();
+                if path
+                    .extension()
+                    .map_or(true, |extension| extension != "html")
+                {
+                    continue;
+                }
+                // Leftovers of failed writes end in `.new.html` and don't
+                // parse as item ids
+                let item_id = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse().ok());
+                if let Some(item_id) = item_id {
+                    index
+                        .items
+                        .entry(feed_id.clone())
+                        .or_default()
+                        .insert(item_id);
+                }
+            }
+        }
+        index
+    }
+
+    pub fn contains(&self, feed_id: &FeedId, item_id: usize) -> bool {
+        self.items
+            .get(feed_id)
+            .map_or(false, |items| items.contains(&item_id))
+    }
+
+    /// Deletes the archived articles of a feed
+    fn remove_feed(&mut self, storage_path: &Path, feed_id: &FeedId) -> std::io::Result<()> {
+        self.failed
+            .retain(|(failed_feed_id, _)| failed_feed_id != feed_id);
+        if self.items.remove(feed_id).is_none() {
+            return Ok(());
+        }
+        match std::fs::remove_dir_all(archive_path(storage_path).join(feed_id)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Database {
+    pub fn is_archived(&self, feed_id: &FeedId, item_id: usize) -> bool {
+        self.archive.contains(feed_id, item_id)
+    }
+
+    /// Turns archiving the linked articles of a feed on or off. Turning it
+    /// off deletes the articles archived so far, unless the feed shows them
+    /// as its content.
+    pub fn set_archive(&mut self, feed_id: &FeedId, archive: bool) -> bool {
+        let full_content = match self.storage.get(feed_id) {
+            Some(feed) => feed.effective_settings(self).full_content,
+            None => return false,
+        };
+        if let Some(feed) = self.storage.get_mut(feed_id) {
+            feed.set_archive(archive);
+        }
+        if !archive && !full_content {
+            self.remove_archived_articles(feed_id);
+        }
+        true
+    }
+
+    /// Deletes the archived articles of a feed, for removed feeds and feeds
+    /// that stopped archiving
+    pub(crate) fn remove_archived_articles(&mut self, feed_id: &FeedId) {
+        if self.is_read_only() {
+            return;
+        }
+        if let Err(e) = self.archive.remove_feed(&self.storage_path, feed_id) {
+            eprintln!(
+                "WARN: could not delete the archived articles of feed {}: {}",
+                feed_id, e
+            );
+        }
+    }
+
+    /// The readable content of the archived article of an item, sanitized
+    /// like the content of items
+    pub fn archived_content(&self, feed_id: &FeedId, item_id: usize) -> Option<ItemContent> {
+        if !self.is_archived(feed_id, item_id) {
+            return None;
+        }
+        let html = read_article(&self.storage_path, feed_id, item_id)?;
+        Some(ItemContent::process(&html))
+    }
+
+    /// The archived article of an item, if it replaces the content of the
+    /// item because its feed has the `full_content` setting
+    pub(crate) fn full_content(
+        &self,
+        feed_id: &FeedId,
+        item_id: usize,
+        full_content: bool,
+    ) -> Option<String> {
+        if !full_content || !self.is_archived(feed_id, item_id) {
+            return None;
+        }
+        read_article(&self.storage_path, feed_id, item_id)
+    }
+
+    /// Shows the archived articles as the content of the items of feeds with
+    /// the `full_content` setting, and the content of the feed for all other
+    /// items. Needed after the setting or the archive changed.
+    pub(crate) fn sync_full_contents(&mut self) {
+        let mut changed = Vec::new();
+        for (feed_id, feed) in self.storage.iter() {
+            let full_content = feed.effective_settings(self).full_content;
+            for item in feed.items() {
+                let archived = full_content && self.is_archived(feed_id, item.id());
+                if archived != item.has_full_content() {
+                    changed.push((feed_id.clone(), item.id(), archived));
+                }
+            }
+        }
+        for (feed_id, item_id, archived) in changed {
+            let html = self.full_content(&feed_id, item_id, archived);
+            let item = self.storage.get_mut(&feed_id).and_then(|feed| {
+                feed.items_mut()
+                    .iter_mut()
+                    .find(|item| item.id() == item_id)
+            });
+            if let Some(item) = item {
+                item.set_full_content(html);
+            }
+        }
+    }
+
+    /// Older feed files stored the linked articles of items inline, this
+    /// moves them to the archive
+    pub(crate) fn archive_inline_contents(&mut self) -> std::io::Result<()> {
+        for (feed_id, feed) in self.storage.iter() {
+            for item in feed.items() {
+                if let Some(html) = item.full_content() {
+                    write_article(&self.storage_path, feed_id, item.id(), html)?;
+                    self.archive
+                        .items
+                        .entry(feed_id.clone())
+                        .or_default()
+                        .insert(item.id());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "fetch")]
+struct ArchiveJob {
+    feed_id: FeedId,
+    item_id: usize,
+    url: String,
+    user_agent: Option<String>,
+}
+
+/// Downloads the linked articles of items of feeds with the `archive` flag
+/// or the `full_content` setting, which don't have an archived copy yet.
+#[cfg(feature = "fetch")]
+pub struct ArchiveTask {
+    client: reqwest::Client,
+    jobs: Vec<ArchiveJob>,
+    max_response_bytes: u64,
+}
+
+#[cfg(feature = "fetch")]
+pub struct ArchiveTaskResult {
+    articles: Vec<(FeedId, usize, String)>,
+    failed: Vec<(FeedId, usize)>,
+}
+
+#[cfg(feature = "fetch")]
+impl ArchiveTask {
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    pub async fn run(self) -> ArchiveTaskResult {
+        let mut articles = Vec::new();
+        let mut failed = Vec::new();
+        for job in self.jobs {
+            let user_agent = job.user_agent.as_deref();
+            let downloaded = crate::fetch::download_page(
+                &self.client,
+                &job.url,
+                user_agent,
+                self.max_response_bytes,
+            )
+            .await;
+            match downloaded {
+                Ok(html) => {
+                    let content = crate::content::extract_readable(&html);
+                    articles.push((job.feed_id, job.item_id, content));
+                }
+                Err(e) => {
+                    eprintln!("WARN: could not archive {}: {}", job.url, e);
+                    failed.push((job.feed_id, job.item_id));
+                }
+            }
+        }
+        ArchiveTaskResult { articles, failed }
+    }
+}
+
+#[cfg(feature = "fetch")]
+impl Database {
+    pub fn archive_task(&self) -> ArchiveTask {
+        let mut jobs = Vec::new();
+        'feeds: for (feed_id, feed) in self.storage.iter() {
+            let settings = feed.effective_settings(self);
+            if !feed.archive() && !settings.full_content {
+                continue;
+            }
+            let user_agent = settings.user_agent;
+            let mut feed_jobs = 0;
+            // Newest first, older items follow in later runs
+            for item in feed.items_newest_first() {
+                if jobs.len() >= ARCHIVE_ITEMS_PER_RUN {
+                    break 'feeds;
+                }
+                if feed_jobs >= ARCHIVE_ITEMS_PER_FEED {
+                    break;
+                }
+                if self.is_archived(feed_id, item.id())
+                    || self.archive.failed.contains(&(feed_id.clone(), item.id()))
+                {
+                    continue;
+                }
+                if let Some(url) = item.content_link() {
+                    jobs.push(ArchiveJob {
+                        feed_id: feed_id.clone(),
+                        item_id: item.id(),
+                        url: url.to_owned(),
+                        user_agent: user_agent.clone(),
+                    });
+                    feed_jobs += 1;
+                }
+            }
+        }
+        ArchiveTask {
+            client: self.client.clone(),
+            jobs,
+            max_response_bytes: self.config.http.response_limit(),
+        }
+    }
+
+    pub fn commit_archive(&mut self, result: ArchiveTaskResult) {
+        self.archive.failed.extend(result.failed);
+        if self.is_read_only() {
+            return;
+        }
+        for (feed_id, item_id, content) in result.articles {
+            match write_article(&self.storage_path, &feed_id, item_id, &content) {
+                Ok(()) => {
+                    self.archive
+                        .items
+                        .entry(feed_id)
+                        .or_default()
+                        .insert(item_id);
+                }
+                Err(e) => eprintln!(
+                    "WARN: could not write archived article {} of feed {}: {}",
+                    item_id, feed_id, e
+                ),
+            }
+        }
+        self.sync_full_contents();
+    }
+}
+
+#[test]
+fn test_full_content_from_archive() {
+    use crate::{Feed, FeedItem, FeedOptions};
+
+    let storage_path =
+        std::env::temp_dir().join(format!("feed-bouncer-test-archive-{}", std::process::id()));
+    let mut db = Database::in_memory();
+    db.storage_path = storage_path.clone();
+    let feed_id = db.insert(Feed::new("Feed".to_owned()));
+    let item_id = db
+        .get_mut(&feed_id)
+        .unwrap()
+        .push_item(FeedItem::Rss(rss::Item {
+            description: Some("Summary".to_owned()),
+            ..rss::Item::default()
+        }));
+    write_article(&storage_path, &feed_id, item_id, "<p>Full article</p>").unwrap();
+    db.archive
+        .items
+        .entry(feed_id.clone())
+        .or_default()
+        .insert(item_id);
+
+    let summary = |db: &Database| {
+        let item = &db.get(&feed_id).unwrap().items()[0];
+        item.content().unwrap().summary.clone()
+    };
+    assert_eq!(summary(&db), "Summary");
+    let options = FeedOptions {
+        full_content: Some(true),
+        ..FeedOptions::default()
+    };
+    db.set_feed_options(&feed_id, options);
+    assert_eq!(summary(&db), "Full article");
+    db.set_feed_options(&feed_id, FeedOptions::default());
+    assert_eq!(summary(&db), "Summary");
+
+    std::fs::remove_dir_all(storage_path).unwrap();
+}
@@ -0,0 +1,43 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    database::{Database, FeedId},
+    query::FeedQuery,
+};
+
+/// The items of one author across feeds, see [`Database::authors`].
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct Author {
+    /// Spelled like in the first item found
+    pub name: String,
+    pub items: usize,
+    pub feeds: BTreeSet<FeedId>,
+}
+
+impl Database {
+    /// The authors of the items of the matching feeds, with the most items
+    /// first. Names that only differ in case are the same author.
+    pub fn authors(&self, feeds: &FeedQuery) -> Vec<Author> {
+        let mut authors: HashMap<String, Author> = HashMap::new();
+        for (feed_id, feed) in self.query_feeds(feeds) {
+            for item in feed.items() {
+                let name = match item.item.author() {
+                    Some(name) => name,
+                    None => continue,
+                };
+                let author = authors
+                    .entry(name.to_lowercase())
+                    .or_insert_with(|| Author {
+                        name: name.to_owned(),
+                        items: 0,
+                        feeds: BTreeSet::new(),
+                    });
+                author.items += 1;
+                author.feeds.insert(feed_id.clone());
+            }
+        }
+        let mut authors: Vec<Author> = authors.into_values().collect();
+        authors.sort_by(|a, b| b.items.cmp(&a.items).then_with(|| a.name.cmp(&b.name)));
+        authors
+    }
+}
@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+
+use crate::{database::Database, query::FeedQuery};
+
+/// A category of items, see [`Database::categories`].
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct ItemCategory {
+    /// Spelled like in the first item found
+    pub name: String,
+    pub items: usize,
+}
+
+impl Database {
+    /// The categories of the items of the matching feeds, with the most
+    /// items first. Narrows down feeds with many items per day, like the
+    /// arXiv listings.
+    pub fn categories(&self, feeds: &FeedQuery) -> Vec<ItemCategory> {
+        let mut categories: HashMap<String, ItemCategory> = HashMap::new();
+        for (_, feed) in self.query_feeds(feeds) {
+            for item in feed.items() {
+                for name in item.item.categories() {
+                    categories
+                        .entry(name.to_lowercase())
+                        .or_insert_with(|| ItemCategory {
+                            name: name.to_owned(),
+                            items: 0,
+                        })
+                        .items += 1;
+                }
+            }
+        }
+        let mut categories: Vec<ItemCategory> = categories.into_values().collect();
+        categories.sort_by(|a, b| b.items.cmp(&a.items).then_with(|| a.name.cmp(&b.name)));
+        categories
+    }
+}
@@ -0,0 +1,91 @@
+use crate::database::{storage_feed_item::FeedItemMeta, timeline::Timeline, Database};
+
+/// What [`Database::compact`] dropped from the storage.
+#[derive(serde::Serialize, Clone, Debug, Default)]
+pub struct CompactReport {
+    pub feeds: usize,
+    /// Items and headers of the first storage format that got numbered
+    pub legacy_entries: usize,
+    /// Headers equivalent to a later one or beyond the configured maximum
+    pub dropped_headers: usize,
+    /// Size of the feed files before and after
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+impl CompactReport {
+    /// Negative if the files grew
+    pub fn bytes_saved(&self) -> i64 {
+        self.bytes_before as i64 - self.bytes_after as i64
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "compacted {} feeds, {} legacy entries migrated, {} headers dropped, {} of {} bytes saved",
+            self.feeds,
+            self.legacy_entries,
+            self.dropped_headers,
+            self.bytes_saved(),
+            self.bytes_before
+        )
+    }
+}
+
+impl Database {
+    /// Shrinks the feed files: moves left over entries of the first storage
+    /// format to the current one, drops redundant headers, sorts the items
+    /// and rewrites all feed files. Nothing is written for a read-only
+    /// storage.
+    pub fn compact(&mut self) -> CompactReport {
+        let mut report = CompactReport {
+            feeds: self.storage.len(),
+            bytes_before: self.stats().total_file_size,
+            ..CompactReport::default()
+        };
+
+        let max_headers = self.config.storage.max_feed_headers;
+        for (_, feed) in self.storage.iter_mut() {
+            report.legacy_entries += feed.legacy_entry_count();
+            feed.number_legacy_items();
+            report.dropped_headers += feed.compact_headers(max_headers);
+            let order = feed.item_order();
+            FeedItemMeta::sort(feed.items_mut(), order);
+        }
+        // Numbered legacy items are new to the timeline and the search
+        self.timeline = Timeline::build(&self.storage);
+        if report.legacy_entries > 0 {
+            self.reindex();
+        }
+
+        if self.is_read_only() {
+            report.bytes_after = report.bytes_before;
+        } else {
+            self.save_shrunk();
+            report.bytes_after = self.stats().total_file_size;
+        }
+        report
+    }
+}
+
+#[test]
+fn test_compact_indexes_legacy_items() {
+    use crate::{Feed, FeedItem};
+
+    let mut db = Database::in_memory();
+    let item = FeedItem::Rss(rss::Item {
+        title: Some("Legacy entry".to_owned()),
+        ..rss::Item::default()
+    });
+    let feed: Feed = serde_json::from_value(serde_json::json!({
+        "name": "Feed",
+        "feeds": [item],
+    }))
+    .unwrap();
+    db.insert(feed);
+    assert!(db.get_items_ordered_by_time().is_empty());
+
+    let report = db.compact();
+    assert_eq!(report.legacy_entries, 1);
+    assert_eq!(db.get_items_ordered_by_time().len(), 1);
+    assert_eq!(db.search("legacy").len(), 1);
+}
@@ -0,0 +1,398 @@
+use std::path::{Path, PathBuf};
+
+use crate::database::transaction::Transaction;
+
+/// Settings read from `config.json` in the storage directory.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    pub http: HttpConfig,
+    pub updates: UpdateConfig,
+    pub storage: StorageConfig,
+    pub ui: UiConfig,
+    pub public: PublicConfig,
+    pub import: ImportConfig,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ImportConfig {
+    /// Tags added to the feeds imported from matching urls, e.g. feeds
+    /// from `*.substack.com` tagged as `newsletter`
+    pub url_tags: Vec<UrlTagRule>,
+    /// Feeds that imports skip
+    pub blocklist: Blocklist,
+}
+
+impl ImportConfig {
+    /// The tags of all rules matching the url
+    pub fn tags_for<'a>(&'a self, url: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.url_tags
+            .iter()
+            .filter(move |rule| rule.matches(url))
+            .flat_map(|rule| rule.tags.iter().map(|tag| &tag[..]))
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct UrlTagRule {
+    /// `*` matches any text. Patterns without a `/` match the host of the
+    /// url, others the whole url without its scheme. Case is ignored.
+    pub pattern: String,
+    pub tags: Vec<String>,
+}
+
+impl UrlTagRule {
+    pub fn matches(&self, url: &str) -> bool {
+        let text = if self.pattern.contains('/') {
+            without_scheme(url)
+        } else {
+            host(url)
+        };
+        wildcard_match(
+            self.pattern.trim().to_lowercase().as_bytes(),
+            text.to_lowercase().as_bytes(),
+        )
+    }
+}
+
+/// Feeds that imports skip, because they are never wanted. Imports report
+/// them instead of adding them.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Blocklist {
+    pub feed_urls: Vec<String>,
+    /// Hosts, together with their subdomains
+    pub domains: Vec<String>,
+    /// Patterns of feed titles, `*` matches any text. Case is ignored.
+    pub titles: Vec<String>,
+}
+
+impl Blocklist {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Why a feed with the url and title is blocked, `None` if it is not
+    pub fn blocks(&self, url: Option<&str>, title: Option<&str>) -> Option<String> {
+        if let Some(url) = url {
+            let normalized = |url: &str| url.trim().trim_end_matches('/').to_lowercase();
+            let feed_url = normalized(url);
+            if self
+                .feed_urls
+                .iter()
+                .any(|blocked| normalized(blocked) == feed_url)
+            {
+                return Some(format!("blocked feed url {}", url.trim()));
+            }
+            let host = host(url).to_lowercase();
+            for domain in &self.domains {
+                let domain = domain.trim().to_lowercase();
+                let is_subdomain = host
+                    .strip_suffix(&domain[..])
+                    .map_or(false, |sub| sub.ends_with('.'));
+                if !domain.is_empty() && (host == domain || is_subdomain) {
+                    return Some(format!("blocked domain {}", domain));
+                }
+            }
+        }
+        if let Some(title) = title {
+            let title = title.trim().to_lowercase();
+            for pattern in &self.titles {
+                let pattern = pattern.trim().to_lowercase();
+                if !pattern.is_empty() && wildcard_match(pattern.as_bytes(), title.as_bytes()) {
+                    return Some(format!("blocked title {}", pattern));
+                }
+            }
+        }
+        None
+    }
+
+    /// Adds the entries of `other` that are not blocked yet
+    pub fn extend(&mut self, other: Blocklist) {
+        for (list, new) in [
+            (&mut self.feed_urls, other.feed_urls),
+            (&mut self.domains, other.domains),
+            (&mut self.titles, other.titles),
+        ] {
+            for entry in new {
+                if !list.contains(&entry) {
+                    list.push(entry);
+                }
+            }
+        }
+    }
+}
+
+fn without_scheme(url: &str) -> &str {
+    let url = url.trim();
+    url.split_once("://").map_or(url, |(_, rest)| rest)
+}
+
+/// The host of the url, without user and port
+fn host(url: &str) -> &str {
+    let authority = without_scheme(url)
+        .split(|c| c == '/' || c == '?' || c == '#')
+        .next()
+        .unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    host.split(':').next().unwrap_or_default()
+}
+
+fn wildcard_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` and the text it currently matches up to
+    let mut star = None;
+    while t < text.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            star = Some((p, t));
+        } else if pattern.get(p) == Some(&text[t]) {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p;
+            t = star_t + 1;
+            star = Some((star_p, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+/// What a publicly reachable instance wants search engines to index, and
+/// how shared pages may be used.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct PublicConfig {
+    /// Address the instance is reachable at, e.g. `https://feeds.example.com`
+    pub base_url: Option<String>,
+    /// Tags whose pages, and the pages of their feeds, may be indexed.
+    /// Everything else is excluded in `robots.txt`.
+    pub tags: Vec<String>,
+    /// Requests per minute a single client may make to a share page
+    pub share_requests_per_minute: u32,
+}
+
+impl Default for PublicConfig {
+    fn default() -> Self {
+        Self {
+            base_url: None,
+            tags: Vec::new(),
+            share_requests_per_minute: 30,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct UiConfig {
+    /// Count locally which filters and tags get used, to order them by
+    /// frequency. Nothing of this leaves the storage directory.
+    pub track_usage: bool,
+    /// Also accept GET requests for actions like marking items read, as
+    /// links from before they became POST forms. Prefetching browsers can
+    /// trigger them by accident. Off by default. Deprecated, will be removed.
+    pub legacy_get_actions: bool,
+    /// Secret the subscribe bookmarklet has to pass as `token`, for
+    /// instances behind an authenticating proxy that lets `/subscribe`
+    /// through. Without it the route is as open as every other page.
+    pub subscribe_token: Option<String>,
+    /// Bearer token of the `/admin/api` routes for scripts, which are off
+    /// without it
+    pub admin_api_token: Option<String>,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            track_usage: true,
+            legacy_get_actions: false,
+            subscribe_token: None,
+            admin_api_token: None,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Write the items of feed files as one compact line each, which keeps
+    /// diffs of a version controlled storage directory small
+    pub compact_items: bool,
+    /// Interval of the automatic save of the server, 0 disables it
+    pub autosave_mins: u64,
+    /// Number of distinct headers kept per feed by a shrinking save
+    pub max_feed_headers: usize,
+    /// Items published longer ago are moved from the feed files to
+    /// `cold/<feed_id>.json` after updates, which keeps loading and saving
+    /// fast. Moved items are only shown in the history of their feed, search
+    /// does not find them. `None` keeps all items in the feed files.
+    pub cold_after_days: Option<u64>,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            compact_items: false,
+            autosave_mins: 15,
+            max_feed_headers: 5,
+            cold_after_days: None,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct UpdateConfig {
+    /// Mark items as unread again when the publisher changes them
+    pub mark_changed_unread: bool,
+    /// How many archive pages (RFC 5005) to follow when a feed is fetched
+    /// for the first time, 0 disables backfilling
+    pub archive_depth: usize,
+    /// Interval of the automatic updates of the server
+    pub interval_mins: u64,
+    /// The first automatic update waits up to this long, so several
+    /// instances started together don't update in sync
+    pub startup_jitter_secs: u64,
+    /// Items older than this many days are skipped when a feed is fetched
+    /// for the first time, unless the import gave its own limit. Later
+    /// updates store all new items.
+    pub max_initial_age_days: Option<u64>,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            mark_changed_unread: false,
+            archive_depth: 10,
+            interval_mins: 60,
+            startup_jitter_secs: 60,
+            max_initial_age_days: None,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Proxy used for all requests, e.g. `socks5://127.0.0.1:1080`
+    pub proxy: Option<String>,
+    /// Additional PEM encoded root certificates, relative to the storage path
+    pub root_certificates: Vec<PathBuf>,
+    /// Timeout of a single request
+    pub timeout_secs: Option<u64>,
+    /// Feed downloads are aborted once the response gets larger than this,
+    /// 32 MiB by default
+    pub max_response_bytes: Option<u64>,
+    /// Sent instead of the default user agent, which names feed-bouncer and
+    /// `contact_url`. Feeds and tags can set their own.
+    pub user_agent: Option<String>,
+    /// Where the hosts of feeds can reach the operator, e.g. a web page or
+    /// `mailto:` url
+    pub contact_url: Option<String>,
+}
+
+/// Named in the default user agent
+const REPOSITORY_URL: &str = "https://github.com/Kimundi/feed-bouncer";
+
+const DEFAULT_MAX_RESPONSE_BYTES: u64 = 32 * 1024 * 1024;
+
+impl HttpConfig {
+    /// The size feed downloads are limited to
+    pub fn response_limit(&self) -> u64 {
+        self.max_response_bytes
+            .unwrap_or(DEFAULT_MAX_RESPONSE_BYTES)
+    }
+
+    /// The configured user agent, or `feed-bouncer/x.y (+repository url)`
+    /// with the contact url added
+    pub fn user_agent(&self) -> String {
+        if let Some(user_agent) = &self.user_agent {
+            return user_agent.clone();
+        }
+        let version = concat!(
+            env!("CARGO_PKG_VERSION_MAJOR"),
+            ".",
+            env!("CARGO_PKG_VERSION_MINOR")
+        );
+        match &self.contact_url {
+            Some(contact) => format!(
+                "feed-bouncer/{} (+{}; contact: {})",
+                version, REPOSITORY_URL, contact
+            ),
+            None => format!("feed-bouncer/{} (+{})", version, REPOSITORY_URL),
+        }
+    }
+}
+
+impl Config {
+    pub fn open_or_default(storage_path: &Path) -> Self {
+        Self::open(storage_path).expect("could not parse config")
+    }
+    /// Like `open_or_default`, but returns an error for a config that can't
+    /// be parsed, so a running instance can keep its current config.
+    pub fn open(storage_path: &Path) -> Result<Self, crate::Error> {
+        let config_path = storage_path.join("config.json");
+        match std::fs::read_to_string(&config_path) {
+            Ok(v) => {
+                serde_json::from_str(&v).map_err(|e| crate::Error::InvalidConfig(e.to_string()))
+            }
+            Err(_) => Ok(Self::default()),
+        }
+    }
+    pub fn save(&self, storage_path: &Path) {
+        let config_path = storage_path.join("config.json");
+        crate::safe_save_json(self, &config_path, "config", true);
+    }
+    pub fn stage(&self, tx: &mut Transaction, storage_path: &Path) -> std::io::Result<()> {
+        tx.stage_json(self, &storage_path.join("config.json"), None)
+    }
+}
+
+#[test]
+fn test_url_tag_rule() {
+    let rule = |pattern: &str| UrlTagRule {
+        pattern: pattern.to_owned(),
+        tags: vec!["tag".to_owned()],
+    };
+    assert!(rule("*.substack.com").matches("https://someone.substack.com/feed"));
+    assert!(!rule("*.substack.com").matches("https://substack.com/feed"));
+    assert!(!rule("*.substack.com").matches("https://example.com/?u=a.substack.com"));
+    assert!(rule("*YouTube.com").matches("https://www.youtube.com/feeds/videos.xml?id=1"));
+    assert!(rule("github.com/*/releases.atom").matches("https://github.com/a/b/releases.atom"));
+    assert!(!rule("github.com/*/releases.atom").matches("https://github.com/a/b/tags.atom"));
+    assert!(rule("a*b*c").matches("abbbc"));
+    assert!(!rule("a*b*c").matches("abbbd"));
+}
+
+#[test]
+fn test_blocklist() {
+    let blocklist = Blocklist {
+        feed_urls: vec!["https://example.com/feed/".to_owned()],
+        domains: vec!["spam.net".to_owned()],
+        titles: vec!["*sponsored*".to_owned()],
+    };
+    assert!(blocklist
+        .blocks(Some("https://Example.com/feed"), None)
+        .is_some());
+    assert!(blocklist
+        .blocks(Some("https://example.com/other"), None)
+        .is_none());
+    assert!(blocklist
+        .blocks(Some("http://spam.net/rss"), None)
+        .is_some());
+    assert!(blocklist
+        .blocks(Some("http://www.spam.net:8080/rss"), None)
+        .is_some());
+    assert!(blocklist
+        .blocks(Some("http://notspam.net/rss"), None)
+        .is_none());
+    assert!(blocklist
+        .blocks(None, Some("Daily Sponsored Links"))
+        .is_some());
+    assert!(blocklist.blocks(None, Some("Daily Links")).is_none());
+}
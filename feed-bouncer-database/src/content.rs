@@ -0,0 +1,290 @@
+use std::collections::HashSet;
+
+/// Number of words kept in plain text summaries
+const SUMMARY_WORDS: usize = 50;
+
+/// The content of an item processed for display: sanitized HTML without
+/// scripts and trackers, a plain text summary and the images it contains.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq, Default)]
+pub struct ItemContent {
+    pub html: String,
+    pub summary: String,
+    pub images: Vec<String>,
+}
+
+impl ItemContent {
+    pub fn process(html: &str) -> Self {
+        let html = sanitize_html(html);
+        Self {
+            summary: summarize(&html, SUMMARY_WORDS),
+            images: image_urls(&html),
+            html,
+        }
+    }
+}
+
+/// Tracking pixels are images with a size of 1x1 or less
+fn is_tracker(attributes: &[(String, String)]) -> bool {
+    attributes.iter().any(|(name, value)| {
+        (name == "width" || name == "height")
+            && value.trim().parse::<u32>().map(|v| v <= 1).unwrap_or(false)
+    })
+}
+
+/// Without ammonia, only the text is kept
+#[cfg(not(feature = "sanitize"))]
+fn sanitize_html(html: &str) -> String {
+    decode_entities(&strip_tags(html))
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Removes scripts, event handlers and the sources of tracking pixels
+#[cfg(feature = "sanitize")]
+fn sanitize_html(html: &str) -> String {
+    let trackers: HashSet<String> = img_tags(html)
+        .iter()
+        .filter(|attributes| is_tracker(attributes))
+        .filter_map(|attributes| attribute(attributes, "src"))
+        .map(str::to_owned)
+        .collect();
+
+    ammonia::Builder::default()
+        .attribute_filter(move |element, attribute, value| {
+            if element == "img" && attribute == "src" && trackers.contains(value) {
+                return None;
+            }
+            Some(value.into())
+        })
+        .clean(html)
+        .to_string()
+}
+
+/// The main content of a web page: the first `article`, `main` or `body`
+/// element, without navigation and other page furniture.
+#[cfg(feature = "fetch")]
+pub(crate) fn extract_readable(html: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let content = ["article", "main", "body"]
+        .iter()
+        .find_map(|tag| {
+            let start = lower.find(&format!("<{}", tag))?;
+            let end = lower
+                .rfind(&format!("</{}>", tag))
+                .filter(|&end| end > start)?;
+            Some(&html[start..end + tag.len() + 3])
+        })
+        .unwrap_or(html);
+
+    remove_furniture(&sanitize_html(content))
+}
+
+/// Removes navigation and other page furniture with their content
+#[cfg(all(feature = "fetch", feature = "sanitize"))]
+fn remove_furniture(html: &str) -> String {
+    let furniture = ["nav", "header", "footer", "aside", "form"];
+    ammonia::Builder::default()
+        .rm_tags(&furniture)
+        .clean_content_tags(
+            furniture
+                .iter()
+                .copied()
+                .chain(["script", "style"])
+                .collect(),
+        )
+        .clean(html)
+        .to_string()
+}
+
+/// Without ammonia the content is plain text already
+#[cfg(all(feature = "fetch", not(feature = "sanitize")))]
+fn remove_furniture(text: &str) -> String {
+    text.to_owned()
+}
+
+/// Replaces all HTML tags with spaces
+pub(crate) fn strip_tags(html: &str) -> String {
+    let mut in_tag = false;
+    html.chars()
+        .map(|c| match c {
+            '<' => {
+                in_tag = true;
+                ' '
+            }
+            '>' => {
+                in_tag = false;
+                ' '
+            }
+            _ if in_tag => ' ',
+            c => c,
+        })
+        .collect()
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn summarize(html: &str, words: usize) -> String {
+    let text = decode_entities(&strip_tags(html));
+    let mut summary: Vec<&str> = text.split_whitespace().take(words + 1).collect();
+    if summary.len() > words {
+        summary.truncate(words);
+        summary.join(" ") + " …"
+    } else {
+        summary.join(" ")
+    }
+}
+
+fn attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, value)| &value[..])
+}
+
+/// The attributes of all `img` tags, with lowercase names and raw values
+fn img_tags(html: &str) -> Vec<Vec<(String, String)>> {
+    tags_named(html, "<img")
+}
+
+/// The attributes of all tags starting with `open`, like `<img`
+fn tags_named(html: &str, open: &str) -> Vec<Vec<(String, String)>> {
+    let lower = html.to_ascii_lowercase();
+    let mut tags = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = lower[offset..].find(open) {
+        let start = offset + start + open.len();
+        let end = lower[start..]
+            .find('>')
+            .map(|i| start + i)
+            .unwrap_or(lower.len());
+        tags.push(parse_attributes(&html[start..end]));
+        offset = end;
+    }
+    tags
+}
+
+fn parse_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag.trim_start();
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].trim_matches('/').to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, remaining) = match after.chars().next() {
+                Some(q @ '"') | Some(q @ '\'') => {
+                    let after = &after[1..];
+                    let end = after.find(q).unwrap_or(after.len());
+                    (&after[..end], after.get(end + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = decode_entities(raw);
+            rest = remaining.trim_start();
+        }
+        if !name.is_empty() {
+            attributes.push((name, value));
+        }
+    }
+    attributes
+}
+
+/// The feeds a web page announces with `<link rel="alternate">` tags, as
+/// given in the page, in page order
+#[cfg(feature = "fetch")]
+pub(crate) fn feed_links(html: &str) -> Vec<String> {
+    const FEED_TYPES: [&str; 4] = [
+        "application/rss+xml",
+        "application/atom+xml",
+        "application/feed+json",
+        "application/json",
+    ];
+    let mut links = Vec::new();
+    for attributes in tags_named(html, "<link") {
+        let is_alternate = attribute(&attributes, "rel").map_or(false, |rel| {
+            rel.split_whitespace()
+                .any(|rel| rel.eq_ignore_ascii_case("alternate"))
+        });
+        let is_feed = attribute(&attributes, "type").map_or(false, |kind| {
+            FEED_TYPES.contains(&kind.trim().to_ascii_lowercase().as_str())
+        });
+        if let (true, true, Some(href)) = (is_alternate, is_feed, attribute(&attributes, "href")) {
+            if !href.is_empty() && !links.iter().any(|link| link == href) {
+                links.push(href.to_owned());
+            }
+        }
+    }
+    links
+}
+
+fn image_urls(html: &str) -> Vec<String> {
+    let mut images = Vec::new();
+    for attributes in img_tags(html) {
+        if let Some(src) = attribute(&attributes, "src") {
+            if !src.is_empty() && !images.iter().any(|image| image == src) {
+                images.push(src.to_owned());
+            }
+        }
+    }
+    images
+}
+
+#[cfg(all(feature = "fetch", feature = "sanitize"))]
+#[test]
+fn test_extract_readable() {
+    let html = r#"<html><body><nav><a href="/">Home</a></nav>
+        <ARTICLE><h1>Title</h1><p>Text</p><aside>Ad</aside></ARTICLE>
+        <footer>Imprint</footer></body></html>"#;
+    let content = extract_readable(html);
+
+    assert!(content.contains("<h1>Title</h1><p>Text</p>"));
+    assert!(!content.contains("Home"));
+    assert!(!content.contains("Ad"));
+    assert!(!content.contains("Imprint"));
+}
+
+#[cfg(feature = "fetch")]
+#[test]
+fn test_feed_links() {
+    let html = r#"<head><link rel="stylesheet" href="/style.css">
+        <LINK REL="alternate" TYPE="application/rss+xml" HREF="/feed.xml" title="RSS">
+        <link rel=alternate type="application/atom+xml" href='https://example.com/atom'>
+        <link rel="alternate" hreflang="de" href="/de/"></head>"#;
+
+    assert_eq!(feed_links(html), ["/feed.xml", "https://example.com/atom"]);
+}
+
+#[cfg(feature = "sanitize")]
+#[test]
+fn test_item_content() {
+    let html = r#"<p>Hello &amp; <b>welcome</b></p><script>alert(1)</script>
+        <img src="https://example.com/a.png?a=1&amp;b=2" alt="A">
+        <IMG SRC='https://example.com/pixel.gif' width="1" height="1">"#;
+    let content = ItemContent::process(html);
+
+    assert!(!content.html.contains("script"));
+    assert!(!content.html.contains("pixel.gif"));
+    assert_eq!(content.summary, "Hello & welcome");
+    assert_eq!(content.images, ["https://example.com/a.png?a=1&b=2"]);
+    assert_eq!(summarize("a b c d", 2), "a b …");
+}
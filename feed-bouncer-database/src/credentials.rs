@@ -0,0 +1,131 @@
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+use crate::{
+    database::{Database, FeedId},
+    Error,
+};
+
+/// Credentials of a private feed, sent with the downloads of the feed url
+/// and its archive pages.
+#[derive(serde::Deserialize, serde::Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum FeedCredentials {
+    /// HTTP basic authentication
+    Basic { username: String, password: String },
+    /// A header like `Authorization: Bearer <token>`
+    Header { name: String, value: String },
+    /// A query parameter like `?token=<token>` added to the url
+    Query { name: String, value: String },
+}
+
+impl FeedCredentials {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Basic { .. } => "basic",
+            Self::Header { .. } => "header",
+            Self::Query { .. } => "query",
+        }
+    }
+    /// The user name, header or parameter, which are fine to show
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Basic { username, .. } => username,
+            Self::Header { name, .. } | Self::Query { name, .. } => name,
+        }
+    }
+    #[cfg(feature = "fetch")]
+    pub(crate) fn apply(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self {
+            Self::Basic { username, password } => request.basic_auth(username, Some(password)),
+            Self::Header { name, value } => request.header(name.as_str(), value.as_str()),
+            Self::Query { name, value } => request.query(&[(name, value)]),
+        }
+    }
+    /// Removes the query parameter added by `apply` from a url
+    #[cfg(feature = "fetch")]
+    pub(crate) fn remove_from(&self, url: &mut reqwest::Url) {
+        let name = match self {
+            Self::Query { name, .. } => name,
+            _ => return,
+        };
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(key, _)| key != name.as_str())
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        if pairs.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(pairs);
+        }
+    }
+}
+
+/// Keeps the secrets out of logs
+impl std::fmt::Debug for FeedCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FeedCredentials({} {:?})", self.kind(), self.name())
+    }
+}
+
+/// The credentials of all feeds, kept in `credentials.json` apart from the
+/// feed files, so they don't end up in exports or backups of the feeds.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Default)]
+pub(crate) struct CredentialStore {
+    feeds: BTreeMap<FeedId, FeedCredentials>,
+}
+
+impl CredentialStore {
+    /// An empty store if there is no file yet
+    pub fn open(storage_path: &Path) -> Result<Self, Error> {
+        match std::fs::read_to_string(storage_path.join("credentials.json")) {
+            Ok(v) => serde_json::from_str(&v).map_err(|e| Error::InvalidCredentials(e.to_string())),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+    /// Writes the file readable by the owner only
+    pub fn save(&self, storage_path: &Path) -> std::io::Result<()> {
+        let path = storage_path.join("credentials.json");
+        let new_path = path.with_extension("new.json");
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&new_path)?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(new_path, path)
+    }
+    pub fn get(&self, feed_id: &FeedId) -> Option<&FeedCredentials> {
+        self.feeds.get(feed_id)
+    }
+}
+
+impl Database {
+    pub fn credentials(&self, feed_id: &FeedId) -> Option<&FeedCredentials> {
+        self.credentials.get(feed_id)
+    }
+
+    /// Sets or with `None` removes the credentials of a feed and saves them
+    pub fn set_credentials(
+        &mut self,
+        feed_id: &FeedId,
+        credentials: Option<FeedCredentials>,
+    ) -> Result<(), Error> {
+        match credentials {
+            Some(credentials) => {
+                self.credentials.feeds.insert(feed_id.clone(), credentials);
+            }
+            None => {
+                self.credentials.feeds.remove(feed_id);
+            }
+        }
+        if !self.is_read_only() {
+            self.credentials.save(&self.storage_path)?;
+        }
+        Ok(())
+    }
+}
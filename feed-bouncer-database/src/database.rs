@@ -1,27 +1,48 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt::Debug,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
 };
 
 use chrono::{DateTime, Utc};
 
-use crate::database::{
-    storage::Storage,
-    storage_feed::Feed,
-    storage_feed_item::{FeedItem, FeedItemMeta},
-    user_data::UserDataStorage,
+use crate::{
+    archive::ArchiveIndex,
+    config::{Blocklist, Config, UrlTagRule},
+    credentials::CredentialStore,
+    database::{
+        journal::{Journal, JournalEntry},
+        lock::StorageLock,
+        migration::{StoreMeta, SCHEMA_VERSION},
+        storage::Storage,
+        storage_feed::Feed,
+        storage_feed_item::{FeedItemMeta, FeedItemOrder},
+        timeline::Timeline,
+        user_data::{
+            ItemGrouping, ItemState, PendingUsage, SavedView, Share, UserDataStorage, ViewMode,
+        },
+    },
+    query::{FeedQuery, ItemOrder, ItemQuery, TagPattern},
+    search::SearchIndex,
+    Error,
 };
 
+pub mod cold_storage;
+pub mod journal;
+pub mod lock;
+pub mod migration;
 pub mod storage;
 pub mod storage_feed;
 pub mod storage_feed_header;
 pub mod storage_feed_item;
+pub mod timeline;
+pub mod transaction;
 pub mod user_data;
 
 pub type FeedId = String;
 
-#[derive(Default)]
+#[derive(Default, PartialEq)]
 pub struct SourceLookup {
     rss_lookup: HashMap<String, HashSet<FeedId>>,
     title_lookup: HashMap<String, HashSet<FeedId>>,
@@ -46,23 +67,74 @@ impl SourceLookup {
                 .insert(feed_id.clone());
         }
     }
-    fn check(&self, key: LookupKey<'_>) -> Option<FeedId> {
+    fn forget(&mut self, feed_id: &FeedId, key: LookupKey<'_>) {
+        fn remove(map: &mut HashMap<String, HashSet<FeedId>>, key: &str, feed_id: &FeedId) {
+            if let Some(feed_ids) = map.get_mut(key) {
+                feed_ids.remove(feed_id);
+                if feed_ids.is_empty() {
+                    map.remove(key);
+                }
+            }
+        }
+
+        remove(&mut self.title_lookup, key.name, feed_id);
+        if let Some(rss) = key.feed_url {
+            remove(&mut self.rss_lookup, rss, feed_id);
+        }
+    }
+    fn has_feed_url(&self, feed_id: &FeedId) -> bool {
+        self.rss_lookup
+            .values()
+            .any(|feed_ids| feed_ids.contains(feed_id))
+    }
+    /// Finds the feed a key refers to:
+    ///
+    /// 1. The only feed with the url of the key
+    /// 2. Of several feeds with the url, the only one with the title of the key
+    /// 3. Without a feed with the url, the only feed with the title of the key.
+    ///    If the key has a url, feeds that have a different url don't count.
+    ///
+    /// Titles are the names and title aliases of feeds. Ambiguous keys don't
+    /// match any feed.
+    pub(crate) fn check(&self, key: LookupKey<'_>) -> Option<FeedId> {
         let title_matches = self.title_lookup.get(key.name).cloned().unwrap_or_default();
         let rss_matches = if let Some(rss) = key.feed_url {
             self.rss_lookup.get(rss).cloned().unwrap_or_default()
         } else {
             HashSet::new()
         };
-        if rss_matches.len() == 1 {
-            return rss_matches.into_iter().next();
-        }
-        if title_matches.len() == 1 {
-            return rss_matches.into_iter().next();
-        }
-        if rss_matches.len() > 1 || title_matches.len() > 1 {
+
+        let candidates: HashSet<FeedId> = match rss_matches.len() {
+            0 => title_matches
+                .into_iter()
+                .filter(|feed_id| key.feed_url.is_none() || !self.has_feed_url(feed_id))
+                .collect(),
+            1 => rss_matches,
+            _ => rss_matches.intersection(&title_matches).cloned().collect(),
+        };
+        if candidates.len() > 1 {
             eprintln!("WARN: Multiple matches for {}", key.name);
+            return None;
         }
-        None
+        candidates.into_iter().next()
+    }
+    fn touch_title(&mut self, feed_id: &FeedId, title: &str) {
+        self.touch(
+            feed_id,
+            LookupKey {
+                name: title,
+                feed_url: None,
+            },
+        );
+    }
+    fn forget_title(&mut self, feed_id: &FeedId, title: &str) {
+        self.forget(
+            feed_id,
+            LookupKey {
+                name: title,
+                feed_url: None,
+            },
+        );
     }
     pub fn check_rss(&self, url: &str) -> Option<&HashSet<FeedId>> {
         self.rss_lookup.get(url)
@@ -70,30 +142,129 @@ impl SourceLookup {
 }
 
 pub struct Database {
+    pub(crate) config: Config,
+    #[cfg(feature = "fetch")]
+    pub(crate) client: reqwest::Client,
     pub(crate) storage: Storage,
     pub(crate) user_data_storage: UserDataStorage,
+    pub(crate) credentials: CredentialStore,
+    pub(crate) search_index: SearchIndex,
+    pub(crate) timeline: Timeline,
+    pub(crate) archive: ArchiveIndex,
     pub(crate) storage_path: PathBuf,
     pub(crate) lookup: SourceLookup,
     pub(crate) last_feed_update: Option<DateTime<Utc>>,
     pub(crate) update_seq_no: u64,
+    /// Feeds whose last fetches failed
+    #[cfg(feature = "fetch")]
+    pub(crate) fetch_failures: BTreeMap<FeedId, crate::diagnostics::FetchFailure>,
+    /// `None` for read-only databases
+    lock: Option<StorageLock>,
+    /// Usage noted by page loads under the read lock
+    pending_usage: Mutex<PendingUsage>,
+    /// `None` for read-only databases
+    pub(crate) journal: Option<Journal>,
 }
 
 impl Database {
-    pub fn init(storage_path: Option<PathBuf>) -> Self {
+    /// Opens the storage, locking it against other instances.
+    pub fn init(storage_path: Option<PathBuf>) -> Result<Self, Error> {
+        let storage_path: PathBuf = storage_path.unwrap_or_else(|| "./storage".into());
+        let lock = StorageLock::acquire(&storage_path)?;
+        Self::open(storage_path, Some(lock))
+    }
+
+    /// Opens the storage without locking it, even if another instance holds
+    /// the lock. Nothing gets written back to it.
+    pub fn init_read_only(storage_path: Option<PathBuf>) -> Result<Self, Error> {
         let storage_path: PathBuf = storage_path.unwrap_or_else(|| "./storage".into());
-        let storage = Storage::open_or_default(&storage_path);
-        let user_data_storage = UserDataStorage::open_or_default(&storage_path);
+        Self::open(storage_path, None)
+    }
+
+    /// An empty database that never touches the disk
+    #[cfg(test)]
+    pub(crate) fn in_memory() -> Self {
+        Self::init_read_only(Some("/nonexistent/feed-bouncer-test".into())).unwrap()
+    }
+
+    pub fn storage_path(&self) -> &Path {
+        &self.storage_path
+    }
+
+    fn open(storage_path: PathBuf, lock: Option<StorageLock>) -> Result<Self, Error> {
+        if lock.is_some() {
+            transaction::recover(&storage_path)?;
+        }
+        let meta = StoreMeta::open_or_default(&storage_path)?;
+        if meta.is_outdated() && lock.is_some() {
+            println!(
+                "Migrating storage from schema version {} to {}: {}",
+                meta.schema_version,
+                SCHEMA_VERSION,
+                migration::steps_since(meta.schema_version).join(", ")
+            );
+            match migration::backup(&storage_path, meta.schema_version) {
+                Ok(Some(backup_path)) => println!("Backed up storage to {:?}", backup_path),
+                Ok(None) => {}
+                Err(e) => {
+                    return Err(Error::Migration(format!(
+                        "could not back up storage before migrating it: {}",
+                        e
+                    )))
+                }
+            }
+        }
+        let config = Config::open(&storage_path)?;
+        let (storage, migrated_feeds) = Storage::open_or_default(&storage_path)?;
+        let user_data_storage = UserDataStorage::open_or_default(&storage_path)?;
+        let credentials = CredentialStore::open(&storage_path)?;
+        let search_index = SearchIndex::open_or_build(&storage_path, &storage);
+        let timeline = Timeline::build(&storage);
+        let archive = ArchiveIndex::open_or_default(&storage_path);
+        let journal = lock.as_ref().map(|_| Journal::open(&storage_path));
+        let journal_entries = Journal::read_if_newer(&storage_path);
 
         let mut ret = Self {
+            #[cfg(feature = "fetch")]
+            client: crate::fetch::build_client(&config.http, &storage_path),
+            config,
             storage,
             user_data_storage,
+            credentials,
+            search_index,
+            timeline,
+            archive,
             storage_path,
             lookup: SourceLookup::default(),
             last_feed_update: None,
             update_seq_no: 0,
+            #[cfg(feature = "fetch")]
+            fetch_failures: BTreeMap::new(),
+            lock,
+            journal,
+            pending_usage: Mutex::default(),
         };
         ret.recreate_cache();
-        ret
+        ret.replay(journal_entries);
+        if !ret.is_read_only() && (meta.is_outdated() || migrated_feeds > 0) {
+            // Files of a migrated storage only get rewritten once they change
+            // otherwise, so write all of them in the current format now.
+            // Migrations may drop data, the backup has it.
+            println!("Saving {} migrated feeds", migrated_feeds);
+            ret.archive_inline_contents()?;
+            ret.storage
+                .save_shrunk(&ret.storage_path, &ret.config.storage);
+            StoreMeta {
+                schema_version: SCHEMA_VERSION,
+            }
+            .save(&ret.storage_path);
+        }
+        ret.sync_full_contents();
+        Ok(ret)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.lock.is_none()
     }
 
     fn recreate_cache(&mut self) {
@@ -101,17 +272,44 @@ impl Database {
     }
 
     pub fn save(&mut self) {
-        self.storage.save(&self.storage_path);
+        self.merge_pending_usage();
+        if self.is_read_only() {
+            return;
+        }
+        self.storage.save(&self.storage_path, &self.config.storage);
         self.user_data_storage.save(&self.storage_path);
+        self.search_index.save(&self.storage_path);
+        self.checkpoint_journal();
     }
 
     pub fn save_shrunk(&mut self) {
-        self.storage.save_shrunk(&self.storage_path);
+        self.merge_pending_usage();
+        if self.is_read_only() {
+            return;
+        }
+        let max_headers = self.config.storage.max_feed_headers;
+        let dropped: usize = self
+            .storage
+            .iter_mut()
+            .map(|(_, feed)| feed.compact_headers(max_headers))
+            .sum();
+        if dropped > 0 {
+            println!("Compacted feed headers, dropped {}", dropped);
+        }
+        self.storage
+            .save_shrunk(&self.storage_path, &self.config.storage);
         self.user_data_storage.save(&self.storage_path);
+        self.search_index.save(&self.storage_path);
+        self.checkpoint_journal();
     }
 
     pub fn save_user_data(&mut self) {
+        self.merge_pending_usage();
+        if self.is_read_only() {
+            return;
+        }
         self.user_data_storage.save(&self.storage_path);
+        self.checkpoint_journal();
     }
 
     pub fn insert(&mut self, mut item: Feed) -> FeedId {
@@ -132,6 +330,7 @@ impl Database {
         let ret = feed_id.clone();
 
         self.lookup.touch(&feed_id, item.key());
+        self.user_data_storage.tag_synonyms().apply(&mut item);
         let existing_entry = self.storage.get_or_insert(feed_id, &item);
 
         update_or_warn(existing_entry.feed_url_mut(), item.feed_url_mut().take());
@@ -143,20 +342,83 @@ impl Database {
     }
 
     pub fn get_items_ordered_by_time(&self) -> Vec<(&FeedId, &Feed, &FeedItemMeta)> {
-        let mut items = Vec::new();
-        for (feed_id, feed) in self.storage.iter() {
-            for item in feed.items() {
-                items.push((feed_id, feed, item));
+        self.query_items(&ItemQuery::new())
+    }
+
+    pub fn get_feeds(&self) -> Vec<(&FeedId, &Feed)> {
+        self.storage.iter().collect()
+    }
+
+    pub fn feed_count(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Number of items of all feeds
+    pub fn item_count(&self) -> usize {
+        self.timeline.len()
+    }
+
+    /// The feeds whose parent is `feed_id`, like the feeds in an OPML folder
+    pub fn children_of(&self, feed_id: &FeedId) -> Vec<(&FeedId, &Feed)> {
+        self.storage
+            .iter()
+            .filter(|(_, feed)| feed.parent() == Some(feed_id))
+            .collect()
+    }
+
+    /// `feed_id` together with its children, their children and so on
+    pub fn with_descendants(&self, feed_id: &FeedId) -> BTreeSet<FeedId> {
+        let mut feed_ids = BTreeSet::new();
+        let mut pending = vec![feed_id.clone()];
+        while let Some(feed_id) = pending.pop() {
+            // Guards against cycles of broken parent links
+            if !feed_ids.insert(feed_id.clone()) {
+                continue;
             }
+            pending.extend(
+                self.children_of(&feed_id)
+                    .into_iter()
+                    .map(|(child_id, _)| child_id.clone()),
+            );
         }
+        feed_ids
+    }
 
-        FeedItem::sort(&mut items, |v| &v.2.item);
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
 
-        items
+    /// Replaces the rules that tag imported feeds by url and saves the
+    /// config
+    pub fn set_url_tag_rules(&mut self, rules: Vec<UrlTagRule>) -> Result<(), Error> {
+        self.config.import.url_tags = rules;
+        self.save_config()
     }
 
-    pub fn get_feeds(&self) -> Vec<(&FeedId, &Feed)> {
-        self.storage.iter().collect()
+    /// Replaces the blocklist of imports and saves the config
+    pub fn set_blocklist(&mut self, blocklist: Blocklist) -> Result<(), Error> {
+        self.config.import.blocklist = blocklist;
+        self.save_config()
+    }
+
+    /// Reads `config.json` again, e.g. after it got edited by hand. The
+    /// current config is kept if the file can't be parsed.
+    pub fn reload_config(&mut self) -> Result<(), Error> {
+        self.config = Config::open(&self.storage_path)?;
+        #[cfg(feature = "fetch")]
+        {
+            self.client = crate::fetch::build_client(&self.config.http, &self.storage_path);
+        }
+        Ok(())
+    }
+
+    fn save_config(&mut self) -> Result<(), Error> {
+        if !self.is_read_only() {
+            let mut tx = self.begin();
+            self.config.stage(&mut tx, &self.storage_path)?;
+            self.commit(tx)?;
+        }
+        Ok(())
     }
 
     pub fn last_feed_update(&self) -> &Option<DateTime<Utc>> {
@@ -171,6 +433,182 @@ impl Database {
         self.storage.get_mut(feed_id)
     }
 
+    /// Looks up an item by its `FeedItemMeta::slug()`
+    pub fn item_by_slug(&self, slug: &str) -> Option<(&FeedId, &Feed, &FeedItemMeta)> {
+        let (feed_id, item_id) = self.timeline.slug(slug)?;
+        let feed = self.storage.get(feed_id)?;
+        let item = self.timeline.item(feed_id, feed, item_id)?;
+        Some((feed_id, feed, item))
+    }
+
+    /// Changes the primary name of a feed. The old name is kept as a title
+    /// alias, so lookups and title prefix stripping keep working for it.
+    pub fn rename_feed(&mut self, feed_id: &FeedId, new_name: &str) -> bool {
+        let new_name = new_name.trim();
+        let feed = match self.storage.get_mut(feed_id) {
+            Some(feed) => feed,
+            None => return false,
+        };
+        if new_name.is_empty() || feed.name() == new_name {
+            return false;
+        }
+
+        self.lookup.forget(feed_id, feed.key());
+        let old_name = feed.set_name(new_name.to_owned());
+        if feed.title_aliases().contains(new_name) {
+            feed.title_alias_remove(new_name);
+        }
+        feed.title_alias_insert(&old_name);
+        self.lookup.touch(feed_id, feed.key());
+        self.lookup.touch_title(feed_id, &old_name);
+
+        true
+    }
+
+    pub fn set_item_order(&mut self, feed_id: &FeedId, order: FeedItemOrder) -> bool {
+        let feed = match self.storage.get_mut(feed_id) {
+            Some(feed) => feed,
+            None => return false,
+        };
+        feed.set_item_order(order);
+        self.timeline.refresh_feed(feed_id, feed);
+        true
+    }
+
+    /// Unsubscribes from a feed and forgets its items, their read state and
+    /// archived articles. The feed file and the cold items are moved to
+    /// `deleted_feeds` in the storage directory. Feeds below it in the
+    /// folder tree become top level feeds.
+    pub fn remove_feed(&mut self, feed_id: &FeedId) -> Option<Feed> {
+        let feed = self.storage.remove(feed_id)?;
+        self.lookup.forget(feed_id, feed.key());
+        for title_alias in feed.title_aliases() {
+            self.lookup.forget(
+                feed_id,
+                LookupKey {
+                    name: title_alias.trim(),
+                    feed_url: None,
+                },
+            );
+        }
+        for feed_url in feed.previous_feed_urls() {
+            self.lookup.forget(
+                feed_id,
+                LookupKey {
+                    name: feed.name(),
+                    feed_url: Some(feed_url),
+                },
+            );
+        }
+        for item in feed.items() {
+            self.search_index
+                .remove_item(feed_id, item.id(), &item.item);
+        }
+        self.timeline.remove_feed(feed_id);
+        #[cfg(feature = "fetch")]
+        self.fetch_failures.remove(feed_id);
+        self.user_data_storage.remove_feed(feed_id);
+        for (_, child) in self.storage.iter_mut() {
+            if child.parent() == Some(feed_id) {
+                child.set_parent(None);
+            }
+        }
+        if self.credentials.get(feed_id).is_some() {
+            if let Err(e) = self.set_credentials(feed_id, None) {
+                eprintln!(
+                    "WARN: could not remove the credentials of feed {}: {}",
+                    feed_id, e
+                );
+            }
+        }
+        if !self.is_read_only() {
+            if let Err(e) = Storage::remove_feed_file(&self.storage_path, feed_id) {
+                eprintln!("WARN: could not move the file of feed {}: {}", feed_id, e);
+            }
+            if let Err(e) = self.remove_cold_items(feed_id) {
+                eprintln!(
+                    "WARN: could not move the cold items of feed {}: {}",
+                    feed_id, e
+                );
+            }
+        }
+        self.remove_archived_articles(feed_id);
+        Some(feed)
+    }
+
+    /// Offset of the item dates of the feed that don't give one, `None` for
+    /// UTC
+    pub fn set_utc_offset(&mut self, feed_id: &FeedId, mins: Option<i32>) -> bool {
+        let feed = match self.storage.get_mut(feed_id) {
+            Some(feed) => feed,
+            None => return false,
+        };
+        feed.set_utc_offset_mins(mins);
+        self.timeline.refresh_feed(feed_id, feed);
+        true
+    }
+
+    pub fn add_title_alias(&mut self, feed_id: &FeedId, alias: &str) -> bool {
+        let feed = match self.storage.get_mut(feed_id) {
+            Some(feed) => feed,
+            None => return false,
+        };
+        if !feed.title_alias_insert(alias) {
+            return false;
+        }
+        self.lookup.touch_title(feed_id, alias.trim());
+        true
+    }
+
+    pub fn remove_title_alias(&mut self, feed_id: &FeedId, alias: &str) -> bool {
+        let feed = match self.storage.get_mut(feed_id) {
+            Some(feed) => feed,
+            None => return false,
+        };
+        if !feed.title_alias_remove(alias) {
+            return false;
+        }
+        // The name can't be forgotten, even if it was an alias as well
+        if feed.name().trim() != alias.trim() {
+            self.lookup.forget_title(feed_id, alias.trim());
+        }
+        true
+    }
+
+    /// Changes the feed url, e.g. after a permanent redirect. The old url is
+    /// kept, so lookups and imports of it still find the feed.
+    pub fn move_feed_url(&mut self, feed_id: &FeedId, new_url: &str) -> bool {
+        let feed = match self.storage.get_mut(feed_id) {
+            Some(feed) => feed,
+            None => return false,
+        };
+        let old_url = feed.feed_url().map(str::to_owned);
+        if old_url.as_deref() == Some(new_url) {
+            return false;
+        }
+
+        println!(
+            "Feed [{}] moved from {} to {}",
+            feed.display_name(),
+            old_url.as_deref().unwrap_or("no url"),
+            new_url
+        );
+        self.lookup.forget(feed_id, feed.key());
+        feed.move_feed_url(new_url.to_owned());
+        self.lookup.touch(feed_id, feed.key());
+        if let Some(old_url) = &old_url {
+            self.lookup.touch(
+                feed_id,
+                LookupKey {
+                    name: feed.name(),
+                    feed_url: Some(old_url),
+                },
+            );
+        }
+
+        true
+    }
+
     pub fn get_update_seq_no(&self) -> u64 {
         self.update_seq_no
     }
@@ -178,14 +616,240 @@ impl Database {
     pub fn set_update_seq_no(&mut self, v: u64) {
         assert!(self.update_seq_no < v);
         self.update_seq_no = v;
+        self.journal(JournalEntry::UpdateSeqNo { seq_no: v });
+    }
+
+    pub fn saved_views(&self) -> &BTreeMap<String, SavedView> {
+        self.user_data_storage.saved_views()
+    }
+    pub fn save_view(&mut self, name: String, view: SavedView) {
+        self.user_data_storage.save_view(name, view)
+    }
+    pub fn remove_view(&mut self, name: &str) -> bool {
+        self.user_data_storage.remove_view(name)
+    }
+    pub fn shares(&self) -> &BTreeMap<String, Share> {
+        self.user_data_storage.shares()
+    }
+    /// Shares `filter` read-only, returns the token of the share
+    pub fn create_share(&mut self, name: &str, filter: &str) -> String {
+        self.user_data_storage.create_share(Share {
+            name: name.trim().to_owned(),
+            filter: filter.trim().to_owned(),
+            created_at: Utc::now(),
+        })
+    }
+    pub fn revoke_share(&mut self, token: &str) -> bool {
+        self.user_data_storage.revoke_share(token)
+    }
+    /// The view mode last picked in the UI
+    pub fn view_mode(&self) -> ViewMode {
+        self.pending_usage()
+            .view_mode()
+            .unwrap_or_else(|| self.user_data_storage.view_mode())
+    }
+    pub fn set_view_mode(&mut self, view_mode: ViewMode) {
+        self.pending_usage().set_view_mode(None);
+        self.user_data_storage.set_view_mode(view_mode)
+    }
+    /// Remembers the view mode a page got loaded with, see `PendingUsage`
+    pub fn note_view_mode(&self, view_mode: ViewMode) {
+        if self.view_mode() != view_mode {
+            self.pending_usage().set_view_mode(Some(view_mode));
+        }
+    }
+    /// The item grouping last picked in the UI
+    pub fn item_grouping(&self) -> ItemGrouping {
+        self.pending_usage()
+            .item_grouping()
+            .unwrap_or_else(|| self.user_data_storage.item_grouping())
+    }
+    pub fn set_item_grouping(&mut self, grouping: ItemGrouping) {
+        self.pending_usage().set_item_grouping(None);
+        self.user_data_storage.set_item_grouping(grouping)
+    }
+    /// Remembers the grouping a page got loaded with, see `PendingUsage`
+    pub fn note_item_grouping(&self, grouping: ItemGrouping) {
+        if self.item_grouping() != grouping {
+            self.pending_usage().set_item_grouping(Some(grouping));
+        }
+    }
+    /// The filter of the index page when the request doesn't give one
+    pub fn default_filter(&self) -> &str {
+        self.user_data_storage.default_filter()
+    }
+    pub fn set_default_filter(&mut self, filter: &str) {
+        self.user_data_storage.set_default_filter(filter)
+    }
+    /// When the previous visit of the index ended, items first seen after
+    /// it are new to the user
+    pub fn last_visit(&self) -> Option<DateTime<Utc>> {
+        self.pending_usage()
+            .last_visit(self.user_data_storage.visits())
+    }
+    /// Notes a page load of the index, see `PendingUsage`
+    pub fn record_visit(&self) {
+        self.pending_usage()
+            .record_visit(self.user_data_storage.visits(), Utc::now());
+    }
+
+    /// Counts a use of a filter and its tags, unless disabled in the config.
+    /// See `PendingUsage`.
+    pub fn record_filter_use(&self, query: &FeedQuery, filter: &str) {
+        let filter = filter.trim();
+        if !self.config.ui.track_usage || filter.is_empty() {
+            return;
+        }
+        let tags = query.tag_patterns().iter().map(|pattern| match pattern {
+            TagPattern::Has(tag) | TagPattern::HasNot(tag) => &tag[..],
+        });
+        self.pending_usage().record_filter_use(filter, tags);
+    }
+    pub fn filter_uses(&self, filter: &str) -> u64 {
+        let filter = filter.trim();
+        self.user_data_storage.filter_uses(filter) + self.pending_usage().filter_uses(filter)
+    }
+    pub fn tag_uses(&self, tag: &str) -> u64 {
+        self.user_data_storage.tag_uses(tag) + self.pending_usage().tag_uses(tag)
+    }
+
+    fn pending_usage(&self) -> MutexGuard<'_, PendingUsage> {
+        // The usage stays consistent even if a page load panicked
+        self.pending_usage
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+    fn merge_pending_usage(&mut self) {
+        let pending = std::mem::take(
+            self.pending_usage
+                .get_mut()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        );
+        self.user_data_storage.merge_usage(pending);
     }
 
     pub fn mark_read(&mut self, feed_id: &FeedId, item_id: usize) {
-        self.user_data_storage.mark_read(feed_id, item_id)
+        let read_at = Utc::now();
+        self.user_data_storage.mark_read(feed_id, item_id, read_at);
+        self.journal(JournalEntry::MarkRead {
+            feed_id: feed_id.clone(),
+            item_id,
+            read_at: Some(read_at),
+        });
+    }
+    /// The most recently read items that are still stored, with the time
+    /// they got read. Reads from before read times were recorded are left
+    /// out.
+    pub fn recently_read(
+        &self,
+        limit: usize,
+    ) -> Vec<(DateTime<Utc>, &FeedId, &Feed, &FeedItemMeta)> {
+        self.user_data_storage
+            .recent_reads()
+            .into_iter()
+            .filter_map(|(read_at, feed_id, item_id)| {
+                let feed = self.get(feed_id)?;
+                let item = feed.items().iter().find(|item| item.id() == item_id)?;
+                Some((read_at, feed_id, feed, item))
+            })
+            .take(limit)
+            .collect()
     }
     pub fn is_read(&self, feed_id: &FeedId, item_id: usize) -> bool {
         self.user_data_storage.is_read(feed_id, item_id)
     }
+    /// Moves an item to the trash, it stays stored but is no longer shown.
+    pub fn hide_item(&mut self, feed_id: &FeedId, item_id: usize) {
+        self.user_data_storage.hide(feed_id, item_id);
+        self.journal(JournalEntry::Hide {
+            feed_id: feed_id.clone(),
+            item_id,
+        });
+    }
+    pub fn unhide_item(&mut self, feed_id: &FeedId, item_id: usize) {
+        self.user_data_storage.unhide(feed_id, item_id);
+        self.journal(JournalEntry::Unhide {
+            feed_id: feed_id.clone(),
+            item_id,
+        });
+    }
+    pub fn is_hidden(&self, feed_id: &FeedId, item_id: usize) -> bool {
+        self.user_data_storage.is_hidden(feed_id, item_id)
+    }
+    pub fn item_state(&self, feed_id: &FeedId, item_id: usize) -> ItemState {
+        self.user_data_storage.item_states(feed_id)(item_id)
+    }
+    /// The items of a feed together with their read and trash state, empty
+    /// if the feed does not exist
+    pub fn items_with_state(
+        &self,
+        feed_id: &FeedId,
+    ) -> impl Iterator<Item = (&FeedItemMeta, ItemState)> + '_ {
+        let states = self.user_data_storage.item_states(feed_id);
+        self.get(feed_id)
+            .into_iter()
+            .flat_map(|feed| feed.items())
+            .map(move |item| (item, states(item.id())))
+    }
+    pub fn unread_count(&self, feed_id: &FeedId) -> usize {
+        self.items_with_state(feed_id)
+            .filter(|(_, state)| !state.read && !state.hidden)
+            .count()
+    }
+
+    /// Number of items marked as read today (UTC)
+    pub fn reads_today(&self) -> u64 {
+        self.user_data_storage.reads_on(Utc::now().date_naive())
+    }
+
+    /// Feeds with unread items that publish rarely, so catching up on them is
+    /// quick. Ordered by the number of items published in the last 90 days.
+    pub fn quiet_feeds(&self, limit: usize) -> Vec<(&FeedId, &Feed, usize)> {
+        let since = Utc::now() - chrono::Duration::days(90);
+        let mut feeds: Vec<_> = self
+            .storage
+            .iter()
+            .map(|(feed_id, feed)| (feed_id, feed, self.unread_count(feed_id)))
+            .filter(|(_, _, unread)| *unread > 0)
+            .map(|(feed_id, feed, unread)| {
+                let recent = feed
+                    .items()
+                    .iter()
+                    .filter(|item| item.publish_date_or_old() >= since)
+                    .count();
+                (recent, feed_id, feed, unread)
+            })
+            .collect();
+        feeds.sort_by_key(|(recent, _, _, unread)| (*recent, *unread));
+        feeds
+            .into_iter()
+            .take(limit)
+            .map(|(_, feed_id, feed, unread)| (feed_id, feed, unread))
+            .collect()
+    }
+
+    /// Marks all items of the matching feeds as read, except for the newest
+    /// `keep` items of each feed. Returns the number of newly read items.
+    pub fn catch_up(&mut self, feeds: &FeedQuery, keep: usize) -> usize {
+        let query = ItemQuery::new()
+            .feeds(feeds.clone())
+            .order(ItemOrder::NewestFirst)
+            .hidden(false);
+        let mut counts = HashMap::new();
+        let mut to_read = Vec::new();
+        for (feed_id, _, item, state) in self.query_items_with_state(&query) {
+            let count = counts.entry(feed_id).or_insert(0);
+            *count += 1;
+            if *count > keep && !state.read {
+                to_read.push((feed_id.clone(), item.id()));
+            }
+        }
+
+        for (feed_id, item_id) in &to_read {
+            self.mark_read(feed_id, *item_id);
+        }
+        to_read.len()
+    }
 }
 
 fn warn_if_not_equal<T: PartialEq + Debug + ?Sized>(dst: &T, value: &T) {
@@ -206,3 +870,103 @@ fn update_or_warn<T: PartialEq + Debug>(dst: &mut Option<T>, value: Option<T>) {
         None => *dst = Some(value),
     }
 }
+
+#[test]
+fn test_source_lookup_precedence() {
+    let key = |name, feed_url| LookupKey { name, feed_url };
+    let a: FeedId = "a".into();
+    let b: FeedId = "b".into();
+    let c: FeedId = "c".into();
+
+    let mut lookup = SourceLookup::default();
+    lookup.touch(&a, key("Blog", Some("https://a.example/feed")));
+    lookup.touch(&b, key("Blog", Some("https://b.example/feed")));
+    lookup.touch(&c, key("Notes", None));
+    lookup.touch_title(&c, "Old Notes");
+
+    // A unique url wins over an ambiguous title
+    assert_eq!(
+        lookup.check(key("Blog", Some("https://a.example/feed"))),
+        Some(a.clone())
+    );
+    // Ambiguous titles don't match
+    assert_eq!(lookup.check(key("Blog", None)), None);
+    // Aliases match, also for keys with an unknown url
+    assert_eq!(lookup.check(key("Old Notes", None)), Some(c.clone()));
+    assert_eq!(
+        lookup.check(key("Notes", Some("https://c.example/feed"))),
+        Some(c.clone())
+    );
+    // Feeds with a different url are not matched by title
+    lookup.touch(&b, key("Other", Some("https://b.example/feed")));
+    assert_eq!(
+        lookup.check(key("Other", Some("https://d.example/feed"))),
+        None
+    );
+
+    // Several feeds with the same url are told apart by title
+    lookup.touch(&c, key("Notes", Some("https://a.example/feed")));
+    assert_eq!(
+        lookup.check(key("Notes", Some("https://a.example/feed"))),
+        Some(c.clone())
+    );
+    assert_eq!(
+        lookup.check(key("Unknown", Some("https://a.example/feed"))),
+        None
+    );
+
+    lookup.forget_title(&c, "Old Notes");
+    assert_eq!(lookup.check(key("Old Notes", None)), None);
+}
+
+#[test]
+fn test_pending_usage() {
+    let mut db = Database::in_memory();
+    let query = FeedQuery::new().with_tag("rust");
+
+    // Page loads only need shared access, their usage counts right away
+    db.record_visit();
+    db.record_filter_use(&query, "rust");
+    db.note_view_mode(ViewMode::Compact);
+    assert_eq!(db.filter_uses("rust"), 1);
+    assert_eq!(db.tag_uses("rust"), 1);
+    assert_eq!(db.view_mode(), ViewMode::Compact);
+    assert_eq!(db.user_data_storage.filter_uses("rust"), 0);
+
+    // The next save takes it over into the user data
+    db.save_user_data();
+    assert_eq!(db.user_data_storage.filter_uses("rust"), 1);
+    assert_eq!(db.user_data_storage.view_mode(), ViewMode::Compact);
+    assert_eq!(db.filter_uses("rust"), 1);
+}
+
+#[test]
+fn test_remove_feed_cleans_up() {
+    let mut db = Database::in_memory();
+    let folder = db.insert(Feed::new("Folder".to_owned()));
+    let mut child = Feed::new("Child".to_owned());
+    child.set_parent(Some(folder.clone()));
+    let child = db.insert(child);
+    db.mark_read(&folder, 0);
+
+    assert!(db.remove_feed(&folder).is_some());
+    assert!(!db.is_read(&folder, 0));
+    assert_eq!(db.get(&child).unwrap().parent(), None);
+}
+
+#[test]
+fn test_rename_feed_keeps_display_name() {
+    let mut db = Database::in_memory();
+    let feed_id = db.insert(Feed::new("Old".to_owned()));
+    assert!(db.rename_feed(&feed_id, "New"));
+    assert_eq!(db.get(&feed_id).unwrap().display_name(), "New");
+
+    db.get_mut(&feed_id)
+        .unwrap()
+        .set_display_name("Mine".to_owned());
+    assert!(db.rename_feed(&feed_id, "Newer"));
+    let feed = db.get(&feed_id).unwrap();
+    assert_eq!(feed.display_name(), "Mine");
+    assert_eq!(feed.name(), "Newer");
+    assert!(feed.title_aliases().contains("New"));
+}
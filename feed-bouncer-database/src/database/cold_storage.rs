@@ -0,0 +1,225 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::database::{storage_feed_item::FeedItemMeta, Database, FeedId};
+use crate::Error;
+
+fn cold_path(storage_path: &Path, feed_id: &FeedId) -> PathBuf {
+    storage_path
+        .join("cold")
+        .join(feed_id)
+        .with_extension("json")
+}
+
+/// Items that got moved out of a feed file because of their age, stored as
+/// `cold/<feed_id>.json`. They are only read when the history of the feed is
+/// requested, see [`crate::StorageConfig::cold_after_days`].
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+struct ColdItems {
+    items: Vec<FeedItemMeta>,
+}
+
+impl ColdItems {
+    fn open(storage_path: &Path, feed_id: &FeedId) -> std::io::Result<Self> {
+        match std::fs::read_to_string(cold_path(storage_path, feed_id)) {
+            Ok(v) => serde_json::from_str(&v)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes to a new file first, so a failed write leaves the old one
+    fn save(&self, storage_path: &Path, feed_id: &FeedId) -> std::io::Result<()> {
+        let path = cold_path(storage_path, feed_id);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let json = serde_json::to_string(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let new_path = path.with_extension("new.json");
+        std::fs::write(&new_path, json)?;
+        std::fs::rename(new_path, path)
+    }
+}
+
+impl Database {
+    /// Moves the items published before the configured age from the feed
+    /// files to cold storage. Returns the number of moved items, the feeds
+    /// need a shrinking save afterwards, also after an error.
+    ///
+    /// Moved items only show up in the history of their feed, search and
+    /// the item queries don't see them anymore. Their permalinks lead to
+    /// the history.
+    pub fn move_to_cold_storage(&mut self) -> Result<usize, Error> {
+        let days = match self.config.storage.cold_after_days {
+            Some(days) if !self.is_read_only() => days,
+            _ => return Ok(0),
+        };
+        self.move_to_cold_storage_before(Utc::now() - Duration::days(days as i64))
+    }
+
+    fn move_to_cold_storage_before(&mut self, cutoff: DateTime<Utc>) -> Result<usize, Error> {
+        let feed_ids: Vec<FeedId> = self
+            .storage
+            .iter()
+            .filter(|(_, feed)| !feed.items_published_before(cutoff).is_empty())
+            .map(|(feed_id, _)| feed_id.clone())
+            .collect();
+
+        let mut moved = 0;
+        let mut result = Ok(());
+        for feed_id in feed_ids {
+            let mut cold = match ColdItems::open(&self.storage_path, &feed_id) {
+                Ok(cold) => cold,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            };
+            let feed = match self.storage.get_mut(&feed_id) {
+                Some(feed) => feed,
+                None => continue,
+            };
+
+            // The cold file is written first and the items only leave the
+            // feed once it is, so a failed move leaves the items in both
+            // files instead of neither
+            let known: BTreeSet<usize> = cold.items.iter().map(|item| item.id()).collect();
+            let items = feed.items_published_before(cutoff);
+            cold.items.extend(
+                items
+                    .into_iter()
+                    .filter(|item| !known.contains(&item.id()))
+                    .cloned(),
+            );
+            if let Err(e) = cold.save(&self.storage_path, &feed_id) {
+                result = Err(e);
+                break;
+            }
+
+            for item in feed.take_items_published_before(cutoff) {
+                self.search_index
+                    .remove_item(&feed_id, item.id(), &item.item);
+                moved += 1;
+            }
+            self.timeline.refresh_feed(&feed_id, feed);
+        }
+        if moved > 0 {
+            println!("Moved {} old items to cold storage", moved);
+        }
+        result?;
+        Ok(moved)
+    }
+
+    /// Moves the cold items of a removed feed next to its feed file in
+    /// `deleted_feeds`
+    pub(crate) fn remove_cold_items(&self, feed_id: &FeedId) -> std::io::Result<()> {
+        let path = cold_path(&self.storage_path, feed_id);
+        if !path.exists() {
+            return Ok(());
+        }
+        let deleted_path = self.storage_path.join("deleted_feeds").join("cold");
+        std::fs::create_dir_all(&deleted_path)?;
+        std::fs::rename(path, deleted_path.join(feed_id).with_extension("json"))
+    }
+
+    /// Looks up an item in cold storage by its `FeedItemMeta::slug()`, for
+    /// permalinks of moved items. Reads the cold files of all feeds.
+    pub fn cold_item_by_slug(&self, slug: &str) -> Option<(FeedId, usize)> {
+        self.storage.iter().find_map(|(feed_id, _)| {
+            if !cold_path(&self.storage_path, feed_id).exists() {
+                return None;
+            }
+            let cold = ColdItems::open(&self.storage_path, feed_id).ok()?;
+            cold.items
+                .iter()
+                .find(|item| item.slug(feed_id) == slug)
+                .map(|item| (feed_id.clone(), item.id()))
+        })
+    }
+
+    /// The items of a feed in cold storage, read from disk, newest first
+    pub fn cold_items(&self, feed_id: &FeedId) -> Vec<FeedItemMeta> {
+        let feed = match self.get(feed_id) {
+            Some(feed) => feed,
+            None => return Vec::new(),
+        };
+        let mut items = match ColdItems::open(&self.storage_path, feed_id) {
+            Ok(cold) => cold.items,
+            Err(e) => {
+                eprintln!("WARN: could not read the cold items of {}: {}", feed_id, e);
+                return Vec::new();
+            }
+        };
+        let offset = feed.default_offset();
+        let full_content = feed.effective_settings(self).full_content;
+        for item in &mut items {
+            item.set_default_offset(offset);
+            item.set_full_content(self.full_content(feed_id, item.id(), full_content));
+        }
+        items.sort_by_key(|item| std::cmp::Reverse(item.publish_date_or_old()));
+        items
+    }
+}
+
+#[cfg(test)]
+fn database_with_old_item(storage_path: PathBuf) -> (Database, FeedId) {
+    use crate::{Feed, FeedItem};
+
+    let mut db = Database::in_memory();
+    db.storage_path = storage_path;
+    let feed_id = db.insert(Feed::new("Feed".to_owned()));
+    let feed = db.get_mut(&feed_id).unwrap();
+    for (guid, days_ago) in [("old", 30), ("new", 1)] {
+        feed.push_item(FeedItem::Rss(rss::Item {
+            guid: Some(rss::Guid {
+                value: guid.to_owned(),
+                permalink: false,
+            }),
+            pub_date: Some((Utc::now() - Duration::days(days_ago)).to_rfc2822()),
+            ..rss::Item::default()
+        }));
+    }
+    (db, feed_id)
+}
+
+#[test]
+fn test_move_to_cold_storage() {
+    let storage_path =
+        std::env::temp_dir().join(format!("feed-bouncer-test-cold-{}", std::process::id()));
+    let (mut db, feed_id) = database_with_old_item(storage_path.clone());
+    let slug = db.get(&feed_id).unwrap().items()[0].slug(&feed_id);
+
+    let cutoff = Utc::now() - Duration::days(7);
+    assert_eq!(db.move_to_cold_storage_before(cutoff).unwrap(), 1);
+    assert_eq!(db.get(&feed_id).unwrap().item_count(), 1);
+    assert_eq!(db.cold_items(&feed_id).len(), 1);
+    assert!(db.cold_item_by_slug(&slug).is_some());
+
+    // Nothing left to move, and the cold file keeps the item once
+    assert_eq!(db.move_to_cold_storage_before(cutoff).unwrap(), 0);
+    assert_eq!(db.cold_items(&feed_id).len(), 1);
+
+    std::fs::remove_dir_all(storage_path).unwrap();
+}
+
+#[test]
+fn test_failed_move_keeps_items() {
+    // A file in place of the storage directory makes writing fail
+    let storage_path = std::env::temp_dir().join(format!(
+        "feed-bouncer-test-cold-file-{}",
+        std::process::id()
+    ));
+    std::fs::write(&storage_path, "").unwrap();
+    let (mut db, feed_id) = database_with_old_item(storage_path.clone());
+
+    assert!(db
+        .move_to_cold_storage_before(Utc::now() - Duration::days(7))
+        .is_err());
+    assert_eq!(db.get(&feed_id).unwrap().item_count(), 2);
+
+    std::fs::remove_file(storage_path).unwrap();
+}
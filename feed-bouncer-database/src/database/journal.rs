@@ -0,0 +1,183 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::database::{Database, FeedId};
+
+/// A mutation of state that is otherwise only saved later on.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalEntry {
+    MarkRead {
+        feed_id: FeedId,
+        item_id: usize,
+        /// Missing in journals written by older versions
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        read_at: Option<DateTime<Utc>>,
+    },
+    MarkUnread {
+        feed_id: FeedId,
+        item_id: usize,
+    },
+    Hide {
+        feed_id: FeedId,
+        item_id: usize,
+    },
+    Unhide {
+        feed_id: FeedId,
+        item_id: usize,
+    },
+    UpdateSeqNo {
+        seq_no: u64,
+    },
+}
+
+/// An append-only log of mutations since the last save, one JSON entry per
+/// line, so they survive a crash.
+///
+/// Saving replaces the journal with a checkpoint of the state that is not
+/// part of the saved files, like the update sequence number.
+pub struct Journal {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+fn journal_path(storage_path: &Path) -> PathBuf {
+    storage_path.join("journal.jsonl")
+}
+
+impl Journal {
+    pub fn open(storage_path: &Path) -> Self {
+        Self {
+            path: journal_path(storage_path),
+            file: None,
+        }
+    }
+
+    /// Reads the entries of the journal, if it got written after the user
+    /// data. An older journal belongs to data that got replaced since.
+    pub fn read_if_newer(storage_path: &Path) -> Vec<JournalEntry> {
+        let path = journal_path(storage_path);
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        let journal_modified = match modified(&path) {
+            Some(v) => v,
+            None => return Vec::new(),
+        };
+        if let Some(data_modified) = modified(&storage_path.join("user_data.json")) {
+            if data_modified > journal_modified {
+                eprintln!("WARN: ignoring journal that is older than the user data");
+                return Vec::new();
+            }
+        }
+
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(_) => return Vec::new(),
+        };
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            // A crash can leave a partially written last line behind
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(_) => eprintln!("WARN: skipping unreadable journal entry {:?}", line),
+            }
+        }
+        entries
+    }
+
+    pub fn append(&mut self, entry: &JournalEntry) {
+        if self.file.is_none() {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path);
+            match file {
+                Ok(file) => self.file = Some(file),
+                Err(e) => {
+                    eprintln!("WARN: could not open journal {:?}: {}", self.path, e);
+                    return;
+                }
+            }
+        }
+        let mut line = serde_json::to_string(entry).unwrap();
+        line.push('\n');
+        if let Err(e) = self.file.as_mut().unwrap().write_all(line.as_bytes()) {
+            eprintln!("WARN: could not write journal: {}", e);
+        }
+    }
+
+    /// Replaces all entries with `checkpoint`, after the state got saved.
+    pub fn reset(&mut self, checkpoint: &[JournalEntry]) {
+        self.file = None;
+        let mut journal = String::new();
+        for entry in checkpoint {
+            journal += &serde_json::to_string(entry).unwrap();
+            journal.push('\n');
+        }
+        let new_path = self.path.with_extension("new.jsonl");
+        let result =
+            std::fs::write(&new_path, journal).and_then(|_| std::fs::rename(&new_path, &self.path));
+        if let Err(e) = result {
+            eprintln!("WARN: could not reset journal: {}", e);
+        }
+    }
+}
+
+impl Database {
+    pub(crate) fn journal(&mut self, entry: JournalEntry) {
+        if let Some(journal) = &mut self.journal {
+            journal.append(&entry);
+        }
+    }
+
+    pub(crate) fn replay(&mut self, entries: Vec<JournalEntry>) {
+        if entries.is_empty() {
+            return;
+        }
+        println!("Replaying {} journal entries", entries.len());
+        for entry in entries {
+            match entry {
+                JournalEntry::MarkRead {
+                    feed_id,
+                    item_id,
+                    read_at,
+                } => self.user_data_storage.mark_read(
+                    &feed_id,
+                    item_id,
+                    read_at.unwrap_or_else(Utc::now),
+                ),
+                JournalEntry::MarkUnread { feed_id, item_id } => {
+                    self.user_data_storage.mark_unread(&feed_id, item_id)
+                }
+                JournalEntry::Hide { feed_id, item_id } => {
+                    self.user_data_storage.hide(&feed_id, item_id)
+                }
+                JournalEntry::Unhide { feed_id, item_id } => {
+                    self.user_data_storage.unhide(&feed_id, item_id)
+                }
+                JournalEntry::UpdateSeqNo { seq_no } => {
+                    self.update_seq_no = self.update_seq_no.max(seq_no)
+                }
+            }
+        }
+    }
+
+    /// Called after saving, the journal only needs to keep what the saved
+    /// files don't contain.
+    pub(crate) fn checkpoint_journal(&mut self) {
+        let checkpoint = [JournalEntry::UpdateSeqNo {
+            seq_no: self.update_seq_no,
+        }];
+        if let Some(journal) = &mut self.journal {
+            journal.reset(&checkpoint);
+        }
+    }
+}
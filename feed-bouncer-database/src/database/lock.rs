@@ -0,0 +1,77 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::Error;
+
+/// An exclusive lock of a storage path, held as long as the value lives.
+///
+/// The lock file contains the PID of the owning process, so a lock left
+/// behind by a crashed process can be detected and replaced.
+pub struct StorageLock {
+    path: PathBuf,
+}
+
+impl StorageLock {
+    pub fn acquire(storage_path: &Path) -> Result<Self, Error> {
+        std::fs::create_dir_all(storage_path)?;
+        let path = storage_path.join("feed-bouncer.lock");
+
+        let mut holder = None;
+        // The second attempt happens after removing a stale lock
+        for _ in 0..2 {
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut file) => {
+                    write!(file, "{}", std::process::id())?;
+                    return Ok(Self { path });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    holder = std::fs::read_to_string(&path)
+                        .ok()
+                        .and_then(|v| v.trim().parse::<u32>().ok());
+                    match holder {
+                        Some(pid) if is_running(pid) => break,
+                        _ => {
+                            eprintln!("WARN: removing stale lock file {:?}", path);
+                            std::fs::remove_file(&path)?;
+                        }
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Err(Error::Locked {
+            holder: match holder {
+                Some(pid) => format!("process {}", pid),
+                None => "an unknown process".to_owned(),
+            },
+            path,
+        })
+    }
+}
+
+impl Drop for StorageLock {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+/// A lock with our own PID can only be left over from an earlier process
+/// that got the same PID, e.g. in a container.
+#[cfg(target_os = "linux")]
+fn is_running(pid: u32) -> bool {
+    pid != std::process::id() && Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Without a portable way to check, the lock is assumed to be held, the error
+/// tells the user which file to remove.
+#[cfg(not(target_os = "linux"))]
+fn is_running(pid: u32) -> bool {
+    pid != std::process::id()
+}
@@ -0,0 +1,221 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use serde_json::Value;
+
+use crate::{database::storage_feed::Feed, Error};
+
+/// The current version of the feed file format. Bump it together with
+/// adding a step to `FEED_MIGRATIONS`.
+pub const SCHEMA_VERSION: u32 = 6;
+
+/// A change of the feed file format, applied to files of older versions.
+struct Migration {
+    /// The version a file has after the step
+    to: u32,
+    name: &'static str,
+    apply: fn(&mut Value) -> serde_json::Result<()>,
+}
+
+/// All steps, ordered by version. Files without a version are version 0.
+const FEED_MIGRATIONS: &[Migration] = &[
+    Migration {
+        to: 1,
+        name: "capitalize hourly update periods",
+        apply: capitalize_hourly_period,
+    },
+    Migration {
+        to: 2,
+        name: "number items and headers",
+        apply: |value| with_feed(value, Feed::number_legacy_items),
+    },
+    Migration {
+        to: 3,
+        name: "process item content",
+        apply: |value| with_feed(value, Feed::process_missing_content),
+    },
+    Migration {
+        to: 4,
+        name: "record when items were first seen",
+        apply: |value| with_feed(value, Feed::backfill_first_seen),
+    },
+    Migration {
+        to: 5,
+        name: "stop storing processed item content",
+        apply: remove_item_content,
+    },
+    Migration {
+        to: 6,
+        name: "move the full content of items to the archive",
+        // The inline content is still read, `Database::open` moves it to the
+        // archive before saving the migrated feeds without it
+        apply: |_| Ok(()),
+    },
+];
+
+/// Older versions stored the `HOURLY` update period of the rss crate
+fn capitalize_hourly_period(value: &mut Value) -> serde_json::Result<()> {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if key == "period" && *value == "HOURLY" {
+                    *value = Value::from("Hourly");
+                } else {
+                    capitalize_hourly_period(value)?;
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                capitalize_hourly_period(value)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The content of items is processed on load since version 5
+fn remove_item_content(value: &mut Value) -> serde_json::Result<()> {
+    if let Some(items) = value.get_mut("feeds_v2").and_then(Value::as_array_mut) {
+        for item in items {
+            if let Some(item) = item.as_object_mut() {
+                item.remove("content");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs a step that is easier to express on the parsed feed
+fn with_feed(value: &mut Value, f: fn(&mut Feed)) -> serde_json::Result<()> {
+    let mut feed: Feed = serde_json::from_value(std::mem::take(value))?;
+    f(&mut feed);
+    *value = serde_json::to_value(feed)?;
+    Ok(())
+}
+
+/// Brings a feed file up to `SCHEMA_VERSION`, returns true if anything
+/// needed to be done. Fails for files of a newer version, or if a step
+/// can't make sense of the file.
+pub fn migrate_feed(value: &mut Value) -> Result<bool, String> {
+    let version = feed_version(value);
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "feed has schema version {}, but only {} is supported",
+            version, SCHEMA_VERSION
+        ));
+    }
+    let mut migrated = false;
+    for migration in FEED_MIGRATIONS.iter().filter(|m| m.to > version) {
+        (migration.apply)(value)
+            .map_err(|e| format!("step \"{}\" failed: {}", migration.name, e))?;
+        value["schema_version"] = Value::from(migration.to);
+        migrated = true;
+    }
+    Ok(migrated)
+}
+
+/// The names of the steps files of version `from` go through
+pub fn steps_since(from: u32) -> Vec<&'static str> {
+    FEED_MIGRATIONS
+        .iter()
+        .filter(|m| m.to > from)
+        .map(|m| m.name)
+        .collect()
+}
+
+fn feed_version(value: &Value) -> u32 {
+    value
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as u32
+}
+
+/// Store-level metadata, so an outdated storage is detected before any of
+/// its files get loaded.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+pub struct StoreMeta {
+    #[serde(default)]
+    pub schema_version: u32,
+}
+
+fn meta_path(storage_path: &Path) -> PathBuf {
+    storage_path.join("meta.json")
+}
+
+impl StoreMeta {
+    pub fn open_or_default(storage_path: &Path) -> Result<Self, Error> {
+        match std::fs::read_to_string(meta_path(storage_path)) {
+            Ok(meta) => serde_json::from_str(&meta)
+                .map_err(|e| Error::InvalidStorage(format!("could not parse meta.json: {}", e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+    pub fn save(&self, storage_path: &Path) {
+        crate::safe_save_json(self, &meta_path(storage_path), "meta", true);
+    }
+    pub fn is_outdated(&self) -> bool {
+        self.schema_version < SCHEMA_VERSION
+    }
+}
+
+/// Copies the feeds and user data to `backups/`, before they get rewritten in
+/// a newer format. Returns the backup directory, or `None` if the storage is
+/// still empty.
+pub fn backup(storage_path: &Path, from_version: u32) -> std::io::Result<Option<PathBuf>> {
+    let feeds_path = storage_path.join("feeds");
+    if !feeds_path.exists() {
+        return Ok(None);
+    }
+    let backup_path = storage_path.join("backups").join(format!(
+        "pre-migration-v{}-{}",
+        from_version,
+        Utc::now().format("%Y%m%dT%H%M%S")
+    ));
+    std::fs::create_dir_all(backup_path.join("feeds"))?;
+    for e in std::fs::read_dir(&feeds_path)? {
+        let e = e?;
+        if e.file_type()?.is_file() {
+            std::fs::copy(e.path(), backup_path.join("feeds").join(e.file_name()))?;
+        }
+    }
+    let user_data_path = storage_path.join("user_data.json");
+    if user_data_path.exists() {
+        std::fs::copy(&user_data_path, backup_path.join("user_data.json"))?;
+    }
+    Ok(Some(backup_path))
+}
+
+#[test]
+fn test_migrate_feed() {
+    let mut value = serde_json::json!({
+        "name": "A",
+        "feed_url": null,
+        "opml": null,
+        "parent": null,
+        "feed_headers": [],
+        "feeds": [],
+        "x": {"period": "HOURLY"},
+    });
+    assert!(migrate_feed(&mut value).unwrap());
+    assert_eq!(feed_version(&value), SCHEMA_VERSION);
+    assert!(!migrate_feed(&mut value).unwrap());
+
+    value["schema_version"] = Value::from(SCHEMA_VERSION + 1);
+    assert!(migrate_feed(&mut value).is_err());
+
+    let mut value = serde_json::json!({"name": "A", "feeds": "not a list"});
+    assert!(migrate_feed(&mut value).is_err());
+}
+
+#[test]
+fn test_capitalize_hourly_period() {
+    let mut value = serde_json::json!([{"a": {"period": "HOURLY"}}, {"period": "DAILY"}]);
+    capitalize_hourly_period(&mut value).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!([{"a": {"period": "Hourly"}}, {"period": "DAILY"}])
+    );
+}
@@ -1,6 +1,12 @@
 use std::{collections::BTreeMap, path::Path};
 
-use crate::database::{storage_feed::Feed, FeedId, SourceLookup};
+use crate::{
+    config::StorageConfig,
+    database::{
+        migration, storage_feed::Feed, transaction::Transaction, FeedId, LookupKey, SourceLookup,
+    },
+    Error,
+};
 
 #[derive(Default)]
 pub struct Storage {
@@ -8,66 +14,125 @@ pub struct Storage {
 }
 
 impl Storage {
-    fn open_feeds(path: &Path) -> std::io::Result<BTreeMap<FeedId, Feed>> {
+    /// Loads all feeds, migrating them to the current schema in memory.
+    /// Also returns the number of migrated feeds.
+    fn open_feeds(path: &Path) -> Result<(BTreeMap<FeedId, Feed>, usize), Error> {
         let feed_path = path.join("feeds");
         let mut sources = BTreeMap::new();
-        for e in std::fs::read_dir(&feed_path)? {
+        let mut migrated = 0;
+        let entries = match std::fs::read_dir(&feed_path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((sources, 0)),
+            Err(e) => return Err(e.into()),
+        };
+        for e in entries {
             let e = e?;
             let feed_file = e.path();
-            let id = feed_file
-                .file_stem()
-                .expect("file does not have a name")
-                .to_str()
-                .expect("file does not have unicode name")
-                .to_owned();
+            let id = match feed_file.file_stem().and_then(|stem| stem.to_str()) {
+                Some(id) => id.to_owned(),
+                None => {
+                    eprintln!("WARN: skipping {:?}, not a feed file name", feed_file);
+                    continue;
+                }
+            };
+            // Left overs of interrupted saves
+            if id.ends_with(".new") || id.ends_with(".tx") {
+                continue;
+            }
             let file = std::fs::read_to_string(&feed_file)?;
-            // TODO: Remove at a later point
-            let file = file.replace(r#""period": "HOURLY""#, r#""period": "Hourly""#);
-            let mut feed: Feed = serde_json::from_str(&file).expect(&format!(
-                "file {:?} could be read, but not parsed",
-                feed_file
-            ));
+            let parse_error = |e: serde_json::Error| {
+                Error::InvalidStorage(format!("could not parse {:?}: {}", feed_file, e))
+            };
+            let mut value: serde_json::Value = serde_json::from_str(&file).map_err(parse_error)?;
+            let was_migrated = migration::migrate_feed(&mut value)
+                .map_err(|e| Error::Migration(format!("{:?}: {}", feed_file, e)))?;
+            if was_migrated {
+                migrated += 1;
+            }
+            let mut feed: Feed = serde_json::from_value(value).map_err(parse_error)?;
 
-            feed.migrate_data();
+            feed.update_auto_title_aliases();
+            feed.index_items();
+            feed.process_missing_content();
+            feed.apply_default_offset();
+            feed.detect_language();
 
             sources.insert(id, feed);
         }
 
-        Ok(sources)
+        Ok((sources, migrated))
     }
 
-    pub fn open_or_default(storage_path: &Path) -> Self {
-        Self {
-            sources: Self::open_feeds(storage_path).unwrap_or_default(),
-        }
+    /// Returns the storage and the number of feeds that got migrated
+    pub fn open_or_default(storage_path: &Path) -> Result<(Self, usize), Error> {
+        let (sources, migrated) = Self::open_feeds(storage_path)?;
+        Ok((Self { sources }, migrated))
     }
-    fn save_internal(&self, path: &Path, allow_shrink: bool) {
+    fn save_internal(&self, path: &Path, config: &StorageConfig, allow_shrink: bool) {
         let feed_path = path.join("feeds");
         std::fs::create_dir_all(&feed_path).unwrap();
+        let compact_key = Some("feeds_v2").filter(|_| config.compact_items);
         for (feed_id, source) in self.iter() {
             let file_path = feed_path.join(feed_id).with_extension("json");
-            crate::safe_save_json(source, &file_path, "database", allow_shrink);
+            crate::safe_save_json_with(source, &file_path, "database", allow_shrink, compact_key);
         }
     }
-    pub fn save(&self, path: &Path) {
-        self.save_internal(path, false)
+    pub fn save(&self, path: &Path, config: &StorageConfig) {
+        self.save_internal(path, config, false)
+    }
+    pub fn save_shrunk(&self, path: &Path, config: &StorageConfig) {
+        self.save_internal(path, config, true)
     }
-    pub fn save_shrunk(&self, path: &Path) {
-        self.save_internal(path, true)
+    pub fn stage(
+        &self,
+        tx: &mut Transaction,
+        path: &Path,
+        config: &StorageConfig,
+    ) -> std::io::Result<()> {
+        let feed_path = path.join("feeds");
+        std::fs::create_dir_all(&feed_path)?;
+        let compact_key = Some("feeds_v2").filter(|_| config.compact_items);
+        for (feed_id, source) in self.iter() {
+            let file_path = feed_path.join(feed_id).with_extension("json");
+            tx.stage_guarded_json(source, &file_path, compact_key, "database")?;
+        }
+        Ok(())
     }
     pub fn write_to_cache(&self, lookup: &mut SourceLookup) {
         for (feed_id, source) in &self.sources {
             lookup.touch(feed_id, source.key());
+            for title_alias in source.title_aliases() {
+                lookup.touch(
+                    feed_id,
+                    LookupKey {
+                        name: title_alias.trim(),
+                        feed_url: None,
+                    },
+                );
+            }
+            for feed_url in source.previous_feed_urls() {
+                lookup.touch(
+                    feed_id,
+                    LookupKey {
+                        name: source.name(),
+                        feed_url: Some(feed_url),
+                    },
+                );
+            }
         }
     }
     pub fn iter(&self) -> impl Iterator<Item = (&FeedId, &Feed)> + '_ {
         self.sources.iter()
     }
-    /*
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
     pub fn iter_mut(&mut self) -> impl Iterator<Item = (&FeedId, &mut Feed)> + '_ {
         self.sources.iter_mut()
     }
-    */
     pub fn get_or_insert(&mut self, feed_id: FeedId, feed: &Feed) -> &mut Feed {
         self.sources.entry(feed_id).or_insert_with(|| feed.clone())
     }
@@ -77,4 +142,17 @@ impl Storage {
     pub fn get_mut(&mut self, feed_id: &FeedId) -> Option<&mut Feed> {
         self.sources.get_mut(feed_id)
     }
+    /// Removes the feed from memory, its file stays until
+    /// `remove_feed_file`
+    pub fn remove(&mut self, feed_id: &FeedId) -> Option<Feed> {
+        self.sources.remove(feed_id)
+    }
+    /// Moves the file of a removed feed out of the way, so it isn't loaded
+    /// again but can still be recovered by hand
+    pub fn remove_feed_file(path: &Path, feed_id: &FeedId) -> std::io::Result<()> {
+        let file_path = path.join("feeds").join(feed_id).with_extension("json");
+        let deleted_path = path.join("deleted_feeds");
+        std::fs::create_dir_all(&deleted_path)?;
+        std::fs::rename(file_path, deleted_path.join(feed_id).with_extension("json"))
+    }
 }
@@ -1,25 +1,48 @@
-use std::collections::BTreeSet;
+use std::{collections::BTreeSet, str::FromStr};
 
-use crate::database::{
-    storage_feed_header::{FeedHeader, FeedHeaderMeta},
-    storage_feed_item::{FeedItem, FeedItemMeta},
-    FeedId, LookupKey,
+use chrono::{DateTime, FixedOffset, Utc};
+
+use crate::{
+    database::{
+        migration::SCHEMA_VERSION,
+        storage_feed_header::{FeedHeader, FeedHeaderMeta},
+        storage_feed_item::{FeedItem, FeedItemMeta, FeedItemOrder, TitleCleaning},
+        FeedId, LookupKey,
+    },
+    feed_settings::FeedOptions,
+    query::ItemSortKey,
 };
 
+#[cfg(feature = "opml")]
+pub type Outline = opml::Outline;
+/// Without the `opml` feature the outline is kept as raw JSON, so it survives
+/// a load and save unchanged.
+#[cfg(not(feature = "opml"))]
+pub type Outline = serde_json::Value;
+
 #[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
 pub struct Feed {
+    /// The format version of the feed file, see `migration`
+    #[serde(default)]
+    schema_version: u32,
     name: String,
     feed_url: Option<String>,
-    opml: Option<opml::Outline>,
+    opml: Option<Outline>,
+    /// The OPML file the feed got imported from, which re-syncs of the file
+    /// may report it removed from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    opml_document: Option<String>,
 
-    #[serde(default)]
+    /// Headers and items of the first storage format, moved to the numbered
+    /// ones by the migration
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     feed_headers: Vec<FeedHeader>,
     #[serde(default)]
     feed_headers_v2: Vec<FeedHeaderMeta>,
     #[serde(default)]
     feed_headers_counter: usize,
 
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     feeds: Vec<FeedItem>,
     #[serde(default)]
     feeds_v2: Vec<FeedItemMeta>,
@@ -33,16 +56,127 @@ pub struct Feed {
     title_aliases: BTreeSet<String>,
     #[serde(default)]
     display_name: Option<String>,
+    #[serde(default)]
+    auto_title_aliases: BTreeSet<String>,
+    #[serde(default)]
+    subscribed_at: Option<DateTime<Utc>>,
+    /// Free-form markdown notes of the user
+    #[serde(default)]
+    notes: String,
+    /// Urls the feed permanently redirected away from
+    #[serde(default)]
+    previous_feed_urls: BTreeSet<String>,
+    /// Keep copies of the linked articles of the items
+    #[serde(default)]
+    archive: bool,
+    /// Settings that override those of the tags
+    #[serde(default, skip_serializing_if = "FeedOptions::is_empty")]
+    options: FeedOptions,
+    /// When the feed url was last fetched successfully
+    #[serde(default)]
+    last_fetched_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    item_order: FeedItemOrder,
+    #[serde(default, skip_serializing_if = "TitleCleaning::is_default")]
+    title_cleaning: TitleCleaning,
+    /// The compiled pattern of `TitleCleaning::Regex`, compiled on first use
+    /// and `None` if it is invalid
+    #[cfg(feature = "regex")]
+    #[serde(skip)]
+    title_regex: std::sync::OnceLock<Option<regex::Regex>>,
+    /// Offset in minutes of item dates that don't give one, for feeds that
+    /// publish local times
+    #[serde(default)]
+    utc_offset_mins: Option<i32>,
+    /// Language code like `en`, from the header or guessed from the titles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    #[serde(default, skip_serializing_if = "SourceKind::is_http")]
+    source: SourceKind,
+    /// Limit of the item age on the first fetch, given on import
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_initial_age_days: Option<u64>,
+    /// Items published before are never stored. Set by the first fetch from
+    /// `max_initial_age_days`, so later updates skip the same old items.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    published_after: Option<DateTime<Utc>>,
+    /// Identities of the items moved to cold storage, so updates don't add
+    /// them again
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    cold_identities: BTreeSet<String>,
+    /// Built on load, kept up to date as items get added or removed
+    #[serde(skip)]
+    item_index: ItemIndex,
     #[serde(skip)]
     _private: (),
 }
 
+/// What the stored items of a feed have in common, so feed queries don't
+/// need to look at every item.
+#[derive(Clone, Debug, Default)]
+struct ItemIndex {
+    /// Lowercase, like the authors and categories of `FeedQuery`
+    authors: BTreeSet<String>,
+    categories: BTreeSet<String>,
+}
+
+impl ItemIndex {
+    fn add(&mut self, item: &FeedItem) {
+        if let Some(author) = item.author() {
+            self.authors.insert(author.to_lowercase());
+        }
+        for category in item.categories() {
+            self.categories.insert(category.to_lowercase());
+        }
+    }
+}
+
+/// Where updates get the items of a feed from. The feed url is interpreted
+/// by the source.
+#[derive(
+    serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    /// An RSS, Atom or JSON feed downloaded over HTTP
+    #[default]
+    Http,
+    /// A local directory of markdown files, one item per file
+    Directory,
+}
+
+impl FromStr for SourceKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "http" => Ok(Self::Http),
+            "directory" => Ok(Self::Directory),
+            _ => Err(format!("unknown source kind {:?}", s)),
+        }
+    }
+}
+
+impl SourceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Http => "http",
+            Self::Directory => "directory",
+        }
+    }
+    fn is_http(&self) -> bool {
+        *self == Self::Http
+    }
+}
+
 impl Feed {
     pub fn new(name: String) -> Self {
         Self {
+            schema_version: SCHEMA_VERSION,
             name,
             feed_url: None,
             opml: None,
+            opml_document: None,
 
             feed_headers: Vec::new(),
             feed_headers_v2: Vec::new(),
@@ -56,12 +190,34 @@ impl Feed {
             tags: BTreeSet::new(),
             title_aliases: BTreeSet::new(),
             display_name: None,
+            auto_title_aliases: BTreeSet::new(),
+            subscribed_at: Some(Utc::now()),
+            notes: String::new(),
+            previous_feed_urls: BTreeSet::new(),
+            archive: false,
+            options: FeedOptions::default(),
+            last_fetched_at: None,
+            item_order: FeedItemOrder::default(),
+            title_cleaning: TitleCleaning::default(),
+            #[cfg(feature = "regex")]
+            title_regex: Default::default(),
+            utc_offset_mins: None,
+            language: None,
+            source: SourceKind::default(),
+            max_initial_age_days: None,
+            published_after: None,
+            cold_identities: BTreeSet::new(),
+            item_index: ItemIndex::default(),
             _private: (),
         }
     }
     pub fn display_name(&self) -> &str {
         self.display_name.as_deref().unwrap_or(&self.name).trim()
     }
+    /// When the feed was added, unknown for feeds added before this was tracked
+    pub fn subscribed_at(&self) -> Option<DateTime<Utc>> {
+        self.subscribed_at
+    }
     pub fn original_display_name(&self) -> &str {
         self.name.trim()
     }
@@ -91,12 +247,36 @@ impl Feed {
         std::slice::from_ref(&self.name)
             .iter()
             .chain(self.title_aliases.iter())
+            .chain(self.auto_title_aliases.iter())
             .map(|s| &s[..])
     }
     pub fn set_display_name(&mut self, name: String) {
         self.display_name = Some(name);
     }
-    pub fn migrate_data(&mut self) {
+    pub fn notes(&self) -> &str {
+        &self.notes
+    }
+    /// Returns true if the notes changed
+    pub fn set_notes(&mut self, notes: String) -> bool {
+        let changed = self.notes != notes;
+        self.notes = notes;
+        changed
+    }
+    /// Replaces the primary name, returning the old name. The display name
+    /// is only cleared if it equals the new name. Use `Database::rename_feed`
+    /// to keep the lookup in sync.
+    pub(crate) fn set_name(&mut self, name: String) -> String {
+        if self.display_name.as_deref().map(str::trim) == Some(name.trim()) {
+            self.display_name = None;
+        }
+        std::mem::replace(&mut self.name, name)
+    }
+    /// Number of headers and items still in the first storage format
+    pub(crate) fn legacy_entry_count(&self) -> usize {
+        self.feed_headers.len() + self.feeds.len()
+    }
+    /// Moves items of the first storage format over to the numbered ones
+    pub(crate) fn number_legacy_items(&mut self) {
         for header in self.feed_headers.drain(..) {
             self.feed_headers_v2
                 .push(FeedHeaderMeta::new(self.feed_headers_counter, header));
@@ -108,30 +288,206 @@ impl Feed {
                 .push(FeedItemMeta::new(self.feeds_counter, item));
             self.feeds_counter += 1;
         }
+        self.index_items();
+    }
+    /// Rebuilds the index of the items, needed after loading and after
+    /// changing items through `items_mut`
+    pub(crate) fn index_items(&mut self) {
+        let mut index = ItemIndex::default();
+        for item in &self.feeds_v2 {
+            index.add(&item.item);
+        }
+        self.item_index = index;
+    }
+    /// Whether an item is by `author`, which needs to be lowercase
+    pub(crate) fn has_author(&self, author: &str) -> bool {
+        self.item_index.authors.contains(author)
+    }
+    /// Whether an item is in `category`, which needs to be lowercase
+    pub(crate) fn has_category(&self, category: &str) -> bool {
+        self.item_index.categories.contains(category)
+    }
+    pub(crate) fn backfill_first_seen(&mut self) {
+        for item in &mut self.feeds_v2 {
+            item.backfill_first_seen();
+        }
+    }
+    /// Processes the content of loaded items, which is not stored
+    pub(crate) fn process_missing_content(&mut self) {
+        for item in &mut self.feeds_v2 {
+            if item.content().is_none() {
+                item.process_content();
+            }
+        }
     }
 
     pub fn feed_headers(&self) -> &[FeedHeaderMeta] {
         &self.feed_headers_v2
     }
-    pub fn contains_feed_header(&self, h: &FeedHeader) -> bool {
-        self.feed_headers_v2.iter().any(|v| v.header == *h)
+    /// The website of the feed according to its most recent header
+    pub fn site_link(&self) -> Option<&str> {
+        self.feed_headers_v2.last()?.header.site_link()
     }
-    pub fn push_feed_header(&mut self, header: FeedHeader) {
+    /// The icon of the feed according to its most recent header, otherwise
+    /// the favicon of its website
+    pub fn icon_link(&self) -> Option<String> {
+        let header = self.feed_headers_v2.last().map(|header| &header.header);
+        if let Some(icon) = header.and_then(|header| header.icon_link()) {
+            return Some(icon.to_owned());
+        }
+        favicon_link(self.site_link().or(self.feed_url())?)
+    }
+    pub fn contains_feed_header(&self, h: &FeedHeader) -> bool {
         self.feed_headers_v2
-            .push(FeedHeaderMeta::new(self.feed_headers_counter, header));
+            .iter()
+            .any(|v| v.header.is_equivalent(h))
+    }
+    /// Drops headers that only differ in volatile fields from a later one,
+    /// and all but the newest `max` of the rest, keeping at least the latest.
+    /// Returns the number of dropped headers.
+    pub fn compact_headers(&mut self, max: usize) -> usize {
+        let before = self.feed_headers_v2.len();
+        let mut kept: Vec<FeedHeaderMeta> = Vec::new();
+        for header in self.feed_headers_v2.drain(..).rev() {
+            if kept.len() < max.max(1)
+                && !kept.iter().any(|k| k.header.is_equivalent(&header.header))
+            {
+                kept.push(header);
+            }
+        }
+        kept.reverse();
+        self.feed_headers_v2 = kept;
+        before - self.feed_headers_v2.len()
+    }
+    /// Raises the item and header counters above the largest ids in use,
+    /// including `cold_max_item_id`. Returns the old and new value of the
+    /// counters that were too low.
+    pub(crate) fn repair_counters(
+        &mut self,
+        cold_max_item_id: Option<usize>,
+    ) -> (Option<(usize, usize)>, Option<(usize, usize)>) {
+        let max_item_id = self
+            .feeds_v2
+            .iter()
+            .map(|item| item.id())
+            .chain(cold_max_item_id)
+            .max();
+        let max_header_id = self.feed_headers_v2.iter().map(|header| header.id()).max();
+        let repair = |counter: &mut usize, max_id: Option<usize>| match max_id {
+            Some(max_id) if *counter <= max_id => {
+                Some((std::mem::replace(counter, max_id + 1), max_id + 1))
+            }
+            _ => None,
+        };
+        (
+            repair(&mut self.feeds_counter, max_item_id),
+            repair(&mut self.feed_headers_counter, max_header_id),
+        )
+    }
+    /// Gives new ids to items that share the id of an earlier item. Returns
+    /// the old and new ids.
+    pub(crate) fn renumber_duplicate_items(&mut self) -> Vec<(usize, usize)> {
+        let mut seen = BTreeSet::new();
+        let mut renumbered = Vec::new();
+        for item in &mut self.feeds_v2 {
+            if !seen.insert(item.id()) {
+                let id = self.feeds_counter;
+                self.feeds_counter += 1;
+                renumbered.push((item.id(), id));
+                item.set_id(id);
+            }
+        }
+        renumbered
+    }
+    /// Stores the header, returns its id
+    pub fn push_feed_header(&mut self, header: FeedHeader) -> usize {
+        let id = self.feed_headers_counter;
+        self.feed_headers_v2.push(FeedHeaderMeta::new(id, header));
         self.feed_headers_counter += 1;
+        id
+    }
+    /// The id of a stored header equivalent to `h`
+    pub fn feed_header_id(&self, h: &FeedHeader) -> Option<usize> {
+        self.feed_headers_v2
+            .iter()
+            .find(|v| v.header.is_equivalent(h))
+            .map(|v| v.id())
     }
 
+    /// The publish date of the newest item. Items are kept in the item
+    /// order of the feed, which is not necessarily by date. Undated items
+    /// are left out.
+    pub fn last_item_date(&self) -> Option<DateTime<FixedOffset>> {
+        self.feeds_v2
+            .iter()
+            .filter_map(FeedItemMeta::publish_date)
+            .max()
+    }
+    /// The publish date of the oldest item, undated items are left out
+    pub fn first_item_date(&self) -> Option<DateTime<FixedOffset>> {
+        self.feeds_v2
+            .iter()
+            .filter_map(FeedItemMeta::publish_date)
+            .min()
+    }
+    /// The items by publish date, newest first, whatever the item order
+    pub fn items_newest_first(&self) -> Vec<&FeedItemMeta> {
+        let mut items: Vec<_> = self.feeds_v2.iter().collect();
+        items.sort_by_key(|item| std::cmp::Reverse(item.publish_date_or_old()));
+        items
+    }
+    pub fn item_count(&self) -> usize {
+        self.feeds_v2.len()
+    }
+    pub fn tag_count(&self) -> usize {
+        self.tags.len()
+    }
     pub fn items(&self) -> &[FeedItemMeta] {
         &self.feeds_v2
     }
+    /// Call `index_items` after changing the items
     pub fn items_mut(&mut self) -> &mut [FeedItemMeta] {
         &mut self.feeds_v2
     }
-    pub fn push_item(&mut self, item: FeedItem) {
+    /// Number of items in cold storage, which are not part of `items`
+    pub fn cold_item_count(&self) -> usize {
+        self.cold_identities.len()
+    }
+    pub fn is_cold(&self, identity: &str) -> bool {
+        self.cold_identities.contains(identity)
+    }
+    pub(crate) fn cold_identities(&self) -> impl Iterator<Item = &String> + '_ {
+        self.cold_identities.iter()
+    }
+    pub(crate) fn items_published_before(&self, cutoff: DateTime<Utc>) -> Vec<&FeedItemMeta> {
         self.feeds_v2
-            .push(FeedItemMeta::new(self.feeds_counter, item));
+            .iter()
+            .filter(|item| item.sort_date(ItemSortKey::Published) < cutoff)
+            .collect()
+    }
+    /// Removes the items for cold storage, remembering their identities
+    pub(crate) fn take_items_published_before(
+        &mut self,
+        cutoff: DateTime<Utc>,
+    ) -> Vec<FeedItemMeta> {
+        let (old, items) = std::mem::take(&mut self.feeds_v2)
+            .into_iter()
+            .partition(|item| item.sort_date(ItemSortKey::Published) < cutoff);
+        self.feeds_v2 = items;
+        for item in &old {
+            self.cold_identities.insert(item.item.identity());
+        }
+        self.index_items();
+        old
+    }
+    pub fn push_item(&mut self, item: FeedItem) -> usize {
+        let id = self.feeds_counter;
+        self.item_index.add(&item);
+        let mut meta = FeedItemMeta::new(id, item);
+        meta.set_default_offset(self.default_offset());
+        self.feeds_v2.push(meta);
         self.feeds_counter += 1;
+        id
     }
 
     pub fn name(&self) -> &str {
@@ -143,18 +499,167 @@ impl Feed {
     pub fn feed_url_mut(&mut self) -> &mut Option<String> {
         &mut self.feed_url
     }
-    pub fn opml(&self) -> Option<&opml::Outline> {
+    pub fn previous_feed_urls(&self) -> &BTreeSet<String> {
+        &self.previous_feed_urls
+    }
+    /// Replaces the feed url, keeping the old one as a previous url.
+    /// Use `Database::move_feed_url` to keep the lookup in sync.
+    pub(crate) fn move_feed_url(&mut self, new_url: String) {
+        if let Some(old_url) = self.feed_url.replace(new_url) {
+            self.previous_feed_urls.insert(old_url);
+        }
+    }
+    pub fn opml(&self) -> Option<&Outline> {
         self.opml.as_ref()
     }
-    pub fn opml_mut(&mut self) -> &mut Option<opml::Outline> {
+    pub fn opml_mut(&mut self) -> &mut Option<Outline> {
         &mut self.opml
     }
+    pub fn opml_document(&self) -> Option<&str> {
+        self.opml_document.as_deref()
+    }
+    pub(crate) fn set_opml_document(&mut self, document: String) {
+        self.opml_document = Some(document);
+    }
+    pub fn archive(&self) -> bool {
+        self.archive
+    }
+    pub(crate) fn set_archive(&mut self, archive: bool) {
+        self.archive = archive;
+    }
+    pub fn max_initial_age_days(&self) -> Option<u64> {
+        self.max_initial_age_days
+    }
+    pub fn set_max_initial_age_days(&mut self, days: Option<u64>) {
+        self.max_initial_age_days = days;
+    }
+    pub fn published_after(&self) -> Option<DateTime<Utc>> {
+        self.published_after
+    }
+    pub(crate) fn set_published_after(&mut self, cutoff: Option<DateTime<Utc>>) {
+        self.published_after = cutoff;
+    }
+    pub fn options(&self) -> &FeedOptions {
+        &self.options
+    }
+    pub fn set_options(&mut self, options: FeedOptions) {
+        self.options = options;
+    }
+    pub fn item_order(&self) -> FeedItemOrder {
+        self.item_order
+    }
+    /// Also reorders the stored items, the timeline needs a refresh after
+    pub(crate) fn set_item_order(&mut self, order: FeedItemOrder) {
+        self.item_order = order;
+        FeedItemMeta::sort(&mut self.feeds_v2, order);
+    }
+    pub fn title_cleaning(&self) -> &TitleCleaning {
+        &self.title_cleaning
+    }
+    pub fn set_title_cleaning(&mut self, title_cleaning: TitleCleaning) {
+        self.title_cleaning = title_cleaning;
+        #[cfg(feature = "regex")]
+        {
+            self.title_regex = Default::default();
+        }
+    }
+    #[cfg(feature = "regex")]
+    pub(crate) fn clean_title_with_regex<'a>(&self, title: &'a str) -> &'a str {
+        let re = self.title_regex.get_or_init(|| {
+            let pattern = self.title_cleaning.pattern()?;
+            regex::Regex::new(pattern).ok()
+        });
+        match re {
+            Some(re) => super::storage_feed_item::clean_title_with_regex(title, re),
+            None => title,
+        }
+    }
+    #[cfg(not(feature = "regex"))]
+    pub(crate) fn clean_title_with_regex<'a>(&self, title: &'a str) -> &'a str {
+        title
+    }
+    pub fn utc_offset_mins(&self) -> Option<i32> {
+        self.utc_offset_mins
+    }
+    pub(crate) fn default_offset(&self) -> Option<FixedOffset> {
+        self.utc_offset_mins
+            .and_then(|mins| FixedOffset::east_opt(mins * 60))
+    }
+    /// Hands the default offset to the items, needed after loading
+    pub(crate) fn apply_default_offset(&mut self) {
+        let offset = self.default_offset();
+        for item in &mut self.feeds_v2 {
+            item.set_default_offset(offset);
+        }
+    }
+    /// Also reorders the stored items, the timeline needs a refresh after
+    pub(crate) fn set_utc_offset_mins(&mut self, mins: Option<i32>) {
+        self.utc_offset_mins = mins;
+        self.apply_default_offset();
+        FeedItemMeta::sort(&mut self.feeds_v2, self.item_order);
+    }
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+    pub(crate) fn set_language(&mut self, language: Option<String>) {
+        self.language = language;
+    }
+    pub fn source(&self) -> SourceKind {
+        self.source
+    }
+    pub fn set_source(&mut self, source: SourceKind) {
+        self.source = source;
+    }
+    pub fn last_fetched_at(&self) -> Option<DateTime<Utc>> {
+        self.last_fetched_at
+    }
+    pub fn set_last_fetched_at(&mut self, at: DateTime<Utc>) {
+        self.last_fetched_at = Some(at);
+    }
+    /// The feed of the OPML folder the feed got imported from
+    pub fn parent(&self) -> Option<&FeedId> {
+        self.parent.as_ref()
+    }
     pub fn set_parent(&mut self, parent: Option<String>) {
         self.parent = parent;
     }
     pub fn title_aliases(&self) -> &BTreeSet<String> {
         &self.title_aliases
     }
+    pub fn auto_title_aliases(&self) -> &BTreeSet<String> {
+        &self.auto_title_aliases
+    }
+    /// Recomputes the automatic title aliases from the iTunes author of the
+    /// feed and its items. An author is only used if it prefixes at least two
+    /// item titles and is not already a manual title.
+    pub fn update_auto_title_aliases(&mut self) {
+        let mut candidates = BTreeSet::new();
+        if let Some(header) = self.feed_headers_v2.last() {
+            candidates.extend(header.header.itunes_author().map(str::trim));
+        }
+        for item in &self.feeds_v2 {
+            candidates.extend(item.item.itunes_author().map(str::trim));
+        }
+
+        let mut aliases = BTreeSet::new();
+        for candidate in candidates {
+            if candidate.is_empty()
+                || self.name.trim() == candidate
+                || self.title_aliases.iter().any(|t| t.trim() == candidate)
+            {
+                continue;
+            }
+            let prefixed = self
+                .feeds_v2
+                .iter()
+                .filter(|item| item.is_title_prefixed_with(candidate))
+                .count();
+            if prefixed >= 2 {
+                aliases.insert(candidate.to_owned());
+            }
+        }
+        self.auto_title_aliases = aliases;
+    }
     pub fn title_alias_insert(&mut self, name: &str) -> bool {
         self.title_aliases.insert(name.trim().to_owned())
     }
@@ -179,3 +684,156 @@ impl Feed {
         was_deleted
     }
 }
+
+/// The conventional `/favicon.ico` of the website of `url`, none if it is
+/// not a web url
+fn favicon_link(url: &str) -> Option<String> {
+    let (scheme, rest) = url.trim().split_once("://")?;
+    if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+        return None;
+    }
+    let authority = rest
+        .split(|c| c == '/' || c == '?' || c == '#')
+        .next()
+        .filter(|authority| !authority.is_empty())?;
+    // Credentials in the url don't belong into a link on a page
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    Some(format!("{}://{}/favicon.ico", scheme, host))
+}
+
+#[test]
+fn test_favicon_link() {
+    assert_eq!(
+        favicon_link("https://example.com/blog/feed.xml?x=1").as_deref(),
+        Some("https://example.com/favicon.ico")
+    );
+    assert_eq!(
+        favicon_link("http://user:pw@example.com:8080").as_deref(),
+        Some("http://example.com:8080/favicon.ico")
+    );
+    assert_eq!(favicon_link("file:///home/feeds"), None);
+    assert_eq!(favicon_link("example.com"), None);
+}
+
+#[test]
+fn test_item_dates_ignore_item_order() {
+    let mut feed = Feed::new("Comic".to_owned());
+    for date in [
+        "2023-01-02T00:00:00Z",
+        "2023-01-03T00:00:00Z",
+        "2023-01-01T00:00:00Z",
+    ] {
+        feed.push_item(FeedItem::Rss(rss::Item {
+            pub_date: Some(DateTime::parse_from_rfc3339(date).unwrap().to_rfc2822()),
+            ..rss::Item::default()
+        }));
+    }
+    // Not the oldest item just because it has no date
+    feed.push_item(FeedItem::Rss(rss::Item::default()));
+    feed.set_item_order(FeedItemOrder::AsFetched);
+
+    let date = |raw| Some(DateTime::parse_from_rfc3339(raw).unwrap());
+    assert_eq!(feed.last_item_date(), date("2023-01-03T00:00:00Z"));
+    assert_eq!(feed.first_item_date(), date("2023-01-01T00:00:00Z"));
+    let newest_first: Vec<_> = feed
+        .items_newest_first()
+        .into_iter()
+        .map(|item| item.id())
+        .collect();
+    assert_eq!(newest_first, [1, 0, 2, 3]);
+}
+
+/// Three items, the last one sharing the id of the first, and an item
+/// counter as if only the first one was stored
+#[cfg(test)]
+pub(crate) fn feed_with_duplicate_ids() -> Feed {
+    let mut feed = Feed::new("Feed".to_owned());
+    for title in ["a", "b", "c"] {
+        feed.push_item(FeedItem::Rss(rss::Item {
+            title: Some(title.to_owned()),
+            ..rss::Item::default()
+        }));
+    }
+    let mut value = serde_json::to_value(&feed).unwrap();
+    value["feeds_v2"][2]["id"] = 0.into();
+    value["feeds_counter"] = 1.into();
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn test_repair_counters() {
+    let mut feed = feed_with_duplicate_ids();
+    assert_eq!(feed.repair_counters(None), (Some((1, 2)), None));
+    assert_eq!(feed.repair_counters(None), (None, None));
+    // Ids of cold items stay taken
+    assert_eq!(feed.repair_counters(Some(5)), (Some((2, 6)), None));
+}
+
+#[test]
+fn test_renumber_duplicate_items() {
+    let mut feed = feed_with_duplicate_ids();
+    feed.repair_counters(None);
+    assert_eq!(feed.renumber_duplicate_items(), vec![(0, 2)]);
+    let ids: Vec<usize> = feed.items().iter().map(|item| item.id()).collect();
+    assert_eq!(ids, vec![0, 1, 2]);
+    assert!(feed.renumber_duplicate_items().is_empty());
+    assert_eq!(feed.push_item(FeedItem::Rss(rss::Item::default())), 3);
+}
+
+#[test]
+fn test_auto_title_aliases() {
+    let itunes_item = |title: &str, author: &str| {
+        FeedItem::Rss(rss::Item {
+            title: Some(title.to_owned()),
+            itunes_ext: Some(rss::extension::itunes::ITunesItemExtension {
+                author: Some(author.to_owned()),
+                ..Default::default()
+            }),
+            ..rss::Item::default()
+        })
+    };
+    let mut feed = Feed::new("Podcast".to_owned());
+    feed.push_item(itunes_item("Jane: First", "Jane"));
+    feed.push_item(itunes_item("Jane - Second", "Jane"));
+    feed.update_auto_title_aliases();
+    assert!(feed.auto_title_aliases().contains("Jane"));
+
+    // The generic author is no iTunes author
+    let mut feed = Feed::new("Blog".to_owned());
+    for title in ["Jane: First", "Jane - Second"] {
+        feed.push_item(FeedItem::Rss(rss::Item {
+            title: Some(title.to_owned()),
+            author: Some("Jane".to_owned()),
+            ..rss::Item::default()
+        }));
+    }
+    feed.update_auto_title_aliases();
+    assert!(feed.auto_title_aliases().is_empty());
+}
+
+#[cfg(feature = "feed-rs")]
+#[test]
+fn test_feed_rs_authors_are_no_itunes_authors() {
+    let atom = r#"<?xml version="1.0" encoding="utf-8"?>
+        <feed xmlns="http://www.w3.org/2005/Atom">
+            <title>Blog</title>
+            <id>urn:blog</id>
+            <updated>2023-01-01T00:00:00Z</updated>
+            <author><name>Jane</name></author>
+            <entry>
+                <title>Jane: First</title>
+                <id>urn:first</id>
+                <updated>2023-01-01T00:00:00Z</updated>
+                <author><name>Jane</name></author>
+            </entry>
+        </feed>"#;
+    let feed = feed_rs::parser::parse(atom.as_bytes()).unwrap();
+    let (header, items) = crate::feeds::feed_rs::FeedHeader::split(feed);
+    let header = FeedHeader::FeedRs(header);
+    let item = FeedItem::FeedRs(items.into_iter().next().unwrap());
+    assert_eq!(item.author(), Some("Jane"));
+    assert_eq!(item.itunes_author(), None);
+    assert_eq!(header.itunes_author(), None);
+}
@@ -4,6 +4,131 @@ pub enum FeedHeader {
     FeedRs(crate::feeds::feed_rs::FeedHeader),
 }
 
+impl FeedHeader {
+    /// Like `FeedItem::itunes_author`, not the generic author
+    pub(crate) fn itunes_author(&self) -> Option<&str> {
+        match self {
+            FeedHeader::Rss(header) => header.itunes_ext.as_ref().and_then(|e| e.author.as_deref()),
+            FeedHeader::FeedRs(_) => None,
+        }
+    }
+    /// A copy without the fields that change on every fetch, like the build
+    /// date of RSS channels or the `updated` date of Atom feeds
+    fn normalized(&self) -> FeedHeader {
+        let mut header = self.clone();
+        match &mut header {
+            FeedHeader::Rss(header) => {
+                header.pub_date = None;
+                header.last_build_date = None;
+            }
+            FeedHeader::FeedRs(header) => {
+                // feed-rs makes up a random id for RSS channels without a link
+                header.id.clear();
+                header.updated = None;
+                header.published = None;
+            }
+        }
+        header
+    }
+    /// Whether the headers only differ in volatile fields
+    pub fn is_equivalent(&self, other: &FeedHeader) -> bool {
+        self.normalized() == other.normalized()
+    }
+    /// The url of the website the feed belongs to
+    pub fn site_link(&self) -> Option<&str> {
+        match self {
+            FeedHeader::Rss(header) => Some(&header.link[..]).filter(|link| !link.is_empty()),
+            FeedHeader::FeedRs(header) => header
+                .links
+                .iter()
+                .find(|link| matches!(link.rel.as_deref(), None | Some("alternate")))
+                .map(|link| &link.href[..]),
+        }
+    }
+    pub fn title(&self) -> Option<&str> {
+        let title = match self {
+            FeedHeader::Rss(header) => Some(&header.title[..]),
+            FeedHeader::FeedRs(header) => header.title.as_ref().map(|text| &text.content[..]),
+        };
+        title.filter(|title| !title.trim().is_empty())
+    }
+    pub fn description(&self) -> Option<&str> {
+        let description = match self {
+            FeedHeader::Rss(header) => Some(&header.description[..]),
+            FeedHeader::FeedRs(header) => header.description.as_ref().map(|text| &text.content[..]),
+        };
+        description.filter(|description| !description.trim().is_empty())
+    }
+    /// When the publisher last changed the feed, as given in the header
+    pub fn date(&self) -> Option<String> {
+        match self {
+            FeedHeader::Rss(header) => header
+                .last_build_date
+                .clone()
+                .or_else(|| header.pub_date.clone()),
+            FeedHeader::FeedRs(header) => header
+                .updated
+                .or(header.published)
+                .map(|date| date.to_rfc3339()),
+        }
+    }
+    /// The fields that differ in the `newer` header, in the order they are
+    /// shown in
+    pub fn diff(&self, newer: &FeedHeader) -> Vec<HeaderChange> {
+        let fields: [(&'static str, fn(&FeedHeader) -> Option<&str>); 4] = [
+            ("title", FeedHeader::title),
+            ("description", FeedHeader::description),
+            ("link", FeedHeader::site_link),
+            ("icon", FeedHeader::icon_link),
+        ];
+        let mut changes = Vec::new();
+        if std::mem::discriminant(self) != std::mem::discriminant(newer) {
+            changes.push(HeaderChange {
+                field: "format",
+                before: Some(self.format().to_owned()),
+                after: Some(newer.format().to_owned()),
+            });
+        }
+        for (field, get) in fields {
+            let (before, after) = (get(self), get(newer));
+            if before != after {
+                changes.push(HeaderChange {
+                    field,
+                    before: before.map(str::to_owned),
+                    after: after.map(str::to_owned),
+                });
+            }
+        }
+        changes
+    }
+    fn format(&self) -> &'static str {
+        match self {
+            FeedHeader::Rss(_) => "rss",
+            FeedHeader::FeedRs(_) => "feed-rs",
+        }
+    }
+    /// The url of the icon or logo of the feed
+    pub fn icon_link(&self) -> Option<&str> {
+        let link = match self {
+            FeedHeader::Rss(header) => header.image.as_ref().map(|image| &image.url[..]),
+            FeedHeader::FeedRs(header) => header
+                .icon
+                .as_ref()
+                .or(header.logo.as_ref())
+                .map(|image| &image.uri[..]),
+        };
+        link.filter(|link| !link.trim().is_empty())
+    }
+}
+
+/// A field that differs between two headers of a feed
+#[derive(serde::Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct HeaderChange {
+    pub field: &'static str,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
 #[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
 pub struct FeedHeaderMeta {
     id: usize,
@@ -17,3 +142,35 @@ impl FeedHeaderMeta {
         self.id
     }
 }
+
+#[cfg(feature = "rss")]
+#[test]
+fn test_header_diff() {
+    let header = |title: &str, link: &str| {
+        let channel = rss::Channel {
+            title: title.to_owned(),
+            link: link.to_owned(),
+            description: "About things".to_owned(),
+            ..Default::default()
+        };
+        FeedHeader::Rss(crate::feeds::rss::ChannelHeader::split(channel).0)
+    };
+    let old = header("Blog", "https://example.com");
+    assert!(old.diff(&old).is_empty());
+    let new = header("The Blog", "");
+    assert_eq!(
+        old.diff(&new),
+        vec![
+            HeaderChange {
+                field: "title",
+                before: Some("Blog".to_owned()),
+                after: Some("The Blog".to_owned()),
+            },
+            HeaderChange {
+                field: "link",
+                before: Some("https://example.com".to_owned()),
+                after: None,
+            },
+        ]
+    );
+}
@@ -1,8 +1,112 @@
-use std::borrow::Cow;
+use std::{collections::BTreeSet, str::FromStr, time::Duration};
 
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
 
-use crate::database::storage_feed::Feed;
+use crate::{
+    content::ItemContent,
+    database::{storage_feed::Feed, FeedId},
+    ItemSortKey,
+};
+
+/// How the items of a feed are ordered. Comics or changelogs are often best
+/// read in the order of the feed instead of by date.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedItemOrder {
+    #[default]
+    Published,
+    /// By the date the publisher last changed the item
+    Updated,
+    /// In the order the feed lists the items, oldest at the bottom
+    AsFetched,
+}
+
+impl FromStr for FeedItemOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "published" => Ok(Self::Published),
+            "updated" => Ok(Self::Updated),
+            "as_fetched" => Ok(Self::AsFetched),
+            _ => Err(format!("unknown feed item order {:?}", s)),
+        }
+    }
+}
+
+impl FeedItemOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Published => "published",
+            Self::Updated => "updated",
+            Self::AsFetched => "as_fetched",
+        }
+    }
+}
+
+/// How the titles of the items of a feed get cleaned up for display.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TitleCleaning {
+    /// Titles are shown as the feed has them
+    Off,
+    /// Strips the titles and aliases of the feed from the start of the title
+    #[default]
+    StripKnownPrefixes,
+    /// Strips the text matching the pattern from the start or end of the
+    /// title. If the pattern has a capture group, only its text is kept.
+    Regex(String),
+}
+
+impl TitleCleaning {
+    /// Checks that the pattern is a valid regular expression
+    #[cfg(feature = "regex")]
+    pub fn regex(pattern: &str) -> Result<Self, String> {
+        regex::Regex::new(pattern).map_err(|e| e.to_string())?;
+        Ok(Self::Regex(pattern.to_owned()))
+    }
+    #[cfg(not(feature = "regex"))]
+    pub fn regex(_pattern: &str) -> Result<Self, String> {
+        Err("built without regex support".to_owned())
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::StripKnownPrefixes => "strip_known_prefixes",
+            Self::Regex(_) => "regex",
+        }
+    }
+    pub fn pattern(&self) -> Option<&str> {
+        match self {
+            Self::Regex(pattern) => Some(pattern),
+            _ => None,
+        }
+    }
+    pub(crate) fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Cleans `title` with the pattern of `TitleCleaning::Regex`. Titles stay as
+/// they are if the pattern only matches in the middle.
+#[cfg(feature = "regex")]
+pub(crate) fn clean_title_with_regex<'a>(title: &'a str, re: &regex::Regex) -> &'a str {
+    let captures = match re.captures(title) {
+        Some(captures) => captures,
+        None => return title,
+    };
+    if let Some(keep) = captures.get(1) {
+        return keep.as_str().trim();
+    }
+    let matched = captures.get(0).unwrap();
+    if matched.start() == 0 {
+        title[matched.end()..].trim()
+    } else if matched.end() == title.len() {
+        title[..matched.start()].trim()
+    } else {
+        title
+    }
+}
 
 #[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
 pub enum FeedItem {
@@ -11,30 +115,18 @@ pub enum FeedItem {
 }
 
 impl FeedItem {
-    fn publish_date(&self) -> Option<DateTime<FixedOffset>> {
+    /// Dates without an offset are taken to be at `default_offset`
+    pub(crate) fn publish_date_at(
+        &self,
+        default_offset: FixedOffset,
+    ) -> Option<DateTime<FixedOffset>> {
         match self {
             FeedItem::Rss(item) => item.pub_date.as_ref().and_then(|v| {
-                // NB: Some feeds have a non-rfc2822 conform date format where
-                // they write out the day fully. We fix this here.
-                let mut v = Cow::Borrowed(v);
-                for day in [
-                    "Monday",
-                    "Tuesday",
-                    "Wednesday",
-                    "Thursday",
-                    "Friday",
-                    "Saturday",
-                    "Sunday",
-                ] {
-                    if v.contains(day) {
-                        v = Cow::Owned(v.replace(day, &day[..3]));
-                    }
-                }
-                let res = DateTime::parse_from_rfc2822(&v);
-                if res.is_err() {
+                let res = parse_date(v, default_offset);
+                if res.is_none() {
                     println!("Could not parse date {}", v);
                 }
-                res.ok()
+                res
             }),
             FeedItem::FeedRs(entry) => entry.published.as_ref().map(|v| {
                 // TODO: This is ugly
@@ -42,11 +134,170 @@ impl FeedItem {
             }),
         }
     }
+    pub(crate) fn publish_date(&self) -> Option<DateTime<FixedOffset>> {
+        self.publish_date_at(utc())
+    }
     fn publish_date_or_old(&self) -> DateTime<FixedOffset> {
         self.publish_date().unwrap_or_else(old_date)
     }
-    pub fn sort<T, F: FnMut(&T) -> &Self>(items: &mut [T], mut f: F) {
-        items.sort_by_cached_key(|k| f(k).publish_date_or_old());
+    fn publish_date_at_or_old(&self, default_offset: FixedOffset) -> DateTime<FixedOffset> {
+        self.publish_date_at(default_offset)
+            .unwrap_or_else(old_date)
+    }
+    /// The date the publisher last changed the item, if the feed tells
+    fn updated_date(&self) -> Option<DateTime<FixedOffset>> {
+        match self {
+            FeedItem::Rss(_) => None,
+            FeedItem::FeedRs(entry) => entry.updated.map(Into::into),
+        }
+    }
+    /// Sorts freshly fetched items oldest first. `AsFetched` expects them in
+    /// the order of the feed, which lists the newest items first.
+    pub fn sort<T, F: FnMut(&T) -> &Self>(items: &mut [T], order: FeedItemOrder, mut f: F) {
+        match order {
+            FeedItemOrder::Published => {
+                items.sort_by_cached_key(|k| f(k).publish_date_or_old());
+            }
+            FeedItemOrder::Updated => items.sort_by_cached_key(|k| {
+                let item = f(k);
+                item.updated_date()
+                    .unwrap_or_else(|| item.publish_date_or_old())
+            }),
+            FeedItemOrder::AsFetched => items.reverse(),
+        }
+    }
+    /// A key identifying the item across fetches, even if its content changes.
+    pub(crate) fn identity(&self) -> String {
+        match self {
+            FeedItem::Rss(item) => {
+                if let Some(guid) = &item.guid {
+                    return format!("guid:{}", guid.value);
+                }
+                if let Some(link) = &item.link {
+                    return format!("link:{}", link);
+                }
+                format!(
+                    "title:{}:{}",
+                    item.title.as_deref().unwrap_or(""),
+                    item.pub_date.as_deref().unwrap_or("")
+                )
+            }
+            FeedItem::FeedRs(entry) => {
+                // feed-rs makes up a random id if there is neither an id nor a link
+                if !entry.links.is_empty() {
+                    return format!("id:{}", entry.id);
+                }
+                format!(
+                    "title:{}:{}",
+                    entry.title.as_ref().map(|v| &v.content[..]).unwrap_or(""),
+                    entry.published.map(|v| v.to_rfc3339()).unwrap_or_default()
+                )
+            }
+        }
+    }
+    /// feed-rs doesn't keep the iTunes extension, its authors are the
+    /// generic ones
+    pub(crate) fn itunes_author(&self) -> Option<&str> {
+        match self {
+            FeedItem::Rss(item) => item.itunes_ext.as_ref().and_then(|e| e.author.as_deref()),
+            FeedItem::FeedRs(_) => None,
+        }
+    }
+    fn itunes_ext(&self) -> Option<&rss::extension::itunes::ITunesItemExtension> {
+        match self {
+            FeedItem::Rss(item) => item.itunes_ext.as_ref(),
+            FeedItem::FeedRs(_) => None,
+        }
+    }
+    /// The iTunes episode number of podcast items
+    pub fn episode(&self) -> Option<u32> {
+        self.itunes_ext()?.episode.as_deref()?.trim().parse().ok()
+    }
+    /// The iTunes season number of podcast items
+    pub fn season(&self) -> Option<u32> {
+        self.itunes_ext()?.season.as_deref()?.trim().parse().ok()
+    }
+    /// The iTunes duration of podcast items
+    pub fn duration(&self) -> Option<Duration> {
+        parse_itunes_duration(self.itunes_ext()?.duration.as_deref()?)
+    }
+    /// Whether the item is marked as explicit, `None` if it is not marked
+    pub fn explicit(&self) -> Option<bool> {
+        let explicit = self.itunes_ext()?.explicit.as_deref()?.trim();
+        match &explicit.to_ascii_lowercase()[..] {
+            "yes" | "true" | "explicit" => Some(true),
+            "no" | "false" | "clean" => Some(false),
+            _ => None,
+        }
+    }
+    /// The first Dublin Core creator
+    pub fn dc_creator(&self) -> Option<&str> {
+        match self {
+            FeedItem::Rss(item) => item
+                .dublin_core_ext
+                .as_ref()
+                .and_then(|e| e.creators.first())
+                .map(|creator| creator.trim())
+                .filter(|creator| !creator.is_empty()),
+            FeedItem::FeedRs(_) => None,
+        }
+    }
+    /// The name of the author, from the Dublin Core creator, the author or
+    /// the iTunes author. Of RSS authors like `mail@example.com (Name)` only
+    /// the name is kept.
+    pub fn author(&self) -> Option<&str> {
+        let author = match self {
+            FeedItem::Rss(item) => self
+                .dc_creator()
+                .or(item.author.as_deref())
+                .or_else(|| self.itunes_author())?,
+            FeedItem::FeedRs(entry) => &entry.authors.first()?.name,
+        };
+        let author = match author.split_once('(') {
+            Some((mail, name)) if mail.contains('@') => name.trim_end().trim_end_matches(')'),
+            _ => author,
+        };
+        Some(author.trim()).filter(|author| !author.is_empty())
+    }
+    /// The categories of RSS items, or the Atom categories and JSON Feed
+    /// tags, preferring their labels. Names that only differ in case are
+    /// given once.
+    pub fn categories(&self) -> Vec<&str> {
+        let names: Vec<&str> = match self {
+            FeedItem::Rss(item) => item
+                .categories
+                .iter()
+                .map(|category| &category.name[..])
+                .collect(),
+            FeedItem::FeedRs(entry) => entry
+                .categories
+                .iter()
+                .map(|category| category.label.as_deref().unwrap_or(&category.term))
+                .collect(),
+        };
+        let mut seen = BTreeSet::new();
+        names
+            .into_iter()
+            .map(str::trim)
+            .filter(|name| !name.is_empty() && seen.insert(name.to_lowercase()))
+            .collect()
+    }
+    /// The HTML content of the item, or its summary if there is no content
+    pub(crate) fn content_html(&self) -> Option<&str> {
+        match self {
+            FeedItem::Rss(item) => item.content.as_deref().or(item.description.as_deref()),
+            FeedItem::FeedRs(entry) => entry
+                .content
+                .as_ref()
+                .and_then(|content| content.body.as_deref())
+                .or(entry.summary.as_ref().map(|summary| &summary.content[..])),
+        }
+    }
+    pub(crate) fn content_link(&self) -> Option<&str> {
+        match self {
+            FeedItem::Rss(item) => item.link.as_deref(),
+            FeedItem::FeedRs(entry) => entry.links.first().map(|link| &link.href[..]),
+        }
     }
     pub(crate) fn display_title(&self) -> Option<&str> {
         match self {
@@ -54,24 +305,200 @@ impl FeedItem {
             FeedItem::FeedRs(entry) => entry.title.as_ref().map(|v| v.content.trim()),
         }
     }
+    /// The title and summary of the item, as used for full-text search
+    pub(crate) fn search_text(&self) -> String {
+        let (title, summary) = match self {
+            FeedItem::Rss(item) => (item.title.as_deref(), item.description.as_deref()),
+            FeedItem::FeedRs(entry) => (
+                entry.title.as_ref().map(|v| &v.content[..]),
+                entry.summary.as_ref().map(|v| &v.content[..]),
+            ),
+        };
+        format!("{}\n{}", title.unwrap_or(""), summary.unwrap_or(""))
+    }
+}
+
+/// Where an item was fetched from, kept when the feed url changes later
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct ItemSource {
+    pub feed_url: String,
+    /// Id of the feed header the item came with, see `FeedHeaderMeta::id`
+    pub header_id: Option<usize>,
 }
 
 #[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
 pub struct FeedItemMeta {
     id: usize,
     pub item: FeedItem,
+    #[serde(default)]
+    updated_at: Option<DateTime<Utc>>,
+    /// Derived from the item when it is loaded or changes, not stored in the
+    /// feed files
+    #[serde(skip)]
+    content: Option<ItemContent>,
+    /// The main content of the linked article, for feeds with the
+    /// `full_content` setting. Used instead of the content of the item.
+    /// Stored in the archive, older feed files have it inline.
+    #[serde(default, skip_serializing)]
+    full_content: Option<String>,
+    /// When and in which update the item was first stored, unknown for
+    /// items stored before this was tracked
+    #[serde(default)]
+    added_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    added_in_update: Option<u64>,
+    /// Unknown for items stored before this was tracked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<ItemSource>,
+    /// When the item got stored, whether by an update or an import.
+    /// Unknown for items stored before this was tracked, unless the
+    /// migration could take it from `added_at`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    first_seen: Option<DateTime<Utc>>,
+    /// The default offset of the feed, see `Feed::default_offset`. Not
+    /// stored, the feed sets it on its items.
+    #[serde(skip)]
+    default_offset: Option<FixedOffset>,
 }
 
 impl FeedItemMeta {
     pub fn new(id: usize, item: FeedItem) -> Self {
-        Self { id, item }
+        let mut meta = Self {
+            id,
+            item,
+            updated_at: None,
+            content: None,
+            full_content: None,
+            added_at: None,
+            added_in_update: None,
+            source: None,
+            first_seen: Some(Utc::now()),
+            default_offset: None,
+        };
+        meta.process_content();
+        meta
+    }
+    pub(crate) fn process_content(&mut self) {
+        self.content = self
+            .full_content
+            .as_deref()
+            .or_else(|| self.item.content_html())
+            .map(ItemContent::process);
+    }
+    pub fn has_full_content(&self) -> bool {
+        self.full_content.is_some()
+    }
+    pub(crate) fn full_content(&self) -> Option<&str> {
+        self.full_content.as_deref()
+    }
+    pub(crate) fn set_full_content(&mut self, html: Option<String>) {
+        self.full_content = html;
+        self.process_content();
+    }
+    pub fn content(&self) -> Option<&ItemContent> {
+        self.content.as_ref()
+    }
+    pub fn added_at(&self) -> Option<DateTime<Utc>> {
+        self.added_at
+    }
+    /// When feed-bouncer first stored the item, if known
+    pub fn first_seen(&self) -> Option<DateTime<Utc>> {
+        self.first_seen
+    }
+    /// Items stored before `first_seen` got tracked only know when an
+    /// update added them, if at all
+    pub(crate) fn backfill_first_seen(&mut self) {
+        if self.first_seen.is_none() {
+            self.first_seen = self.added_at;
+        }
+    }
+    /// The sequence number of the update that stored the item
+    pub fn added_in_update(&self) -> Option<u64> {
+        self.added_in_update
+    }
+    pub(crate) fn set_added(&mut self, now: DateTime<Utc>, update_seq_no: u64) {
+        self.added_at = Some(now);
+        self.added_in_update = Some(update_seq_no);
+    }
+    /// The feed url and header the item was first fetched with
+    pub fn source(&self) -> Option<&ItemSource> {
+        self.source.as_ref()
+    }
+    pub(crate) fn set_source(&mut self, source: ItemSource) {
+        self.source = Some(source);
     }
     pub fn id(&self) -> usize {
         self.id
     }
+    pub(crate) fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+    /// A short name for the item that stays the same across fetches and
+    /// database rewrites, unlike the item id, for links from the outside.
+    pub fn slug(&self, feed_id: &FeedId) -> String {
+        use sha2::Digest;
+        let mut hash = sha2::Sha256::new();
+        hash.update(feed_id.as_str());
+        hash.update([0]);
+        hash.update(self.item.identity());
+        format!("{:x}", hash.finalize())[..16].to_owned()
+    }
+    /// When the publisher last changed the item after it was first stored
+    pub fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.updated_at
+    }
+    pub(crate) fn update(&mut self, item: FeedItem, now: DateTime<Utc>) {
+        self.item = item;
+        self.updated_at = Some(now);
+        self.process_content();
+    }
 
     pub fn publish_date_or_old(&self) -> DateTime<FixedOffset> {
-        self.item.publish_date_or_old()
+        self.item.publish_date_at_or_old(self.default_offset())
+    }
+
+    /// The offset of dates that don't give one, UTC unless the feed sets one
+    fn default_offset(&self) -> FixedOffset {
+        self.default_offset.unwrap_or_else(utc)
+    }
+    pub(crate) fn set_default_offset(&mut self, offset: Option<FixedOffset>) {
+        self.default_offset = offset;
+    }
+
+    /// The date the item last changed, by the feed or else by when a change
+    /// got noticed, falling back to the publish date
+    pub fn last_change_date(&self) -> DateTime<FixedOffset> {
+        self.item
+            .updated_date()
+            .or_else(|| self.updated_at.map(Into::into))
+            .unwrap_or_else(|| self.sort_date(ItemSortKey::Published))
+    }
+
+    /// Sorts stored items oldest first. `AsFetched` uses the item ids, which
+    /// count up in the order the items got stored.
+    pub fn sort(items: &mut [FeedItemMeta], order: FeedItemOrder) {
+        match order {
+            FeedItemOrder::AsFetched => items.sort_by_key(|item| item.id),
+            FeedItemOrder::Updated => items.sort_by_cached_key(|item| item.last_change_date()),
+            FeedItemOrder::Published => items.sort_by_cached_key(|item| item.publish_date_or_old()),
+        }
+    }
+
+    /// The date the publisher gave the item, if any could be parsed
+    pub fn publish_date(&self) -> Option<DateTime<FixedOffset>> {
+        self.item.publish_date_at(self.default_offset())
+    }
+
+    /// The date the item is shown at when sorting by `key`. Undated items
+    /// fall back to when they were first seen and the other way around.
+    /// Only items without either end up at the same old date.
+    pub fn sort_date(&self, key: ItemSortKey) -> DateTime<FixedOffset> {
+        let first_seen = self.first_seen.map(DateTime::from);
+        match key {
+            ItemSortKey::Published => self.publish_date().or(first_seen),
+            ItemSortKey::Discovered => first_seen.or_else(|| self.publish_date()),
+        }
+        .unwrap_or_else(old_date)
     }
 
     pub fn display_title(&self) -> Option<&str> {
@@ -85,23 +512,257 @@ impl FeedItemMeta {
         t = t.strip_prefix(":").unwrap_or(t).trim();
         t
     }
-    pub fn display_title_without_prefixes(&self, feed: &Feed) -> Option<&str> {
-        self.display_title().map(|mut t| {
-            let mut prefixes: Vec<_> = feed.titles().map(|e| e.trim()).collect();
-            prefixes.sort_by_key(|e| e.len());
-            prefixes.reverse();
-            for a in prefixes {
-                t = Self::strip_prefix(t, a);
+    /// Checks if the title starts with `prefix`, followed by a separator that
+    /// `display_title_without_prefixes` would strip.
+    pub fn is_title_prefixed_with(&self, prefix: &str) -> bool {
+        let title = match self.display_title() {
+            Some(title) => title,
+            None => return false,
+        };
+        match title.strip_prefix(prefix) {
+            Some(rest) => {
+                let rest = rest.trim_start();
+                rest.starts_with('-') || rest.starts_with(':')
             }
-            t
-        })
+            None => false,
+        }
+    }
+    pub fn display_title_without_prefixes(&self, feed: &Feed) -> Option<&str> {
+        self.display_title_stripped(feed).map(|(t, _)| t)
+    }
+    /// Like `display_title_without_prefixes`, but also returns the titles of
+    /// the feed that got stripped from the title, or the pattern that cleaned
+    /// it with `TitleCleaning::Regex`.
+    pub fn display_title_stripped<'a>(&'a self, feed: &'a Feed) -> Option<(&'a str, Vec<&'a str>)> {
+        self.display_title()
+            .map(|mut t| match feed.title_cleaning() {
+                TitleCleaning::Off => (t, Vec::new()),
+                TitleCleaning::Regex(pattern) => {
+                    let cleaned = feed.clean_title_with_regex(t);
+                    let matched = if cleaned != t {
+                        vec![&pattern[..]]
+                    } else {
+                        Vec::new()
+                    };
+                    (cleaned, matched)
+                }
+                TitleCleaning::StripKnownPrefixes => {
+                    let mut prefixes: Vec<_> = feed.titles().map(|e| e.trim()).collect();
+                    prefixes.sort_by_key(|e| e.len());
+                    prefixes.reverse();
+                    let mut matched = Vec::new();
+                    for a in prefixes {
+                        if !a.is_empty() && t.trim().starts_with(a) {
+                            matched.push(a);
+                        }
+                        t = Self::strip_prefix(t, a);
+                    }
+                    (t, matched)
+                }
+            })
+    }
+    /// Guesses a prefix that is still left in the display title, like
+    /// `Podcast Name - Episode`, as a candidate for a new title alias.
+    pub fn guess_title_prefix<'a>(&'a self, feed: &'a Feed) -> Option<&'a str> {
+        /// Longer leading parts are probably part of the actual title
+        const MAX_PREFIX_WORDS: usize = 5;
+
+        let (title, _) = self.display_title_stripped(feed)?;
+        let end = [" - ", ": ", " | ", " – "]
+            .iter()
+            .filter_map(|separator| title.find(separator))
+            .min()?;
+        let prefix = title[..end].trim();
+        if prefix.is_empty() || prefix.split_whitespace().count() > MAX_PREFIX_WORDS {
+            return None;
+        }
+        Some(prefix)
     }
     pub fn content_link(&self) -> Option<&str> {
-        match &self.item {
-            FeedItem::Rss(item) => item.link.as_deref(),
-            FeedItem::FeedRs(entry) => entry.links.first().map(|link| &link.href[..]),
+        self.item.content_link()
+    }
+}
+
+/// Parses durations given as seconds, `MM:SS` or `HH:MM:SS`
+fn parse_itunes_duration(duration: &str) -> Option<Duration> {
+    let mut secs = 0;
+    let parts: Vec<_> = duration.trim().split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    for part in parts {
+        secs = secs * 60 + part.trim().parse::<u64>().ok()?;
+    }
+    Some(Duration::from_secs(secs))
+}
+
+/// Timezone abbreviations seen in feeds, which RFC 2822 does not know
+const TIMEZONE_ABBREVIATIONS: &[(&str, &str)] = &[
+    ("UTC", "+0000"),
+    ("Z", "+0000"),
+    ("WET", "+0000"),
+    ("WEST", "+0100"),
+    ("BST", "+0100"),
+    ("CET", "+0100"),
+    ("CEST", "+0200"),
+    ("MEZ", "+0100"),
+    ("MESZ", "+0200"),
+    ("EET", "+0200"),
+    ("EEST", "+0300"),
+    ("MSK", "+0300"),
+    ("IST", "+0530"),
+    ("JST", "+0900"),
+    ("KST", "+0900"),
+    ("AEST", "+1000"),
+    ("AEDT", "+1100"),
+    ("NZST", "+1200"),
+    ("NZDT", "+1300"),
+];
+
+const DAY_NAMES: &[&str] = &[
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+const MONTH_NAMES: &[&str] = &[
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Formats with an offset, tried after RFC 2822 and RFC 3339
+const OFFSET_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S %z",
+    "%Y-%m-%d %H:%M:%S%z",
+    "%Y-%m-%dT%H:%M:%S%z",
+    "%Y-%m-%dT%H:%M:%S%.f%z",
+    "%Y-%m-%dT%H:%M%z",
+    "%a, %d %b %Y %H:%M %z",
+    "%a %b %d %H:%M:%S %z %Y",
+    "%d %b %Y %H:%M:%S %z",
+];
+
+/// Formats without an offset, which get the default offset
+const NAIVE_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+    "%a, %d %b %Y %H:%M:%S",
+    "%a, %d %b %Y %H:%M",
+    "%d %b %Y %H:%M:%S",
+    "%b %d, %Y %H:%M:%S",
+    "%d.%m.%Y %H:%M",
+];
+
+/// Formats without a time, which get midnight at the default offset
+const DATE_FORMATS: &[&str] = &[
+    "%Y-%m-%d",
+    "%Y/%m/%d",
+    "%d.%m.%Y",
+    "%a, %d %b %Y",
+    "%d %b %Y",
+    "%a, %b %d, %Y",
+    "%b %d, %Y",
+];
+
+/// Parses the dates of RSS items, which are supposed to follow RFC 2822 but
+/// often don't. Dates without an offset are taken to be at `default_offset`.
+pub(crate) fn parse_date(raw: &str, default_offset: FixedOffset) -> Option<DateTime<FixedOffset>> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return None;
+    }
+    if let Ok(date) = DateTime::parse_from_rfc2822(raw) {
+        return Some(date);
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(raw) {
+        return Some(date);
+    }
+
+    let cleaned = clean_date(raw);
+    if let Ok(date) = DateTime::parse_from_rfc2822(&cleaned) {
+        return Some(date);
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(&cleaned) {
+        return Some(date);
+    }
+    for format in OFFSET_FORMATS {
+        if let Ok(date) = DateTime::parse_from_str(&cleaned, format) {
+            return Some(date);
+        }
+    }
+    for format in NAIVE_FORMATS {
+        if let Ok(date) = NaiveDateTime::parse_from_str(&cleaned, format) {
+            return default_offset.from_local_datetime(&date).single();
+        }
+    }
+    for format in DATE_FORMATS {
+        if let Ok(date) = NaiveDate::parse_from_str(&cleaned, format) {
+            return default_offset
+                .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+                .single();
         }
     }
+    None
+}
+
+/// Normalizes the parts of broken dates that the formats don't accept:
+/// comments, full day and month names, ordinals and timezone abbreviations.
+fn clean_date(raw: &str) -> String {
+    // Comments like "(UTC)" after the date
+    let raw = raw.split('(').next().unwrap_or(raw);
+    let mut words = Vec::new();
+    for word in raw.split_whitespace() {
+        let (word, comma) = match word.strip_suffix(',') {
+            Some(word) => (word, ","),
+            None => (word, ""),
+        };
+        let mut word = word.to_owned();
+        let is_name = |names: &[&str]| {
+            names
+                .iter()
+                .find(|name| word.len() > 3 && name.eq_ignore_ascii_case(&word))
+                .map(|name| name[..3].to_owned())
+        };
+        if let Some(short) = is_name(DAY_NAMES).or_else(|| is_name(MONTH_NAMES)) {
+            word = short;
+        } else if word.eq_ignore_ascii_case("Sept") {
+            word = "Sep".to_owned();
+        } else if let Some(&(_, offset)) = TIMEZONE_ABBREVIATIONS
+            .iter()
+            .find(|(abbreviation, _)| *abbreviation == word)
+        {
+            word = offset.to_owned();
+        } else if let Some(number) = ["st", "nd", "rd", "th"]
+            .iter()
+            .find_map(|suffix| word.strip_suffix(suffix))
+            .filter(|number| !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+        {
+            word = number.to_owned();
+        }
+        words.push(word + comma);
+    }
+    words.join(" ")
+}
+
+fn utc() -> FixedOffset {
+    FixedOffset::east_opt(0).unwrap()
 }
 
 fn old_date() -> DateTime<FixedOffset> {
@@ -112,3 +773,165 @@ fn old_date() -> DateTime<FixedOffset> {
 fn test_old_date() {
     old_date();
 }
+
+#[test]
+fn test_parse_itunes_duration() {
+    assert_eq!(parse_itunes_duration("90"), Some(Duration::from_secs(90)));
+    assert_eq!(
+        parse_itunes_duration("01:30"),
+        Some(Duration::from_secs(90))
+    );
+    assert_eq!(
+        parse_itunes_duration("1:00:05"),
+        Some(Duration::from_secs(3605))
+    );
+    assert_eq!(parse_itunes_duration("1.5 hours"), None);
+}
+
+#[test]
+fn test_parse_broken_dates() {
+    let utc = FixedOffset::east_opt(0).unwrap();
+    let parse = |raw| parse_date(raw, utc).map(|date| date.to_rfc3339());
+    let cases = [
+        // Valid RFC 2822 and RFC 3339
+        (
+            "Mon, 05 Jun 2023 10:00:00 +0000",
+            "2023-06-05T10:00:00+00:00",
+        ),
+        ("2023-06-05T10:00:00Z", "2023-06-05T10:00:00+00:00"),
+        // Full day and month names
+        (
+            "Monday, 05 Jun 2023 10:00:00 GMT",
+            "2023-06-05T10:00:00+00:00",
+        ),
+        (
+            "Mon, 05 June 2023 10:00:00 GMT",
+            "2023-06-05T10:00:00+00:00",
+        ),
+        ("Tue, 5 Sept 2023 10:00:00 GMT", "2023-09-05T10:00:00+00:00"),
+        // Timezone names RFC 2822 does not know
+        (
+            "Mon, 05 Jun 2023 10:00:00 CEST",
+            "2023-06-05T10:00:00+02:00",
+        ),
+        ("Mon, 05 Jun 2023 10:00:00 UTC", "2023-06-05T10:00:00+00:00"),
+        // Comments after the date
+        (
+            "Mon, 05 Jun 2023 10:00:00 +0000 (Coordinated Universal Time)",
+            "2023-06-05T10:00:00+00:00",
+        ),
+        // ISO 8601 variants
+        ("2023-06-05 10:00:00 +0200", "2023-06-05T10:00:00+02:00"),
+        ("2023-06-05T10:00:00+0200", "2023-06-05T10:00:00+02:00"),
+        ("2023-06-05T10:00", "2023-06-05T10:00:00+00:00"),
+        // No offset
+        ("2023-06-05 10:00:00", "2023-06-05T10:00:00+00:00"),
+        ("Mon, 05 Jun 2023 10:00:00", "2023-06-05T10:00:00+00:00"),
+        // Only a date
+        ("2023-06-05", "2023-06-05T00:00:00+00:00"),
+        ("Monday, June 5th, 2023", "2023-06-05T00:00:00+00:00"),
+        ("05.06.2023", "2023-06-05T00:00:00+00:00"),
+        (
+            "  Mon, 05 Jun 2023 10:00:00 +0000  ",
+            "2023-06-05T10:00:00+00:00",
+        ),
+    ];
+    for (raw, expected) in cases {
+        assert_eq!(parse(raw).as_deref(), Some(expected), "{:?}", raw);
+    }
+
+    assert_eq!(parse(""), None);
+    assert_eq!(parse("yesterday"), None);
+
+    let cet = FixedOffset::east_opt(3600).unwrap();
+    assert_eq!(
+        parse_date("2023-06-05 10:00:00", cet)
+            .map(|date| date.to_rfc3339())
+            .as_deref(),
+        Some("2023-06-05T10:00:00+01:00")
+    );
+}
+
+#[test]
+fn test_slug_is_stable() {
+    let item = |id, title: &str| {
+        FeedItemMeta::new(
+            id,
+            FeedItem::Rss(rss::Item {
+                title: Some(title.to_owned()),
+                guid: Some(rss::Guid {
+                    value: "post-1".to_owned(),
+                    permalink: false,
+                }),
+                ..rss::Item::default()
+            }),
+        )
+    };
+    let feed_id = "feed".to_owned();
+    let slug = item(0, "First title").slug(&feed_id);
+    assert_eq!(slug.len(), 16);
+    // Neither the item id nor edits to the item change the slug
+    assert_eq!(item(7, "Edited title").slug(&feed_id), slug);
+    assert_ne!(item(0, "First title").slug(&"other".to_owned()), slug);
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn test_clean_title_with_regex() {
+    fn clean_title_with_regex<'a>(title: &'a str, pattern: &str) -> &'a str {
+        super::clean_title_with_regex(title, &regex::Regex::new(pattern).unwrap())
+    }
+    assert_eq!(
+        clean_title_with_regex("[Podcast] Episode 1", r"^\[.*?\]"),
+        "Episode 1"
+    );
+    assert_eq!(
+        clean_title_with_regex("Episode 1 | Site", r"\|[^|]*$"),
+        "Episode 1"
+    );
+    assert_eq!(
+        clean_title_with_regex("Comic: Page 3 (new)", r"^Comic: (.*) \(new\)$"),
+        "Page 3"
+    );
+    // Matches in the middle of the title are left alone
+    assert_eq!(clean_title_with_regex("A - B - C", r"B"), "A - B - C");
+
+    // Invalid patterns, like in feed files edited by hand, change nothing
+    let mut feed = Feed::new("Feed".to_owned());
+    feed.set_title_cleaning(TitleCleaning::Regex("(".to_owned()));
+    assert_eq!(feed.clean_title_with_regex("Unchanged"), "Unchanged");
+}
+
+#[test]
+fn test_author() {
+    let item = |author: &str| {
+        FeedItem::Rss(rss::Item {
+            author: Some(author.to_owned()),
+            ..rss::Item::default()
+        })
+    };
+    assert_eq!(item("Jane Doe").author(), Some("Jane Doe"));
+    assert_eq!(
+        item("jane@example.com (Jane Doe)").author(),
+        Some("Jane Doe")
+    );
+    assert_eq!(item("Jane (guest)").author(), Some("Jane (guest)"));
+    assert_eq!(item("  ").author(), None);
+}
+
+#[test]
+fn test_backfill_first_seen() {
+    let mut item = FeedItemMeta::new(0, FeedItem::Rss(rss::Item::default()));
+    item.first_seen = None;
+    // Without anything to go by, the item stays unknown instead of getting
+    // the date of the migration
+    item.backfill_first_seen();
+    assert_eq!(item.first_seen(), None);
+    assert_eq!(item.sort_date(ItemSortKey::Discovered), old_date());
+
+    let added_at = Utc.with_ymd_and_hms(2021, 3, 4, 5, 6, 7).unwrap();
+    item.added_at = Some(added_at);
+    item.backfill_first_seen();
+    assert_eq!(item.first_seen(), Some(added_at));
+    assert_eq!(item.sort_date(ItemSortKey::Published), added_at);
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::{
+    database::{storage::Storage, storage_feed::Feed, storage_feed_item::FeedItemMeta, FeedId},
+    ItemSortKey,
+};
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct TimelineEntry {
+    date: DateTime<FixedOffset>,
+    feed_id: FeedId,
+    item_id: usize,
+}
+
+/// What the timeline holds of an item, to find its entries again
+struct TimelineItem {
+    published: DateTime<FixedOffset>,
+    discovered: DateTime<FixedOffset>,
+    slug: String,
+}
+
+fn entry(date: DateTime<FixedOffset>, feed_id: &FeedId, item_id: usize) -> TimelineEntry {
+    TimelineEntry {
+        date,
+        feed_id: feed_id.clone(),
+        item_id,
+    }
+}
+
+fn insert_sorted(entries: &mut Vec<TimelineEntry>, entry: TimelineEntry) {
+    let i = entries.binary_search(&entry).unwrap_or_else(|i| i);
+    entries.insert(i, entry);
+}
+
+fn remove_sorted(entries: &mut Vec<TimelineEntry>, entry: &TimelineEntry) {
+    if let Ok(i) = entries.binary_search(entry) {
+        entries.remove(i);
+    }
+}
+
+/// All items of all feeds ordered by their publish date and by their
+/// discovery date, so queries don't need to parse and sort every item again.
+///
+/// This is independent of the order of the items in the storage, which
+/// follows the item order of each feed.
+///
+/// Needs to be refreshed whenever the items of a feed change.
+#[derive(Default)]
+pub struct Timeline {
+    published: Vec<TimelineEntry>,
+    discovered: Vec<TimelineEntry>,
+    /// Maps item ids to their position in `Feed::items()`
+    positions: HashMap<FeedId, HashMap<usize, usize>>,
+    /// Maps item slugs to their feed and item id
+    slugs: HashMap<String, (FeedId, usize)>,
+    /// The dates and slugs of the items of each feed, by item id
+    items: HashMap<FeedId, HashMap<usize, TimelineItem>>,
+}
+
+impl Timeline {
+    pub fn build(storage: &Storage) -> Self {
+        let mut timeline = Self::default();
+        for (feed_id, feed) in storage.iter() {
+            let mut items = HashMap::new();
+            for item in feed.items() {
+                let timeline_item = timeline.new_item(feed_id, item);
+                timeline
+                    .published
+                    .push(entry(timeline_item.published, feed_id, item.id()));
+                timeline
+                    .discovered
+                    .push(entry(timeline_item.discovered, feed_id, item.id()));
+                items.insert(item.id(), timeline_item);
+            }
+            timeline.items.insert(feed_id.clone(), items);
+            timeline.update_positions(feed_id, feed);
+        }
+        timeline.published.sort();
+        timeline.discovered.sort();
+        timeline
+    }
+
+    /// Registers the slug of an item that is not in the timeline yet
+    fn new_item(&mut self, feed_id: &FeedId, item: &FeedItemMeta) -> TimelineItem {
+        let slug = item.slug(feed_id);
+        self.slugs
+            .insert(slug.clone(), (feed_id.clone(), item.id()));
+        TimelineItem {
+            published: item.sort_date(ItemSortKey::Published),
+            discovered: item.sort_date(ItemSortKey::Discovered),
+            slug,
+        }
+    }
+
+    fn update_positions(&mut self, feed_id: &FeedId, feed: &Feed) {
+        let positions = feed
+            .items()
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (item.id(), i))
+            .collect();
+        self.positions.insert(feed_id.clone(), positions);
+    }
+
+    fn remove_entries(&mut self, feed_id: &FeedId, item_id: usize, item: &TimelineItem) {
+        remove_sorted(
+            &mut self.published,
+            &entry(item.published, feed_id, item_id),
+        );
+        remove_sorted(
+            &mut self.discovered,
+            &entry(item.discovered, feed_id, item_id),
+        );
+    }
+
+    /// Brings the entries of a feed up to date with its current items. Only
+    /// the entries of added, removed or redated items change.
+    pub fn refresh_feed(&mut self, feed_id: &FeedId, feed: &Feed) {
+        let mut old_items = self.items.remove(feed_id).unwrap_or_default();
+        let mut items = HashMap::with_capacity(feed.items().len());
+        for item in feed.items() {
+            let item_id = item.id();
+            let timeline_item = match old_items.remove(&item_id) {
+                Some(old) => {
+                    let published = item.sort_date(ItemSortKey::Published);
+                    let discovered = item.sort_date(ItemSortKey::Discovered);
+                    if old.published == published && old.discovered == discovered {
+                        items.insert(item_id, old);
+                        continue;
+                    }
+                    self.remove_entries(feed_id, item_id, &old);
+                    TimelineItem {
+                        published,
+                        discovered,
+                        slug: old.slug,
+                    }
+                }
+                None => self.new_item(feed_id, item),
+            };
+            insert_sorted(
+                &mut self.published,
+                entry(timeline_item.published, feed_id, item_id),
+            );
+            insert_sorted(
+                &mut self.discovered,
+                entry(timeline_item.discovered, feed_id, item_id),
+            );
+            items.insert(item_id, timeline_item);
+        }
+        // Items that are gone, like those moved to cold storage
+        for (item_id, old) in old_items {
+            self.remove_entries(feed_id, item_id, &old);
+            self.slugs.remove(&old.slug);
+        }
+        self.items.insert(feed_id.clone(), items);
+        self.update_positions(feed_id, feed);
+    }
+
+    pub fn remove_feed(&mut self, feed_id: &FeedId) {
+        self.published.retain(|entry| entry.feed_id != *feed_id);
+        self.discovered.retain(|entry| entry.feed_id != *feed_id);
+        for item in self.items.remove(feed_id).unwrap_or_default().values() {
+            self.slugs.remove(&item.slug);
+        }
+        self.positions.remove(feed_id);
+    }
+
+    /// Number of items of all feeds
+    pub fn len(&self) -> usize {
+        self.published.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.published.is_empty()
+    }
+
+    /// All items as `(feed_id, item_id)`, oldest first by the date `key`
+    pub fn iter(&self, key: ItemSortKey) -> impl DoubleEndedIterator<Item = (&FeedId, usize)> + '_ {
+        let entries = match key {
+            ItemSortKey::Published => &self.published,
+            ItemSortKey::Discovered => &self.discovered,
+        };
+        entries.iter().map(|entry| (&entry.feed_id, entry.item_id))
+    }
+
+    pub fn item<'a>(
+        &self,
+        feed_id: &FeedId,
+        feed: &'a Feed,
+        item_id: usize,
+    ) -> Option<&'a FeedItemMeta> {
+        let position = *self.positions.get(feed_id)?.get(&item_id)?;
+        feed.items().get(position)
+    }
+
+    /// The feed and item id of the item with the slug
+    pub fn slug(&self, slug: &str) -> Option<(&FeedId, usize)> {
+        let (feed_id, item_id) = self.slugs.get(slug)?;
+        Some((feed_id, *item_id))
+    }
+}
+
+#[cfg(test)]
+fn rss_item(guid: &str, days_ago: i64) -> crate::FeedItem {
+    crate::FeedItem::Rss(rss::Item {
+        guid: Some(rss::Guid {
+            value: guid.to_owned(),
+            permalink: false,
+        }),
+        pub_date: Some((chrono::Utc::now() - chrono::Duration::days(days_ago)).to_rfc2822()),
+        ..rss::Item::default()
+    })
+}
+
+#[test]
+fn test_refresh_feed_matches_build() {
+    let mut db = crate::Database::in_memory();
+    let feed_id = db.insert(Feed::new("Feed".to_owned()));
+    let other_id = db.insert(Feed::new("Other".to_owned()));
+    for (guid, days_ago) in [("a", 5), ("b", 20), ("c", 1)] {
+        db.get_mut(&feed_id)
+            .unwrap()
+            .push_item(rss_item(guid, days_ago));
+    }
+    db.get_mut(&other_id).unwrap().push_item(rss_item("d", 10));
+
+    let mut timeline = Timeline::default();
+    for (feed_id, feed) in db.storage.iter() {
+        timeline.refresh_feed(feed_id, feed);
+    }
+    let old_slug = db.get(&feed_id).unwrap().items()[1].slug(&feed_id);
+    assert!(timeline.slug(&old_slug).is_some());
+
+    let feed = db.get_mut(&feed_id).unwrap();
+    feed.take_items_published_before(chrono::Utc::now() - chrono::Duration::days(15));
+    feed.push_item(rss_item("e", 3));
+    timeline.refresh_feed(&feed_id, db.get(&feed_id).unwrap());
+    assert!(timeline.slug(&old_slug).is_none());
+
+    let built = Timeline::build(&db.storage);
+    for key in [ItemSortKey::Published, ItemSortKey::Discovered] {
+        assert!(timeline.iter(key).eq(built.iter(key)));
+    }
+    assert_eq!(timeline.len(), 4);
+    assert_eq!(timeline.slugs, built.slugs);
+    assert_eq!(timeline.positions, built.positions);
+}
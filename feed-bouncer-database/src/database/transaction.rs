@@ -0,0 +1,245 @@
+use std::path::{Path, PathBuf};
+
+use crate::{database::Database, Error};
+
+/// Lists the staged files of a transaction while they get moved in place.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Manifest {
+    /// Target paths relative to the storage path, the staged file of each is
+    /// the target with a `.tx.json` extension
+    files: Vec<PathBuf>,
+}
+
+fn manifest_path(storage_path: &Path) -> PathBuf {
+    storage_path.join("transaction.json")
+}
+
+fn staged_path(path: &Path) -> PathBuf {
+    path.with_extension("tx.json")
+}
+
+/// A set of JSON files that replace their targets all-or-nothing.
+///
+/// All files are written next to their targets first. Only then a manifest
+/// gets written, which `recover` uses to finish moving the files in place
+/// after a crash. Without a manifest, staged files are left overs of a
+/// transaction that never got committed.
+pub struct Transaction {
+    storage_path: PathBuf,
+    files: Vec<PathBuf>,
+    allow_shrink: bool,
+}
+
+impl Transaction {
+    pub fn new(storage_path: &Path) -> Self {
+        Self {
+            storage_path: storage_path.to_owned(),
+            files: Vec::new(),
+            allow_shrink: false,
+        }
+    }
+
+    /// Lets `stage_guarded_json` replace files with smaller ones, like
+    /// `save_shrunk` does for plain saves.
+    pub fn allow_shrink(&mut self) {
+        self.allow_shrink = true;
+    }
+
+    /// Stages a file at `path`, which needs to be inside the storage path.
+    pub fn stage_json(
+        &mut self,
+        data: &impl serde::Serialize,
+        path: &Path,
+        compact_key: Option<&str>,
+    ) -> std::io::Result<()> {
+        let value = serde_json::to_value(data)?;
+        self.stage_value(&value, path, compact_key)
+    }
+
+    /// Like `stage_json`, but fails the transaction if the file would
+    /// shrink and shrinking is not allowed.
+    pub fn stage_guarded_json(
+        &mut self,
+        data: &impl serde::Serialize,
+        path: &Path,
+        compact_key: Option<&str>,
+        what: &str,
+    ) -> std::io::Result<()> {
+        let value = serde_json::to_value(data)?;
+        if !self.allow_shrink && crate::shrinks(&value, path) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("suspicious file size change when saving {}", what),
+            ));
+        }
+        self.stage_value(&value, path, compact_key)
+    }
+
+    fn stage_value(
+        &mut self,
+        value: &serde_json::Value,
+        path: &Path,
+        compact_key: Option<&str>,
+    ) -> std::io::Result<()> {
+        let relative = path
+            .strip_prefix(&self.storage_path)
+            .map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("staged file {:?} is outside of the storage path", path),
+                )
+            })?
+            .to_owned();
+        std::fs::write(staged_path(path), crate::canonical_json(value, compact_key))?;
+        self.files.push(relative);
+        Ok(())
+    }
+
+    pub fn commit(mut self) -> std::io::Result<()> {
+        let files = std::mem::take(&mut self.files);
+        let manifest = Manifest { files };
+        let manifest_path = manifest_path(&self.storage_path);
+        let manifest_new_path = manifest_path.with_extension("new.json");
+        std::fs::write(&manifest_new_path, serde_json::to_string_pretty(&manifest)?)?;
+        std::fs::rename(&manifest_new_path, &manifest_path)?;
+
+        finish(&self.storage_path, &manifest)?;
+        std::fs::remove_file(&manifest_path)
+    }
+}
+
+impl Drop for Transaction {
+    /// Removes the staged files of a transaction that did not get committed
+    fn drop(&mut self) {
+        for file in &self.files {
+            std::fs::remove_file(staged_path(&self.storage_path.join(file))).ok();
+        }
+    }
+}
+
+fn finish(storage_path: &Path, manifest: &Manifest) -> std::io::Result<()> {
+    for file in &manifest.files {
+        let path = storage_path.join(file);
+        let staged = staged_path(&path);
+        // Files that got moved before a crash are already in place
+        if staged.exists() {
+            std::fs::rename(&staged, &path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Finishes a transaction that got interrupted after its commit started,
+/// or removes the staged files of one that never got committed.
+pub fn recover(storage_path: &Path) -> Result<(), Error> {
+    let manifest_path = manifest_path(storage_path);
+    let manifest = match std::fs::read_to_string(&manifest_path) {
+        Ok(v) => v,
+        Err(_) => {
+            remove_staged_files(storage_path);
+            return Ok(());
+        }
+    };
+    let manifest: Manifest = serde_json::from_str(&manifest).map_err(std::io::Error::from)?;
+
+    eprintln!("Finishing interrupted transaction");
+    finish(storage_path, &manifest)?;
+    std::fs::remove_file(&manifest_path).ok();
+    Ok(())
+}
+
+/// Removes the `.tx.json` files left over when the process died while
+/// staging a transaction.
+fn remove_staged_files(storage_path: &Path) {
+    for dir in [storage_path.to_owned(), storage_path.join("feeds")].iter() {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let staged = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| name.ends_with(".tx.json"));
+            if staged {
+                eprintln!(
+                    "Removing staged file {:?} of an uncommitted transaction",
+                    path
+                );
+                std::fs::remove_file(&path).ok();
+            }
+        }
+    }
+}
+
+impl Database {
+    /// Starts a transaction for a change of many feeds, like an import.
+    pub(crate) fn begin(&self) -> Transaction {
+        Transaction::new(&self.storage_path)
+    }
+
+    /// Saves the feeds and user data all-or-nothing, for changes that
+    /// touch both or many feeds at once.
+    pub fn save_atomic(&mut self) -> Result<(), Error> {
+        let tx = self.begin();
+        self.commit(tx)
+    }
+
+    /// Like `save_atomic`, for changes that remove data from feeds.
+    pub fn save_atomic_shrunk(&mut self) -> Result<(), Error> {
+        let mut tx = self.begin();
+        tx.allow_shrink();
+        self.commit(tx)
+    }
+
+    /// Saves the feeds and user data together with the files staged in `tx`.
+    ///
+    /// Fails without changing anything if a feed file would shrink, unless
+    /// `tx` allows it.
+    pub(crate) fn commit(&mut self, mut tx: Transaction) -> Result<(), Error> {
+        self.merge_pending_usage();
+        if self.is_read_only() {
+            return Ok(());
+        }
+        self.storage
+            .stage(&mut tx, &self.storage_path, &self.config.storage)?;
+        self.user_data_storage.stage(&mut tx, &self.storage_path)?;
+        tx.commit()?;
+
+        self.search_index.save(&self.storage_path);
+        self.checkpoint_journal();
+        Ok(())
+    }
+}
+
+#[test]
+fn test_transaction_shrink_guard_and_leftovers() {
+    let dir = std::env::temp_dir().join(format!(
+        "feed-bouncer-test-transaction-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("data.json");
+    std::fs::write(&file, "[1,2,3]").unwrap();
+
+    let mut tx = Transaction::new(&dir);
+    assert!(tx.stage_guarded_json(&[1], &file, None, "data").is_err());
+    tx.allow_shrink();
+    tx.stage_guarded_json(&[1], &file, None, "data").unwrap();
+    tx.commit().unwrap();
+    let data: Vec<u32> = serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+    assert_eq!(data, vec![1]);
+
+    let mut tx = Transaction::new(&dir);
+    assert!(tx
+        .stage_json(&[1], Path::new("/elsewhere/data.json"), None)
+        .is_err());
+    tx.stage_json(&[1, 2], &file, None).unwrap();
+    std::mem::forget(tx);
+    assert!(staged_path(&file).exists());
+    recover(&dir).unwrap();
+    assert!(!staged_path(&file).exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
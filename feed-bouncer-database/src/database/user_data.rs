@@ -1,49 +1,528 @@
 use std::{
     collections::{BTreeMap, BTreeSet},
     path::Path,
+    str::FromStr,
 };
 
-use crate::FeedId;
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::{
+    database::transaction::Transaction, Error, FeedId, FeedOptions, ItemOrder, TagSynonyms,
+};
 
 #[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default)]
 pub struct FeedUserData {
-    read_ids: BTreeSet<usize>,
+    /// Read items and when they got read, if known
+    #[serde(alias = "read_ids", deserialize_with = "deserialize_read_at")]
+    read_at: BTreeMap<usize, Option<DateTime<Utc>>>,
+    /// Items moved to the trash, kept on disk but not shown
+    #[serde(default)]
+    hidden_ids: BTreeSet<usize>,
+}
+
+/// Older versions stored only the set of read ids, those reads have no time
+fn deserialize_read_at<'de, D>(
+    deserializer: D,
+) -> Result<BTreeMap<usize, Option<DateTime<Utc>>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct ReadAtVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for ReadAtVisitor {
+        type Value = BTreeMap<usize, Option<DateTime<Utc>>>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a list of item ids or a map of item ids to read times")
+        }
+
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(
+            self,
+            mut seq: A,
+        ) -> Result<Self::Value, A::Error> {
+            let mut read_at = BTreeMap::new();
+            while let Some(item_id) = seq.next_element()? {
+                read_at.insert(item_id, None);
+            }
+            Ok(read_at)
+        }
+
+        fn visit_map<A: serde::de::MapAccess<'de>>(
+            self,
+            mut map: A,
+        ) -> Result<Self::Value, A::Error> {
+            let mut read_at = BTreeMap::new();
+            while let Some((item_id, time)) = map.next_entry()? {
+                read_at.insert(item_id, time);
+            }
+            Ok(read_at)
+        }
+    }
+
+    deserializer.deserialize_any(ReadAtVisitor)
+}
+
+/// What the user did with an item
+#[derive(serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ItemState {
+    pub read: bool,
+    /// In the trash
+    pub hidden: bool,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct SavedView {
+    pub filter: String,
+    #[serde(default)]
+    pub order: ItemOrder,
+    #[serde(default)]
+    pub unread_only: bool,
+}
+
+/// A filter shared read-only under a secret token
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct Share {
+    pub name: String,
+    pub filter: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// How lists of items are rendered
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ViewMode {
+    /// Items grouped by week, with summaries
+    #[default]
+    Full,
+    /// A dense list for small screens
+    Compact,
+}
+
+impl FromStr for ViewMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(Self::Full),
+            "compact" => Ok(Self::Compact),
+            _ => Err(format!("unknown view mode {:?}", s)),
+        }
+    }
+}
+
+impl ViewMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Compact => "compact",
+        }
+    }
+}
+
+/// The periods lists of items are split into, each with a heading
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemGrouping {
+    Day,
+    /// ISO weeks, starting on Monday
+    #[default]
+    Week,
+    Month,
+    /// A single list without headings
+    None,
+}
+
+impl FromStr for ItemGrouping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            "none" => Ok(Self::None),
+            _ => Err(format!("unknown item grouping {:?}", s)),
+        }
+    }
+}
+
+impl ItemGrouping {
+    pub const ALL: [Self; 4] = [Self::Day, Self::Week, Self::Month, Self::None];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+            Self::None => "none",
+        }
+    }
+}
+
+/// Page loads of the index apart by more than this count as separate visits
+const VISIT_GAP_MINS: i64 = 30;
+
+/// When the index got loaded, to tell which items are new since the last
+/// visit
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default)]
+pub struct Visits {
+    /// The end of the previous visit
+    #[serde(default)]
+    last_visit: Option<DateTime<Utc>>,
+    /// The latest page load of the current visit
+    #[serde(default)]
+    last_seen: Option<DateTime<Utc>>,
+}
+
+impl Visits {
+    /// Returns true if `now` starts a new visit, which moves the mark
+    fn record(&mut self, now: DateTime<Utc>) -> bool {
+        let new_visit = self.last_seen.map_or(true, |seen| {
+            now - seen > chrono::Duration::minutes(VISIT_GAP_MINS)
+        });
+        if new_visit {
+            self.last_visit = self.last_seen;
+        }
+        self.last_seen = Some(now);
+        new_visit
+    }
+}
+
+/// Usage noted by page loads, which only hold the read lock of the
+/// database. It gets merged into the user data by the next save, which the
+/// autosave takes care of.
+#[derive(Debug, Default)]
+pub struct PendingUsage {
+    /// The stored visits with the pending page loads applied
+    visits: Option<Visits>,
+    filters: BTreeMap<String, u64>,
+    tags: BTreeMap<String, u64>,
+    view_mode: Option<ViewMode>,
+    item_grouping: Option<ItemGrouping>,
+}
+
+impl PendingUsage {
+    pub fn record_visit(&mut self, stored: Visits, now: DateTime<Utc>) {
+        self.visits.get_or_insert(stored).record(now);
+    }
+    pub fn last_visit(&self, stored: Visits) -> Option<DateTime<Utc>> {
+        self.visits.unwrap_or(stored).last_visit
+    }
+    pub fn record_filter_use<'a>(&mut self, filter: &str, tags: impl IntoIterator<Item = &'a str>) {
+        *self.filters.entry(filter.to_owned()).or_default() += 1;
+        for tag in tags {
+            *self.tags.entry(tag.to_owned()).or_default() += 1;
+        }
+    }
+    pub fn filter_uses(&self, filter: &str) -> u64 {
+        self.filters.get(filter).copied().unwrap_or(0)
+    }
+    pub fn tag_uses(&self, tag: &str) -> u64 {
+        self.tags.get(tag).copied().unwrap_or(0)
+    }
+    pub fn view_mode(&self) -> Option<ViewMode> {
+        self.view_mode
+    }
+    pub fn set_view_mode(&mut self, view_mode: Option<ViewMode>) {
+        self.view_mode = view_mode;
+    }
+    pub fn item_grouping(&self) -> Option<ItemGrouping> {
+        self.item_grouping
+    }
+    pub fn set_item_grouping(&mut self, grouping: Option<ItemGrouping>) {
+        self.item_grouping = grouping;
+    }
+}
+
+/// How often filters and tags got used in the UI
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default)]
+pub struct UsageStats {
+    #[serde(default)]
+    filters: BTreeMap<String, u64>,
+    #[serde(default)]
+    tags: BTreeMap<String, u64>,
+    /// Number of items marked as read per day
+    #[serde(default)]
+    reads: BTreeMap<NaiveDate, u64>,
 }
 
-#[derive(Default)]
+#[derive(serde::Deserialize, serde::Serialize, Default)]
 pub struct UserDataStorage {
-    storage: BTreeMap<FeedId, FeedUserData>,
+    #[serde(default)]
+    feeds: BTreeMap<FeedId, FeedUserData>,
+    #[serde(default)]
+    saved_views: BTreeMap<String, SavedView>,
+    #[serde(default)]
+    usage: UsageStats,
+    #[serde(default)]
+    view_mode: ViewMode,
+    #[serde(default)]
+    item_grouping: ItemGrouping,
+    /// Shares by token
+    #[serde(default)]
+    shares: BTreeMap<String, Share>,
+    /// Settings inherited by the feeds with a tag
+    #[serde(default)]
+    tag_settings: BTreeMap<String, FeedOptions>,
+    #[serde(default, skip_serializing_if = "TagSynonyms::is_empty")]
+    tag_synonyms: TagSynonyms,
+    /// Filter of the index page when none is given
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    default_filter: String,
+    #[serde(default)]
+    visits: Visits,
 }
 
 impl UserDataStorage {
-    fn open_user_data(path: &Path) -> std::io::Result<BTreeMap<FeedId, FeedUserData>> {
+    fn open_user_data(path: &Path) -> Result<Self, Error> {
         let user_data_path = path.join("user_data.json");
-        let user_data = std::fs::read_to_string(&user_data_path)
-            .map(|v| serde_json::from_str(&v).expect("could not parse user_data"))?;
+        let user_data = std::fs::read_to_string(&user_data_path)?;
+        let parse_error = |e: serde_json::Error| {
+            Error::InvalidStorage(format!("could not parse user_data.json: {}", e))
+        };
+        let user_data: serde_json::Value = serde_json::from_str(&user_data).map_err(parse_error)?;
+
+        // Older versions stored only the map of feeds
+        let user_data = if user_data.get("feeds").is_some() {
+            serde_json::from_value(user_data).map_err(parse_error)?
+        } else {
+            Self {
+                feeds: serde_json::from_value(user_data).map_err(parse_error)?,
+                ..Self::default()
+            }
+        };
 
         Ok(user_data)
     }
 
-    pub fn open_or_default(storage_path: &Path) -> Self {
-        Self {
-            storage: Self::open_user_data(storage_path).unwrap_or_default(),
+    /// The default only if there is no user data yet. Other errors are
+    /// returned, so the next save doesn't overwrite the data.
+    pub fn open_or_default(storage_path: &Path) -> Result<Self, Error> {
+        match Self::open_user_data(storage_path) {
+            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            result => result,
         }
     }
     pub fn save(&self, path: &Path) {
         let user_data_path = path.join("user_data.json");
-        crate::safe_save_json(&self.storage, &user_data_path, "user_data", true);
+        crate::safe_save_json(self, &user_data_path, "user_data", true);
+    }
+    pub fn stage(&self, tx: &mut Transaction, path: &Path) -> std::io::Result<()> {
+        tx.stage_json(self, &path.join("user_data.json"), None)
+    }
+    pub fn saved_views(&self) -> &BTreeMap<String, SavedView> {
+        &self.saved_views
+    }
+    pub fn save_view(&mut self, name: String, view: SavedView) {
+        self.saved_views.insert(name, view);
+    }
+    pub fn remove_view(&mut self, name: &str) -> bool {
+        self.saved_views.remove(name).is_some()
+    }
+    pub fn shares(&self) -> &BTreeMap<String, Share> {
+        &self.shares
+    }
+    pub fn tag_settings(&self) -> &BTreeMap<String, FeedOptions> {
+        &self.tag_settings
+    }
+    pub fn set_tag_settings(&mut self, tag: &str, options: FeedOptions) {
+        if options.is_empty() {
+            self.tag_settings.remove(tag);
+        } else {
+            self.tag_settings.insert(tag.to_owned(), options);
+        }
+    }
+    pub fn tag_synonyms(&self) -> &TagSynonyms {
+        &self.tag_synonyms
+    }
+    pub fn tag_synonyms_mut(&mut self) -> &mut TagSynonyms {
+        &mut self.tag_synonyms
+    }
+    /// Stores the share under a new random token of 128 bits, which is
+    /// returned
+    pub fn create_share(&mut self, share: Share) -> String {
+        use rand::RngCore;
+        let token = loop {
+            let mut bytes = [0u8; 16];
+            rand::rngs::OsRng.fill_bytes(&mut bytes);
+            let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            if !self.shares.contains_key(&token) {
+                break token;
+            }
+        };
+        self.shares.insert(token.clone(), share);
+        token
+    }
+    pub fn revoke_share(&mut self, token: &str) -> bool {
+        self.shares.remove(token).is_some()
+    }
+    pub fn view_mode(&self) -> ViewMode {
+        self.view_mode
+    }
+    pub fn set_view_mode(&mut self, view_mode: ViewMode) {
+        self.view_mode = view_mode;
+    }
+    pub fn item_grouping(&self) -> ItemGrouping {
+        self.item_grouping
+    }
+    pub fn set_item_grouping(&mut self, grouping: ItemGrouping) {
+        self.item_grouping = grouping;
+    }
+    pub fn default_filter(&self) -> &str {
+        &self.default_filter
+    }
+    pub fn set_default_filter(&mut self, filter: &str) {
+        self.default_filter = filter.trim().to_owned();
+    }
+    pub fn visits(&self) -> Visits {
+        self.visits
+    }
+    /// Takes over the usage noted by page loads
+    pub fn merge_usage(&mut self, pending: PendingUsage) {
+        if let Some(visits) = pending.visits {
+            self.visits = visits;
+        }
+        for (filter, uses) in pending.filters {
+            *self.usage.filters.entry(filter).or_default() += uses;
+        }
+        for (tag, uses) in pending.tags {
+            *self.usage.tags.entry(tag).or_default() += uses;
+        }
+        if let Some(view_mode) = pending.view_mode {
+            self.view_mode = view_mode;
+        }
+        if let Some(grouping) = pending.item_grouping {
+            self.item_grouping = grouping;
+        }
+    }
+    pub fn filter_uses(&self, filter: &str) -> u64 {
+        self.usage.filters.get(filter).copied().unwrap_or(0)
+    }
+    pub fn tag_uses(&self, tag: &str) -> u64 {
+        self.usage.tags.get(tag).copied().unwrap_or(0)
+    }
+    /// Forgets the read and hidden items of a removed feed
+    pub fn remove_feed(&mut self, feed_id: &FeedId) {
+        self.feeds.remove(feed_id);
+    }
+    pub fn mark_read(&mut self, feed_id: &FeedId, item_id: usize, read_at: DateTime<Utc>) {
+        let read = &mut self.feeds.entry(feed_id.clone()).or_default().read_at;
+        if !read.contains_key(&item_id) {
+            read.insert(item_id, Some(read_at));
+            *self.usage.reads.entry(read_at.date_naive()).or_default() += 1;
+        }
+    }
+    /// Like `mark_read`, but for read state synced from elsewhere, which
+    /// does not count as reading today. Returns true if the item was unread.
+    pub fn merge_read(&mut self, feed_id: &FeedId, item_id: usize) -> bool {
+        let read = &mut self.feeds.entry(feed_id.clone()).or_default().read_at;
+        if read.contains_key(&item_id) {
+            return false;
+        }
+        read.insert(item_id, None);
+        true
+    }
+    /// All reads with a known time, the most recent first
+    pub fn recent_reads(&self) -> Vec<(DateTime<Utc>, &FeedId, usize)> {
+        let mut reads: Vec<_> = self
+            .feeds
+            .iter()
+            .flat_map(|(feed_id, data)| {
+                data.read_at
+                    .iter()
+                    .filter_map(move |(item_id, read_at)| Some(((*read_at)?, feed_id, *item_id)))
+            })
+            .collect();
+        reads.sort_by(|a, b| b.0.cmp(&a.0));
+        reads
+    }
+    pub fn reads_on(&self, date: NaiveDate) -> u64 {
+        self.usage.reads.get(&date).copied().unwrap_or(0)
+    }
+    pub fn mark_unread(&mut self, feed_id: &FeedId, item_id: usize) {
+        if let Some(user_data) = self.feeds.get_mut(feed_id) {
+            user_data.read_at.remove(&item_id);
+        }
+    }
+    pub fn is_read(&self, feed_id: &FeedId, item_id: usize) -> bool {
+        self.feeds
+            .get(feed_id)
+            .map(|v| v.read_at.contains_key(&item_id))
+            .unwrap_or(false)
     }
-    pub fn mark_read(&mut self, feed_id: &FeedId, item_id: usize) {
-        self.storage
+    pub fn hide(&mut self, feed_id: &FeedId, item_id: usize) {
+        self.feeds
             .entry(feed_id.clone())
             .or_default()
-            .read_ids
+            .hidden_ids
             .insert(item_id);
     }
-    pub fn is_read(&self, feed_id: &FeedId, item_id: usize) -> bool {
-        self.storage
+    pub fn unhide(&mut self, feed_id: &FeedId, item_id: usize) {
+        if let Some(user_data) = self.feeds.get_mut(feed_id) {
+            user_data.hidden_ids.remove(&item_id);
+        }
+    }
+    pub fn is_hidden(&self, feed_id: &FeedId, item_id: usize) -> bool {
+        self.feeds
             .get(feed_id)
-            .map(|v| v.read_ids.contains(&item_id))
+            .map(|v| v.hidden_ids.contains(&item_id))
             .unwrap_or(false)
     }
+    /// Looks up the data of the feed once, to get the state of many items
+    pub fn item_states(&self, feed_id: &FeedId) -> impl Fn(usize) -> ItemState + '_ {
+        let data = self.feeds.get(feed_id);
+        move |item_id| match data {
+            Some(data) => ItemState {
+                read: data.read_at.contains_key(&item_id),
+                hidden: data.hidden_ids.contains(&item_id),
+            },
+            None => ItemState::default(),
+        }
+    }
+}
+
+#[test]
+fn test_read_at_migration() {
+    let old: FeedUserData = serde_json::from_str(r#"{"read_ids": [1, 3]}"#).unwrap();
+    assert_eq!(
+        old.read_at.into_iter().collect::<Vec<_>>(),
+        vec![(1, None), (3, None)]
+    );
+
+    let mut new = FeedUserData::default();
+    new.read_at.insert(2, Some(Utc::now()));
+    new.read_at.insert(5, None);
+    let json = serde_json::to_string(&new).unwrap();
+    let reloaded: FeedUserData = serde_json::from_str(&json).unwrap();
+    assert_eq!(reloaded.read_at, new.read_at);
+}
+
+#[test]
+fn test_visits() {
+    let start = Utc::now();
+    let mins = chrono::Duration::minutes;
+    let mut visits = Visits::default();
+    assert!(visits.record(start));
+    assert_eq!(visits.last_visit, None);
+    // Page loads close together belong to the same visit
+    assert!(!visits.record(start + mins(10)));
+    assert!(!visits.record(start + mins(35)));
+    assert_eq!(visits.last_visit, None);
+    assert!(visits.record(start + mins(120)));
+    assert_eq!(visits.last_visit, Some(start + mins(35)));
+}
+
+#[test]
+fn test_open_user_data_errors() {
+    let path = std::env::temp_dir().join(format!(
+        "feed-bouncer-test-user-data-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&path).unwrap();
+    assert!(UserDataStorage::open_or_default(&path).is_ok());
+
+    std::fs::write(path.join("user_data.json"), "{\"feeds\": 1}").unwrap();
+    let result = UserDataStorage::open_or_default(&path);
+    std::fs::remove_dir_all(&path).unwrap();
+    assert!(matches!(result, Err(Error::InvalidStorage(_))));
 }
@@ -0,0 +1,207 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    database::{storage_feed::Feed, Database, FeedId},
+    fetch::ParseFailure,
+};
+
+/// Larger payloads get truncated before they are stored
+const MAX_PAYLOAD_SIZE: usize = 1024 * 1024;
+/// Only the most recent failures of each feed are kept
+const MAX_PAYLOADS_PER_FEED: usize = 5;
+
+/// A feed body that could not be parsed, kept to report and reproduce
+/// parser bugs.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+pub struct FailedPayload {
+    #[serde(skip)]
+    pub feed_id: FeedId,
+    /// Name of the stored payload, unique per feed
+    #[serde(skip)]
+    pub name: String,
+    pub url: String,
+    pub fetched_at: DateTime<Utc>,
+    pub errors: Vec<String>,
+    pub size: usize,
+    pub truncated: bool,
+}
+
+/// A feed whose source could not be reached, until it is fetched again.
+/// Only kept in memory, the next update after a restart tries again anyway.
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct FetchFailure {
+    pub error: String,
+    pub first_failed_at: DateTime<Utc>,
+    pub last_failed_at: DateTime<Utc>,
+    /// Updates and retries that failed in a row
+    pub attempts: u32,
+    /// The retries gave up, the feed waits for the next regular update
+    pub escalated: bool,
+}
+
+fn payloads_path(storage_path: &Path) -> PathBuf {
+    storage_path.join("failed_payloads")
+}
+
+/// Payload names and feed ids end up in paths, so only allow plain names
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub(crate) fn save_failed_payload(storage_path: &Path, feed_id: &FeedId, failure: ParseFailure) {
+    if !is_plain_name(feed_id) {
+        return;
+    }
+    let feed_path = payloads_path(storage_path).join(feed_id);
+    if let Err(e) = std::fs::create_dir_all(&feed_path) {
+        eprintln!("WARN: could not create {:?}: {}", feed_path, e);
+        return;
+    }
+
+    let fetched_at = Utc::now();
+    let name = fetched_at.format("%Y%m%d-%H%M%S-%3f").to_string();
+    let mut body = failure.body;
+    let size = body.len();
+    body.truncate(MAX_PAYLOAD_SIZE);
+    let meta = FailedPayload {
+        feed_id: feed_id.clone(),
+        name: name.clone(),
+        url: failure.url,
+        fetched_at,
+        errors: failure.errors,
+        size,
+        truncated: size > MAX_PAYLOAD_SIZE,
+    };
+
+    if let Err(e) = std::fs::write(feed_path.join(&name).with_extension("body"), &body) {
+        eprintln!("WARN: could not save failed payload: {}", e);
+        return;
+    }
+    crate::safe_save_json(
+        &meta,
+        &feed_path.join(&name).with_extension("json"),
+        "failed payload",
+        true,
+    );
+
+    // Rotate out the oldest payloads, names sort by time
+    let mut names = payload_names(&feed_path);
+    names.sort();
+    while names.len() > MAX_PAYLOADS_PER_FEED {
+        let name = names.remove(0);
+        std::fs::remove_file(feed_path.join(&name).with_extension("body")).ok();
+        std::fs::remove_file(feed_path.join(&name).with_extension("json")).ok();
+    }
+}
+
+fn payload_names(feed_path: &Path) -> Vec<String> {
+    let entries = match std::fs::read_dir(feed_path) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.extension().map(|ext| ext == "json").unwrap_or(false))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_owned()))
+        .filter(|name| !name.ends_with(".new"))
+        .collect()
+}
+
+impl Database {
+    /// All stored payloads that failed to parse, newest first.
+    pub fn failed_payloads(&self) -> Vec<FailedPayload> {
+        let path = payloads_path(&self.storage_path);
+        let mut payloads = Vec::new();
+        for (feed_id, _) in self.storage.iter() {
+            let feed_path = path.join(feed_id);
+            for name in payload_names(&feed_path) {
+                let meta = std::fs::read_to_string(feed_path.join(&name).with_extension("json"))
+                    .ok()
+                    .and_then(|v| serde_json::from_str::<FailedPayload>(&v).ok());
+                if let Some(mut meta) = meta {
+                    meta.feed_id = feed_id.clone();
+                    meta.name = name;
+                    payloads.push(meta);
+                }
+            }
+        }
+        payloads.sort_by(|a, b| b.fetched_at.cmp(&a.fetched_at));
+        payloads
+    }
+
+    pub fn failed_payload_body(&self, feed_id: &FeedId, name: &str) -> Option<Vec<u8>> {
+        if !is_plain_name(feed_id) || !is_plain_name(name) {
+            return None;
+        }
+        let path = payloads_path(&self.storage_path)
+            .join(feed_id)
+            .join(name)
+            .with_extension("body");
+        std::fs::read(path).ok()
+    }
+}
+
+impl Database {
+    pub(crate) fn record_fetch_failure(&mut self, feed_id: &FeedId, error: String) {
+        let now = Utc::now();
+        let failure = self
+            .fetch_failures
+            .entry(feed_id.clone())
+            .or_insert_with(|| FetchFailure {
+                error: String::new(),
+                first_failed_at: now,
+                last_failed_at: now,
+                attempts: 0,
+                escalated: false,
+            });
+        failure.error = error;
+        failure.last_failed_at = now;
+        failure.attempts += 1;
+        // A new failure starts a new round of retries
+        failure.escalated = false;
+    }
+
+    /// Marks the failures of feeds that the retries gave up on, so they
+    /// show up as needing attention
+    pub fn escalate_fetch_failures(&mut self, feed_ids: &[FeedId]) {
+        for feed_id in feed_ids {
+            if let Some(failure) = self.fetch_failures.get_mut(feed_id) {
+                failure.escalated = true;
+            }
+        }
+    }
+
+    /// Feeds that could not be fetched since their last successful update,
+    /// most attempts first
+    pub fn fetch_failures(&self) -> Vec<(&FeedId, &Feed, &FetchFailure)> {
+        let mut failures: Vec<_> = self
+            .fetch_failures
+            .iter()
+            .filter_map(|(feed_id, failure)| Some((feed_id, self.storage.get(feed_id)?, failure)))
+            .collect();
+        failures.sort_by_key(|(_, _, failure)| std::cmp::Reverse(failure.attempts));
+        failures
+    }
+}
+
+#[test]
+fn test_new_fetch_failure_clears_escalation() {
+    let mut db = Database::in_memory();
+    let feed_id = db.insert(Feed::new("Feed".to_owned()));
+
+    db.record_fetch_failure(&feed_id, "timeout".to_owned());
+    db.escalate_fetch_failures(&[feed_id.clone()]);
+    assert!(db.fetch_failures()[0].2.escalated);
+
+    db.record_fetch_failure(&feed_id, "connection refused".to_owned());
+    let (_, _, failure) = db.fetch_failures()[0];
+    assert!(!failure.escalated);
+    assert_eq!(failure.attempts, 2);
+    assert_eq!(failure.error, "connection refused");
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::database::{storage_feed::Feed, Database, FeedId};
+
+/// Settings of a feed that can also be set for a tag, so all feeds with the
+/// tag share them. Unset fields are inherited.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeedOptions {
+    /// Minimum time between two fetches of the feed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_interval_mins: Option<u64>,
+    /// Items older than this are not shown anymore
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention_days: Option<u64>,
+    /// Keep the items out of the unfiltered index
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mute_from_index: Option<bool>,
+    /// Show the main content of the linked article instead of the content
+    /// of the feed, for feeds that only publish summaries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_content: Option<bool>,
+    /// Sent instead of the configured user agent, for hosts that block it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+impl FeedOptions {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The settings that apply to a feed, see [`Feed::effective_settings`].
+#[derive(serde::Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EffectiveSettings {
+    /// `None` fetches the feed on every update
+    pub update_interval_mins: Option<u64>,
+    /// `None` keeps showing all items
+    pub retention_days: Option<u64>,
+    pub mute_from_index: bool,
+    pub full_content: bool,
+    /// `None` uses the user agent of the http config
+    pub user_agent: Option<String>,
+}
+
+impl EffectiveSettings {
+    /// Takes each setting from the first layer that sets it
+    fn resolve<'a>(layers: impl IntoIterator<Item = &'a FeedOptions>) -> Self {
+        let mut options = FeedOptions::default();
+        for layer in layers {
+            options.update_interval_mins =
+                options.update_interval_mins.or(layer.update_interval_mins);
+            options.retention_days = options.retention_days.or(layer.retention_days);
+            options.mute_from_index = options.mute_from_index.or(layer.mute_from_index);
+            options.full_content = options.full_content.or(layer.full_content);
+            options.user_agent = options.user_agent.or_else(|| layer.user_agent.clone());
+        }
+        Self {
+            update_interval_mins: options.update_interval_mins,
+            retention_days: options.retention_days,
+            mute_from_index: options.mute_from_index.unwrap_or(false),
+            full_content: options.full_content.unwrap_or(false),
+            user_agent: options.user_agent,
+        }
+    }
+
+    /// Whether the feed should be fetched, given when it was fetched last
+    pub fn is_due(&self, last_fetched_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+        match (self.update_interval_mins, last_fetched_at) {
+            (Some(mins), Some(last)) => now - last >= Duration::minutes(mins as i64),
+            _ => true,
+        }
+    }
+
+    /// The oldest date of items that are still shown
+    pub fn retention_cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.retention_days
+            .map(|days| now - Duration::days(days as i64))
+    }
+}
+
+impl Feed {
+    /// The settings of the feed itself, falling back to those of its tags in
+    /// alphabetical order, and then to the defaults.
+    pub fn effective_settings(&self, db: &Database) -> EffectiveSettings {
+        let tag_settings = db.tag_settings();
+        EffectiveSettings::resolve(
+            std::iter::once(self.options())
+                .chain(self.tags().filter_map(|tag| tag_settings.get(tag))),
+        )
+    }
+}
+
+impl Database {
+    pub fn tag_settings(&self) -> &BTreeMap<String, FeedOptions> {
+        self.user_data_storage.tag_settings()
+    }
+    /// Empty settings remove the entry of the tag
+    pub fn set_tag_settings(&mut self, tag: &str, options: FeedOptions) {
+        self.user_data_storage.set_tag_settings(tag, options);
+        self.sync_full_contents();
+    }
+    pub fn set_feed_options(&mut self, feed_id: &FeedId, options: FeedOptions) -> bool {
+        match self.storage.get_mut(feed_id) {
+            Some(feed) => {
+                feed.set_options(options);
+                self.sync_full_contents();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[test]
+fn test_resolve_settings() {
+    let feed = FeedOptions {
+        retention_days: Some(7),
+        ..FeedOptions::default()
+    };
+    let podcast = FeedOptions {
+        update_interval_mins: Some(360),
+        retention_days: Some(30),
+        mute_from_index: Some(true),
+        full_content: None,
+        user_agent: Some("podcast-client".to_owned()),
+    };
+    let video = FeedOptions {
+        update_interval_mins: Some(60),
+        ..FeedOptions::default()
+    };
+
+    let settings = EffectiveSettings::resolve([&feed, &podcast, &video]);
+    assert_eq!(
+        settings,
+        EffectiveSettings {
+            update_interval_mins: Some(360),
+            retention_days: Some(7),
+            mute_from_index: true,
+            full_content: false,
+            user_agent: Some("podcast-client".to_owned()),
+        }
+    );
+    assert_eq!(
+        EffectiveSettings::resolve([&FeedOptions::default()]),
+        EffectiveSettings::default()
+    );
+}
+
+#[test]
+fn test_is_due() {
+    let now = Utc::now();
+    let settings = EffectiveSettings {
+        update_interval_mins: Some(60),
+        ..EffectiveSettings::default()
+    };
+    assert!(settings.is_due(None, now));
+    assert!(settings.is_due(Some(now - Duration::minutes(61)), now));
+    assert!(!settings.is_due(Some(now - Duration::minutes(30)), now));
+    assert!(EffectiveSettings::default().is_due(Some(now), now));
+}
@@ -0,0 +1,48 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+pub trait FeedConvert {
+    type Output;
+    fn convert(self) -> Self::Output;
+}
+
+impl<K, V> FeedConvert for HashMap<K, V>
+where
+    V: FeedConvert,
+    K: Hash + Ord,
+{
+    type Output = BTreeMap<K, V::Output>;
+    fn convert(self) -> Self::Output {
+        self.into_iter().map(|(k, v)| (k, v.convert())).collect()
+    }
+}
+
+impl<T> FeedConvert for Vec<T>
+where
+    T: FeedConvert,
+{
+    type Output = Vec<T::Output>;
+    fn convert(self) -> Self::Output {
+        self.into_iter().map(|v| v.convert()).collect()
+    }
+}
+
+impl FeedConvert for String {
+    type Output = String;
+
+    fn convert(self) -> Self::Output {
+        self
+    }
+}
+
+impl<T> FeedConvert for Option<T>
+where
+    T: FeedConvert,
+{
+    type Output = Option<T::Output>;
+    fn convert(self) -> Self::Output {
+        self.map(|v| v.convert())
+    }
+}
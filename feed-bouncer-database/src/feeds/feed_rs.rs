@@ -1,7 +1,9 @@
 use std::time::Duration;
 
+#[cfg(feature = "feed-rs")]
 use super::FeedConvert;
 use chrono::{DateTime, Utc};
+#[cfg(feature = "feed-rs")]
 use feed_rs::model::Feed;
 
 /// Type of a feed (RSS, Atom etc)
@@ -13,6 +15,7 @@ pub enum FeedType {
     RSS1,
     RSS2,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::FeedType {
     type Output = FeedType;
 
@@ -35,6 +38,7 @@ pub struct Text {
     pub src: Option<String>,
     pub content: String,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::Text {
     type Output = Text;
 
@@ -62,6 +66,7 @@ pub struct Person {
     /// Atom: An email address for the person.
     pub email: Option<String>,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::Person {
     type Output = Person;
 
@@ -92,6 +97,7 @@ pub struct Link {
     /// The length of the resource, in bytes.
     pub length: Option<u64>,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::Link {
     type Output = Link;
 
@@ -131,6 +137,7 @@ pub struct Category {
     /// Atom (optional): Provides a human-readable label for display.
     pub label: Option<String>,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::Category {
     type Output = Category;
 
@@ -161,6 +168,7 @@ pub struct Generator {
     /// Atom: Tool version
     pub version: Option<String>,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::Generator {
     type Output = Generator;
 
@@ -201,6 +209,7 @@ pub struct Image {
     /// RSS 2 (optional): contains text that is included in the TITLE attribute of the link formed around the image in the HTML rendering.
     pub description: Option<String>,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::Image {
     type Output = Image;
 
@@ -232,6 +241,7 @@ pub struct MediaRating {
     // The rating text
     pub value: String,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::MediaRating {
     type Output = MediaRating;
 
@@ -310,6 +320,7 @@ pub struct FeedHeader {
     pub ttl: Option<u32>,
 }
 
+#[cfg(feature = "feed-rs")]
 impl FeedHeader {
     pub fn split(feed: Feed) -> (FeedHeader, Vec<Entry>) {
         let Feed {
@@ -382,6 +393,7 @@ pub struct Content {
     /// * RSS 2.0: where the enclosure is located
     pub src: Option<Link>,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::Content {
     type Output = Content;
 
@@ -419,6 +431,7 @@ pub struct MediaContent {
     /// Rating
     pub rating: Option<MediaRating>,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::MediaContent {
     type Output = MediaContent;
 
@@ -451,6 +464,7 @@ pub struct MediaThumbnail {
     /// The time this thumbnail represents
     pub time: Option<Duration>,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::MediaThumbnail {
     type Output = MediaThumbnail;
 
@@ -473,6 +487,7 @@ pub struct MediaText {
     /// The end time that the text is relevant. If this attribute is not provided, and a start time is used, it is expected that the end time is either the end of the clip or the start of the next <media:text> element.
     pub end_time: Option<Duration>,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::MediaText {
     type Output = MediaText;
 
@@ -503,6 +518,7 @@ pub struct MediaCommunity {
     pub stats_views: Option<u64>,
     pub stats_favorites: Option<u64>,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::MediaCommunity {
     type Output = MediaCommunity;
 
@@ -532,6 +548,7 @@ pub struct MediaCredit {
     /// The entity being credited
     pub entity: String,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::MediaCredit {
     type Output = MediaCredit;
 
@@ -562,6 +579,7 @@ pub struct MediaObject {
     /// Credits
     pub credits: Vec<MediaCredit>,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::MediaObject {
     type Output = MediaObject;
 
@@ -655,6 +673,7 @@ pub struct Entry {
     /// See the Atom tests for youtube and newscred for examples
     pub media: Vec<MediaObject>,
 }
+#[cfg(feature = "feed-rs")]
 impl FeedConvert for feed_rs::model::Entry {
     type Output = Entry;
 
@@ -1,8 +1,10 @@
 use std::collections::BTreeMap;
 
+#[cfg(any(feature = "rss", feature = "fetch"))]
+use rss::Channel;
 use rss::{
     extension::{atom, dublincore, itunes, syndication, ExtensionMap},
-    Category, Channel, Cloud, Image, TextInput,
+    Category, Cloud, Image, TextInput,
 };
 
 pub use rss::Item;
@@ -61,6 +63,8 @@ pub struct ChannelHeader {
     pub namespaces: BTreeMap<String, String>,
 }
 
+/// Directory sources build their header from a channel as well
+#[cfg(any(feature = "rss", feature = "fetch"))]
 impl ChannelHeader {
     pub fn split(channel: Channel) -> (Self, Vec<Item>) {
         let Channel {
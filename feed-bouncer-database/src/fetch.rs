@@ -0,0 +1,1410 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+
+#[cfg(feature = "feed-rs")]
+use ::feed_rs::model::Feed as FeedRs;
+#[cfg(feature = "rss")]
+use ::rss::Channel;
+
+#[cfg(feature = "rss")]
+use crate::feeds::rss::ChannelHeader;
+use crate::{
+    config::{Blocklist, HttpConfig},
+    credentials::FeedCredentials,
+    database::{
+        journal::JournalEntry,
+        storage_feed::SourceKind,
+        storage_feed_header::FeedHeader,
+        storage_feed_item::{FeedItem, FeedItemOrder, ItemSource},
+        Database, FeedId,
+    },
+    source::{SourceError, SourceFetch, SourceRequest},
+    Feed, FeedItemMeta,
+};
+
+#[cfg(not(any(feature = "rss", feature = "feed-rs")))]
+compile_error!("the `fetch` feature requires at least one of the `rss` or `feed-rs` features");
+
+/// Builds the HTTP client shared by all downloads. Invalid settings are
+/// reported and fall back to a default client, so updates keep working.
+pub(crate) fn build_client(config: &HttpConfig, storage_path: &Path) -> reqwest::Client {
+    let build = || -> reqwest::Result<reqwest::Client> {
+        // Redirects are followed by `download`, to notice permanent ones
+        let mut builder = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none());
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        for path in &config.root_certificates {
+            let path = storage_path.join(path);
+            match std::fs::read(&path) {
+                Ok(pem) => {
+                    builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
+                }
+                Err(e) => eprintln!("WARN: could not read certificate {:?}: {}", path, e),
+            }
+        }
+        if let Some(timeout_secs) = config.timeout_secs {
+            builder = builder.timeout(Duration::from_secs(timeout_secs));
+        }
+        builder = builder.user_agent(config.user_agent());
+        builder.build()
+    };
+
+    build().unwrap_or_else(|e| {
+        eprintln!("WARN: invalid http config, using defaults: {}", e);
+        reqwest::Client::new()
+    })
+}
+
+/// Downloads are attempted this often before a feed is skipped
+pub(crate) const MAX_DOWNLOAD_ATTEMPTS: usize = 6;
+
+/// Feeds that an update could not fetch get retried this often before they
+/// wait for the next update
+pub const UPDATE_RETRY_ATTEMPTS: usize = 5;
+/// The delay before the first retry, doubled for each following one. All
+/// retries are done within 31 minutes.
+pub const FIRST_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+struct FeedTask {
+    feed_id: FeedId,
+    feed_url: String,
+    known_keys: HashSet<ItemKey>,
+    known_identities: HashSet<String>,
+    name: String,
+    /// Number of archive pages to follow, only set on the first fetch
+    archive_depth: usize,
+    /// The cutoff of the item age the first fetch stores with the feed
+    published_after: Option<DateTime<Utc>>,
+    item_order: FeedItemOrder,
+    source: SourceKind,
+    /// Replaces the user agent of the client
+    user_agent: Option<String>,
+    credentials: Option<FeedCredentials>,
+    max_response_bytes: u64,
+}
+
+/// What [`Database::commit_from`] changed.
+#[derive(serde::Serialize, Clone, Debug, Default)]
+pub struct CommitSummary {
+    pub seq_no: u64,
+    pub committed_at: DateTime<Utc>,
+    /// Only some feeds were updated
+    pub partial: bool,
+    pub fetched_feeds: usize,
+    /// Feeds that could not be fetched
+    pub unavailable: Vec<FeedId>,
+    /// Only the feeds that changed
+    pub feeds: Vec<FeedCommitSummary>,
+    /// Items of all feeds after the update
+    pub total_items: usize,
+}
+
+#[derive(serde::Serialize, Clone, Debug, Default)]
+pub struct FeedCommitSummary {
+    pub feed_id: FeedId,
+    pub name: String,
+    pub new_items: usize,
+    /// Stored items the publisher changed
+    pub updated_items: usize,
+    pub new_headers: usize,
+}
+
+impl FeedCommitSummary {
+    fn has_changes(&self) -> bool {
+        self.new_items > 0 || self.updated_items > 0 || self.new_headers > 0
+    }
+}
+
+impl CommitSummary {
+    pub fn new_items(&self) -> usize {
+        self.feeds.iter().map(|feed| feed.new_items).sum()
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "fetched {} feeds, {} new items in {} feeds, {} feeds unavailable, {} items in total",
+            self.fetched_feeds,
+            self.new_items(),
+            self.feeds.iter().filter(|feed| feed.new_items > 0).count(),
+            self.unavailable.len(),
+            self.total_items
+        )
+    }
+}
+
+pub struct UpdateFeedsTask {
+    client: reqwest::Client,
+    feeds: Vec<FeedTask>,
+    seq_no: u64,
+    /// Only some feeds get updated
+    partial: bool,
+    progress: Arc<UpdateProgress>,
+}
+
+/// How far a running update got, shared between the task and whoever
+/// wants to show it. See [`UpdateFeedsTask::with_progress`].
+#[derive(Debug, Default)]
+pub struct UpdateProgress {
+    running: AtomicBool,
+    /// A full update runs or waits for a partial one to end
+    full: AtomicBool,
+    total: AtomicUsize,
+    completed: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+/// The state of an [`UpdateProgress`] at one point in time
+#[derive(serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UpdateProgressSnapshot {
+    pub running: bool,
+    /// Feeds the update fetches
+    pub total: usize,
+    /// Feeds that are done, including the failed ones
+    pub completed: usize,
+    pub failed: usize,
+}
+
+impl UpdateProgress {
+    pub fn snapshot(&self) -> UpdateProgressSnapshot {
+        UpdateProgressSnapshot {
+            running: self.running.load(Ordering::Relaxed),
+            total: self.total.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+    /// Reserves the progress for a full update, false if another one runs
+    /// or waits already. Partial updates don't start until
+    /// `end_full_update` is called, so small retries can't hold off the full
+    /// update for long.
+    pub fn queue_full_update(&self) -> bool {
+        !self.full.swap(true, Ordering::Relaxed)
+    }
+    pub fn end_full_update(&self) {
+        self.full.store(false, Ordering::Relaxed);
+    }
+    /// Claims the progress for an update, false if one is running already,
+    /// or for a `partial` one if a full update waits. The claim ends with
+    /// the `run` of a task that reports to it.
+    pub fn try_start(&self, partial: bool) -> bool {
+        if partial && self.full.load(Ordering::Relaxed) {
+            return false;
+        }
+        self.running
+            .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    }
+    fn start(&self, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+        self.completed.store(0, Ordering::Relaxed);
+        self.failed.store(0, Ordering::Relaxed);
+        self.running.store(true, Ordering::Relaxed);
+    }
+    fn feed_done(&self, failed: bool) {
+        if failed {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+        self.completed.fetch_add(1, Ordering::Relaxed);
+    }
+    fn finish(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Ends the update of an [`UpdateProgress`] when dropped, also if the task
+/// panics or gets cancelled
+struct Running<'a>(&'a UpdateProgress);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+impl UpdateFeedsTask {
+    /// Reports the progress of `run` to `progress`
+    pub fn with_progress(mut self, progress: Arc<UpdateProgress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub async fn run(self) -> UpdateFeedsTaskResult {
+        let mut results = HashMap::new();
+        let mut unavailable = Vec::new();
+        let progress = self.progress;
+        progress.start(self.feeds.len());
+        let _running = Running(&progress);
+
+        for mut feed in self.feeds {
+            let request = SourceRequest {
+                location: &feed.feed_url,
+                name: &feed.name,
+                archive_depth: feed.archive_depth,
+                user_agent: feed.user_agent.as_deref(),
+                credentials: feed.credentials.as_ref(),
+                max_response_bytes: feed.max_response_bytes,
+            };
+            let fetched = feed.source.source(&self.client).fetch(&request).await;
+            let SourceFetch {
+                header,
+                items: mut current_feed_items,
+                moved_to,
+            } = match fetched {
+                Ok(fetched) => fetched,
+                Err(SourceError::Unavailable(e)) => {
+                    eprintln!("WARN: could not fetch {}: {}", feed.feed_url, e);
+                    unavailable.push((feed.feed_id, e));
+                    progress.feed_done(true);
+                    continue;
+                }
+                Err(SourceError::Invalid(failure)) => {
+                    eprintln!("WARN: could not parse {}", feed.feed_url);
+                    results.entry(feed.feed_id).or_default().parse_failure = Some(failure);
+                    progress.feed_done(true);
+                    continue;
+                }
+            };
+            FeedItem::sort(&mut current_feed_items, feed.item_order, |v| v);
+
+            let update: &mut FeedUpdate = results.entry(feed.feed_id).or_default();
+            update.headers.push(header);
+            update.feed_url = feed.feed_url.clone();
+            update.moved_to = moved_to;
+            update.published_after = feed.published_after;
+
+            let mut header = true;
+            for item in current_feed_items {
+                if feed.known_identities.contains(&item.identity()) {
+                    update.known_items.push(item);
+                } else if !feed.known_keys.contains(&item_key(&item)) {
+                    if header {
+                        println!("New entries for [{}]", feed.name);
+                        header = false;
+                    }
+                    println!("  [{}]", item.display_title().unwrap_or(""));
+                    // Archive pages can overlap with each other
+                    feed.known_keys.insert(item_key(&item));
+                    feed.known_identities.insert(item.identity());
+                    update.new_items.push(item);
+                }
+            }
+            progress.feed_done(false);
+        }
+
+        UpdateFeedsTaskResult {
+            results,
+            unavailable,
+            seq_no: self.seq_no,
+            partial: self.partial,
+        }
+    }
+}
+
+#[derive(Default)]
+struct FeedUpdate {
+    headers: Vec<FeedHeader>,
+    /// The url the update was fetched from
+    feed_url: String,
+    new_items: Vec<FeedItem>,
+    /// Items that are already stored, possibly with different content
+    known_items: Vec<FeedItem>,
+    /// The new url if the feed url permanently redirected
+    moved_to: Option<String>,
+    parse_failure: Option<ParseFailure>,
+    /// The item age cutoff of a first fetch
+    published_after: Option<DateTime<Utc>>,
+}
+
+pub struct UpdateFeedsTaskResult {
+    results: HashMap<FeedId, FeedUpdate>,
+    /// Feeds whose source could not be reached, with the error
+    unavailable: Vec<(FeedId, String)>,
+    seq_no: u64,
+    partial: bool,
+}
+
+impl UpdateFeedsTaskResult {
+    /// Feeds that could not be fetched, even after all download attempts
+    pub fn unavailable_feeds(&self) -> Vec<FeedId> {
+        self.unavailable
+            .iter()
+            .map(|(feed_id, _)| feed_id.clone())
+            .collect()
+    }
+}
+
+impl Database {
+    fn feed_task(&self, feed_id: &FeedId, source: &Feed) -> Option<FeedTask> {
+        /*
+        println!(
+            "Prepare to query RSS feed of [{}]...",
+            &source.display_name()
+        );
+        */
+        let feed_url = source.feed_url()?.to_owned();
+        let settings = source.effective_settings(self);
+
+        let mut known_keys = HashSet::new();
+        let mut known_identities = HashSet::new();
+        for item in source.items() {
+            known_keys.insert(item_key(&item.item));
+            known_identities.insert(item.item.identity());
+        }
+        known_identities.extend(source.cold_identities().cloned());
+        // Feeds fetched before are only checked for new items
+        let first_fetch = source.items().is_empty() && source.last_fetched_at().is_none();
+
+        Some(FeedTask {
+            feed_id: feed_id.clone(),
+            feed_url,
+            known_keys,
+            known_identities,
+            name: source.display_name().to_string(),
+            archive_depth: if first_fetch {
+                self.config.updates.archive_depth
+            } else {
+                0
+            },
+            published_after: source
+                .max_initial_age_days()
+                .or(self.config.updates.max_initial_age_days)
+                .filter(|_| first_fetch)
+                .map(|days| Utc::now() - chrono::Duration::days(days as i64)),
+            item_order: source.item_order(),
+            source: source.source(),
+            user_agent: settings.user_agent,
+            credentials: self.credentials.get(feed_id).cloned(),
+            max_response_bytes: self.config.http.response_limit(),
+        })
+    }
+
+    pub fn update_feeds_task(&self) -> UpdateFeedsTask {
+        let now = chrono::Utc::now();
+        let feeds = self
+            .storage
+            .iter()
+            .filter(|(_, source)| {
+                source
+                    .effective_settings(self)
+                    .is_due(source.last_fetched_at(), now)
+            })
+            .filter_map(|(feed_id, source)| self.feed_task(feed_id, source))
+            .collect();
+
+        println!("Prepared query tasks");
+        UpdateFeedsTask {
+            client: self.client.clone(),
+            feeds,
+            seq_no: self.get_update_seq_no(),
+            partial: false,
+            progress: Arc::default(),
+        }
+    }
+
+    /// Like `update_feeds_task`, but only for one feed. `None` if the feed
+    /// does not exist or has no feed url.
+    pub fn update_single_feed_task(&self, feed_id: &FeedId) -> Option<UpdateFeedsTask> {
+        let source = self.storage.get(feed_id)?;
+        let feed = self.feed_task(feed_id, source)?;
+
+        Some(UpdateFeedsTask {
+            client: self.client.clone(),
+            feeds: vec![feed],
+            seq_no: self.get_update_seq_no(),
+            partial: true,
+            progress: Arc::default(),
+        })
+    }
+
+    /// Like `update_feeds_task`, but only for the given feeds, whether they
+    /// are due or not. Used to retry feeds that failed.
+    pub fn retry_feeds_task(&self, feed_ids: &[FeedId]) -> UpdateFeedsTask {
+        let feeds = feed_ids
+            .iter()
+            .filter_map(|feed_id| self.feed_task(feed_id, self.storage.get(feed_id)?))
+            .collect();
+
+        UpdateFeedsTask {
+            client: self.client.clone(),
+            feeds,
+            seq_no: self.get_update_seq_no(),
+            partial: true,
+            progress: Arc::default(),
+        }
+    }
+
+    pub async fn commit_from(&mut self, mut results: UpdateFeedsTaskResult) -> CommitSummary {
+        let now = chrono::Utc::now();
+        let mut summary = CommitSummary {
+            seq_no: results.seq_no,
+            committed_at: now,
+            partial: results.partial,
+            fetched_feeds: results.results.len(),
+            unavailable: results
+                .unavailable
+                .iter()
+                .map(|(feed_id, _)| feed_id.clone())
+                .collect(),
+            ..CommitSummary::default()
+        };
+        for (feed_id, error) in std::mem::take(&mut results.unavailable) {
+            self.record_fetch_failure(&feed_id, error);
+        }
+        for feed_id in results.results.keys() {
+            self.fetch_failures.remove(feed_id);
+        }
+        for (feed_id, update) in &mut results.results {
+            if let Some(failure) = update.parse_failure.take() {
+                if !self.is_read_only() {
+                    crate::diagnostics::save_failed_payload(&self.storage_path, feed_id, failure);
+                }
+            }
+        }
+
+        // Another update may have been committed since this one started. Its
+        // items are recognized below, so nothing gets added twice.
+        if results.seq_no != self.get_update_seq_no() {
+            println!(
+                "Update {} started before update {} got committed, rebasing",
+                results.seq_no,
+                self.get_update_seq_no().saturating_sub(1)
+            );
+        }
+
+        println!("Committing new items, seq_no={}...", results.seq_no);
+        for (feed_id, update) in results.results {
+            let mut changed_ids = Vec::new();
+            let mut feed_summary = FeedCommitSummary {
+                feed_id: feed_id.clone(),
+                ..FeedCommitSummary::default()
+            };
+            let search_index = &mut self.search_index;
+            if let Some(feed) = self.storage.get_mut(&feed_id) {
+                feed.set_last_fetched_at(now);
+                if feed.published_after().is_none() {
+                    feed.set_published_after(update.published_after);
+                }
+                let cutoff = feed.published_after();
+                let mut skipped = 0;
+                // println!("Commit feed of [{}]...", &feed.display_name());
+                let mut header_id = None;
+                for feed_header in update.headers {
+                    let id = match feed.feed_header_id(&feed_header) {
+                        Some(id) => id,
+                        None => {
+                            feed_summary.new_headers += 1;
+                            feed.push_feed_header(feed_header)
+                        }
+                    };
+                    header_id.get_or_insert(id);
+                }
+                let source = ItemSource {
+                    feed_url: update.feed_url,
+                    header_id,
+                };
+                // Whether an item is new is decided again against the stored
+                // items, they may have changed since the fetch
+                let mut positions: HashMap<String, usize> = feed
+                    .items()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| (item.item.identity(), i))
+                    .collect();
+                for feed_item in update.known_items.into_iter().chain(update.new_items) {
+                    let identity = feed_item.identity();
+                    if feed.is_cold(&identity) {
+                        continue;
+                    }
+                    if let Some(&i) = positions.get(&identity) {
+                        let stored = &mut feed.items_mut()[i];
+                        if stored.item != feed_item {
+                            println!(
+                                "Updated entry [{}]",
+                                feed_item.display_title().unwrap_or("")
+                            );
+                            search_index.remove_item(&feed_id, stored.id(), &stored.item);
+                            search_index.add_item(&feed_id, stored.id(), &feed_item);
+                            stored.update(feed_item, now);
+                            changed_ids.push(stored.id());
+                        }
+                        continue;
+                    }
+                    let too_old = cutoff.map_or(false, |cutoff| {
+                        feed_item
+                            .publish_date()
+                            .map_or(false, |date| date.with_timezone(&Utc) < cutoff)
+                    });
+                    if too_old {
+                        skipped += 1;
+                        continue;
+                    }
+                    let item_id = feed.push_item(feed_item);
+                    feed_summary.new_items += 1;
+                    let stored = feed.items_mut().last_mut().unwrap();
+                    stored.set_added(now, results.seq_no);
+                    stored.set_source(source.clone());
+                    search_index.add_item(&feed_id, item_id, &stored.item);
+                    positions.insert(identity, feed.items().len() - 1);
+                }
+                if skipped > 0 {
+                    println!("Skipped {} old items of [{}]", skipped, feed.display_name());
+                }
+                let item_order = feed.item_order();
+                FeedItemMeta::sort(feed.items_mut(), item_order);
+                feed.index_items();
+                feed.update_auto_title_aliases();
+                feed.detect_language();
+                feed_summary.updated_items = changed_ids.len();
+                // An unchanged feed keeps its timeline entries
+                if feed_summary.has_changes() {
+                    self.timeline.refresh_feed(&feed_id, feed);
+                }
+                feed_summary.name = feed.display_name().to_owned();
+            }
+            if feed_summary.has_changes() {
+                summary.feeds.push(feed_summary);
+            }
+            if let Some(moved_to) = &update.moved_to {
+                self.move_feed_url(&feed_id, moved_to);
+            }
+            if self.config.updates.mark_changed_unread {
+                for item_id in changed_ids {
+                    self.user_data_storage.mark_unread(&feed_id, item_id);
+                    self.journal(JournalEntry::MarkUnread {
+                        feed_id: feed_id.clone(),
+                        item_id,
+                    });
+                }
+            }
+        }
+        // The time of the last full update, which single feeds don't count as
+        if !results.partial {
+            self.last_feed_update = Some(now);
+        }
+        // The seq_no of the update stays on its items, to tell which update
+        // added them
+        self.set_update_seq_no(self.get_update_seq_no().max(results.seq_no) + 1);
+        println!("  Done, seq_no={}", self.get_update_seq_no());
+
+        summary.total_items = self.item_count();
+        summary
+    }
+
+    /*
+    pub async fn update_feeds(&mut self) {
+        let tasks = self.update_feeds_task();
+        let results = tasks.run().await;
+        self.commit_from(results).await;
+    }
+    */
+
+    pub async fn import_from_rss(
+        &mut self,
+        url: &str,
+        initial_tags: &[String],
+        max_initial_age_days: Option<u64>,
+    ) -> reqwest::Result<HashSet<FeedId>> {
+        let results = self
+            .import_task(url, initial_tags)
+            .max_initial_age_days(max_initial_age_days)
+            .run()
+            .await;
+        self.commit_import(results)
+    }
+
+    /// Prepares subscribing to a feed, so the download can happen without
+    /// access to the database.
+    pub fn import_task(&self, url: &str, initial_tags: &[String]) -> ImportTask {
+        let mut tags: Vec<String> = initial_tags
+            .iter()
+            .map(|tag| self.tag_synonyms().canonical(tag).to_owned())
+            .collect();
+        for tag in self.config.import.tags_for(url) {
+            let tag = self.tag_synonyms().canonical(tag);
+            if !tags.iter().any(|t| t == tag) {
+                tags.push(tag.to_owned());
+            }
+        }
+        ImportTask {
+            client: self.client.clone(),
+            url: url.to_owned(),
+            initial_tags: tags,
+            max_initial_age_days: None,
+            is_known: self.lookup.check_rss(url).is_some(),
+            blocklist: self.config.import.blocklist.clone(),
+            max_response_bytes: self.config.http.response_limit(),
+        }
+    }
+
+    /// Adds the feed downloaded by an [`ImportTask`], or adds the initial
+    /// tags to the feeds that already have its url. Blocked feeds are not
+    /// added, see [`ImportTaskResult::blocked`].
+    pub fn commit_import(&mut self, result: ImportTaskResult) -> reqwest::Result<HashSet<FeedId>> {
+        let ImportTaskResult {
+            url,
+            initial_tags,
+            max_initial_age_days,
+            download,
+            blocked,
+        } = result;
+        if blocked.is_some() {
+            return Ok(HashSet::new());
+        }
+        if let Some(feed_ids) = self.lookup.check_rss(&url) {
+            for feed_id in feed_ids {
+                let source = self.storage.get_mut(feed_id).unwrap();
+                source.extend_tags(initial_tags.iter().map(|s| &s[..]));
+            }
+            return Ok(feed_ids.clone());
+        }
+        if let Some(channel) = download? {
+            let mut source = Feed::new(channel.title().to_owned());
+            *source.feed_url_mut() = Some(url);
+            source.extend_tags(initial_tags.iter().map(|s| &s[..]));
+            source.set_max_initial_age_days(max_initial_age_days);
+            let feed_id = self.insert(source);
+
+            return Ok(<_>::into_iter([feed_id]).collect());
+        }
+        return Ok(HashSet::new());
+    }
+}
+
+pub struct ImportTask {
+    client: reqwest::Client,
+    url: String,
+    initial_tags: Vec<String>,
+    max_initial_age_days: Option<u64>,
+    /// Known feeds only get the initial tags, without a download
+    is_known: bool,
+    /// Checked for new feeds only
+    blocklist: Blocklist,
+    max_response_bytes: u64,
+}
+
+pub struct ImportTaskResult {
+    url: String,
+    initial_tags: Vec<String>,
+    max_initial_age_days: Option<u64>,
+    download: reqwest::Result<Option<FeedDownload>>,
+    /// Why the feed is on the blocklist
+    blocked: Option<String>,
+}
+
+impl ImportTaskResult {
+    /// Why the feed did not get imported because of the blocklist, `None`
+    /// if it is not blocked
+    pub fn blocked(&self) -> Option<&str> {
+        self.blocked.as_deref()
+    }
+}
+
+impl ImportTask {
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Skips items older than this on the first fetch of the new feed,
+    /// instead of the default of the config
+    pub fn max_initial_age_days(mut self, days: Option<u64>) -> Self {
+        self.max_initial_age_days = days;
+        self
+    }
+
+    /// Downloads the feed, retrying like updates do.
+    pub async fn run(self) -> ImportTaskResult {
+        let mut result = Ok(None);
+        let mut blocked = None;
+        if !self.is_known {
+            // The title is only known after the download
+            blocked = self.blocklist.blocks(Some(&self.url), None);
+            if blocked.is_none() {
+                for _ in 0..MAX_DOWNLOAD_ATTEMPTS {
+                    result = download(&self.client, &self.url, None, None, self.max_response_bytes)
+                        .await;
+                    if result.is_ok() {
+                        break;
+                    }
+                }
+            }
+            if let Ok(Some(channel)) = &result {
+                blocked = self.blocklist.blocks(None, Some(channel.title()));
+            }
+        }
+        if let Some(reason) = &blocked {
+            println!("Skipped {}: {}", self.url, reason);
+        }
+        ImportTaskResult {
+            url: self.url,
+            initial_tags: self.initial_tags,
+            max_initial_age_days: self.max_initial_age_days,
+            download: result,
+            blocked,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FeedUrlError {
+    #[error("not an http or https url")]
+    InvalidUrl,
+    #[error("download failed: {0}")]
+    Download(reqwest::Error),
+    #[error("the url does not serve a feed")]
+    NotAFeed,
+    #[error("the url already belongs to feed {0}")]
+    InUse(FeedId),
+    #[error("unknown feed")]
+    UnknownFeed,
+}
+
+/// Prepares checking that a url serves a feed, before
+/// [`Database::set_feed_url`] makes it the url of an existing feed. The
+/// download can happen without access to the database.
+pub struct FeedUrlCheck {
+    client: reqwest::Client,
+    url: String,
+    max_response_bytes: u64,
+}
+
+pub struct FeedUrlCheckResult {
+    url: String,
+    download: Result<(), FeedUrlError>,
+}
+
+impl FeedUrlCheck {
+    pub async fn run(self) -> FeedUrlCheckResult {
+        let download = match reqwest::Url::parse(self.url.trim()) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {
+                match download(
+                    &self.client,
+                    url.as_str(),
+                    None,
+                    None,
+                    self.max_response_bytes,
+                )
+                .await
+                {
+                    Ok(Some(_)) => Ok(()),
+                    Ok(None) => Err(FeedUrlError::NotAFeed),
+                    Err(e) => Err(FeedUrlError::Download(e)),
+                }
+            }
+            _ => Err(FeedUrlError::InvalidUrl),
+        };
+        FeedUrlCheckResult {
+            url: self.url.trim().to_owned(),
+            download,
+        }
+    }
+}
+
+impl Database {
+    pub fn feed_url_check(&self, url: &str) -> FeedUrlCheck {
+        FeedUrlCheck {
+            client: self.client.clone(),
+            url: url.to_owned(),
+            max_response_bytes: self.config.http.response_limit(),
+        }
+    }
+
+    /// Replaces the url of a feed with one that passed a [`FeedUrlCheck`].
+    /// The old url is kept in the history of the feed.
+    pub fn set_feed_url(
+        &mut self,
+        feed_id: &FeedId,
+        check: FeedUrlCheckResult,
+    ) -> Result<(), FeedUrlError> {
+        check.download?;
+        if self.storage.get(feed_id).is_none() {
+            return Err(FeedUrlError::UnknownFeed);
+        }
+        let other = self
+            .lookup
+            .check_rss(&check.url)
+            .and_then(|feed_ids| feed_ids.iter().find(|other| *other != feed_id));
+        if let Some(other) = other {
+            return Err(FeedUrlError::InUse(other.clone()));
+        }
+        self.move_feed_url(feed_id, &check.url);
+        Ok(())
+    }
+}
+
+impl Database {
+    /// Prepares finding the feeds of a web page, so the download can happen
+    /// without access to the database.
+    pub fn discover_task(&self, url: &str) -> DiscoverTask {
+        DiscoverTask {
+            client: self.client.clone(),
+            url: url.to_owned(),
+            max_response_bytes: self.config.http.response_limit(),
+        }
+    }
+}
+
+pub struct DiscoverTask {
+    client: reqwest::Client,
+    url: String,
+    max_response_bytes: u64,
+}
+
+impl DiscoverTask {
+    /// The feeds to subscribe to for the web page. A feed url is returned as
+    /// is, for other pages the feeds they announce in their head, as
+    /// absolute urls. The page is downloaded once for both.
+    pub async fn run(self) -> Result<Vec<String>, PageError> {
+        let limit = self.max_response_bytes;
+        let (res, _) = get_following_redirects(&self.client, &self.url, None, None).await?;
+        let res = res.error_for_status()?;
+        let page_url = res.url().clone();
+        let html = match parse_feed(res, limit, None).await? {
+            Ok(_) => return Ok(vec![self.url]),
+            Err(failure) if failure.too_large => return Err(PageError::TooLarge(limit)),
+            Err(failure) => failure.body,
+        };
+        let html = String::from_utf8_lossy(&html);
+        Ok(crate::content::feed_links(&html)
+            .iter()
+            .filter_map(|href| page_url.join(href).ok())
+            .map(|url| url.to_string())
+            .collect())
+    }
+}
+
+/// How many recent items of a candidate feed are compared against the
+/// items of existing feeds
+const PREVIEW_SAMPLE_SIZE: usize = 20;
+
+/// A feed that is possibly already followed, found while previewing an import.
+pub struct ImportOverlap<'a> {
+    pub feed_id: &'a FeedId,
+    pub feed: &'a Feed,
+    pub reasons: Vec<String>,
+}
+
+pub struct ImportPreview<'a> {
+    pub title: String,
+    pub site_link: Option<String>,
+    pub item_count: usize,
+    pub overlaps: Vec<ImportOverlap<'a>>,
+}
+
+fn same_link(a: &str, b: &str) -> bool {
+    a.trim_end_matches('/') == b.trim_end_matches('/')
+}
+
+/// Downloads a feed to preview, without access to the database
+pub struct PreviewTask {
+    client: reqwest::Client,
+    url: String,
+    max_response_bytes: u64,
+}
+
+pub struct PreviewTaskResult {
+    url: String,
+    download: reqwest::Result<Option<FeedDownload>>,
+}
+
+impl PreviewTask {
+    pub async fn run(self) -> PreviewTaskResult {
+        let download = download(&self.client, &self.url, None, None, self.max_response_bytes).await;
+        PreviewTaskResult {
+            url: self.url,
+            download,
+        }
+    }
+}
+
+impl Database {
+    /// Prepares downloading a feed without subscribing to it
+    pub fn preview_task(&self, url: &str) -> PreviewTask {
+        PreviewTask {
+            client: self.client.clone(),
+            url: url.to_owned(),
+            max_response_bytes: self.config.http.response_limit(),
+        }
+    }
+
+    /// Summarizes the feed downloaded by a [`PreviewTask`], and looks for
+    /// feeds in the database that likely contain the same content.
+    pub fn preview_import(
+        &self,
+        result: PreviewTaskResult,
+    ) -> Result<ImportPreview<'_>, FeedUrlError> {
+        let url = &result.url;
+        let channel = result
+            .download
+            .map_err(FeedUrlError::Download)?
+            .ok_or(FeedUrlError::NotAFeed)?;
+        let title = channel.title().trim().to_owned();
+        let (header, mut items) = channel.split_header();
+        let site_link = header.site_link().map(str::to_owned);
+        FeedItem::sort(&mut items, FeedItemOrder::Published, |v| v);
+        let item_links: HashSet<String> = items
+            .iter()
+            .rev()
+            .take(PREVIEW_SAMPLE_SIZE)
+            .filter_map(|item| item.content_link().map(str::to_owned))
+            .collect();
+
+        let mut overlaps = Vec::new();
+        for (feed_id, feed) in self.storage.iter() {
+            let mut reasons = Vec::new();
+            if feed.feed_url().map(|u| same_link(u, url)).unwrap_or(false) {
+                reasons.push("same feed url".to_owned());
+            }
+            if !title.is_empty() && feed.titles().any(|t| t.trim().eq_ignore_ascii_case(&title)) {
+                reasons.push("same title".to_owned());
+            }
+            if let (Some(a), Some(b)) = (feed.site_link(), &site_link) {
+                if same_link(a, b) {
+                    reasons.push(format!("same website {}", b));
+                }
+            }
+            let shared_items = feed
+                .items()
+                .iter()
+                .filter_map(|item| item.content_link())
+                .filter(|link| item_links.contains(*link))
+                .count();
+            if shared_items > 0 {
+                reasons.push(format!(
+                    "{} of the recent items are also in this feed",
+                    shared_items
+                ));
+            }
+            if !reasons.is_empty() {
+                overlaps.push(ImportOverlap {
+                    feed_id,
+                    feed,
+                    reasons,
+                });
+            }
+        }
+        overlaps.sort_by_key(|overlap| std::cmp::Reverse(overlap.reasons.len()));
+
+        Ok(ImportPreview {
+            title,
+            site_link,
+            item_count: items.len(),
+            overlaps,
+        })
+    }
+}
+
+type ItemKey = (Option<String>, Option<String>);
+fn item_key(item: &FeedItem) -> ItemKey {
+    match item {
+        FeedItem::Rss(item) => (item.title.clone(), item.pub_date.clone()),
+        FeedItem::FeedRs(item) => (
+            item.title.as_ref().map(|text| text.content.clone()),
+            item.published.as_ref().map(|v| v.to_rfc2822()),
+        ),
+    }
+}
+
+pub enum FeedDownload {
+    #[cfg(feature = "rss")]
+    Rss(Channel),
+    #[cfg(feature = "feed-rs")]
+    Feed(FeedRs),
+}
+
+impl FeedDownload {
+    fn title(&self) -> &str {
+        match self {
+            #[cfg(feature = "rss")]
+            FeedDownload::Rss(x) => &x.title,
+            #[cfg(feature = "feed-rs")]
+            FeedDownload::Feed(x) => x
+                .title
+                .as_ref()
+                .map(|v| v.content.as_str())
+                .unwrap_or_default(),
+        }
+    }
+    /// The link to older entries of an archived or paged feed (RFC 5005),
+    /// resolved against the url of this document.
+    pub(crate) fn archive_link(&self, base_url: &str) -> Option<String> {
+        let links: Vec<(&str, &str)> = match self {
+            #[cfg(feature = "rss")]
+            FeedDownload::Rss(x) => x
+                .atom_ext()
+                .map(|ext| ext.links().iter().map(|l| (l.rel(), l.href())).collect())
+                .unwrap_or_default(),
+            #[cfg(feature = "feed-rs")]
+            FeedDownload::Feed(x) => x
+                .links
+                .iter()
+                .map(|l| (l.rel.as_deref().unwrap_or_default(), &l.href[..]))
+                .collect(),
+        };
+        let href = ["prev-archive", "next"]
+            .iter()
+            .find_map(|rel| links.iter().find(|(link_rel, _)| link_rel == rel))
+            .map(|(_, href)| *href)?;
+        let base = reqwest::Url::parse(base_url).ok()?;
+        base.join(href).ok().map(|url| url.to_string())
+    }
+    pub(crate) fn split_header(self) -> (FeedHeader, Vec<FeedItem>) {
+        match self {
+            #[cfg(feature = "rss")]
+            FeedDownload::Rss(feed) => {
+                let (header, items) = ChannelHeader::split(feed);
+                (
+                    FeedHeader::Rss(header),
+                    items.into_iter().map(FeedItem::Rss).collect(),
+                )
+            }
+            #[cfg(feature = "feed-rs")]
+            FeedDownload::Feed(feed) => {
+                let (header, items) = crate::feeds::feed_rs::FeedHeader::split(feed);
+                (
+                    FeedHeader::FeedRs(header),
+                    items.into_iter().map(FeedItem::FeedRs).collect(),
+                )
+            }
+        }
+    }
+}
+
+/// Upper limit of redirects followed for one download
+pub(crate) const MAX_REDIRECTS: usize = 10;
+
+pub(crate) async fn download(
+    client: &reqwest::Client,
+    url: &str,
+    user_agent: Option<&str>,
+    credentials: Option<&FeedCredentials>,
+    max_bytes: u64,
+) -> reqwest::Result<Option<FeedDownload>> {
+    Ok(
+        download_tracked(client, url, user_agent, credentials, max_bytes)
+            .await?
+            .0
+            .ok(),
+    )
+}
+
+/// Downloads a feed, following redirects. If all redirects were permanent,
+/// the final url is returned as well. Bodies larger than `max_bytes` are
+/// not read to the end and fail to parse.
+pub(crate) async fn download_tracked(
+    client: &reqwest::Client,
+    url: &str,
+    user_agent: Option<&str>,
+    credentials: Option<&FeedCredentials>,
+    max_bytes: u64,
+) -> reqwest::Result<(Result<FeedDownload, ParseFailure>, Option<String>)> {
+    let (res, moved_to) = get_following_redirects(client, url, user_agent, credentials).await?;
+    Ok((parse_feed(res, max_bytes, credentials).await?, moved_to))
+}
+
+/// Why a web page could not be downloaded
+#[derive(thiserror::Error, Debug)]
+pub enum PageError {
+    #[error("{0}")]
+    Request(#[from] reqwest::Error),
+    #[error("response larger than {0} bytes")]
+    TooLarge(u64),
+}
+
+/// Downloads the HTML of a web page, like the article an item links to.
+/// Pages larger than `max_bytes` are not read to the end.
+pub(crate) async fn download_page(
+    client: &reqwest::Client,
+    url: &str,
+    user_agent: Option<&str>,
+    max_bytes: u64,
+) -> Result<String, PageError> {
+    let (res, _) = get_following_redirects(client, url, user_agent, None).await?;
+    let body = read_body(res.error_for_status()?, max_bytes)
+        .await?
+        .map_err(|_| PageError::TooLarge(max_bytes))?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// A GET request, with `user_agent` replacing the one of the client
+pub(crate) fn get(
+    client: &reqwest::Client,
+    url: impl reqwest::IntoUrl,
+    user_agent: Option<&str>,
+    credentials: Option<&FeedCredentials>,
+) -> reqwest::RequestBuilder {
+    request(client, reqwest::Method::GET, url, user_agent, credentials)
+}
+
+/// Like `get`, for any method
+fn request(
+    client: &reqwest::Client,
+    method: reqwest::Method,
+    url: impl reqwest::IntoUrl,
+    user_agent: Option<&str>,
+    credentials: Option<&FeedCredentials>,
+) -> reqwest::RequestBuilder {
+    let mut request = client.request(method, url);
+    if let Some(user_agent) = user_agent {
+        request = request.header(reqwest::header::USER_AGENT, user_agent);
+    }
+    match credentials {
+        Some(credentials) => credentials.apply(request),
+        None => request,
+    }
+}
+
+/// The url without the query parameter of `credentials`, for logs and
+/// everything that gets stored
+pub(crate) fn url_without_credentials(
+    url: &reqwest::Url,
+    credentials: Option<&FeedCredentials>,
+) -> String {
+    let mut url = url.clone();
+    if let Some(credentials) = credentials {
+        credentials.remove_from(&mut url);
+    }
+    url.to_string()
+}
+
+/// Errors show their url, which must not contain the credentials
+fn error_without_credentials(
+    mut e: reqwest::Error,
+    credentials: Option<&FeedCredentials>,
+) -> reqwest::Error {
+    if let (Some(credentials), Some(url)) = (credentials, e.url_mut()) {
+        credentials.remove_from(url);
+    }
+    e
+}
+
+/// The client does not follow redirects itself, so permanent ones can be
+/// noticed. If all redirects were permanent, the final url is returned.
+async fn get_following_redirects(
+    client: &reqwest::Client,
+    url: &str,
+    user_agent: Option<&str>,
+    credentials: Option<&FeedCredentials>,
+) -> reqwest::Result<(reqwest::Response, Option<String>)> {
+    let (res, redirects) = send_following_redirects(
+        client,
+        reqwest::Method::GET,
+        url,
+        user_agent,
+        credentials,
+        None,
+    )
+    .await?;
+    // The new feed url gets stored, without the credentials
+    let moved_to = Some(res.url())
+        .filter(|_| redirects.redirected && redirects.permanent && res.status().is_success())
+        .map(|moved_to| url_without_credentials(moved_to, credentials));
+
+    Ok((res, moved_to))
+}
+
+/// Which redirects `send_following_redirects` followed
+pub(crate) struct Redirects {
+    pub(crate) redirected: bool,
+    /// All redirects were permanent
+    pub(crate) permanent: bool,
+}
+
+/// Sends a request and follows its redirects, with `timeout` applying to
+/// each request. Credentials are only sent to the origin of `url`, so
+/// neither to other hosts nor over plain http after a redirect from https.
+pub(crate) async fn send_following_redirects(
+    client: &reqwest::Client,
+    method: reqwest::Method,
+    url: &str,
+    user_agent: Option<&str>,
+    credentials: Option<&FeedCredentials>,
+    timeout: Option<Duration>,
+) -> reqwest::Result<(reqwest::Response, Redirects)> {
+    let send = |url: reqwest::Url, credentials: Option<&FeedCredentials>| {
+        let mut request = request(client, method.clone(), url, user_agent, credentials);
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        request.send()
+    };
+    let mut res = match reqwest::Url::parse(url) {
+        Ok(url) => send(url, credentials).await,
+        // Let reqwest report the invalid url
+        Err(_) => client.request(method.clone(), url).send().await,
+    }
+    .map_err(|e| error_without_credentials(e, credentials))?;
+    let origin = res.url().origin();
+    let mut redirected = false;
+    let mut permanent = true;
+    for _ in 0..MAX_REDIRECTS {
+        if !res.status().is_redirection() {
+            break;
+        }
+        let location = res
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| res.url().join(location).ok());
+        let location = match location {
+            Some(location) => location,
+            None => break,
+        };
+        redirected = true;
+        permanent &= matches!(
+            res.status(),
+            reqwest::StatusCode::MOVED_PERMANENTLY | reqwest::StatusCode::PERMANENT_REDIRECT
+        );
+        let same_origin = location.origin() == origin;
+        res = send(location, credentials.filter(|_| same_origin))
+            .await
+            .map_err(|e| error_without_credentials(e, credentials))?;
+    }
+
+    Ok((
+        res,
+        Redirects {
+            redirected,
+            permanent,
+        },
+    ))
+}
+
+/// A downloaded body that none of the parsers could read
+pub(crate) struct ParseFailure {
+    pub(crate) url: String,
+    pub(crate) body: Vec<u8>,
+    pub(crate) errors: Vec<String>,
+    /// The body exceeded the size limit, `body` is only its start
+    pub(crate) too_large: bool,
+}
+
+/// Reads the body in chunks, stopping once it exceeds `max_bytes`. Returns
+/// the part read so far as the error in that case.
+async fn read_body(
+    mut res: reqwest::Response,
+    max_bytes: u64,
+) -> reqwest::Result<Result<Vec<u8>, Vec<u8>>> {
+    if res
+        .content_length()
+        .map_or(false, |length| length > max_bytes)
+    {
+        return Ok(Err(Vec::new()));
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = res.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() as u64 > max_bytes {
+            return Ok(Err(body));
+        }
+    }
+    Ok(Ok(body))
+}
+
+/// `credentials` are removed from the url, which gets logged and stored
+/// with failures
+async fn parse_feed(
+    res: reqwest::Response,
+    max_bytes: u64,
+    credentials: Option<&FeedCredentials>,
+) -> reqwest::Result<Result<FeedDownload, ParseFailure>> {
+    let url = url_without_credentials(res.url(), credentials);
+    // Both parsers need the whole body, so it gets buffered, but never
+    // beyond the limit
+    let body = match read_body(res, max_bytes)
+        .await
+        .map_err(|e| error_without_credentials(e, credentials))?
+    {
+        Ok(body) => body,
+        Err(start) => {
+            eprintln!(
+                "WARN: {} is larger than {} bytes, aborted the download",
+                url, max_bytes
+            );
+            return Ok(Err(ParseFailure {
+                url,
+                body: start,
+                errors: vec![format!("response larger than {} bytes", max_bytes)],
+                too_large: true,
+            }));
+        }
+    };
+    let mut errors = Vec::new();
+
+    #[cfg(feature = "rss")]
+    match Channel::read_from(&body[..]) {
+        Ok(chan) => return Ok(Ok(FeedDownload::Rss(chan))),
+        Err(e) => errors.push(format!("rss: {}", e)),
+    }
+
+    #[cfg(feature = "feed-rs")]
+    match ::feed_rs::parser::parse_with_uri(&body[..], Some(&url)) {
+        Ok(alt) => return Ok(Ok(FeedDownload::Feed(alt))),
+        Err(e) => errors.push(format!("feed-rs: {}", e)),
+    }
+
+    Ok(Err(ParseFailure {
+        url,
+        body,
+        errors,
+        too_large: false,
+    }))
+}
+
+#[test]
+fn test_initial_age_cutoff_is_kept() {
+    let mut db = Database::in_memory();
+    let mut feed = Feed::new("Feed".to_owned());
+    *feed.feed_url_mut() = Some("https://example.com/feed.xml".to_owned());
+    let feed_id = db.insert(feed);
+
+    let now = Utc::now();
+    let item = |guid: &str, days_ago: i64| {
+        FeedItem::Rss(rss::Item {
+            guid: Some(rss::Guid {
+                value: guid.to_owned(),
+                permalink: false,
+            }),
+            pub_date: Some((now - chrono::Duration::days(days_ago)).to_rfc2822()),
+            ..rss::Item::default()
+        })
+    };
+    let commit = |db: &mut Database, items: Vec<FeedItem>, published_after| {
+        let update = FeedUpdate {
+            new_items: items,
+            published_after,
+            ..FeedUpdate::default()
+        };
+        let results = UpdateFeedsTaskResult {
+            results: HashMap::from([(feed_id.clone(), update)]),
+            unavailable: Vec::new(),
+            seq_no: db.get_update_seq_no(),
+            partial: true,
+        };
+        futures::executor::block_on(db.commit_from(results))
+    };
+
+    // The first fetch brings the cutoff
+    let cutoff = now - chrono::Duration::days(30);
+    commit(&mut db, vec![item("new", 1), item("old", 60)], Some(cutoff));
+    assert_eq!(db.get(&feed_id).unwrap().item_count(), 1);
+    assert_eq!(db.get(&feed_id).unwrap().published_after(), Some(cutoff));
+
+    // Later fetches don't, the stored one still applies
+    let summary = commit(
+        &mut db,
+        vec![item("newer", 0), item("new", 1), item("old", 60)],
+        None,
+    );
+    assert_eq!(summary.new_items(), 1);
+    assert_eq!(db.get(&feed_id).unwrap().item_count(), 2);
+}
@@ -0,0 +1,110 @@
+use crate::database::{timeline::Timeline, Database, FeedId, SourceLookup};
+
+/// What [`Database::fsck`] found and repaired.
+#[derive(serde::Serialize, Clone, Debug, Default)]
+pub struct FsckReport {
+    pub feeds: usize,
+    /// The lookup of feeds by url and title differed from the stored feeds
+    pub lookup_rebuilt: bool,
+    /// Feed, old and new counter of the item ids
+    pub item_counters: Vec<(FeedId, usize, usize)>,
+    /// Feed, old and new counter of the header ids
+    pub header_counters: Vec<(FeedId, usize, usize)>,
+    /// Feed, old and new id of items that shared an id with another item
+    pub renumbered_items: Vec<(FeedId, usize, usize)>,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        !self.lookup_rebuilt
+            && self.item_counters.is_empty()
+            && self.header_counters.is_empty()
+            && self.renumbered_items.is_empty()
+    }
+
+    pub fn summary(&self) -> String {
+        if self.is_clean() {
+            return format!("checked {} feeds, no problems found", self.feeds);
+        }
+        format!(
+            "checked {} feeds, {}{} item counters, {} header counters and {} duplicate item ids repaired",
+            self.feeds,
+            if self.lookup_rebuilt {
+                "rebuilt the feed lookup, "
+            } else {
+                ""
+            },
+            self.item_counters.len(),
+            self.header_counters.len(),
+            self.renumbered_items.len()
+        )
+    }
+}
+
+impl Database {
+    /// Repairs the caches and id counters after the storage was edited by
+    /// hand: rebuilds the lookup of feeds, raises counters that are not
+    /// above the largest id in use and renumbers items with duplicate ids.
+    /// The read state of renumbered items is lost. Changed feeds still need
+    /// to be saved.
+    pub fn fsck(&mut self) -> FsckReport {
+        let mut report = FsckReport {
+            feeds: self.storage.len(),
+            ..FsckReport::default()
+        };
+
+        let mut lookup = SourceLookup::default();
+        self.storage.write_to_cache(&mut lookup);
+        if lookup != self.lookup {
+            report.lookup_rebuilt = true;
+            self.lookup = lookup;
+        }
+
+        let cold_max_item_ids: Vec<(FeedId, Option<usize>)> = self
+            .storage
+            .iter()
+            .filter(|(_, feed)| feed.cold_item_count() > 0)
+            .map(|(feed_id, _)| {
+                let max = self.cold_items(feed_id).iter().map(|item| item.id()).max();
+                (feed_id.clone(), max)
+            })
+            .collect();
+        for (feed_id, feed) in self.storage.iter_mut() {
+            let cold_max_item_id = cold_max_item_ids
+                .iter()
+                .find(|(cold_feed_id, _)| cold_feed_id == feed_id)
+                .and_then(|(_, max)| *max);
+            let (items, headers) = feed.repair_counters(cold_max_item_id);
+            if let Some((old, new)) = items {
+                report.item_counters.push((feed_id.clone(), old, new));
+            }
+            if let Some((old, new)) = headers {
+                report.header_counters.push((feed_id.clone(), old, new));
+            }
+            for (old, new) in feed.renumber_duplicate_items() {
+                report.renumbered_items.push((feed_id.clone(), old, new));
+            }
+        }
+
+        if !report.renumbered_items.is_empty() {
+            self.timeline = Timeline::build(&self.storage);
+            self.reindex();
+        }
+        report
+    }
+}
+
+#[test]
+fn test_fsck() {
+    let mut db = Database::in_memory();
+    let feed_id = db.insert(crate::database::storage_feed::feed_with_duplicate_ids());
+
+    let report = db.fsck();
+    assert!(!report.lookup_rebuilt);
+    assert_eq!(report.item_counters, vec![(feed_id.clone(), 1, 2)]);
+    assert!(report.header_counters.is_empty());
+    assert_eq!(report.renumbered_items, vec![(feed_id, 0, 2)]);
+    assert!(!report.is_clean());
+
+    assert!(db.fsck().is_clean());
+}
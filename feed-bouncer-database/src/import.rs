@@ -1,4 +1,10 @@
-use crate::{database::Database, safe_save_json};
+use std::collections::BTreeMap;
+
+use crate::{
+    config::Blocklist,
+    database::Database,
+    fetch::{ImportTask, ImportTaskResult},
+};
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(tag = "type")]
@@ -10,6 +16,9 @@ enum ImportEntry {
         ignore: bool,
         #[serde(default)]
         tags: Vec<String>,
+        /// Skip items older than this many days on the first fetch
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_initial_age_days: Option<u64>,
     },
     Opml {
         path: String,
@@ -17,6 +26,16 @@ enum ImportEntry {
         ignore: bool,
         #[serde(default)]
         tags: Vec<String>,
+        /// Tag the feeds with the names of their folders
+        #[serde(default)]
+        folder_tags: bool,
+    },
+    /// Adds to the blocklist of the config
+    Block {
+        #[serde(default)]
+        ignore: bool,
+        #[serde(flatten)]
+        blocklist: Blocklist,
     },
 }
 
@@ -25,45 +44,169 @@ struct Import {
     sources: Vec<ImportEntry>,
 }
 
+/// The entries of the import file, read so that the new feeds can be
+/// downloaded without access to the database
+pub struct ImportFileTask {
+    import: Import,
+    /// The downloads of the new feed entries, by their index in the file
+    tasks: Vec<(usize, ImportTask)>,
+}
+
+pub struct ImportFileTaskResult {
+    import: Import,
+    results: Vec<(usize, ImportTaskResult)>,
+}
+
+impl ImportFileTask {
+    pub async fn run(self) -> ImportFileTaskResult {
+        let mut results = Vec::new();
+        for (index, task) in self.tasks {
+            eprintln!("   add {}", task.url());
+            results.push((index, task.run().await));
+        }
+        ImportFileTaskResult {
+            import: self.import,
+            results,
+        }
+    }
+}
+
 impl Database {
-    pub async fn import(&mut self) {
-        let import_file = self.storage_path.join("import.json");
+    /// Reads the import file, `None` if there is none or the database is
+    /// read-only. Imports mark their entries in the import file as done.
+    pub fn import_file_task(&self) -> Option<ImportFileTask> {
+        if self.is_read_only() {
+            return None;
+        }
+        let v = std::fs::read(self.storage_path.join("import.json")).ok()?;
+        eprintln!("Import from import file");
+        let import: Import = match serde_json::from_slice(&v) {
+            Ok(import) => import,
+            Err(e) => {
+                eprintln!("Error when importing: {}", e);
+                eprintln!();
+                return None;
+            }
+        };
+        let tasks = import
+            .sources
+            .iter()
+            .enumerate()
+            .filter_map(|(index, source)| match source {
+                ImportEntry::Rss {
+                    url,
+                    ignore: false,
+                    tags,
+                    max_initial_age_days,
+                } => Some((
+                    index,
+                    self.import_task(url, tags)
+                        .max_initial_age_days(*max_initial_age_days),
+                )),
+                _ => None,
+            })
+            .collect();
+        Some(ImportFileTask { import, tasks })
+    }
 
-        if let Ok(v) = std::fs::read(&import_file) {
-            eprintln!("Import from import file");
-            match serde_json::from_slice(&v) {
-                Ok(import) => {
-                    let mut import: Import = import;
-                    for source in &mut import.sources {
-                        match source {
-                            ImportEntry::Rss { url, ignore, tags } => {
-                                if !*ignore {
-                                    eprintln!("   add {}", url);
-                                    self.import_from_rss(&url, &tags).await.ok();
-                                    *ignore = true;
-                                } else {
-                                    eprintln!("  skip {}", url);
+    /// Adds the downloaded feeds and imports the other entries of the
+    /// import file
+    pub fn commit_import_file(&mut self, result: ImportFileTaskResult) {
+        let ImportFileTaskResult {
+            mut import,
+            results,
+        } = result;
+        let mut results: BTreeMap<usize, ImportTaskResult> = results.into_iter().collect();
+        let import_file = self.storage_path.join("import.json");
+        // The blocklist is part of the config
+        let mut blocked = false;
+        for (index, source) in import.sources.iter_mut().enumerate() {
+            match source {
+                ImportEntry::Rss { url, ignore, .. } => match results.remove(&index) {
+                    Some(result) => {
+                        self.commit_import(result).ok();
+                        *ignore = true;
+                    }
+                    None => eprintln!("  skip {}", url),
+                },
+                #[cfg(feature = "opml")]
+                ImportEntry::Opml {
+                    path,
+                    ignore,
+                    tags,
+                    folder_tags,
+                } => {
+                    if !*ignore {
+                        eprintln!("   add {}", path);
+                        let path = self.storage_path.join(path);
+                        // Entries that fail as a whole are tried again next time
+                        match self.import_from_opml(path.as_ref(), &tags, *folder_tags) {
+                            Ok(report) => {
+                                eprintln!("         {}", report.summary());
+                                for problem in &report.blocked {
+                                    eprintln!(
+                                        "         blocked {}: {}",
+                                        problem.outline, problem.reason
+                                    );
                                 }
-                            }
-                            ImportEntry::Opml { path, ignore, tags } => {
-                                if !*ignore {
-                                    eprintln!("   add {}", path);
-                                    let path = self.storage_path.join(path);
-                                    self.import_from_opml(path.as_ref(), &tags);
-                                    *ignore = true;
-                                } else {
-                                    eprintln!("  skip {}", path);
+                                for problem in &report.errors {
+                                    eprintln!(
+                                        "         error in {}: {}",
+                                        problem.outline, problem.reason
+                                    );
                                 }
+                                *ignore = true;
                             }
+                            Err(e) => eprintln!("         error: {}", e),
                         }
+                    } else {
+                        eprintln!("  skip {}", path);
                     }
-                    safe_save_json(&import, &import_file, "import file", true);
                 }
-                Err(e) => {
-                    eprintln!("Error when importing: {}", e);
+                #[cfg(not(feature = "opml"))]
+                ImportEntry::Opml { path, .. } => {
+                    eprintln!("  skip {}, built without OPML support", path);
+                }
+                ImportEntry::Block { ignore, blocklist } => {
+                    if !*ignore {
+                        eprintln!("  add to the blocklist");
+                        self.config
+                            .import
+                            .blocklist
+                            .extend(std::mem::take(blocklist));
+                        blocked = true;
+                        *ignore = true;
+                    } else {
+                        eprintln!("  skip blocklist");
+                    }
                 }
             }
-            eprintln!();
+        }
+        // The feeds and the entries marked as done get saved together
+        let mut tx = self.begin();
+        let result = tx
+            .stage_json(&import, &import_file, None)
+            .and_then(|_| {
+                if blocked {
+                    self.config.stage(&mut tx, &self.storage_path)
+                } else {
+                    Ok(())
+                }
+            })
+            .map_err(crate::Error::from)
+            .and_then(|_| self.commit(tx));
+        if let Err(e) = result {
+            eprintln!("Error when saving the import: {}", e);
+        }
+        eprintln!();
+    }
+
+    /// Imports the import file in one go, see `import_file_task` for
+    /// downloading without holding the database
+    pub async fn import(&mut self) {
+        if let Some(task) = self.import_file_task() {
+            let result = task.run().await;
+            self.commit_import_file(result);
         }
     }
 }
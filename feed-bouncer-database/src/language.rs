@@ -0,0 +1,144 @@
+use crate::database::{storage_feed::Feed, storage_feed_header::FeedHeader};
+
+/// Frequent short words of the languages that can be guessed
+const COMMON_WORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "and", "of", "to", "in", "is", "for", "with", "on", "how", "what", "why", "you",
+            "your", "from", "this", "are", "my", "we",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "mit", "für", "auf", "von", "den", "ein", "eine",
+            "nicht", "wie", "im", "zu", "des", "auch", "wir",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "et", "des", "est", "pour", "une", "dans", "du", "sur", "avec",
+            "pas", "qui", "au", "un", "nous",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "los", "las", "y", "que", "es", "para", "con", "una", "por", "del", "como",
+            "más", "al", "se",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "di", "che", "è", "per", "con", "una", "della", "non", "gli", "nel", "alla",
+            "sono", "come",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "een", "en", "van", "is", "voor", "met", "op", "niet", "dat", "zijn",
+            "hoe", "wat",
+        ],
+    ),
+];
+
+/// Hits of common words needed before a language is guessed
+const MIN_HITS: usize = 5;
+/// Titles of the most recent items used for guessing
+const GUESS_TITLES: usize = 50;
+
+/// The primary subtag of a language tag like `en-US`, in lowercase
+pub fn normalize_language(raw: &str) -> Option<String> {
+    let primary = raw.trim().split(['-', '_']).next()?.to_ascii_lowercase();
+    let valid = (2..=3).contains(&primary.len()) && primary.chars().all(|c| c.is_ascii_lowercase());
+    valid.then(|| primary)
+}
+
+/// Guesses the language of titles by counting common words. Returns `None`
+/// if too few of them are known, or if no language clearly wins.
+pub fn guess_language<'a>(titles: impl IntoIterator<Item = &'a str>) -> Option<&'static str> {
+    let mut hits = vec![0; COMMON_WORDS.len()];
+    for title in titles {
+        for word in title.split(|c: char| !c.is_alphanumeric()) {
+            let word = word.to_lowercase();
+            for (hits, (_, words)) in hits.iter_mut().zip(COMMON_WORDS) {
+                if words.contains(&&word[..]) {
+                    *hits += 1;
+                }
+            }
+        }
+    }
+
+    let mut ranked: Vec<_> = hits
+        .into_iter()
+        .zip(COMMON_WORDS)
+        .map(|(hits, (language, _))| (hits, *language))
+        .collect();
+    ranked.sort_by_key(|&(hits, _)| std::cmp::Reverse(hits));
+    let (best, language) = ranked[0];
+    let second = ranked[1].0;
+    (best >= MIN_HITS && 2 * best >= 3 * second).then(|| language)
+}
+
+impl FeedHeader {
+    pub fn language(&self) -> Option<&str> {
+        match self {
+            FeedHeader::Rss(header) => header.language.as_deref(),
+            FeedHeader::FeedRs(header) => header.language.as_deref(),
+        }
+    }
+}
+
+impl Feed {
+    /// Takes the language from the most recent header, or guesses it from
+    /// the recent item titles. A previous result is kept if neither works.
+    pub(crate) fn detect_language(&mut self) {
+        let language = self
+            .feed_headers()
+            .last()
+            .and_then(|meta| meta.header.language())
+            .and_then(normalize_language)
+            .or_else(|| {
+                guess_language(
+                    self.items_newest_first()
+                        .into_iter()
+                        .take(GUESS_TITLES)
+                        .filter_map(|item| item.display_title()),
+                )
+                .map(str::to_owned)
+            });
+        if language.is_some() {
+            self.set_language(language);
+        }
+    }
+}
+
+#[test]
+fn test_normalize_language() {
+    assert_eq!(normalize_language("en-US").as_deref(), Some("en"));
+    assert_eq!(normalize_language(" DE ").as_deref(), Some("de"));
+    assert_eq!(normalize_language("de_AT").as_deref(), Some("de"));
+    assert_eq!(normalize_language("english"), None);
+    assert_eq!(normalize_language(""), None);
+}
+
+#[test]
+fn test_guess_language() {
+    let english = [
+        "How to build a house",
+        "The best of the week",
+        "Why you should learn Rust",
+    ];
+    assert_eq!(guess_language(english), Some("en"));
+    let german = [
+        "Wie man ein Haus baut",
+        "Das Beste der Woche",
+        "Die Bahn und das Wetter",
+    ];
+    assert_eq!(guess_language(german), Some("de"));
+    assert_eq!(guess_language(["Rust 1.70", "Episode 12"]), None);
+}
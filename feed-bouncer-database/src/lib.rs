@@ -1,31 +1,167 @@
+// The storage format of RSS items is defined by the rss crate, see Cargo.toml
+extern crate rss_types as rss;
+
+mod archive;
+mod authors;
+mod categories;
+mod compact;
+mod config;
+mod content;
+mod credentials;
 mod database;
+#[cfg(feature = "fetch")]
+mod diagnostics;
+mod feed_settings;
 mod feeds;
+#[cfg(feature = "fetch")]
+mod fetch;
+mod fsck;
+#[cfg(feature = "fetch")]
 mod import;
+mod language;
+#[cfg(feature = "opml")]
 mod opml_utils;
+#[cfg(feature = "fetch")]
+mod probe;
+mod query;
+mod search;
+mod settings;
+#[cfg(feature = "fetch")]
+mod source;
+mod stats;
+mod sync;
+mod tag_synonyms;
 
 use std::path::Path;
 
+#[cfg(feature = "fetch")]
+pub use archive::{ArchiveTask, ArchiveTaskResult};
+pub use authors::Author;
+pub use categories::ItemCategory;
+pub use compact::CompactReport;
+pub use config::Blocklist;
+pub use config::Config;
+pub use config::HttpConfig;
+pub use config::ImportConfig;
+pub use config::PublicConfig;
+pub use config::StorageConfig;
+pub use config::UiConfig;
+pub use config::UpdateConfig;
+pub use config::UrlTagRule;
+pub use content::ItemContent;
+pub use credentials::FeedCredentials;
 pub use database::storage_feed::Feed;
+pub use database::storage_feed::SourceKind;
 pub use database::storage_feed_header::FeedHeader;
 pub use database::storage_feed_header::FeedHeaderMeta;
+pub use database::storage_feed_header::HeaderChange;
 pub use database::storage_feed_item::FeedItem;
 pub use database::storage_feed_item::FeedItemMeta;
+pub use database::storage_feed_item::FeedItemOrder;
+pub use database::storage_feed_item::ItemSource;
+pub use database::storage_feed_item::TitleCleaning;
+pub use database::user_data::ItemGrouping;
+pub use database::user_data::ItemState;
+pub use database::user_data::SavedView;
+pub use database::user_data::Share;
+pub use database::user_data::ViewMode;
 pub use database::Database;
 pub use database::FeedId;
+#[cfg(feature = "fetch")]
+pub use diagnostics::FailedPayload;
+#[cfg(feature = "fetch")]
+pub use diagnostics::FetchFailure;
+pub use feed_settings::EffectiveSettings;
+pub use feed_settings::FeedOptions;
+#[cfg(feature = "fetch")]
+pub use fetch::{
+    CommitSummary, DiscoverTask, FeedCommitSummary, FeedUrlCheck, FeedUrlCheckResult, FeedUrlError,
+    ImportOverlap, ImportPreview, ImportTask, ImportTaskResult, PageError, PreviewTask,
+    PreviewTaskResult, UpdateProgress, UpdateProgressSnapshot, FIRST_RETRY_DELAY,
+    UPDATE_RETRY_ATTEMPTS,
+};
+pub use fsck::FsckReport;
+#[cfg(feature = "fetch")]
+pub use import::{ImportFileTask, ImportFileTaskResult};
+pub use language::normalize_language;
+#[cfg(feature = "opml")]
+pub use opml_utils::OpmlGrouping;
+#[cfg(feature = "opml")]
+pub use opml_utils::OpmlImportReport;
+#[cfg(feature = "opml")]
+pub use opml_utils::OutlineProblem;
+#[cfg(feature = "opml")]
+pub use opml_utils::REMOVED_FROM_OPML_TAG;
+#[cfg(feature = "fetch")]
+pub use probe::{ProbeResult, ProbeTask};
+pub use query::FeedQuery;
+pub use query::ItemOrder;
+pub use query::ItemPage;
+pub use query::ItemProvenance;
+pub use query::ItemQuery;
+pub use query::ItemSortKey;
+pub use query::LanguagePattern;
+pub use query::TagPattern;
+pub use settings::FeedSettings;
+pub use settings::SettingsBundle;
+pub use settings::SettingsImportReport;
+pub use stats::ActivityHistogram;
+pub use stats::ActivityPeriod;
+pub use stats::FeedStats;
+pub use stats::StorageStats;
+pub use sync::FeedReadState;
+pub use sync::UserDataExport;
+pub use sync::UserDataMergeReport;
+pub use tag_synonyms::TagSynonyms;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
+    #[cfg(feature = "fetch")]
     #[error("reqwest error {0}")]
     Reqwest(reqwest::Error),
+    #[error("invalid settings bundle: {0}")]
+    InvalidSettings(String),
+    #[error("invalid user data export: {0}")]
+    InvalidUserData(String),
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+    #[error("invalid credentials.json: {0}")]
+    InvalidCredentials(String),
+    #[error("invalid storage: {0}")]
+    InvalidStorage(String),
+    #[error("could not migrate storage: {0}")]
+    Migration(String),
+    #[cfg(feature = "opml")]
+    #[error("opml error {0}")]
+    Opml(opml::Error),
+    #[error("io error {0}")]
+    Io(#[from] std::io::Error),
+    #[error("storage is in use by {holder}, stop it, open the storage read-only or remove {path:?} if it is stale")]
+    Locked {
+        holder: String,
+        path: std::path::PathBuf,
+    },
 }
 
 fn safe_save_json(data: &impl serde::Serialize, path: &Path, what: &str, allow_shrink: bool) {
-    let storage = serde_json::to_string_pretty(data).unwrap();
+    safe_save_json_with(data, path, what, allow_shrink, None)
+}
+
+/// Like `safe_save_json`, but writes the elements of the top level array
+/// stored under `compact_key` as one compact line each.
+fn safe_save_json_with(
+    data: &impl serde::Serialize,
+    path: &Path,
+    what: &str,
+    allow_shrink: bool,
+    compact_key: Option<&str>,
+) {
+    let value = serde_json::to_value(data).unwrap();
+    let storage = canonical_json(&value, compact_key);
     let new_path = path.with_extension("new.json");
     std::fs::write(&new_path, storage).unwrap();
-    let new_size = std::fs::metadata(&new_path).unwrap().len();
-    let old_size = std::fs::metadata(path).map(|v| v.len()).unwrap_or(0);
-    if allow_shrink || (new_size >= old_size) {
+
+    if allow_shrink || !shrinks(&value, path) {
         std::fs::rename(new_path, path).unwrap();
     } else {
         eprintln!(
@@ -34,3 +170,81 @@ fn safe_save_json(data: &impl serde::Serialize, path: &Path, what: &str, allow_s
         )
     }
 }
+
+/// Whether `value` is smaller than the JSON file at `path`.
+///
+/// Compares the compact encodings, so switching the layout is not mistaken
+/// for lost data.
+fn shrinks(value: &serde_json::Value, path: &Path) -> bool {
+    let new_size = serde_json::to_string(value).unwrap().len();
+    let old_size = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|old| serde_json::from_str::<serde_json::Value>(&old).ok())
+        .map(|old| serde_json::to_string(&old).unwrap().len())
+        .unwrap_or(0);
+    new_size < old_size
+}
+
+/// Formats JSON with sorted keys and a trailing newline, so saving the same
+/// data always produces the same file.
+fn canonical_json(value: &serde_json::Value, compact_key: Option<&str>) -> String {
+    use serde_json::Value;
+
+    let mut out = match (value, compact_key) {
+        (Value::Object(map), Some(compact_key)) => {
+            let mut out = String::from("{");
+            for (i, (key, value)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str("\n  ");
+                out.push_str(&serde_json::to_string(key).unwrap());
+                out.push_str(": ");
+                match value {
+                    Value::Array(items) if key == compact_key && !items.is_empty() => {
+                        out.push('[');
+                        for (j, item) in items.iter().enumerate() {
+                            if j > 0 {
+                                out.push(',');
+                            }
+                            out.push_str("\n    ");
+                            out.push_str(&serde_json::to_string(item).unwrap());
+                        }
+                        out.push_str("\n  ]");
+                    }
+                    value => {
+                        let pretty = serde_json::to_string_pretty(value).unwrap();
+                        out.push_str(&pretty.replace('\n', "\n  "));
+                    }
+                }
+            }
+            out.push_str("\n}");
+            out
+        }
+        // serde_json sorts the keys of objects
+        (value, _) => serde_json::to_string_pretty(value).unwrap(),
+    };
+    out.push('\n');
+    out
+}
+
+#[test]
+fn test_canonical_json() {
+    let value = serde_json::json!({
+        "b": [{"y": 1, "x": [2]}, {"x": 3}],
+        "a": {"d": 4, "c": 5},
+    });
+
+    let pretty = canonical_json(&value, None);
+    assert!(pretty.find("\"a\"").unwrap() < pretty.find("\"b\"").unwrap());
+
+    let compact = canonical_json(&value, Some("b"));
+    assert_eq!(
+        compact,
+        "{\n  \"a\": {\n    \"c\": 5,\n    \"d\": 4\n  },\n  \"b\": [\n    {\"x\":[2],\"y\":1},\n    {\"x\":3}\n  ]\n}\n"
+    );
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&compact).unwrap(),
+        value
+    );
+}
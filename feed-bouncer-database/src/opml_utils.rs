@@ -1,43 +1,472 @@
-use std::path::Path;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    str::FromStr,
+};
 
-use opml::OPML;
+use opml::{Outline, OPML};
 
 use crate::{
     database::{Database, FeedId},
-    Feed,
+    Error, Feed,
 };
 
-fn open(path: &Path) -> OPML {
-    let opml = std::fs::read_to_string(path).unwrap();
-    let opml = opml::OPML::from_str(&opml).unwrap();
-    opml
+/// How feeds are arranged in an exported OPML file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpmlGrouping {
+    /// All feeds at the top level
+    Flat,
+    /// One folder per tag, feeds with multiple tags appear in each of them
+    Tags,
+    /// One folder per tag, feeds are only placed under their first tag
+    PrimaryTag,
+}
+
+impl FromStr for OpmlGrouping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(Self::Flat),
+            "tags" => Ok(Self::Tags),
+            "primary_tag" => Ok(Self::PrimaryTag),
+            _ => Err(format!(
+                "unknown grouping {:?}, expected flat, tags or primary_tag",
+                s
+            )),
+        }
+    }
+}
+
+/// Tag of the feeds that a re-synced OPML file no longer has
+pub const REMOVED_FROM_OPML_TAG: &str = "removed_from_opml";
+
+/// An outline that was not imported
+#[derive(serde::Serialize, Debug)]
+pub struct OutlineProblem {
+    pub outline: String,
+    pub reason: String,
+}
+
+/// What happened to each outline of an OPML import
+#[derive(serde::Serialize, Debug, Default)]
+pub struct OpmlImportReport {
+    /// Feeds and folders that were added
+    pub imported: Vec<String>,
+    /// Feeds and folders that already existed, their tags got added
+    pub merged: Vec<String>,
+    /// Outlines without anything to import
+    pub skipped: Vec<OutlineProblem>,
+    /// Broken outlines, their children are still imported
+    pub errors: Vec<OutlineProblem>,
+    /// New feeds on the blocklist of the config
+    pub blocked: Vec<OutlineProblem>,
+    /// Existing feeds whose outline or folder changed, only by a re-sync
+    pub updated: Vec<String>,
+    /// Feeds from an OPML import that are no longer in the re-synced file
+    pub removed: Vec<String>,
+}
+
+impl OpmlImportReport {
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} imported, {} already subscribed, {} skipped, {} blocked, {} errors",
+            self.imported.len(),
+            self.merged.len(),
+            self.skipped.len(),
+            self.blocked.len(),
+            self.errors.len()
+        );
+        if !self.updated.is_empty() || !self.removed.is_empty() {
+            summary += &format!(
+                ", {} updated, {} removed",
+                self.updated.len(),
+                self.removed.len()
+            );
+        }
+        summary
+    }
+}
+
+/// The tag for an OPML folder, like `tech_news` for "Tech News". Letters are
+/// lowercased and kept with digits, everything else turns into single
+/// underscores between them.
+pub fn folder_tag(name: &str) -> Option<String> {
+    let mut tag = String::new();
+    for c in name.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            tag.push(c);
+        } else if !tag.is_empty() && !tag.ends_with('_') {
+            tag.push('_');
+        }
+    }
+    let tag = tag.trim_end_matches('_');
+    (!tag.is_empty()).then(|| tag.to_owned())
+}
+
+/// Names an OPML file for `Feed::opml_document`, the same file should get
+/// the same name however the path is written
+fn opml_document(path: &Path) -> String {
+    std::fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_owned())
+        .to_string_lossy()
+        .into_owned()
 }
 
 impl Database {
+    /// `folders` has the tags of the enclosing folders, if they are turned
+    /// into tags of the feeds at all. `seen` collects the feeds of the
+    /// document. A `resync` updates the outlines and parents of the existing
+    /// ones.
+    #[allow(clippy::too_many_arguments)]
     fn add_opml_outline(
         &mut self,
         mut outline: opml::Outline,
         parent: Option<&FeedId>,
         initial_tags: &[String],
+        folders: Option<&[String]>,
+        resync: bool,
+        seen: &mut BTreeSet<FeedId>,
+        report: &mut OpmlImportReport,
     ) {
-        let name = outline.title.as_deref().unwrap_or(&outline.text).to_owned();
-        let rss = outline.xml_url.as_deref().map(|s| s.to_owned());
+        let name = outline
+            .title
+            .as_deref()
+            .unwrap_or(&outline.text)
+            .trim()
+            .to_owned();
+        let rss = outline
+            .xml_url
+            .as_deref()
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(str::to_owned);
+        let label = match (&name[..], &rss) {
+            ("", Some(rss)) => rss.clone(),
+            ("", None) => "(unnamed outline)".to_owned(),
+            _ => name.clone(),
+        };
         let children: Vec<_> = outline.outlines.drain(..).collect();
-        let mut source = Feed::new(name);
+
+        if rss.is_none() && children.is_empty() {
+            report.skipped.push(OutlineProblem {
+                outline: label,
+                reason: "neither a feed url nor child outlines".to_owned(),
+            });
+            return;
+        }
+        let error = match &rss {
+            Some(rss) if !rss.starts_with("http://") && !rss.starts_with("https://") => {
+                Some(format!("unsupported feed url {:?}", rss))
+            }
+            None if name.is_empty() => Some("folder without a name".to_owned()),
+            _ => None,
+        };
+        if let Some(reason) = error {
+            report.errors.push(OutlineProblem {
+                outline: label,
+                reason,
+            });
+            for child in children {
+                self.add_opml_outline(child, parent, initial_tags, folders, resync, seen, report);
+            }
+            return;
+        }
+        let is_folder = rss.is_none();
+        if let Some(rss) = rss
+            .as_deref()
+            .filter(|rss| self.lookup.check_rss(rss).is_none())
+        {
+            if let Some(reason) = self.config.import.blocklist.blocks(Some(rss), Some(&name)) {
+                report.blocked.push(OutlineProblem {
+                    outline: label,
+                    reason,
+                });
+                return;
+            }
+        }
+
+        let mut source = Feed::new(label.clone());
         *source.feed_url_mut() = rss;
         *source.opml_mut() = Some(outline);
         source.set_parent(parent.map(|v| v.to_owned()));
         source.extend_tags(initial_tags.iter().map(|s| &s[..]));
+        if !is_folder {
+            source.extend_tags(folders.unwrap_or_default().iter().map(|s| &s[..]));
+        }
+        if let Some(url) = source.feed_url().map(str::to_owned) {
+            source.extend_tags(self.config.import.tags_for(&url));
+        }
+        let exists = self.lookup.check(source.key()).is_some();
+        if exists {
+            report.merged.push(label.clone());
+        } else {
+            report.imported.push(label.clone());
+        }
+        // The first import keeps the outline and parent of existing feeds
+        let resynced =
+            (resync && exists).then(|| (source.opml().cloned(), source.parent().cloned()));
         let parent_feed_id = self.insert(source);
+        if let Some((outline, parent)) = resynced {
+            let feed = self.get_mut(&parent_feed_id).expect("inserted above");
+            if feed.opml() != outline.as_ref() || feed.parent() != parent.as_ref() {
+                *feed.opml_mut() = outline;
+                feed.set_parent(parent);
+                report.updated.push(label);
+            }
+        }
+        seen.insert(parent_feed_id.clone());
 
+        let child_folders: Option<Vec<String>> = folders.map(|folders| {
+            let tag = folder_tag(&name).filter(|_| is_folder);
+            folders.iter().cloned().chain(tag).collect()
+        });
         for child in children {
-            self.add_opml_outline(child, Some(&parent_feed_id), initial_tags.clone());
+            self.add_opml_outline(
+                child,
+                Some(&parent_feed_id),
+                initial_tags,
+                child_folders.as_deref(),
+                resync,
+                seen,
+                report,
+            );
         }
     }
-    pub fn import_from_opml(&mut self, path: &Path, initial_tags: &[String]) {
-        let opml = open(path);
+    /// Imports the outlines of an OPML document, returns the report and the
+    /// feeds of the document. `document` names the file the feeds came from.
+    fn add_opml_document(
+        &mut self,
+        opml: &str,
+        initial_tags: &[String],
+        folder_tags: bool,
+        resync: bool,
+        document: Option<&str>,
+    ) -> Result<(OpmlImportReport, BTreeSet<FeedId>), Error> {
+        let opml = OPML::from_str(opml).map_err(Error::Opml)?;
+        let mut report = OpmlImportReport::default();
+        let folders = folder_tags.then(Vec::new);
+        let mut seen = BTreeSet::new();
         for outline in opml.body.outlines {
-            self.add_opml_outline(outline, None, initial_tags);
+            self.add_opml_outline(
+                outline,
+                None,
+                initial_tags,
+                folders.as_deref(),
+                resync,
+                &mut seen,
+                &mut report,
+            );
         }
+        if let Some(document) = document {
+            for feed_id in &seen {
+                if let Some(feed) = self.get_mut(feed_id) {
+                    feed.set_opml_document(document.to_owned());
+                }
+            }
+        }
+        Ok((report, seen))
+    }
+    /// Imports the outlines of an OPML document, continuing past broken
+    /// ones. Fails only if the document can not be parsed at all. With
+    /// `folder_tags`, feeds get tagged with the names of their folders.
+    pub fn import_opml(
+        &mut self,
+        opml: &str,
+        initial_tags: &[String],
+        folder_tags: bool,
+    ) -> Result<OpmlImportReport, Error> {
+        let (report, _) = self.add_opml_document(opml, initial_tags, folder_tags, false, None)?;
+        Ok(report)
+    }
+    /// Imports an OPML document again, keeping it the source of truth:
+    /// existing feeds get the outlines and folders of the document, new
+    /// ones are added. Feeds of earlier imports of the same `document` that
+    /// it lacks are reported as removed, with `tag_removed` they get tagged
+    /// [`REMOVED_FROM_OPML_TAG`]. Feeds that are back lose that tag. Feeds
+    /// of other documents and of outlines that got skipped, blocked or
+    /// failed are left alone.
+    pub fn resync_opml(
+        &mut self,
+        opml: &str,
+        document: &str,
+        initial_tags: &[String],
+        folder_tags: bool,
+        tag_removed: bool,
+    ) -> Result<OpmlImportReport, Error> {
+        let (mut report, seen) =
+            self.add_opml_document(opml, initial_tags, folder_tags, true, Some(document))?;
+
+        // Outlines that are still there, but were not imported this time
+        let problems: BTreeSet<&str> = report
+            .skipped
+            .iter()
+            .chain(&report.blocked)
+            .chain(&report.errors)
+            .map(|problem| &problem.outline[..])
+            .collect();
+        let removed: Vec<FeedId> = self
+            .storage
+            .iter()
+            .filter(|(feed_id, feed)| {
+                feed.opml_document() == Some(document)
+                    && !seen.contains(*feed_id)
+                    && !problems.contains(feed.name())
+                    && !feed.feed_url().map_or(false, |url| problems.contains(url))
+            })
+            .map(|(feed_id, _)| feed_id.clone())
+            .collect();
+        for feed_id in &seen {
+            if let Some(feed) = self.get_mut(feed_id) {
+                feed.remove_tag(REMOVED_FROM_OPML_TAG);
+            }
+        }
+        for feed_id in &removed {
+            let feed = self.get_mut(feed_id).expect("listed above");
+            report.removed.push(feed.display_name().to_owned());
+            if tag_removed {
+                feed.extend_tags([REMOVED_FROM_OPML_TAG]);
+            }
+        }
+        Ok(report)
+    }
+    pub fn import_from_opml(
+        &mut self,
+        path: &Path,
+        initial_tags: &[String],
+        folder_tags: bool,
+    ) -> Result<OpmlImportReport, Error> {
+        let opml = std::fs::read_to_string(path)?;
+        let document = opml_document(path);
+        let (report, _) =
+            self.add_opml_document(&opml, initial_tags, folder_tags, false, Some(&document))?;
+        Ok(report)
+    }
+    pub fn resync_from_opml(
+        &mut self,
+        path: &Path,
+        initial_tags: &[String],
+        folder_tags: bool,
+        tag_removed: bool,
+    ) -> Result<OpmlImportReport, Error> {
+        let opml = std::fs::read_to_string(path)?;
+        self.resync_opml(
+            &opml,
+            &opml_document(path),
+            initial_tags,
+            folder_tags,
+            tag_removed,
+        )
     }
+
+    pub fn export_opml(&self, grouping: OpmlGrouping) -> OPML {
+        let mut feeds: Vec<_> = self
+            .storage
+            .iter()
+            .filter(|(_, feed)| feed.feed_url().is_some())
+            .map(|(_, feed)| feed)
+            .collect();
+        feeds.sort_by_key(|feed| feed.display_name().to_ascii_lowercase());
+
+        let mut outlines = Vec::new();
+        let mut folders: BTreeMap<&str, Vec<Outline>> = BTreeMap::new();
+        for feed in feeds {
+            let outline = export_outline(feed);
+            let mut tags = feed.tags();
+            match (grouping, tags.next()) {
+                (OpmlGrouping::Flat, _) | (_, None) => outlines.push(outline),
+                (OpmlGrouping::PrimaryTag, Some(tag)) => {
+                    folders.entry(tag).or_default().push(outline);
+                }
+                (OpmlGrouping::Tags, Some(tag)) => {
+                    for tag in tags {
+                        folders.entry(tag).or_default().push(outline.clone());
+                    }
+                    folders.entry(tag).or_default().push(outline);
+                }
+            }
+        }
+
+        let mut folder_outlines: Vec<_> = folders
+            .into_iter()
+            .map(|(tag, children)| Outline {
+                text: tag.to_owned(),
+                title: Some(tag.to_owned()),
+                outlines: children,
+                ..Outline::default()
+            })
+            .collect();
+        folder_outlines.append(&mut outlines);
+
+        let mut opml = OPML::default();
+        opml.head = Some(opml::Head {
+            title: Some("feed-bouncer subscriptions".to_owned()),
+            ..opml::Head::default()
+        });
+        opml.body.outlines = folder_outlines;
+        opml
+    }
+}
+
+/// Uses the outline of the original import if there is one, to keep
+/// attributes like the html url.
+fn export_outline(feed: &Feed) -> Outline {
+    let mut outline = feed.opml().cloned().unwrap_or_default();
+    outline.text = feed.display_name().to_owned();
+    outline.title = Some(feed.display_name().to_owned());
+    outline.xml_url = feed.feed_url().map(str::to_owned);
+    if outline.r#type.is_none() {
+        outline.r#type = Some("rss".to_owned());
+    }
+    outline.outlines.clear();
+    outline
+}
+
+#[test]
+fn test_folder_tag() {
+    assert_eq!(folder_tag("Tech News").as_deref(), Some("tech_news"));
+    assert_eq!(folder_tag("  Rust & Go ").as_deref(), Some("rust_go"));
+    assert_eq!(folder_tag("News / Blogs").as_deref(), Some("news_blogs"));
+    assert_eq!(folder_tag("Web 2.0").as_deref(), Some("web_2_0"));
+    assert_eq!(folder_tag("Café Blogs").as_deref(), Some("café_blogs"));
+    assert_eq!(folder_tag(" - "), None);
+}
+
+#[test]
+fn test_resync_opml() {
+    let document = |outlines: &str| {
+        format!(
+            r#"<opml version="2.0"><head/><body>{}</body></opml>"#,
+            outlines
+        )
+    };
+    let outline = |name: &str, url: &str| {
+        format!(r#"<outline text="{}" type="rss" xmlUrl="{}"/>"#, name, url)
+    };
+    let mut db = Database::in_memory();
+    let resync = |db: &mut Database, opml: &str, name: &str| {
+        db.resync_opml(opml, name, &[], false, true).unwrap()
+    };
+
+    let a = outline("A", "https://a.example/feed");
+    let b = outline("B", "https://b.example/feed");
+    let c = outline("C", "https://c.example/feed");
+    resync(&mut db, &document(&format!("{}{}", a, b)), "one.opml");
+    resync(&mut db, &document(&c), "two.opml");
+
+    // Feeds of the other document are not removed, outlines that fail now
+    // are not either
+    let broken_b = outline("B", "ftp://b.example/feed");
+    let report = resync(&mut db, &document(&broken_b), "one.opml");
+    assert_eq!(report.removed, ["A"]);
+    assert_eq!(report.errors.len(), 1);
+
+    let report = resync(&mut db, &document(&format!("{}{}", a, b)), "one.opml");
+    assert!(report.removed.is_empty());
+    let tagged = db
+        .get_feeds()
+        .into_iter()
+        .filter(|(_, feed)| feed.tags().any(|tag| tag == REMOVED_FROM_OPML_TAG))
+        .count();
+    assert_eq!(tagged, 0);
 }
@@ -0,0 +1,173 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+
+use crate::{
+    credentials::FeedCredentials,
+    database::{storage_feed::SourceKind, Database, FeedId},
+    fetch::{send_following_redirects, url_without_credentials, Redirects},
+};
+
+/// How many feeds are probed at the same time
+const PROBE_CONCURRENCY: usize = 32;
+/// Probes only check the response headers, so they can give up early
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a quick request to a feed url returned, without its body.
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct ProbeResult {
+    pub feed_id: FeedId,
+    pub feed_name: String,
+    pub url: String,
+    /// Status of the response after all redirects
+    pub status: Option<u16>,
+    /// Where the redirects ended, if there were any
+    pub redirected_to: Option<String>,
+    pub permanent_redirect: bool,
+    /// Connection, TLS or timeout errors, with all their causes
+    pub error: Option<String>,
+}
+
+impl ProbeResult {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+            && self
+                .status
+                .map_or(false, |status| (200..300).contains(&status))
+    }
+}
+
+/// Checks that the feed urls respond, without downloading or storing any
+/// items. The requests can happen without access to the database.
+pub struct ProbeTask {
+    client: reqwest::Client,
+    feeds: Vec<ProbeRequest>,
+    max_response_bytes: u64,
+}
+
+struct ProbeRequest {
+    feed_id: FeedId,
+    feed_name: String,
+    url: String,
+    user_agent: Option<String>,
+    credentials: Option<FeedCredentials>,
+}
+
+impl Database {
+    pub fn probe_task(&self) -> ProbeTask {
+        let feeds = self
+            .storage
+            .iter()
+            .filter(|(_, feed)| feed.source() == SourceKind::Http)
+            .filter_map(|(feed_id, feed)| {
+                let url = feed.feed_url()?;
+                Some(ProbeRequest {
+                    feed_id: feed_id.clone(),
+                    feed_name: feed.display_name().to_owned(),
+                    url: url.to_owned(),
+                    user_agent: feed.effective_settings(self).user_agent,
+                    credentials: self.credentials.get(feed_id).cloned(),
+                })
+            })
+            .collect();
+        ProbeTask {
+            client: self.client.clone(),
+            feeds,
+            max_response_bytes: self.config.http.response_limit(),
+        }
+    }
+}
+
+impl ProbeTask {
+    /// Probes all feeds, the results are ordered by feed name.
+    pub async fn run(self) -> Vec<ProbeResult> {
+        let client = &self.client;
+        let max_bytes = self.max_response_bytes;
+        let mut results: Vec<ProbeResult> = futures::stream::iter(self.feeds)
+            .map(|request| async move {
+                let mut result = ProbeResult {
+                    feed_id: request.feed_id,
+                    feed_name: request.feed_name,
+                    url: request.url,
+                    status: None,
+                    redirected_to: None,
+                    permanent_redirect: false,
+                    error: None,
+                };
+                let credentials = request.credentials.as_ref();
+                match head_or_get(
+                    client,
+                    &result.url,
+                    request.user_agent.as_deref(),
+                    credentials,
+                )
+                .await
+                {
+                    Ok((res, redirects)) => {
+                        result.status = Some(res.status().as_u16());
+                        if redirects.redirected {
+                            result.redirected_to =
+                                Some(url_without_credentials(res.url(), credentials));
+                            result.permanent_redirect = redirects.permanent;
+                        }
+                        // Updates would fail to read a body this large
+                        if res
+                            .content_length()
+                            .map_or(false, |length| length > max_bytes)
+                        {
+                            result.error =
+                                Some(format!("response larger than {} bytes", max_bytes));
+                        }
+                    }
+                    Err(e) => result.error = Some(error_chain(&e)),
+                }
+                result
+            })
+            .buffer_unordered(PROBE_CONCURRENCY)
+            .collect()
+            .await;
+        results.sort_by_key(|result| result.feed_name.to_lowercase());
+        results
+    }
+}
+
+/// Sends a HEAD request, or a GET request for servers that don't support
+/// HEAD, following redirects like updates do. The body of a GET response is
+/// never read.
+async fn head_or_get(
+    client: &reqwest::Client,
+    url: &str,
+    user_agent: Option<&str>,
+    credentials: Option<&FeedCredentials>,
+) -> reqwest::Result<(reqwest::Response, Redirects)> {
+    let send = |method| {
+        send_following_redirects(
+            client,
+            method,
+            url,
+            user_agent,
+            credentials,
+            Some(PROBE_TIMEOUT),
+        )
+    };
+    let (res, redirects) = send(reqwest::Method::HEAD).await?;
+    if matches!(
+        res.status(),
+        reqwest::StatusCode::METHOD_NOT_ALLOWED | reqwest::StatusCode::NOT_IMPLEMENTED
+    ) {
+        return send(reqwest::Method::GET).await;
+    }
+    Ok((res, redirects))
+}
+
+/// TLS problems only show up in the causes of the error
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut chain = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        chain.push_str(": ");
+        chain.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    chain
+}
@@ -0,0 +1,778 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    str::FromStr,
+};
+
+use chrono::{DateTime, FixedOffset, Utc};
+
+use crate::{
+    database::{storage_feed::Feed, user_data::ItemState, Database, FeedId},
+    FeedItem, FeedItemMeta, ItemSource, TagSynonyms,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagPattern {
+    Has(String),
+    HasNot(String),
+}
+
+/// Selects feeds by the language code of [`Feed::language`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum LanguagePattern {
+    Is(String),
+    /// Feeds without a known language are not excluded
+    IsNot(String),
+}
+
+/// Selects feeds by tag and id.
+#[derive(Debug, Clone, Default)]
+pub struct FeedQuery {
+    tags: Vec<TagPattern>,
+    exact_tags: bool,
+    languages: Vec<LanguagePattern>,
+    feed_ids: Option<BTreeSet<FeedId>>,
+    excluded_feed_ids: BTreeSet<FeedId>,
+    /// Lowercase parts the display name must contain
+    title_parts: Vec<String>,
+    /// Lowercase names of which items need to have one. Feeds match if
+    /// they have such an item.
+    authors: Vec<String>,
+    /// Lowercase categories of which items need to have one, like
+    /// `authors`
+    categories: Vec<String>,
+    /// Lowercase categories of which items must have none. Only hides
+    /// items, feeds still match for their other items.
+    excluded_categories: Vec<String>,
+}
+
+impl FeedQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(TagPattern::Has(tag.into()));
+        self
+    }
+    pub fn without_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(TagPattern::HasNot(tag.into()));
+        self
+    }
+    /// Replaces the synonyms among the tags by their canonical tags
+    pub fn with_tag_synonyms(mut self, synonyms: &TagSynonyms) -> Self {
+        for pattern in &mut self.tags {
+            let (TagPattern::Has(tag) | TagPattern::HasNot(tag)) = pattern;
+            *tag = synonyms.canonical(tag).to_owned();
+        }
+        self
+    }
+    /// Only match feeds whose tags are exactly the required ones.
+    pub fn exact_tags(mut self, exact: bool) -> Self {
+        self.exact_tags = exact;
+        self
+    }
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.languages.push(LanguagePattern::Is(language.into()));
+        self
+    }
+    pub fn without_language(mut self, language: impl Into<String>) -> Self {
+        self.languages.push(LanguagePattern::IsNot(language.into()));
+        self
+    }
+    /// Restrict the query to the given feed. Can be called multiple times.
+    pub fn feed(mut self, feed_id: impl Into<FeedId>) -> Self {
+        self.feed_ids
+            .get_or_insert_with(BTreeSet::new)
+            .insert(feed_id.into());
+        self
+    }
+    pub fn without_feed(mut self, feed_id: impl Into<FeedId>) -> Self {
+        self.excluded_feed_ids.insert(feed_id.into());
+        self
+    }
+    /// Only match feeds whose display name contains `part`, ignoring case
+    pub fn title_contains(mut self, part: &str) -> Self {
+        self.title_parts.push(part.to_lowercase());
+        self
+    }
+    /// Only match items by `author`, ignoring case. Can be called multiple
+    /// times to allow several authors.
+    pub fn by_author(mut self, author: &str) -> Self {
+        self.authors.push(author.trim().to_lowercase());
+        self
+    }
+    pub fn matches_author(&self, item: &FeedItem) -> bool {
+        self.authors.is_empty()
+            || item
+                .author()
+                .map(str::to_lowercase)
+                .map_or(false, |author| self.authors.contains(&author))
+    }
+    /// Only match items in `category`, ignoring case. Can be called
+    /// multiple times to allow several categories.
+    pub fn in_category(mut self, category: &str) -> Self {
+        self.categories.push(category.trim().to_lowercase());
+        self
+    }
+    /// Hides the items in `category`, ignoring case
+    pub fn not_in_category(mut self, category: &str) -> Self {
+        self.excluded_categories
+            .push(category.trim().to_lowercase());
+        self
+    }
+    pub fn matches_category(&self, item: &FeedItem) -> bool {
+        if self.categories.is_empty() && self.excluded_categories.is_empty() {
+            return true;
+        }
+        let categories: Vec<String> = item
+            .categories()
+            .into_iter()
+            .map(str::to_lowercase)
+            .collect();
+        (self.categories.is_empty()
+            || categories
+                .iter()
+                .any(|category| self.categories.contains(category)))
+            && !categories
+                .iter()
+                .any(|category| self.excluded_categories.contains(category))
+    }
+    pub fn tag_patterns(&self) -> &[TagPattern] {
+        &self.tags
+    }
+    pub fn matches(&self, feed_id: &FeedId, feed: &Feed) -> bool {
+        if let Some(feed_ids) = &self.feed_ids {
+            if !feed_ids.contains(feed_id) {
+                return false;
+            }
+        }
+        if self.excluded_feed_ids.contains(feed_id) {
+            return false;
+        }
+        if !self.title_parts.is_empty() {
+            let title = feed.display_name().to_lowercase();
+            if !self
+                .title_parts
+                .iter()
+                .all(|part| title.contains(&part[..]))
+            {
+                return false;
+            }
+        }
+
+        if !self.authors.is_empty() && !self.authors.iter().any(|author| feed.has_author(author)) {
+            return false;
+        }
+        if !self.categories.is_empty()
+            && !self
+                .categories
+                .iter()
+                .any(|category| feed.has_category(category))
+        {
+            return false;
+        }
+
+        for pattern in &self.languages {
+            let matches = match pattern {
+                LanguagePattern::Is(language) => feed.language() == Some(&language[..]),
+                LanguagePattern::IsNot(language) => feed.language() != Some(&language[..]),
+            };
+            if !matches {
+                return false;
+            }
+        }
+
+        let mut matches: usize = 0;
+        for pattern in &self.tags {
+            match pattern {
+                TagPattern::Has(tag) => {
+                    if !feed.contains_tag(tag) {
+                        return false;
+                    }
+                    matches += 1;
+                }
+                TagPattern::HasNot(tag) => {
+                    if feed.contains_tag(tag) {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        (!self.exact_tags) || (matches == feed.tags().count())
+    }
+    /// Describes how each part of the query applies to the feed
+    pub fn explain(&self, feed_id: &FeedId, feed: &Feed) -> Vec<String> {
+        let mut reasons = Vec::new();
+        if let Some(feed_ids) = &self.feed_ids {
+            if feed_ids.contains(feed_id) {
+                reasons.push("selected by feed id".to_owned());
+            } else {
+                reasons.push("not selected by feed id".to_owned());
+            }
+        }
+        if self.excluded_feed_ids.contains(feed_id) {
+            reasons.push("excluded by feed id".to_owned());
+        }
+        let title = feed.display_name().to_lowercase();
+        for part in &self.title_parts {
+            if title.contains(&part[..]) {
+                reasons.push(format!("title contains {}", part));
+            } else {
+                reasons.push(format!("title lacks {}", part));
+            }
+        }
+        for pattern in &self.tags {
+            reasons.push(match pattern {
+                TagPattern::Has(tag) if feed.contains_tag(tag) => format!("has tag {}", tag),
+                TagPattern::Has(tag) => format!("lacks required tag {}", tag),
+                TagPattern::HasNot(tag) if feed.contains_tag(tag) => {
+                    format!("has excluded tag {}", tag)
+                }
+                TagPattern::HasNot(tag) => format!("lacks excluded tag {}", tag),
+            });
+        }
+        let language = feed.language().unwrap_or("unknown");
+        for pattern in &self.languages {
+            reasons.push(match pattern {
+                LanguagePattern::Is(wanted) if feed.language() == Some(&wanted[..]) => {
+                    format!("has language {}", wanted)
+                }
+                LanguagePattern::Is(wanted) => {
+                    format!("lacks required language {}, has {}", wanted, language)
+                }
+                LanguagePattern::IsNot(excluded) if feed.language() == Some(&excluded[..]) => {
+                    format!("has excluded language {}", excluded)
+                }
+                LanguagePattern::IsNot(excluded) => {
+                    format!("lacks excluded language {}, has {}", excluded, language)
+                }
+            });
+        }
+        if !self.authors.is_empty() {
+            reasons.push(format!("items by {}", self.authors.join(" or ")));
+        }
+        if !self.categories.is_empty() {
+            reasons.push(format!("items in {}", self.categories.join(" or ")));
+        }
+        if !self.excluded_categories.is_empty() {
+            reasons.push(format!(
+                "no items in {}",
+                self.excluded_categories.join(" or ")
+            ));
+        }
+        if self.exact_tags {
+            reasons.push(format!(
+                "exact tags required, feed has {}",
+                feed.tags().collect::<Vec<_>>().join(", ")
+            ));
+        }
+        reasons
+    }
+}
+
+/// Where an item came from and why it matches a query, to debug surprising
+/// items in filtered views.
+#[derive(serde::Serialize, Debug)]
+pub struct ItemProvenance {
+    pub feed_id: FeedId,
+    pub feed_name: String,
+    pub feed_url: Option<String>,
+    pub previous_feed_urls: Vec<String>,
+    pub item_id: usize,
+    /// The key used to recognize the item across updates
+    pub identity: String,
+    /// Items with the same link are shown once
+    pub content_link: Option<String>,
+    pub first_seen: Option<DateTime<Utc>>,
+    pub added_at: Option<DateTime<Utc>>,
+    pub added_in_update: Option<u64>,
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Where the item was first fetched from, if known
+    pub source: Option<ItemSource>,
+    pub matches_filter: bool,
+    pub filter: Vec<String>,
+    pub tags: Vec<String>,
+    pub categories: Vec<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemOrder {
+    #[default]
+    OldestFirst,
+    NewestFirst,
+}
+
+impl FromStr for ItemOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oldest_first" => Ok(Self::OldestFirst),
+            "newest_first" => Ok(Self::NewestFirst),
+            _ => Err(format!("unknown item order {:?}", s)),
+        }
+    }
+}
+
+impl ItemOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::OldestFirst => "oldest_first",
+            Self::NewestFirst => "newest_first",
+        }
+    }
+}
+
+/// The date items are ordered by.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemSortKey {
+    /// The date given by the publisher
+    #[default]
+    Published,
+    /// The date the item was first fetched
+    Discovered,
+}
+
+impl FromStr for ItemSortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "published" => Ok(Self::Published),
+            "discovered" => Ok(Self::Discovered),
+            _ => Err(format!("unknown item sort key {:?}", s)),
+        }
+    }
+}
+
+impl ItemSortKey {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Published => "published",
+            Self::Discovered => "discovered",
+        }
+    }
+}
+
+/// Selects items of the feeds matched by a [`FeedQuery`].
+#[derive(Debug, Clone, Default)]
+pub struct ItemQuery {
+    feeds: FeedQuery,
+    read: Option<bool>,
+    hidden: Option<bool>,
+    from: Option<DateTime<FixedOffset>>,
+    to: Option<DateTime<FixedOffset>>,
+    seen_after: Option<DateTime<Utc>>,
+    order: ItemOrder,
+    sort_key: ItemSortKey,
+    dedup_links: bool,
+    limit: Option<usize>,
+    text: Option<String>,
+    without_muted: bool,
+}
+
+impl ItemQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn feeds(mut self, feeds: FeedQuery) -> Self {
+        self.feeds = feeds;
+        self
+    }
+    pub fn read(mut self, read: bool) -> Self {
+        self.read = Some(read);
+        self
+    }
+    /// Only include items that are in the trash, or only those that are not.
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = Some(hidden);
+        self
+    }
+    /// Only include items published at or after `from`.
+    pub fn published_from(mut self, from: DateTime<FixedOffset>) -> Self {
+        self.from = Some(from);
+        self
+    }
+    /// Only include items published before `to`.
+    pub fn published_to(mut self, to: DateTime<FixedOffset>) -> Self {
+        self.to = Some(to);
+        self
+    }
+    /// Only include items first seen after `after`.
+    pub fn first_seen_after(mut self, after: DateTime<Utc>) -> Self {
+        self.seen_after = Some(after);
+        self
+    }
+    pub fn order(mut self, order: ItemOrder) -> Self {
+        self.order = order;
+        self
+    }
+    /// Which date of the items `order` applies to.
+    pub fn sort_by(mut self, key: ItemSortKey) -> Self {
+        self.sort_key = key;
+        self
+    }
+    /// Drop consecutive items that point to the same content link.
+    pub fn dedup_links(mut self) -> Self {
+        self.dedup_links = true;
+        self
+    }
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+    /// Only include items whose title or summary contain all words of `text`.
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+    /// Leave out the feeds muted from the index, see [`crate::FeedOptions`].
+    pub fn without_muted(mut self) -> Self {
+        self.without_muted = true;
+        self
+    }
+    pub fn feed_query(&self) -> &FeedQuery {
+        &self.feeds
+    }
+
+    fn matches_item(&self, db: &Database, feed_id: &FeedId, item: &FeedItemMeta) -> bool {
+        if !self.feeds.matches_author(&item.item) || !self.feeds.matches_category(&item.item) {
+            return false;
+        }
+        let state = db.item_state(feed_id, item.id());
+        if self.read.map_or(false, |read| state.read != read) {
+            return false;
+        }
+        if self.hidden.map_or(false, |hidden| state.hidden != hidden) {
+            return false;
+        }
+        if self.seen_after.map_or(false, |after| {
+            item.first_seen().map_or(true, |seen| seen <= after)
+        }) {
+            return false;
+        }
+        if self.from.is_some() || self.to.is_some() {
+            let date = item.publish_date_or_old();
+            if self.from.map(|from| date < from).unwrap_or(false) {
+                return false;
+            }
+            if self.to.map(|to| date >= to).unwrap_or(false) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Where a page of query results lies, with the counts for a "showing
+/// 1-50 of 1234" line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ItemPage {
+    /// Starting at 1
+    pub number: usize,
+    pub size: usize,
+    /// Number of pages, at least 1 even without items
+    pub pages: usize,
+    /// Items matching the query
+    pub matching_items: usize,
+    /// Items of all feeds, before any filtering
+    pub total_items: usize,
+    /// Positions of the first and last item of the page, starting at 1.
+    /// Both are 0 if the page is empty.
+    pub first_item: usize,
+    pub last_item: usize,
+}
+
+impl ItemPage {
+    /// Pages past the end are moved to the last page
+    pub fn new(number: usize, size: usize, matching_items: usize, total_items: usize) -> Self {
+        let size = size.max(1);
+        let pages = ((matching_items + size - 1) / size).max(1);
+        let number = number.clamp(1, pages);
+        let offset = (number - 1) * size;
+        let last_item = matching_items.min(offset + size);
+        Self {
+            number,
+            size,
+            pages,
+            matching_items,
+            total_items,
+            first_item: if last_item > offset { offset + 1 } else { 0 },
+            last_item,
+        }
+    }
+    pub fn prev(&self) -> Option<usize> {
+        (self.number > 1).then(|| self.number - 1)
+    }
+    pub fn next(&self) -> Option<usize> {
+        (self.number < self.pages).then(|| self.number + 1)
+    }
+    /// The page numbers to link: the first and last page and those within
+    /// `radius` of the current one, `None` for the gaps between them.
+    pub fn window(&self, radius: usize) -> Vec<Option<usize>> {
+        let mut window = Vec::new();
+        for number in 1..=self.pages {
+            let near = number + radius >= self.number && number <= self.number + radius;
+            if number == 1 || number == self.pages || near {
+                window.push(Some(number));
+            } else if window.last() != Some(&None) {
+                window.push(None);
+            }
+        }
+        window
+    }
+}
+
+impl Database {
+    pub fn item_provenance(
+        &self,
+        feed_id: &FeedId,
+        item_id: usize,
+        filter: &FeedQuery,
+    ) -> Option<ItemProvenance> {
+        let feed = self.get(feed_id)?;
+        let item = feed.items().iter().find(|item| item.id() == item_id)?;
+
+        Some(ItemProvenance {
+            feed_id: feed_id.clone(),
+            feed_name: feed.display_name().to_owned(),
+            feed_url: feed.feed_url().map(str::to_owned),
+            previous_feed_urls: feed.previous_feed_urls().iter().cloned().collect(),
+            item_id,
+            identity: item.item.identity(),
+            content_link: item.content_link().map(str::to_owned),
+            first_seen: item.first_seen(),
+            added_at: item.added_at(),
+            added_in_update: item.added_in_update(),
+            updated_at: item.updated_at(),
+            source: item.source().cloned(),
+            matches_filter: filter.matches(feed_id, feed),
+            filter: filter.explain(feed_id, feed),
+            tags: feed.tags().map(str::to_owned).collect(),
+            categories: item
+                .item
+                .categories()
+                .into_iter()
+                .map(str::to_owned)
+                .collect(),
+        })
+    }
+
+    pub fn query_feeds(&self, query: &FeedQuery) -> Vec<(&FeedId, &Feed)> {
+        self.storage
+            .iter()
+            .filter(|(feed_id, feed)| query.matches(feed_id, feed))
+            .collect()
+    }
+
+    pub fn query_items(&self, query: &ItemQuery) -> Vec<(&FeedId, &Feed, &FeedItemMeta)> {
+        let now = Utc::now();
+        let mut feeds: HashMap<&FeedId, &Feed> = HashMap::new();
+        // Items published before the retention of their feed are not shown
+        let mut cutoffs: HashMap<&FeedId, DateTime<Utc>> = HashMap::new();
+        for (feed_id, feed) in self.query_feeds(&query.feeds) {
+            let settings = feed.effective_settings(self);
+            if query.without_muted && settings.mute_from_index {
+                continue;
+            }
+            if let Some(cutoff) = settings.retention_cutoff(now) {
+                cutoffs.insert(feed_id, cutoff);
+            }
+            feeds.insert(feed_id, feed);
+        }
+        let hits = query.text.as_ref().map(|text| self.search(text));
+
+        let timeline: Box<dyn Iterator<Item = (&FeedId, usize)> + '_> = match query.order {
+            ItemOrder::OldestFirst => Box::new(self.timeline.iter(query.sort_key)),
+            ItemOrder::NewestFirst => Box::new(self.timeline.iter(query.sort_key).rev()),
+        };
+        let mut items: Vec<(&FeedId, &Feed, &FeedItemMeta)> = Vec::new();
+        for (feed_id, item_id) in timeline {
+            if Some(items.len()) == query.limit {
+                break;
+            }
+            let (feed_id, feed) = match feeds.get_key_value(feed_id) {
+                Some((feed_id, feed)) => (*feed_id, *feed),
+                None => continue,
+            };
+            if let Some(hits) = &hits {
+                if !hits.contains(&(feed_id.clone(), item_id)) {
+                    continue;
+                }
+            }
+            let item = match self.timeline.item(feed_id, feed, item_id) {
+                Some(item) => item,
+                None => continue,
+            };
+            if let Some(cutoff) = cutoffs.get(feed_id) {
+                if item.sort_date(ItemSortKey::Published) < *cutoff {
+                    continue;
+                }
+            }
+            if !query.matches_item(self, feed_id, item) {
+                continue;
+            }
+            if query.dedup_links {
+                if let Some((_, _, last)) = items.last() {
+                    if last.content_link() == item.content_link() {
+                        continue;
+                    }
+                }
+            }
+            items.push((feed_id, feed, item));
+        }
+
+        items
+    }
+    /// Like [`Database::query_items`], together with the read and trash state
+    /// of each item
+    pub fn query_items_with_state(
+        &self,
+        query: &ItemQuery,
+    ) -> Vec<(&FeedId, &Feed, &FeedItemMeta, ItemState)> {
+        self.query_items(query)
+            .into_iter()
+            .map(|(feed_id, feed, item)| (feed_id, feed, item, self.item_state(feed_id, item.id())))
+            .collect()
+    }
+    /// Like [`Database::query_items_with_state`], limited to page `page` of
+    /// `page_size` items
+    pub fn query_items_page(
+        &self,
+        query: &ItemQuery,
+        page: usize,
+        page_size: usize,
+    ) -> (Vec<(&FeedId, &Feed, &FeedItemMeta, ItemState)>, ItemPage) {
+        let items = self.query_items(query);
+        let page = ItemPage::new(page, page_size, items.len(), self.timeline.len());
+        let items = items
+            .into_iter()
+            .skip((page.number - 1) * page.size)
+            .take(page.size)
+            .map(|(feed_id, feed, item)| (feed_id, feed, item, self.item_state(feed_id, item.id())))
+            .collect();
+        (items, page)
+    }
+}
+
+#[test]
+fn test_feed_query_tags() {
+    let feed_id: FeedId = "a".into();
+    let mut feed = Feed::new("A".into());
+    feed.extend_tags(["rust", "blog"]);
+
+    assert!(FeedQuery::new().matches(&feed_id, &feed));
+    assert!(FeedQuery::new().with_tag("rust").matches(&feed_id, &feed));
+    assert!(!FeedQuery::new().with_tag("go").matches(&feed_id, &feed));
+    assert!(!FeedQuery::new()
+        .without_tag("blog")
+        .matches(&feed_id, &feed));
+    assert!(!FeedQuery::new()
+        .with_tag("rust")
+        .exact_tags(true)
+        .matches(&feed_id, &feed));
+    assert!(FeedQuery::new()
+        .with_tag("rust")
+        .with_tag("blog")
+        .exact_tags(true)
+        .matches(&feed_id, &feed));
+}
+
+#[test]
+fn test_item_page() {
+    let page = ItemPage::new(2, 50, 1234, 5000);
+    assert_eq!((page.first_item, page.last_item, page.pages), (51, 100, 25));
+    assert_eq!((page.prev(), page.next()), (Some(1), Some(3)));
+
+    let last = ItemPage::new(30, 50, 1234, 5000);
+    assert_eq!(
+        (last.number, last.first_item, last.last_item),
+        (25, 1201, 1234)
+    );
+    assert_eq!(last.next(), None);
+
+    let empty = ItemPage::new(1, 50, 0, 0);
+    assert_eq!((empty.pages, empty.first_item, empty.last_item), (1, 0, 0));
+
+    assert_eq!(
+        ItemPage::new(10, 50, 1234, 5000).window(1),
+        vec![Some(1), None, Some(9), Some(10), Some(11), None, Some(25)]
+    );
+}
+
+#[test]
+fn test_feed_query_feed_ids() {
+    let feed = Feed::new("A".into());
+
+    let query = FeedQuery::new().feed("a").feed("b");
+    assert!(query.matches(&"a".into(), &feed));
+    assert!(query.matches(&"b".into(), &feed));
+    assert!(!query.matches(&"c".into(), &feed));
+}
+
+#[test]
+fn test_feed_query_categories() {
+    let item = |categories: &[&str]| {
+        FeedItem::Rss(rss::Item {
+            categories: categories
+                .iter()
+                .map(|name| rss::Category {
+                    name: name.to_string(),
+                    domain: None,
+                })
+                .collect(),
+            ..rss::Item::default()
+        })
+    };
+    let item = item(&["Physics", " cs.LG ", "physics", ""]);
+    assert_eq!(item.categories(), vec!["Physics", "cs.LG"]);
+
+    assert!(FeedQuery::new().matches_category(&item));
+    assert!(FeedQuery::new()
+        .in_category("cs.lg")
+        .matches_category(&item));
+    assert!(FeedQuery::new()
+        .in_category("math")
+        .in_category("PHYSICS")
+        .matches_category(&item));
+    assert!(!FeedQuery::new().in_category("math").matches_category(&item));
+    assert!(!FeedQuery::new()
+        .not_in_category("PHYSICS")
+        .matches_category(&item));
+    assert!(FeedQuery::new()
+        .not_in_category("math")
+        .matches_category(&item));
+
+    let feed_id: FeedId = "a".into();
+    let mut feed = Feed::new("A".into());
+    feed.push_item(item);
+    assert!(FeedQuery::new()
+        .in_category("cs.lg")
+        .matches(&feed_id, &feed));
+    assert!(!FeedQuery::new()
+        .in_category("math")
+        .matches(&feed_id, &feed));
+}
+
+#[test]
+fn test_feed_query_authors() {
+    let feed_id: FeedId = "a".into();
+    let mut feed = Feed::new("A".into());
+    let query = FeedQuery::new().by_author("Jane Doe");
+    assert!(!query.matches(&feed_id, &feed));
+
+    feed.push_item(FeedItem::Rss(rss::Item {
+        author: Some("jane doe".into()),
+        ..rss::Item::default()
+    }));
+    assert!(query.matches(&feed_id, &feed));
+    assert!(!FeedQuery::new()
+        .by_author("John Doe")
+        .matches(&feed_id, &feed));
+
+    // Items moved to cold storage don't count anymore
+    feed.take_items_published_before(Utc::now() + chrono::Duration::days(1));
+    assert!(!query.matches(&feed_id, &feed));
+}
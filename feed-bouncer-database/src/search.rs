@@ -0,0 +1,123 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use crate::database::{storage::Storage, storage_feed_item::FeedItem, Database, FeedId};
+
+/// Bump this to force a rebuild of stored indexes after changing tokenization
+const SEARCH_INDEX_VERSION: u32 = 1;
+
+type ItemRef = (FeedId, usize);
+
+/// An inverted index from lowercase words in titles and summaries to items.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+pub struct SearchIndex {
+    version: u32,
+    terms: BTreeMap<String, BTreeSet<ItemRef>>,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+fn tokenize(text: &str) -> BTreeSet<String> {
+    // Summaries are often HTML
+    let text = crate::content::strip_tags(text);
+
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 2)
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+impl SearchIndex {
+    /// Opens the stored index, or builds it if there is none or it is outdated.
+    pub fn open_or_build(storage_path: &Path, storage: &Storage) -> Self {
+        let index_path = storage_path.join("search_index.json");
+        let index = std::fs::read_to_string(&index_path)
+            .ok()
+            .and_then(|v| serde_json::from_str::<Self>(&v).ok())
+            .filter(|index| index.version == SEARCH_INDEX_VERSION);
+
+        index.unwrap_or_else(|| {
+            eprintln!("Building search index");
+            Self::build(storage)
+        })
+    }
+    pub fn build(storage: &Storage) -> Self {
+        let mut index = Self {
+            version: SEARCH_INDEX_VERSION,
+            terms: BTreeMap::new(),
+            dirty: true,
+        };
+        for (feed_id, feed) in storage.iter() {
+            for item in feed.items() {
+                index.add_item(feed_id, item.id(), &item.item);
+            }
+        }
+        index
+    }
+    pub fn save(&mut self, storage_path: &Path) {
+        if self.dirty {
+            let index_path = storage_path.join("search_index.json");
+            crate::safe_save_json(self, &index_path, "search index", true);
+            self.dirty = false;
+        }
+    }
+    pub fn add_item(&mut self, feed_id: &FeedId, item_id: usize, item: &FeedItem) {
+        for term in tokenize(&item.search_text()) {
+            self.terms
+                .entry(term)
+                .or_default()
+                .insert((feed_id.clone(), item_id));
+        }
+        self.dirty = true;
+    }
+    pub fn remove_item(&mut self, feed_id: &FeedId, item_id: usize, item: &FeedItem) {
+        let key = (feed_id.clone(), item_id);
+        for term in tokenize(&item.search_text()) {
+            if let Some(items) = self.terms.get_mut(&term) {
+                items.remove(&key);
+                if items.is_empty() {
+                    self.terms.remove(&term);
+                }
+            }
+        }
+        self.dirty = true;
+    }
+    /// Returns the items containing all words of the query
+    pub fn search(&self, query: &str) -> BTreeSet<ItemRef> {
+        let mut result: Option<BTreeSet<ItemRef>> = None;
+        for term in tokenize(query) {
+            let items = self.terms.get(&term).cloned().unwrap_or_default();
+            result = Some(match result {
+                Some(result) => result.intersection(&items).cloned().collect(),
+                None => items,
+            });
+        }
+        result.unwrap_or_default()
+    }
+}
+
+impl Database {
+    pub fn search(&self, query: &str) -> BTreeSet<(FeedId, usize)> {
+        self.search_index.search(query)
+    }
+
+    /// Rebuilds the search index from scratch and saves it.
+    pub fn reindex(&mut self) {
+        self.search_index = SearchIndex::build(&self.storage);
+        if !self.is_read_only() {
+            self.search_index.save(&self.storage_path);
+        }
+    }
+}
+
+#[test]
+fn test_tokenize() {
+    let terms = tokenize("Rust 1.60: <a href=\"x\">Cargo's</a> new features, a-b");
+    let expected: BTreeSet<_> = ["rust", "60", "cargo", "new", "features"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    assert_eq!(terms, expected);
+}
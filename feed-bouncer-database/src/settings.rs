@@ -0,0 +1,244 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    config::Config, database::Database, Error, Feed, FeedItemOrder, FeedOptions, SourceKind,
+    TitleCleaning,
+};
+
+/// Version 2 added the options, item order, time zone, archiving and source
+/// of feeds, and the tag settings and default filter
+const SETTINGS_VERSION: u32 = 2;
+
+/// The user maintained settings of a feed, identified by name and feed url.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+pub struct FeedSettings {
+    pub name: String,
+    pub feed_url: Option<String>,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    #[serde(default)]
+    pub title_aliases: BTreeSet<String>,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub title_cleaning: TitleCleaning,
+    #[serde(default)]
+    pub options: FeedOptions,
+    #[serde(default)]
+    pub item_order: FeedItemOrder,
+    #[serde(default)]
+    pub utc_offset_mins: Option<i32>,
+    #[serde(default)]
+    pub archive: bool,
+    #[serde(default)]
+    pub source: SourceKind,
+}
+
+/// All settings of an instance in a single file, to set up another instance.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+pub struct SettingsBundle {
+    pub version: u32,
+    pub config: Config,
+    pub feeds: Vec<FeedSettings>,
+    /// Options shared by the feeds with a tag, by tag
+    #[serde(default)]
+    pub tag_settings: BTreeMap<String, FeedOptions>,
+    #[serde(default)]
+    pub default_filter: String,
+}
+
+#[derive(Debug, Default)]
+pub struct SettingsImportReport {
+    pub new_feeds: usize,
+    pub merged_feeds: usize,
+}
+
+impl SettingsBundle {
+    fn validate(&self) -> Result<(), Error> {
+        if self.version != SETTINGS_VERSION {
+            return Err(Error::InvalidSettings(format!(
+                "unsupported version {}, expected {}",
+                self.version, SETTINGS_VERSION
+            )));
+        }
+        for feed in &self.feeds {
+            if feed.name.trim().is_empty() {
+                return Err(Error::InvalidSettings("feed without a name".into()));
+            }
+            if feed.tags.iter().any(|tag| tag.trim().is_empty()) {
+                return Err(Error::InvalidSettings(format!(
+                    "empty tag on feed {}",
+                    feed.name
+                )));
+            }
+            if let Some(mins) = feed.utc_offset_mins {
+                if mins.abs() >= 24 * 60 {
+                    return Err(Error::InvalidSettings(format!(
+                        "utc offset of {} minutes on feed {}",
+                        mins, feed.name
+                    )));
+                }
+            }
+            if let Some(pattern) = feed.title_cleaning.pattern() {
+                TitleCleaning::regex(pattern).map_err(|e| {
+                    Error::InvalidSettings(format!(
+                        "invalid title cleaning pattern on feed {}: {}",
+                        feed.name, e
+                    ))
+                })?;
+            }
+        }
+        if self.tag_settings.keys().any(|tag| tag.trim().is_empty()) {
+            return Err(Error::InvalidSettings("settings for an empty tag".into()));
+        }
+        Ok(())
+    }
+}
+
+impl Database {
+    pub fn export_settings(&self) -> SettingsBundle {
+        let feeds = self
+            .storage
+            .iter()
+            .map(|(_, feed)| FeedSettings {
+                name: feed.name().to_owned(),
+                feed_url: feed.feed_url().map(str::to_owned),
+                display_name: Some(feed.display_name())
+                    .filter(|name| *name != feed.original_display_name())
+                    .map(str::to_owned),
+                tags: feed.tags().map(str::to_owned).collect(),
+                title_aliases: feed.title_aliases().clone(),
+                notes: feed.notes().to_owned(),
+                title_cleaning: feed.title_cleaning().clone(),
+                options: feed.options().clone(),
+                item_order: feed.item_order(),
+                utc_offset_mins: feed.utc_offset_mins(),
+                archive: feed.archive(),
+                source: feed.source(),
+            })
+            .collect();
+
+        // The tokens protect this instance, they don't belong into a file
+        // meant for setting up others
+        let mut config = self.config.clone();
+        config.ui.subscribe_token = None;
+        config.ui.admin_api_token = None;
+
+        SettingsBundle {
+            version: SETTINGS_VERSION,
+            config,
+            feeds,
+            tag_settings: self.tag_settings().clone(),
+            default_filter: self.default_filter().to_owned(),
+        }
+    }
+
+    /// Merges a settings bundle into this database and saves it. Feeds are
+    /// matched the same way as during imports, unknown feeds get added. The
+    /// config, tag settings and default filter of the bundle replace the
+    /// current ones, except for the tokens of this instance.
+    pub fn import_settings(
+        &mut self,
+        bundle: SettingsBundle,
+    ) -> Result<SettingsImportReport, Error> {
+        bundle.validate()?;
+
+        let mut report = SettingsImportReport::default();
+        for settings in bundle.feeds {
+            let mut feed = Feed::new(settings.name);
+            *feed.feed_url_mut() = settings.feed_url;
+            feed.extend_tags(settings.tags.iter().map(|s| &s[..]));
+
+            let is_new = self.lookup.check(feed.key()).is_none();
+            let feed_id = self.insert(feed);
+            for title_alias in &settings.title_aliases {
+                self.add_title_alias(&feed_id, title_alias);
+            }
+            let feed = self.storage.get_mut(&feed_id).unwrap();
+            if let Some(display_name) = settings.display_name {
+                feed.set_display_name(display_name);
+            }
+            if !settings.notes.is_empty() {
+                feed.set_notes(settings.notes);
+            }
+            feed.set_title_cleaning(settings.title_cleaning);
+            feed.set_options(settings.options);
+            feed.set_source(settings.source);
+            self.set_item_order(&feed_id, settings.item_order);
+            self.set_utc_offset(&feed_id, settings.utc_offset_mins);
+            self.set_archive(&feed_id, settings.archive);
+
+            if is_new {
+                report.new_feeds += 1;
+            } else {
+                report.merged_feeds += 1;
+            }
+        }
+
+        let replaced_tags: Vec<String> = self
+            .tag_settings()
+            .keys()
+            .filter(|tag| !bundle.tag_settings.contains_key(*tag))
+            .cloned()
+            .collect();
+        for tag in replaced_tags {
+            self.set_tag_settings(&tag, FeedOptions::default());
+        }
+        for (tag, options) in bundle.tag_settings {
+            self.set_tag_settings(&tag, options);
+        }
+        self.set_default_filter(&bundle.default_filter);
+
+        let mut config = bundle.config;
+        config.ui.subscribe_token = self.config.ui.subscribe_token.take();
+        config.ui.admin_api_token = self.config.ui.admin_api_token.take();
+        self.config = config;
+        #[cfg(feature = "fetch")]
+        {
+            self.client = crate::fetch::build_client(&self.config.http, &self.storage_path);
+        }
+
+        if !self.is_read_only() {
+            let mut tx = self.begin();
+            self.config.stage(&mut tx, &self.storage_path)?;
+            self.commit(tx)?;
+        }
+
+        Ok(report)
+    }
+}
+
+#[test]
+fn test_settings_roundtrip() {
+    let mut db = Database::in_memory();
+    let mut feed = Feed::new("Feed".to_owned());
+    *feed.feed_url_mut() = Some("https://example.com/feed.xml".to_owned());
+    let feed_id = db.insert(feed);
+    let options = FeedOptions {
+        update_interval_mins: Some(90),
+        ..FeedOptions::default()
+    };
+    db.set_feed_options(&feed_id, options.clone());
+    db.set_item_order(&feed_id, FeedItemOrder::Updated);
+    db.set_utc_offset(&feed_id, Some(120));
+    db.set_tag_settings("news", options.clone());
+    db.set_default_filter("!tag:muted");
+
+    db.config.ui.admin_api_token = Some("secret".to_owned());
+
+    let bundle = db.export_settings();
+    assert_eq!(bundle.config.ui.admin_api_token, None);
+    let mut other = Database::in_memory();
+    other.config.ui.admin_api_token = Some("local".to_owned());
+    other.import_settings(bundle).unwrap();
+    assert_eq!(other.config().ui.admin_api_token.as_deref(), Some("local"));
+
+    let feed = other.get(&feed_id).unwrap();
+    assert_eq!(feed.options(), &options);
+    assert_eq!(feed.item_order(), FeedItemOrder::Updated);
+    assert_eq!(feed.utc_offset_mins(), Some(120));
+    assert_eq!(other.tag_settings().get("news"), Some(&options));
+    assert_eq!(other.default_filter(), "!tag:muted");
+}
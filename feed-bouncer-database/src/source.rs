@@ -0,0 +1,253 @@
+use std::{
+    collections::HashSet,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    credentials::FeedCredentials,
+    database::{
+        storage_feed::{Feed, SourceKind},
+        storage_feed_header::FeedHeader,
+        storage_feed_item::FeedItem,
+        Database, FeedId,
+    },
+    feeds::rss::{ChannelHeader, Item},
+    fetch::{download, download_tracked, ParseFailure, MAX_DOWNLOAD_ATTEMPTS},
+};
+
+/// What an update asks a source for
+pub struct SourceRequest<'a> {
+    /// The feed url, interpreted by the source
+    pub location: &'a str,
+    /// Display name of the feed, for messages
+    pub name: &'a str,
+    /// Number of archive pages to follow, for sources that have them
+    pub archive_depth: usize,
+    /// Replaces the user agent of the client, for sources that use http
+    pub user_agent: Option<&'a str>,
+    /// Sent with the requests of sources that use http
+    pub credentials: Option<&'a FeedCredentials>,
+    /// Sources that use http abort downloads larger than this
+    pub max_response_bytes: u64,
+}
+
+/// The current header and items of a feed, in no particular order
+pub struct SourceFetch {
+    pub header: FeedHeader,
+    pub items: Vec<FeedItem>,
+    /// The new location if the source permanently moved
+    pub moved_to: Option<String>,
+}
+
+pub enum SourceError {
+    /// The source could not be reached, the feed is skipped this time
+    Unavailable(String),
+    /// The source answered with something that is not a feed
+    Invalid(ParseFailure),
+}
+
+pub type SourceFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<SourceFetch, SourceError>> + Send + 'a>>;
+
+/// A way to get the items of a feed. Sources don't see the stored items,
+/// updates recognize the known ones themselves.
+pub trait FeedSource: Send + Sync {
+    fn fetch<'a>(&'a self, request: &'a SourceRequest<'a>) -> SourceFuture<'a>;
+}
+
+impl SourceKind {
+    pub(crate) fn source(self, client: &reqwest::Client) -> Box<dyn FeedSource> {
+        match self {
+            SourceKind::Http => Box::new(HttpSource {
+                client: client.clone(),
+            }),
+            SourceKind::Directory => Box::new(DirectorySource),
+        }
+    }
+}
+
+impl Database {
+    /// Subscribes to a local directory of markdown files, named after it
+    pub fn add_directory_feed(&mut self, path: &Path) -> FeedId {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string_lossy().into_owned());
+        let mut feed = Feed::new(name);
+        *feed.feed_url_mut() = Some(path.to_string_lossy().into_owned());
+        let feed_id = self.insert(feed);
+        if let Some(feed) = self.storage.get_mut(&feed_id) {
+            feed.set_source(SourceKind::Directory);
+        }
+        feed_id
+    }
+}
+
+/// Downloads RSS, Atom and JSON feeds, following redirects and archive pages
+pub struct HttpSource {
+    client: reqwest::Client,
+}
+
+impl FeedSource for HttpSource {
+    fn fetch<'a>(&'a self, request: &'a SourceRequest<'a>) -> SourceFuture<'a> {
+        Box::pin(async move {
+            let mut retries = 0;
+            let (channel, moved_to) = loop {
+                retries += 1;
+                match download_tracked(
+                    &self.client,
+                    request.location,
+                    request.user_agent,
+                    request.credentials,
+                    request.max_response_bytes,
+                )
+                .await
+                {
+                    Ok(res) => break res,
+                    Err(e) => {
+                        if retries >= MAX_DOWNLOAD_ATTEMPTS {
+                            return Err(SourceError::Unavailable(e.to_string()));
+                        }
+                    }
+                }
+            };
+            if let Some(moved_to) = &moved_to {
+                println!("[{}] permanently moved to {}", request.name, moved_to);
+            }
+            let channel = match channel {
+                // Retrying would only download it again
+                Err(failure) if failure.too_large => {
+                    return Err(SourceError::Unavailable(failure.errors.join(", ")))
+                }
+                channel => channel.map_err(SourceError::Invalid)?,
+            };
+
+            let mut pages = vec![channel];
+            let mut page_url = request.location.to_owned();
+            let mut visited = HashSet::new();
+            visited.insert(page_url.clone());
+            while pages.len() <= request.archive_depth {
+                let link = match pages.last().unwrap().archive_link(&page_url) {
+                    Some(link) if visited.insert(link.clone()) => link,
+                    _ => break,
+                };
+                let page = download(
+                    &self.client,
+                    &link,
+                    request.user_agent,
+                    request.credentials,
+                    request.max_response_bytes,
+                );
+                match page.await {
+                    Ok(Some(page)) => pages.push(page),
+                    _ => {
+                        eprintln!("WARN: could not download archive page {}", link);
+                        break;
+                    }
+                }
+                page_url = link;
+            }
+            if pages.len() > 1 {
+                println!(
+                    "Fetched {} archive pages for [{}]",
+                    pages.len() - 1,
+                    request.name
+                );
+            }
+
+            let mut pages = pages.into_iter();
+            let (header, mut items) = pages.next().unwrap().split_header();
+            for page in pages {
+                items.extend(page.split_header().1);
+            }
+            Ok(SourceFetch {
+                header,
+                items,
+                moved_to,
+            })
+        })
+    }
+}
+
+/// Reads the markdown files of a local directory. The file name identifies
+/// an item, its first heading is the title and the modification time the
+/// publish date. The files are rendered to HTML, raw HTML in them is
+/// escaped.
+pub struct DirectorySource;
+
+impl FeedSource for DirectorySource {
+    fn fetch<'a>(&'a self, request: &'a SourceRequest<'a>) -> SourceFuture<'a> {
+        Box::pin(async move {
+            let dir = PathBuf::from(request.location);
+            tokio::task::spawn_blocking(move || read_directory(&dir))
+                .await
+                .map_err(|e| SourceError::Unavailable(e.to_string()))?
+                .map_err(|e| SourceError::Unavailable(e.to_string()))
+        })
+    }
+}
+
+fn render_markdown(text: &str) -> String {
+    use pulldown_cmark::{Event, Parser};
+
+    let parser = Parser::new(text).map(|event| match event {
+        Event::Html(html) => Event::Text(html),
+        event => event,
+    });
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, parser);
+    html
+}
+
+fn read_directory(dir: &Path) -> std::io::Result<SourceFetch> {
+    let mut items = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().map_or(true, |ext| ext != "md") {
+            continue;
+        }
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("WARN: could not read {:?}: {}", path, e);
+                continue;
+            }
+        };
+        let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let title = text
+            .lines()
+            .find_map(|line| line.strip_prefix("# "))
+            .map(|title| title.trim().to_owned())
+            .unwrap_or_else(|| file_name.trim_end_matches(".md").to_owned());
+        let modified = std::fs::metadata(&path)?.modified().ok();
+
+        items.push(FeedItem::Rss(Item {
+            title: Some(title),
+            guid: Some(rss::Guid {
+                value: file_name,
+                permalink: false,
+            }),
+            pub_date: modified.map(|modified| DateTime::<Utc>::from(modified).to_rfc2822()),
+            content: Some(render_markdown(&text)),
+            ..Item::default()
+        }));
+    }
+
+    let title = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (header, _) = ChannelHeader::split(rss::Channel {
+        title,
+        ..rss::Channel::default()
+    });
+    Ok(SourceFetch {
+        header: FeedHeader::Rss(header),
+        items,
+        moved_to: None,
+    })
+}
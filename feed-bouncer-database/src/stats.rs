@@ -0,0 +1,161 @@
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate};
+
+use crate::database::{storage_feed::Feed, Database, FeedId};
+
+/// Storage use of a single feed.
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct FeedStats {
+    pub feed_id: FeedId,
+    pub name: String,
+    pub items: usize,
+    pub headers: usize,
+    /// Size of the feed file, 0 if it was not saved yet
+    pub file_size: u64,
+    /// Publish dates of the oldest and newest item, by date and not by the
+    /// item order. `None` if no item has a date.
+    pub oldest_item: Option<DateTime<FixedOffset>>,
+    pub newest_item: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(serde::Serialize, Clone, Debug, Default)]
+pub struct StorageStats {
+    pub feeds: Vec<FeedStats>,
+    pub total_items: usize,
+    pub total_headers: usize,
+    pub total_file_size: u64,
+}
+
+impl Database {
+    /// Item counts and file sizes of all feeds, largest files first.
+    pub fn stats(&self) -> StorageStats {
+        let feed_path = self.storage_path.join("feeds");
+        let mut stats = StorageStats::default();
+        for (feed_id, feed) in self.storage.iter() {
+            let file_size = std::fs::metadata(feed_path.join(feed_id).with_extension("json"))
+                .map(|metadata| metadata.len())
+                .unwrap_or(0);
+            let feed_stats = FeedStats {
+                feed_id: feed_id.clone(),
+                name: feed.display_name().to_owned(),
+                items: feed.item_count(),
+                headers: feed.feed_headers().len(),
+                file_size,
+                oldest_item: feed.first_item_date(),
+                newest_item: feed.last_item_date(),
+            };
+            stats.total_items += feed_stats.items;
+            stats.total_headers += feed_stats.headers;
+            stats.total_file_size += feed_stats.file_size;
+            stats.feeds.push(feed_stats);
+        }
+        stats
+            .feeds
+            .sort_by(|a, b| b.file_size.cmp(&a.file_size).then(a.name.cmp(&b.name)));
+        stats
+    }
+}
+
+/// The length of the periods of an [`ActivityHistogram`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityPeriod {
+    /// Weeks starting on monday
+    Week,
+    Month,
+}
+
+impl ActivityPeriod {
+    /// The first day of the period that contains `date`
+    fn start_of(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            ActivityPeriod::Week => {
+                date - Duration::days(date.weekday().num_days_from_monday() as i64)
+            }
+            ActivityPeriod::Month => date.with_day(1).unwrap(),
+        }
+    }
+    /// The first day of the period before the one starting at `start`
+    fn previous(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            ActivityPeriod::Week => start - Duration::days(7),
+            ActivityPeriod::Month => self.start_of(start - Duration::days(1)),
+        }
+    }
+    /// The starts of the `periods` periods up to the one containing `today`,
+    /// oldest first
+    fn starts(self, periods: usize, today: NaiveDate) -> Vec<NaiveDate> {
+        let mut starts = Vec::with_capacity(periods);
+        let mut start = self.start_of(today);
+        for _ in 0..periods {
+            starts.push(start);
+            start = self.previous(start);
+        }
+        starts.reverse();
+        starts
+    }
+}
+
+/// How many items a feed published per week or month
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct ActivityHistogram {
+    /// The first day of each period, oldest first
+    pub starts: Vec<NaiveDate>,
+    pub counts: Vec<usize>,
+    /// Average over all periods, per week, rounded to one decimal
+    pub per_week: f64,
+}
+
+impl Feed {
+    /// Counts the items by publish date, for the `periods` periods up to the
+    /// one containing `today`. Items without a date are left out.
+    pub fn activity_histogram(
+        &self,
+        period: ActivityPeriod,
+        periods: usize,
+        today: NaiveDate,
+    ) -> ActivityHistogram {
+        let starts = period.starts(periods, today);
+        let mut counts = vec![0; starts.len()];
+        for item in self.items() {
+            let date = match item.publish_date() {
+                Some(date) => date.naive_utc().date(),
+                None => continue,
+            };
+            // Newer items than `today` count for the last period
+            match starts.partition_point(|start| *start <= date) {
+                0 => {}
+                i => counts[i - 1] += 1,
+            }
+        }
+
+        let days = starts
+            .first()
+            .map_or(0, |first| (today - *first).num_days() + 1);
+        let total: usize = counts.iter().sum();
+        let per_week = if days > 0 {
+            (total as f64 * 70.0 / days as f64).round() / 10.0
+        } else {
+            0.0
+        };
+        ActivityHistogram {
+            starts,
+            counts,
+            per_week,
+        }
+    }
+}
+
+#[test]
+fn test_activity_periods() {
+    let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+    // A wednesday
+    let today = date(2023, 3, 8);
+    assert_eq!(
+        ActivityPeriod::Week.starts(3, today),
+        [date(2023, 2, 20), date(2023, 2, 27), date(2023, 3, 6)]
+    );
+    assert_eq!(
+        ActivityPeriod::Month.starts(3, today),
+        [date(2023, 1, 1), date(2023, 2, 1), date(2023, 3, 1)]
+    );
+    assert!(ActivityPeriod::Month.starts(0, today).is_empty());
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use crate::{database::Database, Error, Feed};
+
+const USER_DATA_VERSION: u32 = 1;
+
+/// The read state of the items of a feed, by item identity, so it can be
+/// applied to another storage that numbered the items differently.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default)]
+pub struct FeedReadState {
+    pub name: String,
+    pub feed_url: Option<String>,
+    #[serde(default)]
+    pub read: BTreeSet<String>,
+    /// Items in the trash
+    #[serde(default)]
+    pub hidden: BTreeSet<String>,
+}
+
+/// The read state of all feeds, to sync it between instances.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+pub struct UserDataExport {
+    pub version: u32,
+    pub feeds: Vec<FeedReadState>,
+}
+
+#[derive(Debug, Default)]
+pub struct UserDataMergeReport {
+    pub marked_read: usize,
+    pub hidden: usize,
+    /// Feeds of the export that are not subscribed here
+    pub unknown_feeds: usize,
+}
+
+impl UserDataExport {
+    fn validate(&self) -> Result<(), Error> {
+        if self.version != USER_DATA_VERSION {
+            return Err(Error::InvalidUserData(format!(
+                "unsupported version {}, expected {}",
+                self.version, USER_DATA_VERSION
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn read_from(path: &Path) -> Result<Self, Error> {
+        let export = std::fs::read_to_string(path)?;
+        serde_json::from_str(&export).map_err(|e| Error::InvalidUserData(e.to_string()))
+    }
+    pub fn write_to(&self, path: &Path) -> Result<(), Error> {
+        std::fs::write(path, serde_json::to_string_pretty(self).unwrap())?;
+        Ok(())
+    }
+
+    /// Adds the read state of `other`. Items stay read once they are read
+    /// anywhere, so merging in any order gives the same result.
+    pub fn merge(&mut self, other: UserDataExport) -> Result<(), Error> {
+        self.validate()?;
+        other.validate()?;
+        let mut feeds: BTreeMap<(String, Option<String>), FeedReadState> = self
+            .feeds
+            .drain(..)
+            .map(|feed| ((feed.name.clone(), feed.feed_url.clone()), feed))
+            .collect();
+        for feed in other.feeds {
+            let entry = feeds
+                .entry((feed.name.clone(), feed.feed_url.clone()))
+                .or_insert_with(|| FeedReadState {
+                    name: feed.name,
+                    feed_url: feed.feed_url,
+                    ..FeedReadState::default()
+                });
+            entry.read.extend(feed.read);
+            entry.hidden.extend(feed.hidden);
+        }
+        self.feeds = feeds.into_values().collect();
+        Ok(())
+    }
+}
+
+impl Database {
+    pub fn export_user_data(&self) -> UserDataExport {
+        let feeds = self
+            .storage
+            .iter()
+            .map(|(feed_id, feed)| {
+                let mut state = FeedReadState {
+                    name: feed.name().to_owned(),
+                    feed_url: feed.feed_url().map(str::to_owned),
+                    ..FeedReadState::default()
+                };
+                for (item, item_state) in self.items_with_state(feed_id) {
+                    if item_state.read {
+                        state.read.insert(item.item.identity());
+                    }
+                    if item_state.hidden {
+                        state.hidden.insert(item.item.identity());
+                    }
+                }
+                state
+            })
+            .filter(|state| !state.read.is_empty() || !state.hidden.is_empty())
+            .collect();
+
+        UserDataExport {
+            version: USER_DATA_VERSION,
+            feeds,
+        }
+    }
+
+    /// Marks the items of an export as read or hidden. Nothing gets marked
+    /// unread, and items that are not stored here yet are skipped. Feeds are
+    /// matched the same way as during imports.
+    pub fn merge_user_data(
+        &mut self,
+        export: UserDataExport,
+    ) -> Result<UserDataMergeReport, Error> {
+        export.validate()?;
+
+        let mut report = UserDataMergeReport::default();
+        for state in export.feeds {
+            let mut key = Feed::new(state.name);
+            *key.feed_url_mut() = state.feed_url;
+            let feed_id = match self.lookup.check(key.key()) {
+                Some(feed_id) => feed_id,
+                None => {
+                    report.unknown_feeds += 1;
+                    continue;
+                }
+            };
+            let feed = match self.storage.get(&feed_id) {
+                Some(feed) => feed,
+                None => continue,
+            };
+            for item in feed.items() {
+                let identity = item.item.identity();
+                if state.read.contains(&identity)
+                    && self.user_data_storage.merge_read(&feed_id, item.id())
+                {
+                    report.marked_read += 1;
+                }
+                if state.hidden.contains(&identity) && !self.is_hidden(&feed_id, item.id()) {
+                    self.user_data_storage.hide(&feed_id, item.id());
+                    report.hidden += 1;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[test]
+fn test_merge_exports() {
+    let state = |read: &[&str]| FeedReadState {
+        name: "A".into(),
+        feed_url: Some("https://a.example/feed".into()),
+        read: read.iter().map(|id| id.to_string()).collect(),
+        hidden: BTreeSet::new(),
+    };
+    let mut laptop = UserDataExport {
+        version: USER_DATA_VERSION,
+        feeds: vec![state(&["guid:1", "guid:2"])],
+    };
+    let server = UserDataExport {
+        version: USER_DATA_VERSION,
+        feeds: vec![state(&["guid:2", "guid:3"])],
+    };
+    laptop.merge(server).unwrap();
+
+    assert_eq!(laptop.feeds.len(), 1);
+    assert_eq!(
+        laptop.feeds[0].read.iter().collect::<Vec<_>>(),
+        ["guid:1", "guid:2", "guid:3"]
+    );
+
+    let outdated = UserDataExport {
+        version: 0,
+        feeds: Vec::new(),
+    };
+    assert!(laptop.merge(outdated).is_err());
+}
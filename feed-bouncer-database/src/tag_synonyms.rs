@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+
+use crate::database::{storage_feed::Feed, Database};
+
+/// Other names of tags, like `golang` for `go`. Synonyms get replaced by
+/// their canonical tag when feeds are tagged and when filters are parsed.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct TagSynonyms(BTreeMap<String, String>);
+
+impl TagSynonyms {
+    /// The tag `tag` stands for, itself if it is no synonym
+    pub fn canonical<'a>(&'a self, tag: &'a str) -> &'a str {
+        self.0.get(tag).map_or(tag, |canonical| &canonical[..])
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// Pairs of synonym and canonical tag, by synonym
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(synonym, canonical)| (&synonym[..], &canonical[..]))
+    }
+    /// The synonyms of `canonical`
+    pub fn synonyms_of<'a>(&'a self, canonical: &'a str) -> impl Iterator<Item = &'a str> {
+        self.iter()
+            .filter(move |(_, c)| *c == canonical)
+            .map(|(synonym, _)| synonym)
+    }
+    /// Makes `synonym` stand for `canonical`, or for the tag `canonical` is
+    /// a synonym of. Former synonyms of `synonym` move along.
+    pub fn insert(&mut self, synonym: &str, canonical: &str) -> Result<(), String> {
+        let canonical = self.canonical(canonical).to_owned();
+        if synonym == canonical {
+            return Err(format!("{} can't be a synonym of itself", synonym));
+        }
+        for target in self.0.values_mut() {
+            if target == synonym {
+                *target = canonical.clone();
+            }
+        }
+        self.0.insert(synonym.to_owned(), canonical);
+        Ok(())
+    }
+    pub fn remove(&mut self, synonym: &str) -> bool {
+        self.0.remove(synonym).is_some()
+    }
+    /// Replaces the synonyms among the tags of `feed`, returns whether any
+    /// were found
+    pub fn apply(&self, feed: &mut Feed) -> bool {
+        let synonyms: Vec<String> = feed
+            .tags()
+            .filter(|tag| self.0.contains_key(*tag))
+            .map(str::to_owned)
+            .collect();
+        for synonym in &synonyms {
+            feed.remove_tag(synonym);
+            feed.extend_tags([self.canonical(synonym)]);
+        }
+        !synonyms.is_empty()
+    }
+}
+
+impl Database {
+    pub fn tag_synonyms(&self) -> &TagSynonyms {
+        self.user_data_storage.tag_synonyms()
+    }
+    pub fn add_tag_synonym(&mut self, synonym: &str, canonical: &str) -> Result<(), String> {
+        self.user_data_storage
+            .tag_synonyms_mut()
+            .insert(synonym, canonical)
+    }
+    pub fn remove_tag_synonym(&mut self, synonym: &str) -> bool {
+        self.user_data_storage.tag_synonyms_mut().remove(synonym)
+    }
+    /// Replaces the synonyms among the tags of all feeds by their canonical
+    /// tags. Returns the number of changed feeds.
+    pub fn merge_tag_synonyms(&mut self) -> usize {
+        let synonyms = self.user_data_storage.tag_synonyms();
+        let mut changed = 0;
+        for (_, feed) in self.storage.iter_mut() {
+            if synonyms.apply(feed) {
+                changed += 1;
+            }
+        }
+        changed
+    }
+}
+
+#[test]
+fn test_tag_synonyms() {
+    let mut synonyms = TagSynonyms::default();
+    synonyms.insert("golang", "go").unwrap();
+    synonyms.insert("go_lang", "golang").unwrap();
+    assert_eq!(synonyms.canonical("go_lang"), "go");
+    assert_eq!(synonyms.canonical("golang"), "go");
+    assert_eq!(synonyms.canonical("rust"), "rust");
+    assert!(synonyms.insert("go", "golang").is_err());
+
+    // `go` becomes a synonym itself, its synonyms follow
+    synonyms.insert("go", "programming").unwrap();
+    assert_eq!(synonyms.canonical("golang"), "programming");
+    assert_eq!(synonyms.synonyms_of("programming").count(), 3);
+
+    let mut feed = Feed::new("Blog".to_owned());
+    feed.extend_tags(["golang", "rust", "programming"]);
+    assert!(synonyms.apply(&mut feed));
+    assert_eq!(feed.tags().collect::<Vec<_>>(), ["programming", "rust"]);
+    assert!(!synonyms.apply(&mut feed));
+}
@@ -0,0 +1,112 @@
+use std::io::Cursor;
+
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    Request, Response,
+};
+
+/// Serves the pages under a path prefix, for reverse proxies that don't strip
+/// it. The routes are mounted below the prefix, and the root-relative links
+/// of HTML pages and redirects get it added, so templates and `uri!` can keep
+/// using root-relative urls.
+pub struct BasePath(String);
+
+/// The attributes whose root-relative urls get prefixed, including those the
+/// scripts of the pages fetch. Other attributes, like `value` of a form
+/// field, are left alone.
+const LINK_ATTRIBUTES: &[&str] = &[
+    "href",
+    "src",
+    "action",
+    "formaction",
+    "data-link",
+    "data-partial",
+];
+
+impl BasePath {
+    /// `None` for an empty prefix or `/`
+    pub fn new(raw: &str) -> Option<Self> {
+        let base = raw.trim().trim_matches('/');
+        (!base.is_empty()).then(|| Self(format!("/{}", base)))
+    }
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Prefixes the values of `LINK_ATTRIBUTES` starting with a single `/`
+    fn prefix_links(&self, html: &str) -> String {
+        let mut prefixed = String::with_capacity(html.len());
+        let mut rest = html;
+        while let Some(pos) = rest.find("=\"/") {
+            let (before, after) = rest.split_at(pos + 2);
+            prefixed.push_str(before);
+            rest = after;
+            let attribute = before[..pos]
+                .rsplit(|c: char| c.is_ascii_whitespace())
+                .next()
+                .unwrap_or_default();
+            let is_link = LINK_ATTRIBUTES
+                .iter()
+                .any(|name| attribute.eq_ignore_ascii_case(name));
+            // Protocol relative urls point to other hosts
+            if is_link && !rest.starts_with("//") {
+                prefixed.push_str(&self.0);
+            }
+        }
+        prefixed.push_str(rest);
+        prefixed
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for BasePath {
+    fn info(&self) -> Info {
+        Info {
+            name: "Base path",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, _req: &'r Request<'_>, res: &mut Response<'r>) {
+        let location = res
+            .headers()
+            .get_one("Location")
+            .filter(|location| location.starts_with('/') && !location.starts_with("//"))
+            .map(|location| format!("{}{}", self.0, location));
+        if let Some(location) = location {
+            res.set_raw_header("Location", location);
+        }
+
+        if res
+            .content_type()
+            .map_or(false, |content_type| content_type.is_html())
+        {
+            match res.body_mut().to_string().await {
+                Ok(html) => {
+                    let html = self.prefix_links(&html);
+                    res.set_sized_body(html.len(), Cursor::new(html));
+                }
+                Err(e) => eprintln!("WARN: Could not prefix the links of a page: {}", e),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_prefix_links() {
+    let base = BasePath::new("/feeds/").unwrap();
+    assert_eq!(
+        base.prefix_links(r#"<a href="/feed/a">a</a><form action="/update">"#),
+        r#"<a href="/feeds/feed/a">a</a><form action="/feeds/update">"#
+    );
+    assert_eq!(
+        base.prefix_links(
+            r#"<span data-link="/api/update_progress"><div data-partial="/?page=2">"#
+        ),
+        r#"<span data-link="/feeds/api/update_progress"><div data-partial="/feeds/?page=2">"#
+    );
+    // Other hosts, other attributes and text stay as they are
+    let untouched = r#"<img src="//cdn.example.com/a.png"><input value="/path"> x="/y""#;
+    assert_eq!(base.prefix_links(untouched), untouched);
+    assert!(BasePath::new("/").is_none());
+}
@@ -1,27 +1,165 @@
 use std::sync::Arc;
 
-use chrono::{Datelike, IsoWeek, NaiveDate, Weekday};
-use feed_bouncer_database::{Database, Feed, FeedId, FeedItemMeta};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Utc, Weekday};
+use feed_bouncer_database::{
+    normalize_language, Database, Feed, FeedId, FeedItemMeta, FeedQuery, ItemGrouping, ItemPage,
+    ItemQuery, ItemSortKey, ItemState, TagSynonyms, UpdateProgress, UpdateProgressSnapshot,
+    ViewMode,
+};
 use rocket::tokio::sync::RwLock;
 
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct ItemBase<S> {
     feed_name: S,
     feed_id: S,
+    /// Icon of the feed, shown in its source chip
+    feed_icon: Option<String>,
+    /// The item is from the same feed as the item before it, they share a
+    /// source chip
+    same_feed_as_previous: bool,
     item_name: S,
+    /// The item name got shortened for the compact view
+    item_name_truncated: bool,
     content_link: Option<S>,
+    summary: Option<S>,
     show_feed: bool,
     item_id: usize,
+    /// Stable id of the item's row, for links and keyboard navigation
+    anchor: String,
     is_read: bool,
+    is_hidden: bool,
+    /// Shown on a share page, without links to private pages or actions
+    shared: bool,
+    provenance_link: String,
+    /// Link to the item that stays valid across fetches
+    permalink: String,
+    /// Link to the archived copy of the linked article
+    archived_link: Option<String>,
+    podcast: Option<PodcastInfo<S>>,
+    categories: Vec<CategoryChip<S>>,
+    /// The feed url the item was fetched from, if the feed has another one
+    /// by now
+    via: Option<S>,
+    /// Dates of the item, for a tooltip
+    published: Option<String>,
+    first_seen: Option<String>,
+}
+
+/// iTunes and Dublin Core metadata of podcast items
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PodcastInfo<S> {
+    episode: Option<u32>,
+    season: Option<u32>,
+    duration: Option<String>,
+    explicit: bool,
+    creator: Option<S>,
+}
+
+/// A category of an item, linking to the items of the category within the
+/// current filter
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct CategoryChip<S> {
+    name: S,
+    link: String,
+}
+
+impl<'a> CategoryChip<&'a str> {
+    fn for_item(item: &'a FeedItemMeta, filter: Option<&str>) -> Vec<Self> {
+        item.item
+            .categories()
+            .into_iter()
+            // Commas separate the terms of filters
+            .filter(|name| !name.contains(','))
+            .map(|name| Self {
+                name,
+                link: category_link(filter, name),
+            })
+            .collect()
+    }
+}
+
+/// The index filtered by `category`, within `filter`
+pub fn category_link(filter: Option<&str>, category: &str) -> String {
+    let filter = match filter {
+        Some(raw) => format!("{},cat:{}", raw, category),
+        None => format!("cat:{}", category),
+    };
+    uri!(crate::pages::index::index(
+        Some(&filter[..]),
+        _,
+        _,
+        _,
+        _,
+        _,
+        _,
+        _,
+        _,
+        _,
+        _
+    ))
+    .to_string()
+}
+
+impl<'a> PodcastInfo<&'a str> {
+    fn new(item: &'a FeedItemMeta) -> Option<Self> {
+        let item = &item.item;
+        let info = Self {
+            episode: item.episode(),
+            season: item.season(),
+            duration: item.duration().map(|duration| {
+                let secs = duration.as_secs();
+                if secs >= 60 * 60 {
+                    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+                } else {
+                    format!("{}:{:02}", secs / 60, secs % 60)
+                }
+            }),
+            explicit: item.explicit().unwrap_or(false),
+            creator: item.dc_creator(),
+        };
+        let is_empty = info.episode.is_none()
+            && info.season.is_none()
+            && info.duration.is_none()
+            && !info.explicit
+            && info.creator.is_none();
+        (!is_empty).then(|| info)
+    }
 }
 
 pub type Item<'a> = ItemBase<&'a str>;
 pub type ItemOwned = ItemBase<String>;
 
+pub fn item_anchor(feed_id: &FeedId, item_id: usize) -> String {
+    format!("item-{}-{}", feed_id, item_id)
+}
+
+/// A row between the items that are new since the last visit and the others
+#[derive(serde::Serialize)]
+pub struct Divider {
+    is_divider: bool,
+}
+
+/// A row marking the point in time a feed was subscribed to.
+#[derive(serde::Serialize)]
+pub struct Marker<'a> {
+    is_marker: bool,
+    feed_name: &'a str,
+    feed_id: &'a str,
+}
+
+#[derive(serde::Serialize)]
+#[serde(untagged)]
+pub enum Row<'a> {
+    Item(Item<'a>),
+    Marker(Marker<'a>),
+    Divider(Divider),
+}
+
 #[derive(serde::Serialize)]
 pub struct ItemsGroup<'a> {
-    items: Vec<Item<'a>>,
-    week: u32,
+    items: Vec<Row<'a>>,
+    /// Heading of the group, none if all items are in a single group
+    label: Option<String>,
     start: NaiveDate,
     end: NaiveDate,
 }
@@ -29,6 +167,79 @@ pub struct ItemsGroup<'a> {
 #[derive(serde::Serialize)]
 pub struct ItemsGroups<'a> {
     item_groups: Vec<ItemsGroup<'a>>,
+    /// All items are in a single group without a heading
+    compact: bool,
+    /// Set if the items are one page of a longer list
+    page: Option<PageNav>,
+}
+
+/// A page of items, rendered with `{{page_nav items.page}}`
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PageNav {
+    #[serde(flatten)]
+    pub page: ItemPage,
+    /// Link to the list without a `page` parameter, which gets added for
+    /// the links to the other pages
+    pub link: String,
+}
+
+/// Length item names get shortened to in the compact view
+const COMPACT_TITLE_CHARS: usize = 60;
+
+/// Number of tag timelines linked in the navigation
+const NAV_TAGS: usize = 15;
+
+/// Resolves the `view` parameter of a page. An explicitly given view mode
+/// becomes the new preference, stored by the next save, otherwise the
+/// current one is used.
+pub async fn view_mode(db: &SyncDatabase, view: Option<&str>) -> ViewMode {
+    let db = db.read().await;
+    match view.and_then(|view| view.parse().ok()) {
+        Some(view) => {
+            db.note_view_mode(view);
+            view
+        }
+        None => db.view_mode(),
+    }
+}
+
+/// Resolves the `group` parameter of a page, remembered like the view mode
+pub async fn item_grouping(db: &SyncDatabase, group: Option<&str>) -> ItemGrouping {
+    let db = db.read().await;
+    match group.and_then(|group| group.parse().ok()) {
+        Some(grouping) => {
+            db.note_item_grouping(grouping);
+            grouping
+        }
+        None => db.item_grouping(),
+    }
+}
+
+/// A link to the same page with another item grouping, none for the
+/// current one
+#[derive(serde::Serialize)]
+pub struct GroupingLink {
+    name: &'static str,
+    link: Option<String>,
+}
+
+pub fn grouping_links(
+    current: ItemGrouping,
+    link: impl Fn(ItemGrouping) -> String,
+) -> Vec<GroupingLink> {
+    ItemGrouping::ALL
+        .into_iter()
+        .map(|grouping| GroupingLink {
+            name: grouping.as_str(),
+            link: (grouping != current).then(|| link(grouping)),
+        })
+        .collect()
+}
+
+#[derive(serde::Serialize)]
+pub struct ViewLink<'a> {
+    name: &'a str,
+    link: String,
 }
 
 #[derive(serde::Serialize)]
@@ -37,39 +248,175 @@ pub struct Nav<'a> {
     filter: &'a str,
     home_link: String,
     feeds_link: String,
+    saved_views: Vec<ViewLink<'a>>,
+    /// The timelines of the most used tags
+    tag_links: Vec<ViewLink<'a>>,
+    /// Unread items matching the filter, also shown in the page title
+    unread: usize,
+    dates: Option<DateRangeNav>,
+    /// Set while an update is running
+    update: Option<UpdateProgressSnapshot>,
+}
+
+/// Links to move the date range of a page by its length, a week by default
+#[derive(serde::Serialize)]
+pub struct DateRangeNav {
+    /// Describes the range, `None` if the page shows all dates
+    label: Option<String>,
+    /// The previous range, or the last week if the page shows all dates
+    prev_link: String,
+    /// `None` if the range already reaches today
+    next_link: Option<String>,
+    all_link: Option<String>,
 }
 
 impl<'a> Nav<'a> {
-    pub fn new(db: &Database, filter: &'a Filter) -> Self {
+    pub fn new(db: &'a Database, filter: &'a Filter) -> Self {
+        let mut saved_views: Vec<_> = db.saved_views().iter().collect();
+        // Most used first, the sort is stable so ties stay ordered by name
+        saved_views.sort_by_key(|(_, view)| std::cmp::Reverse(db.filter_uses(&view.filter)));
+        let saved_views = saved_views
+            .into_iter()
+            .map(|(name, view)| ViewLink {
+                name,
+                link: uri!(crate::pages::index::index(
+                    Some(&view.filter[..]),
+                    Some(view.order.as_str()),
+                    Some(view.unread_only),
+                    _,
+                    _,
+                    _,
+                    _,
+                    _,
+                    _,
+                    _,
+                    _
+                ))
+                .to_string(),
+            })
+            .collect();
+        let tag_links = crate::pages::timeline::known_tags(db)
+            .into_iter()
+            .take(NAV_TAGS)
+            .map(|tag| ViewLink {
+                name: tag,
+                link: uri!(crate::pages::timeline::tag_timeline(tag, _, _, _, _, _, _)).to_string(),
+            })
+            .collect();
+
+        // Counted like the items of the index page
+        let mut unread = ItemQuery::new()
+            .feeds(filter.query().clone())
+            .read(false)
+            .hidden(false)
+            .dedup_links();
+        if filter.hides_muted() {
+            unread = unread.without_muted();
+        }
+        let unread = db.query_items(&unread).len();
+
         Self {
             last_update: db.last_feed_update().map(|v| v.to_rfc3339()),
             filter: filter.raw(),
-            home_link: uri!(crate::pages::index::index(filter.raw_opt())).to_string(),
-            feeds_link: uri!(crate::pages::feeds::feeds(filter.raw_opt())).to_string(),
+            home_link: uri!(crate::pages::index::index(
+                filter.raw_opt(),
+                _,
+                _,
+                _,
+                _,
+                _,
+                _,
+                _,
+                _,
+                _,
+                _
+            ))
+            .to_string(),
+            feeds_link: uri!(crate::pages::feeds::feeds(filter.raw_opt(), _, _, _)).to_string(),
+            saved_views,
+            tag_links,
+            unread,
+            dates: None,
+            update: None,
         }
     }
+
+    /// Shows the progress of the update, if one is running
+    pub fn with_update_progress(mut self, progress: &UpdateProgress) -> Self {
+        self.update = Some(progress.snapshot()).filter(|progress| progress.running);
+        self
+    }
+
+    /// Adds links to the previous and next range of dates. `to` is
+    /// inclusive, `link` builds the page url for a range.
+    pub fn with_date_range(
+        mut self,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        link: impl Fn(Option<NaiveDate>, Option<NaiveDate>) -> String,
+    ) -> Self {
+        let today = Utc::now().date_naive();
+        let label = match (from, to) {
+            (None, None) => None,
+            (Some(from), None) => Some(format!("since {}", from)),
+            (None, Some(to)) => Some(format!("until {}", to)),
+            (Some(from), Some(to)) => Some(format!("from {} to {}", from, to)),
+        };
+        let dates = match (from, to) {
+            (None, None) => {
+                let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+                DateRangeNav {
+                    label,
+                    prev_link: link(
+                        Some(monday - Duration::days(7)),
+                        Some(monday - Duration::days(1)),
+                    ),
+                    next_link: None,
+                    all_link: None,
+                }
+            }
+            _ => {
+                let length = match (from, to) {
+                    (Some(from), Some(to)) if to >= from => to - from + Duration::days(1),
+                    _ => Duration::days(7),
+                };
+                let shift = |date: Option<NaiveDate>, by: Duration| date.map(|date| date + by);
+                let reaches_today = to.map_or(true, |to| to >= today);
+                DateRangeNav {
+                    label,
+                    prev_link: link(shift(from, -length), shift(to, -length)),
+                    next_link: (!reaches_today)
+                        .then(|| link(shift(from, length), shift(to, length))),
+                    all_link: Some(link(None, None)),
+                }
+            }
+        };
+        self.dates = Some(dates);
+        self
+    }
 }
 
 pub type SyncDatabase = Arc<RwLock<Database>>;
 
-pub enum FilterPattern {
-    Has(Tag),
-    HasNot(Tag),
-}
 pub struct Filter {
-    pattern: Vec<FilterPattern>,
+    query: FeedQuery,
     raw: String,
-    exact: bool,
+    /// The default filter was used because the request gave none
+    is_default: bool,
 }
 
-pub const VALID_TAG_CHARS: &str = "abcdefghijklmnopqrstuvwxyz_";
+/// Tags consist of lowercase letters, digits and underscores, like the
+/// tags of OPML folders
+pub fn is_valid_tag_char(c: char) -> bool {
+    c == '_' || (c.is_alphanumeric() && !c.is_uppercase())
+}
 
 #[derive(Debug, Clone)]
 pub struct Tag(String);
 impl Tag {
     pub fn new(raw: &str) -> Option<Self> {
         let raw = raw.trim();
-        if raw.is_empty() || raw.chars().any(|c| !VALID_TAG_CHARS.contains(c)) {
+        if raw.is_empty() || !raw.chars().all(is_valid_tag_char) {
             return None;
         }
         Some(Self(raw.to_owned()))
@@ -80,121 +427,373 @@ impl Tag {
 }
 
 impl Filter {
-    pub fn new(raw: Option<String>) -> Self {
+    /// Parses comma separated terms: tags, `lang:<code>`, `feed:<feed_id>`,
+    /// `title:<text>`, `author:<name>` and `cat:<category>`, all but titles
+    /// and authors negated with a leading `!`, and `=` to require exactly the
+    /// given tags. `!cat:` hides the items of a category. Tag synonyms are
+    /// replaced by their canonical tags.
+    pub fn new(raw: Option<String>, synonyms: &TagSynonyms) -> Self {
         let raw = raw.unwrap_or_default();
-        let mut pattern = Vec::new();
-        let mut exact = false;
+        let mut query = FeedQuery::new();
         for raw in raw.split(',') {
             let raw = raw.trim();
             if raw == "=" {
-                exact = true;
+                query = query.exact_tags(true);
                 continue;
             }
-            let (raw, pat) = raw
+            let (raw, negated) = raw
                 .strip_prefix("!")
-                .map(|raw| (raw, FilterPattern::HasNot as fn(_) -> _))
-                .unwrap_or((raw, FilterPattern::Has));
+                .map(|raw| (raw, true))
+                .unwrap_or((raw, false));
+            if let Some(language) = raw.strip_prefix("lang:") {
+                query = match normalize_language(language) {
+                    Some(language) if negated => query.without_language(language),
+                    Some(language) => query.with_language(language),
+                    None => query,
+                };
+                continue;
+            }
+            if let Some(feed_id) = raw.strip_prefix("feed:") {
+                let feed_id = feed_id.trim();
+                if !feed_id.is_empty() {
+                    query = if negated {
+                        query.without_feed(feed_id)
+                    } else {
+                        query.feed(feed_id)
+                    };
+                }
+                continue;
+            }
+            if let Some(part) = raw.strip_prefix("title:") {
+                // Titles can't be excluded
+                let part = part.trim();
+                if !part.is_empty() && !negated {
+                    query = query.title_contains(part);
+                }
+                continue;
+            }
+            if let Some(author) = raw.strip_prefix("author:") {
+                // Authors can't be excluded either
+                let author = author.trim();
+                if !author.is_empty() && !negated {
+                    query = query.by_author(author);
+                }
+                continue;
+            }
+            if let Some(category) = raw.strip_prefix("cat:") {
+                let category = category.trim();
+                if !category.is_empty() {
+                    query = if negated {
+                        query.not_in_category(category)
+                    } else {
+                        query.in_category(category)
+                    };
+                }
+                continue;
+            }
             let tag = match Tag::new(raw) {
                 Some(tag) => tag,
                 None => continue,
             };
-            pattern.push(pat(tag));
+            query = if negated {
+                query.without_tag(tag.0)
+            } else {
+                query.with_tag(tag.0)
+            };
         }
 
         Self {
-            pattern,
+            query: query.with_tag_synonyms(synonyms),
             raw,
-            exact,
-        }
-    }
-    pub fn matches(&self, feed: &Feed) -> bool {
-        let mut matches: usize = 0;
-        for pattern in &self.pattern {
-            match pattern {
-                FilterPattern::Has(tag) => {
-                    if !feed.contains_tag(tag.as_str()) {
-                        return false;
-                    }
-                    matches += 1;
-                }
-                FilterPattern::HasNot(tag) => {
-                    if feed.contains_tag(tag.as_str()) {
-                        return false;
-                    }
-                }
-            }
+            is_default: false,
         }
-
-        (!self.exact) || (matches == feed.tags().count())
+    }
+    /// Uses the default filter of `db` if the request gave no filter. An
+    /// empty filter in the request turns the default off.
+    pub fn or_default(raw: Option<String>, db: &Database) -> Self {
+        let default = db.default_filter();
+        match raw {
+            None if !default.is_empty() => Self {
+                is_default: true,
+                ..Self::new(Some(default.to_owned()), db.tag_synonyms())
+            },
+            raw => Self::new(raw, db.tag_synonyms()),
+        }
+    }
+    pub fn is_default(&self) -> bool {
+        self.is_default
+    }
+    /// Muted feeds only show up when a filter asks for them
+    pub fn hides_muted(&self) -> bool {
+        self.is_default || self.raw.trim().is_empty()
+    }
+    pub fn query(&self) -> &FeedQuery {
+        &self.query
     }
     pub fn raw(&self) -> &str {
         &self.raw
     }
+    /// The filter for links, `None` if it is empty or the default
     pub fn raw_opt(&self) -> Option<&str> {
-        (!self.raw.is_empty()).then(|| &self.raw[..])
+        (!self.raw.is_empty() && !self.is_default).then(|| &self.raw[..])
     }
 }
 
-fn week_bounds(year: i32, week: u32) -> (NaiveDate, NaiveDate) {
-    let mon = NaiveDate::from_isoywd(year, week, Weekday::Mon);
-    let sun = NaiveDate::from_isoywd(year, week, Weekday::Sun);
-    (mon, sun)
+/// The first and last day of the group containing `date`, and its heading
+fn group_bounds(grouping: ItemGrouping, date: NaiveDate) -> (NaiveDate, NaiveDate, Option<String>) {
+    match grouping {
+        ItemGrouping::Day => (date, date, Some(date.format("%A, %Y-%m-%d").to_string())),
+        ItemGrouping::Week => {
+            let week = date.iso_week();
+            let mon = NaiveDate::from_isoywd(week.year(), week.week(), Weekday::Mon);
+            let sun = NaiveDate::from_isoywd(week.year(), week.week(), Weekday::Sun);
+            let label = format!("Week {} ({} \u{2014} {})", week.week(), mon, sun);
+            (mon, sun, Some(label))
+        }
+        ItemGrouping::Month => {
+            let first = date.with_day(1).unwrap();
+            let next_month = if first.month() == 12 {
+                NaiveDate::from_ymd_opt(first.year() + 1, 1, 1)
+            } else {
+                NaiveDate::from_ymd_opt(first.year(), first.month() + 1, 1)
+            };
+            let last = next_month.and_then(|date| date.pred_opt()).unwrap_or(date);
+            (first, last, Some(first.format("%B %Y").to_string()))
+        }
+        ItemGrouping::None => (NaiveDate::MIN, NaiveDate::MAX, None),
+    }
 }
 
 pub struct ItemBuilder<'a> {
     items: Vec<ItemsGroup<'a>>,
-    year: i32,
-    week: Option<IsoWeek>,
+    grouping: ItemGrouping,
     show_feed: bool,
+    markers: Vec<(DateTime<Utc>, Marker<'a>)>,
+    /// Cleared once the divider after the new items is pushed
+    last_visit: Option<DateTime<Utc>>,
+    pushed_new_item: bool,
+    filter: Option<&'a str>,
+    in_trash: bool,
+    shared: bool,
+    /// Looks up which items have an archived article
+    archive: Option<&'a Database>,
+    sort_key: ItemSortKey,
+    compact: bool,
+    page: Option<PageNav>,
 }
 
 impl<'a> ItemBuilder<'a> {
     pub fn new(show_feed: bool) -> Self {
         Self {
             items: Vec::new(),
-            year: 0,
-            week: None,
+            grouping: ItemGrouping::default(),
             show_feed,
+            markers: Vec::new(),
+            last_visit: None,
+            pushed_new_item: false,
+            filter: None,
+            in_trash: false,
+            shared: false,
+            archive: None,
+            sort_key: ItemSortKey::Published,
+            compact: false,
+            page: None,
         }
     }
 
-    pub fn push_sorted(
-        &mut self,
-        item: &'a FeedItemMeta,
-        feed_id: &'a FeedId,
-        feed: &'a Feed,
-        is_read: bool,
-    ) {
-        let date = item.publish_date_or_old().naive_utc();
-        let week = date.iso_week();
-        let year = date.year();
+    /// The items are shown in the trash, with links to restore them
+    pub fn in_trash(mut self) -> Self {
+        self.in_trash = true;
+        self
+    }
+
+    /// The items are shown on a share page, read-only
+    pub fn shared(mut self) -> Self {
+        self.shared = true;
+        self
+    }
+
+    /// Links the archived articles of the items
+    pub fn with_archive_links(mut self, db: &'a Database) -> Self {
+        self.archive = Some(db);
+        self
+    }
 
-        if Some(week) != self.week || year != self.year {
-            let (start, end) = week_bounds(week.year(), week.week());
+    /// Renders the items for the compact view
+    pub fn with_view_mode(mut self, view_mode: ViewMode) -> Self {
+        self.compact = view_mode == ViewMode::Compact;
+        self
+    }
+
+    /// The periods the items are grouped by, ignored in the compact view
+    pub fn with_grouping(mut self, grouping: ItemGrouping) -> Self {
+        self.grouping = grouping;
+        self
+    }
+
+    /// The date the items are sorted and grouped by
+    pub fn with_sort_key(mut self, key: ItemSortKey) -> Self {
+        self.sort_key = key;
+        self
+    }
+
+    /// The items are one page of a longer list, `link` leads to the list
+    pub fn with_page(mut self, page: ItemPage, link: String) -> Self {
+        self.page = Some(PageNav { page, link });
+        self
+    }
+
+    /// The filter the items are shown for, passed on to the provenance links
+    pub fn with_filter(mut self, filter: &'a Filter) -> Self {
+        self.filter = filter.raw_opt();
+        self
+    }
+
+    /// Adds a marker row for each feed at its subscription date. Markers that
+    /// are older than the last pushed item are not shown.
+    pub fn with_subscription_markers(
+        mut self,
+        feeds: impl IntoIterator<Item = (&'a FeedId, &'a Feed)>,
+    ) -> Self {
+        for (feed_id, feed) in feeds {
+            if let Some(date) = feed.subscribed_at() {
+                self.markers.push((
+                    date,
+                    Marker {
+                        is_marker: true,
+                        feed_name: feed.display_name(),
+                        feed_id,
+                    },
+                ));
+            }
+        }
+        self.markers.sort_by_key(|(date, _)| *date);
+        self
+    }
+
+    /// Adds a divider after the items first seen since `last_visit`, before
+    /// the first older item. Only fits items pushed newest first.
+    pub fn with_last_visit(mut self, last_visit: DateTime<Utc>) -> Self {
+        self.last_visit = Some(last_visit);
+        self
+    }
+
+    fn group_for(&mut self, date: NaiveDateTime) -> &mut ItemsGroup<'a> {
+        let date = date.date();
+        let grouping = if self.compact {
+            ItemGrouping::None
+        } else {
+            self.grouping
+        };
+        let in_last_group = matches!(
+            self.items.last(),
+            Some(group) if group.start <= date && date <= group.end
+        );
+        if !in_last_group {
+            let (start, end, label) = group_bounds(grouping, date);
             self.items.push(ItemsGroup {
                 items: Vec::new(),
-                week: week.week(),
+                label,
                 start,
                 end,
             });
-            self.year = year;
-            self.week = Some(week);
         }
 
-        self.items.last_mut().unwrap().items.push(Item {
+        self.items.last_mut().unwrap()
+    }
+
+    fn push_markers_until(&mut self, date: DateTime<Utc>) {
+        while matches!(self.markers.last(), Some((marker_date, _)) if *marker_date >= date) {
+            let (marker_date, marker) = self.markers.pop().unwrap();
+            self.group_for(marker_date.naive_utc())
+                .items
+                .push(Row::Marker(marker));
+        }
+    }
+
+    pub fn push_sorted(
+        &mut self,
+        item: &'a FeedItemMeta,
+        feed_id: &'a FeedId,
+        feed: &'a Feed,
+        state: ItemState,
+    ) {
+        let date = item.sort_date(self.sort_key);
+        self.push_markers_until(date.with_timezone(&Utc));
+        if let Some(last_visit) = self.last_visit {
+            if item.first_seen().map_or(false, |seen| seen > last_visit) {
+                self.pushed_new_item = true;
+            } else if self.pushed_new_item {
+                self.last_visit = None;
+                self.group_for(date.naive_utc())
+                    .items
+                    .push(Row::Divider(Divider { is_divider: true }));
+            }
+        }
+
+        let show_feed = self.show_feed;
+        let filter = self.filter;
+        let in_trash = self.in_trash;
+        let shared = self.shared;
+        let archived_link = self
+            .archive
+            .filter(|db| db.is_archived(feed_id, item.id()))
+            .map(|_| uri!(crate::pages::feed::item_archived(feed_id, item.id())).to_string());
+        let mut item_name = item.display_title_without_prefixes(&feed).unwrap_or("???");
+        let mut item_name_truncated = false;
+        if self.compact {
+            if let Some((end, _)) = item_name.char_indices().nth(COMPACT_TITLE_CHARS) {
+                item_name = item_name[..end].trim_end();
+                item_name_truncated = true;
+            }
+        }
+        let group = self.group_for(date.naive_utc());
+        let same_feed_as_previous = matches!(
+            group.items.last(),
+            Some(Row::Item(previous)) if previous.feed_id == feed_id.as_str()
+        );
+        group.items.push(Row::Item(Item {
             feed_name: feed.display_name(),
             feed_id: &feed_id,
-            item_name: item.display_title_without_prefixes(&feed).unwrap_or("???"),
+            feed_icon: feed.icon_link(),
+            same_feed_as_previous,
+            item_name,
+            item_name_truncated,
             content_link: item.content_link(),
+            summary: item
+                .content()
+                .map(|content| &content.summary[..])
+                .filter(|summary| !summary.is_empty()),
             item_id: item.id(),
-            show_feed: self.show_feed,
-            is_read: is_read,
-        });
+            anchor: item_anchor(feed_id, item.id()),
+            show_feed,
+            is_read: state.read,
+            is_hidden: in_trash,
+            shared,
+            provenance_link: uri!(crate::pages::feed::item_provenance(
+                feed_id,
+                item.id(),
+                filter
+            ))
+            .to_string(),
+            permalink: uri!(crate::pages::feed::item_permalink(item.slug(feed_id))).to_string(),
+            archived_link,
+            podcast: PodcastInfo::new(item),
+            categories: CategoryChip::for_item(item, filter),
+            via: item
+                .source()
+                .map(|source| &source.feed_url[..])
+                .filter(|url| !shared && Some(*url) != feed.feed_url()),
+            published: item.publish_date().map(|date| date.to_rfc3339()),
+            first_seen: item.first_seen().map(|date| date.to_rfc3339()),
+        }));
     }
 
     pub fn into_groups(self) -> ItemsGroups<'a> {
         ItemsGroups {
             item_groups: self.items,
+            compact: self.compact,
+            page: self.page,
         }
     }
 }
@@ -1,12 +1,15 @@
+use pulldown_cmark::{CowStr, Event, Parser, Tag};
 use rocket::serde::DeserializeOwned;
 use rocket_dyn_templates::{
-    handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderError},
+    handlebars::{
+        html_escape, Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderError,
+    },
     Engines,
 };
 
 use rocket_dyn_templates::handlebars::HelperDef;
 
-use crate::common::ItemOwned;
+use crate::common::{ItemOwned, PageNav};
 
 fn param_des<T: DeserializeOwned>(h: &Helper<'_, '_>, idx: usize) -> Result<T, RenderError> {
     let v = h
@@ -37,7 +40,174 @@ impl HelperDef for FeedList {
     }
 }
 
+/// Link schemes that markdown may link to, other links are emptied
+const MARKDOWN_LINK_SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+/// Relative links have no scheme, a `:` before any `/`, `?` or `#` starts one
+fn is_allowed_link(dest: &str) -> bool {
+    let end = dest
+        .find(|c| matches!(c, '/' | '?' | '#'))
+        .unwrap_or(dest.len());
+    match dest[..end].find(':') {
+        Some(colon) => MARKDOWN_LINK_SCHEMES
+            .iter()
+            .any(|scheme| dest[..colon].eq_ignore_ascii_case(scheme)),
+        None => true,
+    }
+}
+
+fn allowed_link(dest: CowStr<'_>) -> CowStr<'_> {
+    if is_allowed_link(&dest) {
+        dest
+    } else {
+        CowStr::Borrowed("")
+    }
+}
+
+/// Renders a markdown string, raw HTML in it is escaped. Links and images
+/// only keep http, https and mailto urls.
+struct Markdown;
+impl HelperDef for Markdown {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _r: &'reg Handlebars<'reg>,
+        _ctx: &'rc Context,
+        _rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let text: String = param_des(h, 0)?;
+
+        let parser = Parser::new(&text).map(|event| match event {
+            Event::Html(html) => Event::Text(html),
+            Event::Start(Tag::Link(kind, dest, title)) => {
+                Event::Start(Tag::Link(kind, allowed_link(dest), title))
+            }
+            Event::Start(Tag::Image(kind, dest, title)) => {
+                Event::Start(Tag::Image(kind, allowed_link(dest), title))
+            }
+            event => event,
+        });
+        let mut html = String::new();
+        pulldown_cmark::html::push_html(&mut html, parser);
+        out.write(&html)?;
+
+        Ok(())
+    }
+}
+
+/// Renders numbers as a small inline SVG bar chart. An optional second
+/// parameter has a label per bar, shown when hovering it:
+/// `{{sparkline activity.counts activity.starts}}`
+struct Sparkline;
+impl HelperDef for Sparkline {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _r: &'reg Handlebars<'reg>,
+        _ctx: &'rc Context,
+        _rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        const BAR_WIDTH: usize = 4;
+        const HEIGHT: f64 = 20.0;
+
+        let values: Vec<f64> = param_des(h, 0)?;
+        let labels: Vec<String> = match h.param(1) {
+            Some(_) => param_des(h, 1)?,
+            None => Vec::new(),
+        };
+        let max = values.iter().copied().fold(0.0, f64::max);
+
+        let width = values.len() * BAR_WIDTH;
+        out.write(&format!(
+            r#"<svg class="sparkline" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+            width, HEIGHT
+        ))?;
+        for (i, value) in values.iter().enumerate() {
+            let height = if max > 0.0 { value / max * HEIGHT } else { 0.0 };
+            out.write(&format!(
+                r#"<rect x="{}" y="{:.1}" width="{}" height="{:.1}">"#,
+                i * BAR_WIDTH,
+                HEIGHT - height,
+                BAR_WIDTH - 1,
+                height
+            ))?;
+            let title = match labels.get(i) {
+                Some(label) => format!("{}: {}", label, value),
+                None => value.to_string(),
+            };
+            out.write(&format!("<title>{}</title></rect>", html_escape(&title)))?;
+        }
+        out.write("</svg>")?;
+
+        Ok(())
+    }
+}
+
+/// Renders "showing 51-100 of 1234" and links to the neighbouring, first
+/// and last pages: `{{page_nav items.page}}`. Renders nothing if the
+/// items are not split into pages.
+struct PageNavHelper;
+impl HelperDef for PageNavHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _r: &'reg Handlebars<'reg>,
+        _ctx: &'rc Context,
+        _rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        /// Pages linked on each side of the current one
+        const RADIUS: usize = 2;
+
+        if h.param(0).map_or(true, |param| param.value().is_null()) {
+            return Ok(());
+        }
+        let nav: PageNav = param_des(h, 0)?;
+        let page = nav.page;
+        let separator = if nav.link.contains('?') { '&' } else { '?' };
+        let link = |number: usize| {
+            let link = format!("{}{}page={}", nav.link, separator, number);
+            format!(r#"<a href="{}">"#, html_escape(&link))
+        };
+
+        out.write(r#"<div class="page_nav">"#)?;
+        out.write(&format!(
+            "showing {}&ndash;{} of {}",
+            page.first_item, page.last_item, page.matching_items
+        ))?;
+        if page.matching_items != page.total_items {
+            out.write(&format!(" ({} items in total)", page.total_items))?;
+        }
+        if page.pages > 1 {
+            out.write(" - ")?;
+            if let Some(prev) = page.prev() {
+                out.write(&format!("{}&laquo; previous</a> ", link(prev)))?;
+            }
+            for number in page.window(RADIUS) {
+                match number {
+                    Some(number) if number == page.number => {
+                        out.write(&format!("<b>{}</b> ", number))?
+                    }
+                    Some(number) => out.write(&format!("{}{}</a> ", link(number), number))?,
+                    None => out.write("&hellip; ")?,
+                }
+            }
+            if let Some(next) = page.next() {
+                out.write(&format!("{}next &raquo;</a>", link(next)))?;
+            }
+        }
+        out.write("</div>")?;
+
+        Ok(())
+    }
+}
+
 pub fn register(engines: &mut Engines) {
     let engine = &mut engines.handlebars;
     engine.register_helper("feed_list", Box::new(FeedList));
+    engine.register_helper("markdown", Box::new(Markdown));
+    engine.register_helper("page_nav", Box::new(PageNavHelper));
+    engine.register_helper("sparkline", Box::new(Sparkline));
 }
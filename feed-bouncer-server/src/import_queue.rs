@@ -0,0 +1,160 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use rocket::tokio::sync::Notify;
+
+use crate::{common::SyncDatabase, supervisor::Supervisor};
+
+#[derive(serde::Serialize, Clone)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobState {
+    Pending,
+    Running,
+    Done {
+        feed_ids: Vec<String>,
+    },
+    Failed {
+        error: String,
+    },
+    /// The feed is on the blocklist
+    Blocked {
+        reason: String,
+    },
+}
+
+#[derive(serde::Serialize, Clone)]
+pub struct ImportJob {
+    pub id: usize,
+    pub url: String,
+    pub tags: Vec<String>,
+    /// Overrides the configured age limit for the first fetch
+    pub max_initial_age_days: Option<u64>,
+    /// Jobs queued together by a bulk import
+    pub batch: Option<usize>,
+    pub queued_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub state: JobState,
+}
+
+#[derive(Default)]
+struct Jobs {
+    jobs: Vec<ImportJob>,
+    next_id: usize,
+    next_batch: usize,
+}
+
+/// Feeds to subscribe to, downloaded one after another by a background
+/// worker instead of inside the request.
+#[derive(Clone, Default)]
+pub struct ImportQueue {
+    jobs: Arc<Mutex<Jobs>>,
+    notify: Arc<Notify>,
+}
+
+impl ImportQueue {
+    pub fn new_batch(&self) -> usize {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.next_batch += 1;
+        jobs.next_batch
+    }
+
+    pub fn enqueue(
+        &self,
+        url: &str,
+        tags: Vec<String>,
+        max_initial_age_days: Option<u64>,
+        batch: Option<usize>,
+    ) -> usize {
+        let mut jobs = self.jobs.lock().unwrap();
+        let id = jobs.next_id;
+        jobs.next_id += 1;
+        jobs.jobs.push(ImportJob {
+            id,
+            url: url.trim().to_owned(),
+            tags,
+            max_initial_age_days,
+            batch,
+            queued_at: Utc::now(),
+            finished_at: None,
+            state: JobState::Pending,
+        });
+        self.notify.notify_one();
+        id
+    }
+
+    /// All jobs, newest first
+    pub fn jobs(&self) -> Vec<ImportJob> {
+        let mut jobs = self.jobs.lock().unwrap().jobs.clone();
+        jobs.reverse();
+        jobs
+    }
+
+    fn start_next(&self) -> Option<ImportJob> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs
+            .jobs
+            .iter_mut()
+            .find(|job| matches!(job.state, JobState::Pending))?;
+        job.state = JobState::Running;
+        Some(job.clone())
+    }
+
+    fn finish(&self, id: usize, state: JobState) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.jobs.iter_mut().find(|job| job.id == id) {
+            job.state = state;
+            job.finished_at = Some(Utc::now());
+        }
+    }
+}
+
+pub fn start_import_worker(supervisor: &Supervisor, db: &SyncDatabase, queue: &ImportQueue) {
+    let db: SyncDatabase = db.clone();
+    let queue = queue.clone();
+    supervisor.spawn("import_worker", move || {
+        let db = db.clone();
+        let queue = queue.clone();
+        async move {
+            loop {
+                let job = match queue.start_next() {
+                    Some(job) => job,
+                    None => {
+                        queue.notify.notified().await;
+                        continue;
+                    }
+                };
+
+                // Download while the lock is not held
+                let task = db
+                    .read()
+                    .await
+                    .import_task(&job.url, &job.tags)
+                    .max_initial_age_days(job.max_initial_age_days);
+                let result = task.run().await;
+                if let Some(reason) = result.blocked() {
+                    let reason = reason.to_owned();
+                    queue.finish(job.id, JobState::Blocked { reason });
+                    continue;
+                }
+
+                let mut db = db.write().await;
+                let state = match db.commit_import(result) {
+                    Ok(feed_ids) if feed_ids.is_empty() => JobState::Failed {
+                        error: "not a feed".to_owned(),
+                    },
+                    Ok(feed_ids) => {
+                        db.save();
+                        JobState::Done {
+                            feed_ids: feed_ids.into_iter().collect(),
+                        }
+                    }
+                    Err(e) => JobState::Failed {
+                        error: e.to_string(),
+                    },
+                };
+                queue.finish(job.id, state);
+            }
+        }
+    });
+}
@@ -1,55 +1,218 @@
 #[macro_use]
 extern crate rocket;
 
-use std::{path::PathBuf, sync::Arc};
+use std::{net::IpAddr, path::PathBuf, sync::Arc, time::Duration};
 
 use clap::Parser;
-use feed_bouncer_database::Database;
+use feed_bouncer_database::{Database, UpdateProgress};
 use rocket::tokio::sync::RwLock;
 use rocket_dyn_templates::Template;
 
-use crate::common::SyncDatabase;
+use crate::{
+    base_path::BasePath,
+    common::SyncDatabase,
+    import_queue::ImportQueue,
+    supervisor::Supervisor,
+    triggers::update::{RetryQueue, UpdateHistory, UpdateSchedule},
+};
 
+mod base_path;
 mod common;
 mod handlebars_helper;
+mod import_queue;
 mod pages;
+mod supervisor;
 mod triggers;
 
 #[derive(Parser)]
 struct Opts {
     #[clap(short, long)]
     storage_path: Option<PathBuf>,
+
+    /// Open the storage without locking it or saving any changes
+    #[clap(long)]
+    read_only: bool,
+
+    /// Minutes between automatic updates, overrides the config
+    #[clap(long)]
+    update_interval: Option<u64>,
+
+    /// Address to listen on, overrides the Rocket config
+    #[clap(long)]
+    address: Option<IpAddr>,
+
+    /// Port to listen on, overrides the Rocket config
+    #[clap(long)]
+    port: Option<u16>,
+
+    /// Serve all pages below this path, e.g. `/feeds`
+    #[clap(long)]
+    base_path: Option<String>,
 }
 
 #[rocket::main]
 async fn main() {
     let opts = Opts::parse();
 
-    let mut db = Database::init(opts.storage_path);
+    let db = if opts.read_only {
+        Database::init_read_only(opts.storage_path)
+    } else {
+        Database::init(opts.storage_path)
+    };
+    let mut db = match db {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            std::process::exit(1);
+        }
+    };
     db.import().await;
+    let autosave_mins = db.config().storage.autosave_mins;
+    let update_schedule = UpdateSchedule::new(
+        Duration::from_secs(
+            60 * opts
+                .update_interval
+                .unwrap_or(db.config().updates.interval_mins)
+                .max(1),
+        ),
+        Duration::from_secs(db.config().updates.startup_jitter_secs),
+    );
     let db: SyncDatabase = Arc::new(RwLock::new(db));
-    triggers::update::start_periodic_update(&db);
+    let supervisor = Supervisor::default();
+    let update_progress = Arc::new(UpdateProgress::default());
+    let update_history = UpdateHistory::default();
+    let retry_queue = RetryQueue::default();
+    triggers::update::start_periodic_update(
+        &supervisor,
+        &db,
+        &update_schedule,
+        &update_progress,
+        &update_history,
+        &retry_queue,
+    );
+    triggers::autosave::start_autosave(&supervisor, &db, autosave_mins);
+    triggers::reload::start_reload_on_sighup(&supervisor, &db);
+    let import_queue = ImportQueue::default();
+    import_queue::start_import_worker(&supervisor, &db, &import_queue);
+
+    let mut figment = rocket::Config::figment();
+    if let Some(address) = opts.address {
+        figment = figment.merge(("address", address));
+    }
+    if let Some(port) = opts.port {
+        figment = figment.merge(("port", port));
+    }
+    let base_path = opts.base_path.as_deref().and_then(BasePath::new);
+    let mount_path = base_path
+        .as_ref()
+        .map_or("/".to_owned(), |base_path| base_path.as_str().to_owned());
 
-    let cfg = rocket::build()
+    let mut cfg = rocket::custom(figment)
         .mount(
-            "/",
+            mount_path.as_str(),
             routes![
                 pages::index::index,
+                pages::index::index_partial,
+                pages::api::next_unread,
+                pages::api::update_progress,
+                pages::admin_api::api_update,
+                pages::admin_api::api_import,
+                pages::admin_api::api_prune,
+                pages::admin_api::api_compact,
+                pages::admin_api::api_export,
                 pages::feed::feed,
                 pages::feed::feed_add_tag,
                 pages::feed::feed_remove_tag,
                 pages::feed::feed_add_alias,
                 pages::feed::feed_remove_alias,
                 pages::feed::feed_set_display,
+                pages::feed::feed_rename,
+                pages::feed::feed_set_notes,
+                pages::feed::feed_set_url,
+                pages::feed::feed_catch_up,
+                pages::feed::feed_set_archive,
+                pages::feed::feed_set_item_order,
+                pages::feed::feed_set_title_cleaning,
+                pages::feed::feed_set_credentials,
+                pages::feed::feed_set_source,
+                pages::feed::feed_set_utc_offset,
+                pages::feed::feed_set_options,
+                pages::feed::feed_history,
+                pages::feed::feed_headers,
+                pages::feed::item_archived,
+                pages::feed::item_provenance,
+                pages::feed::item_permalink,
                 pages::feeds::feeds,
+                pages::feeds::feeds_catch_up,
+                pages::feeds::feeds_bulk,
+                pages::tags::tags,
+                pages::tags::tag_set_settings,
+                pages::tags::tag_set_url_rules,
+                pages::tags::tag_add_synonym,
+                pages::tags::tag_remove_synonym,
+                pages::timeline::tag_timeline,
+                pages::timeline::tag_timeline_rss,
+                pages::timeline::tag_timeline_json,
+                pages::export::export_opml,
+                pages::diagnostics::diagnostics,
+                pages::diagnostics::diagnostics_payload,
+                pages::diagnostics::diagnostics_probe,
                 pages::import::import,
+                pages::import::import_preview,
+                pages::import::subscribe,
                 pages::import::import_rss,
+                pages::import::import_opml,
+                pages::import::import_bulk,
+                pages::import::imports,
+                pages::public::robots,
+                pages::public::sitemap,
+                pages::settings::settings,
+                pages::settings::settings_default_filter,
+                pages::settings::settings_blocklist,
+                pages::settings::settings_export,
+                pages::settings::settings_import,
+                pages::share::share,
+                pages::share::shares,
+                pages::share::shares_create,
+                pages::share::shares_revoke,
+                pages::stats::stats,
+                pages::stats::stats_compact,
+                pages::authors::authors,
+                pages::history::history,
+                pages::trash::trash,
+                pages::views::views,
+                pages::views::views_save,
+                pages::views::views_remove,
                 triggers::update::update,
+                triggers::update::update_get,
+                triggers::update::update_feed,
+                triggers::update::update_feed_get,
+                triggers::update::updates_pause,
+                triggers::update::updates_pause_get,
+                triggers::update::updates_resume,
+                triggers::update::updates_resume_get,
                 triggers::mark_read::mark_read,
+                triggers::mark_read::mark_read_get,
+                triggers::hide::hide,
+                triggers::hide::hide_get,
+                triggers::hide::unhide,
+                triggers::hide::unhide_get,
+                triggers::reload::reload,
+                supervisor::health,
             ],
         )
         .attach(Template::custom(handlebars_helper::register))
-        .manage(db);
+        .manage(db)
+        .manage(supervisor)
+        .manage(import_queue)
+        .manage(update_schedule)
+        .manage(update_progress)
+        .manage(update_history)
+        .manage(retry_queue)
+        .manage(pages::share::ShareLimiter::default());
+    if let Some(base_path) = base_path {
+        cfg = cfg.attach(base_path);
+    }
 
     if let Err(e) = cfg.launch().await {
         println!("Whoops! Rocket didn't launch!");
@@ -1,4 +1,18 @@
+pub mod admin_api;
+pub mod api;
+pub mod authors;
+pub mod diagnostics;
+pub mod export;
 pub mod feed;
 pub mod feeds;
+pub mod history;
 pub mod import;
 pub mod index;
+pub mod public;
+pub mod settings;
+pub mod share;
+pub mod stats;
+pub mod tags;
+pub mod timeline;
+pub mod trash;
+pub mod views;
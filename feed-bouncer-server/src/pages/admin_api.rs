@@ -0,0 +1,184 @@
+use std::sync::Arc;
+
+use feed_bouncer_database::UpdateProgress;
+use rocket::form::Form;
+use rocket::http::{ContentType, Status};
+use rocket::request::{self, FromRequest, Request};
+use rocket::State;
+
+use crate::common::{SyncDatabase, Tag};
+use crate::supervisor::Supervisor;
+use crate::triggers::update::{spawn_update, RetryQueue, UpdateHistory};
+
+type JsonResponse = (Status, (ContentType, String));
+
+fn json(status: Status, value: &impl serde::Serialize) -> JsonResponse {
+    (
+        status,
+        (ContentType::JSON, serde_json::to_string(value).unwrap()),
+    )
+}
+
+/// Requests of the admin API, which need `Authorization: Bearer <token>`
+/// with the `admin_api_token` of the config. Without a configured token
+/// the routes are not found.
+pub struct AdminToken;
+
+/// Compares without returning early, so the time taken doesn't tell how
+/// much of the token was right
+pub(crate) fn same_token(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminToken {
+    type Error = String;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let db = match req.rocket().state::<SyncDatabase>() {
+            Some(db) => db,
+            None => return request::Outcome::Failure((Status::InternalServerError, String::new())),
+        };
+        let expected = match db.read().await.config().ui.admin_api_token.clone() {
+            Some(token) if !token.is_empty() => token,
+            _ => {
+                return request::Outcome::Failure((
+                    Status::NotFound,
+                    "the admin api is off".to_owned(),
+                ))
+            }
+        };
+        let given = req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        match given {
+            Some(given) if same_token(given, &expected) => request::Outcome::Success(AdminToken),
+            _ => request::Outcome::Failure((Status::Unauthorized, "wrong token".to_owned())),
+        }
+    }
+}
+
+/// Starts an update of all feeds, unless one is running. Its progress is
+/// at `/api/update_progress`. Answers with `started: false` and a 409 if an
+/// update runs already.
+#[post("/admin/api/update")]
+pub async fn api_update(
+    _token: AdminToken,
+    db: &State<SyncDatabase>,
+    supervisor: &State<Supervisor>,
+    progress: &State<Arc<UpdateProgress>>,
+    history: &State<UpdateHistory>,
+    retries: &State<RetryQueue>,
+) -> JsonResponse {
+    let started = spawn_update(
+        db.inner(),
+        supervisor.inner(),
+        progress.inner(),
+        history.inner(),
+        retries.inner(),
+    );
+    if !started {
+        return json(
+            Status::Conflict,
+            &serde_json::json!({
+                "started": false,
+                "reason": "another update is running",
+            }),
+        );
+    }
+    json(Status::Accepted, &serde_json::json!({ "started": true }))
+}
+
+#[derive(FromForm)]
+pub struct ImportUrl<'r> {
+    url: &'r str,
+    /// Comma separated
+    tags: Option<&'r str>,
+}
+
+/// Subscribes to a feed url and waits for the download. Returns the ids of
+/// the feeds with the url.
+#[post("/admin/api/import", data = "<import>")]
+pub async fn api_import(
+    _token: AdminToken,
+    db: &State<SyncDatabase>,
+    import: Form<ImportUrl<'_>>,
+) -> JsonResponse {
+    let tags: Vec<String> = import
+        .tags
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(Tag::new)
+        .map(|tag| tag.as_str().to_owned())
+        .collect();
+    let task = db.read().await.import_task(import.url.trim(), &tags);
+    let result = task.run().await;
+    let blocked = result.blocked().map(str::to_owned);
+
+    let mut db = db.write().await;
+    match db.commit_import(result) {
+        Ok(feed_ids) => {
+            db.save();
+            json(
+                Status::Ok,
+                &serde_json::json!({ "feed_ids": feed_ids, "blocked": blocked }),
+            )
+        }
+        Err(e) => json(
+            Status::BadGateway,
+            &serde_json::json!({ "error": e.to_string() }),
+        ),
+    }
+}
+
+/// Moves the items older than `storage.cold_after_days` to cold storage
+#[post("/admin/api/prune")]
+pub async fn api_prune(_token: AdminToken, db: &State<SyncDatabase>) -> JsonResponse {
+    let mut db = db.write().await;
+    match db.move_to_cold_storage() {
+        Ok(moved_items) => {
+            if moved_items > 0 {
+                db.save_shrunk();
+            }
+            json(
+                Status::Ok,
+                &serde_json::json!({ "moved_items": moved_items }),
+            )
+        }
+        Err(e) => {
+            // Items of other feeds may have moved before the error
+            db.save_shrunk();
+            json(
+                Status::InternalServerError,
+                &serde_json::json!({ "error": e.to_string() }),
+            )
+        }
+    }
+}
+
+#[post("/admin/api/compact")]
+pub async fn api_compact(_token: AdminToken, db: &State<SyncDatabase>) -> JsonResponse {
+    let report = db.write().await.compact();
+    json(Status::Ok, &report)
+}
+
+/// A backup of the settings and the read state, which the settings import
+/// and the `pull` command of the CLI can restore
+#[get("/admin/api/export")]
+pub async fn api_export(_token: AdminToken, db: &State<SyncDatabase>) -> JsonResponse {
+    let db = db.read().await;
+    json(
+        Status::Ok,
+        &serde_json::json!({
+            "settings": db.export_settings(),
+            "read_state": db.export_user_data(),
+        }),
+    )
+}
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use feed_bouncer_database::{ItemOrder, ItemQuery, UpdateProgress};
+use rocket::http::ContentType;
+use rocket::State;
+
+use crate::common::{item_anchor, Filter, SyncDatabase};
+
+#[derive(serde::Serialize)]
+struct NextUnread<'a> {
+    feed_id: &'a str,
+    item_id: usize,
+    anchor: String,
+    title: Option<&'a str>,
+    content_link: Option<&'a str>,
+}
+
+/// The first unread item of the index page below `after`, given as
+/// `<feed_id>/<item_id>`, or from the top without it. Returns `null` if
+/// there is none.
+#[get("/api/next_unread?<after>&<filter>")]
+pub async fn next_unread(
+    db: &State<SyncDatabase>,
+    after: Option<&str>,
+    filter: Option<String>,
+) -> (ContentType, String) {
+    let filter = Filter::new(filter, db.read().await.tag_synonyms());
+    let after = after.and_then(|after| {
+        let (feed_id, item_id) = after.rsplit_once('/')?;
+        Some((feed_id, item_id.parse::<usize>().ok()?))
+    });
+
+    let db = db.read().await;
+    let query = ItemQuery::new()
+        .feeds(filter.query().clone())
+        .order(ItemOrder::NewestFirst)
+        .hidden(false)
+        .dedup_links();
+    let items = db.query_items_with_state(&query);
+
+    let start = match after {
+        Some((after_feed, after_item)) => items
+            .iter()
+            .position(|(feed_id, _, item, _)| *feed_id == after_feed && item.id() == after_item)
+            .map(|i| i + 1)
+            .unwrap_or(0),
+        None => 0,
+    };
+    let next = items[start..]
+        .iter()
+        .find(|(_, _, _, state)| !state.read)
+        .map(|(feed_id, feed, item, _)| NextUnread {
+            feed_id,
+            item_id: item.id(),
+            anchor: item_anchor(feed_id, item.id()),
+            title: item.display_title_without_prefixes(feed),
+            content_link: item.content_link(),
+        });
+
+    (ContentType::JSON, serde_json::to_string(&next).unwrap())
+}
+
+/// How far the running update got. `running` is false between updates, the
+/// counts are then those of the last one.
+#[get("/api/update_progress")]
+pub async fn update_progress(progress: &State<Arc<UpdateProgress>>) -> (ContentType, String) {
+    (
+        ContentType::JSON,
+        serde_json::to_string(&progress.snapshot()).unwrap(),
+    )
+}
@@ -0,0 +1,66 @@
+use rocket::State;
+use rocket_dyn_templates::Template;
+
+use crate::common::{Filter, SyncDatabase};
+
+#[derive(serde::Serialize)]
+struct AuthorRow<'a> {
+    name: &'a str,
+    items: usize,
+    feeds: usize,
+    /// The index filtered by the author, within the current filter
+    link: String,
+}
+
+#[derive(serde::Serialize)]
+struct Context<'a> {
+    authors: Vec<AuthorRow<'a>>,
+    filter: &'a str,
+}
+
+/// The authors of the items of the matching feeds, most items first.
+#[get("/authors?<filter>")]
+pub async fn authors(db: &State<SyncDatabase>, filter: Option<String>) -> Template {
+    let db = db.read().await;
+    let filter = Filter::new(filter, db.tag_synonyms());
+    let authors = db.authors(filter.query());
+
+    let authors = authors
+        .iter()
+        // Commas separate the terms of filters
+        .filter(|author| !author.name.contains(','))
+        .map(|author| {
+            let author_filter = match filter.raw_opt() {
+                Some(raw) => format!("{},author:{}", raw, author.name),
+                None => format!("author:{}", author.name),
+            };
+            AuthorRow {
+                name: &author.name,
+                items: author.items,
+                feeds: author.feeds.len(),
+                link: uri!(crate::pages::index::index(
+                    Some(&author_filter[..]),
+                    _,
+                    _,
+                    _,
+                    _,
+                    _,
+                    _,
+                    _,
+                    _,
+                    _,
+                    _
+                ))
+                .to_string(),
+            }
+        })
+        .collect();
+
+    Template::render(
+        "pages/authors",
+        &Context {
+            authors,
+            filter: filter.raw(),
+        },
+    )
+}
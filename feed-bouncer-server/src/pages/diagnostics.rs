@@ -0,0 +1,123 @@
+use feed_bouncer_database::{FailedPayload, FetchFailure, ProbeResult};
+use rocket::http::ContentType;
+use rocket::State;
+use rocket_dyn_templates::Template;
+
+use crate::common::SyncDatabase;
+
+#[derive(serde::Serialize)]
+struct PayloadRow<'a> {
+    feed_name: &'a str,
+    feed_id: &'a str,
+    name: &'a str,
+    url: &'a str,
+    fetched_at: String,
+    errors: &'a [String],
+    size: usize,
+    truncated: bool,
+}
+
+#[derive(serde::Serialize)]
+struct FetchFailureRow<'a> {
+    feed_name: &'a str,
+    feed_id: &'a str,
+    failure: &'a FetchFailure,
+}
+
+#[derive(serde::Serialize)]
+struct Context<'a> {
+    payloads: Vec<PayloadRow<'a>>,
+    /// Feeds the retries gave up on
+    escalated: Vec<FetchFailureRow<'a>>,
+    /// Feeds that are still being retried
+    retrying: Vec<FetchFailureRow<'a>>,
+}
+
+/// Lists feeds that could not be fetched and feed bodies that could not be
+/// parsed.
+#[get("/diagnostics")]
+pub async fn diagnostics(db: &State<SyncDatabase>) -> Template {
+    let db = db.read().await;
+    let payloads = db.failed_payloads();
+
+    let payloads = payloads
+        .iter()
+        .map(|payload: &FailedPayload| PayloadRow {
+            feed_name: db
+                .get(&payload.feed_id)
+                .map(|feed| feed.display_name())
+                .unwrap_or(""),
+            feed_id: &payload.feed_id,
+            name: &payload.name,
+            url: &payload.url,
+            fetched_at: payload.fetched_at.to_rfc3339(),
+            errors: &payload.errors,
+            size: payload.size,
+            truncated: payload.truncated,
+        })
+        .collect();
+
+    let (escalated, retrying) = db
+        .fetch_failures()
+        .into_iter()
+        .map(|(feed_id, feed, failure)| FetchFailureRow {
+            feed_name: feed.display_name(),
+            feed_id,
+            failure,
+        })
+        .partition(|row| row.failure.escalated);
+
+    Template::render(
+        "pages/diagnostics",
+        &Context {
+            payloads,
+            escalated,
+            retrying,
+        },
+    )
+}
+
+#[get("/diagnostics/payload/<feed_id>/<name>")]
+pub async fn diagnostics_payload(
+    db: &State<SyncDatabase>,
+    feed_id: String,
+    name: &str,
+) -> Option<(ContentType, Vec<u8>)> {
+    let db = db.read().await;
+    let body = db.failed_payload_body(&feed_id, name)?;
+
+    Some((ContentType::Plain, body))
+}
+
+#[derive(serde::Serialize)]
+struct ProbeContext {
+    failed: Vec<ProbeResult>,
+    redirected: Vec<ProbeResult>,
+    ok: usize,
+}
+
+/// Checks that all feed urls respond, without downloading items. Takes as
+/// long as the slowest feed.
+#[get("/diagnostics/probe")]
+pub async fn diagnostics_probe(db: &State<SyncDatabase>) -> Template {
+    // Probe while the lock is not held
+    let task = db.read().await.probe_task();
+    let results = task.run().await;
+
+    let mut context = ProbeContext {
+        failed: Vec::new(),
+        redirected: Vec::new(),
+        ok: 0,
+    };
+    for result in results {
+        if !result.is_ok() {
+            context.failed.push(result);
+        } else if result.redirected_to.is_some() {
+            context.redirected.push(result);
+        } else {
+            context.ok += 1;
+        }
+    }
+
+    Template::render("pages/probe", &context)
+}
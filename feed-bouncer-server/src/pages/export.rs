@@ -0,0 +1,25 @@
+use feed_bouncer_database::OpmlGrouping;
+use rocket::http::{ContentType, Status};
+use rocket::State;
+
+use crate::common::SyncDatabase;
+
+/// `group` is one of `flat` (default), `tags` or `primary_tag`
+#[get("/export/opml?<group>")]
+pub async fn export_opml(
+    db: &State<SyncDatabase>,
+    group: Option<&str>,
+) -> Result<(ContentType, String), (Status, String)> {
+    let grouping = match group {
+        Some(group) => group.parse().map_err(|e| (Status::BadRequest, e))?,
+        None => OpmlGrouping::Flat,
+    };
+
+    let db = db.read().await;
+    let opml = db
+        .export_opml(grouping)
+        .to_string()
+        .map_err(|e| (Status::InternalServerError, e.to_string()))?;
+
+    Ok((ContentType::XML, opml))
+}
@@ -1,44 +1,266 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
-use feed_bouncer_database::{FeedItem, FeedItemMeta};
+use chrono::Utc;
+use feed_bouncer_database::{
+    ActivityHistogram, ActivityPeriod, EffectiveSettings, Feed, FeedCredentials, FeedHeader,
+    FeedItemMeta, FeedItemOrder, FeedOptions, FeedQuery, HeaderChange, ItemOrder, ItemQuery,
+    TitleCleaning, ViewMode,
+};
 use rocket::form::Form;
+use rocket::http::ContentType;
+use rocket::request::FlashMessage;
+use rocket::response::Flash;
 use rocket::{response::Redirect, State};
 use rocket_dyn_templates::Template;
 
-use crate::common::{ItemBuilder, ItemsGroups, SyncDatabase, Tag};
+use crate::common::{
+    category_link, item_anchor, view_mode, Filter, ItemBuilder, ItemsGroups, SyncDatabase, Tag,
+};
+use crate::pages::tags::OptionsForm;
+use crate::triggers::SameOrigin;
+
+/// Number of items left unread by the catch up actions
+pub const DEFAULT_CATCH_UP_KEEP: usize = 10;
+
+/// Number of recent items the title prefix statistics look at
+const PREFIX_STATS_ITEMS: usize = 50;
+/// Number of recent items whose cleaned titles are previewed
+const TITLE_PREVIEW_ITEMS: usize = 10;
+
+/// Number of weeks and months shown in the activity sparklines
+const ACTIVITY_WEEKS: usize = 26;
+const ACTIVITY_MONTHS: usize = 24;
+
+/// Number of item categories listed on the page of a feed
+const FEED_CATEGORIES: usize = 50;
+
+#[derive(serde::Serialize)]
+struct AliasMatches<'a> {
+    alias: &'a str,
+    items: usize,
+}
+
+#[derive(serde::Serialize)]
+struct SuggestedAlias<'a> {
+    prefix: &'a str,
+    titles: Vec<&'a str>,
+}
+
+#[derive(serde::Serialize)]
+struct TitlePreview<'a> {
+    before: &'a str,
+    after: &'a str,
+}
+
+/// How well the title aliases clean the titles of the recent items
+#[derive(serde::Serialize)]
+struct PrefixStats<'a> {
+    items: usize,
+    stripped: usize,
+    alias_matches: Vec<AliasMatches<'a>>,
+    suggestions: Vec<SuggestedAlias<'a>>,
+    preview: Vec<TitlePreview<'a>>,
+}
+
+impl<'a> PrefixStats<'a> {
+    fn new(feed: &'a Feed, items: &[&'a FeedItemMeta]) -> Self {
+        let mut stripped = 0;
+        let mut alias_matches: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut guesses: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        let mut preview = Vec::new();
+        for &item in items {
+            let (title, matched) = match item.display_title_stripped(feed) {
+                Some(v) => v,
+                None => continue,
+            };
+            if preview.len() < TITLE_PREVIEW_ITEMS {
+                preview.push(TitlePreview {
+                    before: item.display_title().unwrap_or_default(),
+                    after: title,
+                });
+            }
+            if !matched.is_empty() {
+                stripped += 1;
+            }
+            for alias in matched {
+                *alias_matches.entry(alias).or_default() += 1;
+            }
+            if let Some(prefix) = item.guess_title_prefix(feed) {
+                guesses.entry(prefix).or_default().push(title);
+            }
+        }
+
+        let mut alias_matches: Vec<_> = alias_matches
+            .into_iter()
+            .map(|(alias, items)| AliasMatches { alias, items })
+            .collect();
+        alias_matches.sort_by_key(|matches| std::cmp::Reverse(matches.items));
+
+        // A leading part only looks like a prefix if several titles share it
+        let mut suggestions: Vec<_> = guesses
+            .into_iter()
+            .filter(|(_, titles)| titles.len() >= 2)
+            .map(|(prefix, titles)| SuggestedAlias { prefix, titles })
+            .collect();
+        suggestions.sort_by_key(|suggestion| std::cmp::Reverse(suggestion.titles.len()));
+
+        Self {
+            items: items.len(),
+            stripped,
+            alias_matches,
+            suggestions,
+            preview,
+        }
+    }
+}
 
 #[derive(serde::Serialize)]
 struct Context<'a> {
     title: &'a str,
     original_title: &'a str,
     title_aliases: Vec<&'a str>,
+    auto_title_aliases: Vec<&'a str>,
     tags: Vec<&'a str>,
     known_tags: Vec<&'a str>,
     items: ItemsGroups<'a>,
     feed_id: &'a str,
     feed_url: Option<&'a str>,
+    previous_feed_urls: Vec<&'a str>,
+    notes: &'a str,
+    catch_up_keep: usize,
+    prefix_stats: PrefixStats<'a>,
+    has_episodes: bool,
+    sort_by_episode: bool,
+    item_order: &'static str,
+    title_cleaning: &'static str,
+    title_cleaning_pattern: Option<&'a str>,
+    /// Why the last change of the title cleaning failed
+    title_cleaning_error: Option<&'a str>,
+    source: &'static str,
+    utc_offset_mins: Option<i32>,
+    language: Option<&'a str>,
+    /// Why the last change of the feed url failed
+    feed_url_error: Option<&'a str>,
+    /// The secret of the credentials is never shown
+    credentials_kind: Option<&'static str>,
+    credentials_name: Option<&'a str>,
+    credentials_error: Option<&'a str>,
+    weekly_activity: ActivityHistogram,
+    monthly_activity: ActivityHistogram,
+    compact: bool,
+    /// Link to the same page in the other view mode
+    other_view_link: String,
+    /// Links to the same page with the other item groupings
+    grouping_links: Vec<GroupingLink>,
+    /// Feeds of the OPML folder, whose items are shown as well
+    children: Vec<ChildFeed<'a>>,
+    /// The categories of the items, most items first
+    categories: Vec<CategoryRow>,
+    archive: bool,
+    /// Old items moved out of the feed file, shown on the history page
+    cold_items: usize,
+    /// The settings of the feed itself
+    options: &'a FeedOptions,
+    /// Including those inherited from the tags
+    settings: EffectiveSettings,
+}
+
+#[derive(serde::Serialize)]
+struct CategoryRow {
+    name: String,
+    items: usize,
+    /// The index filtered by the feed and the category
+    link: String,
+}
+
+#[derive(serde::Serialize)]
+struct ChildFeed<'a> {
+    feed_id: &'a str,
+    feed_name: &'a str,
 }
 
-#[get("/feed/<feed_id>")]
-pub async fn feed(db: &State<SyncDatabase>, feed_id: String) -> Option<Template> {
+/// `sort` is `date` (default), which follows the item order of the feed, or
+/// `episode`, which orders podcast items by season and episode number.
+/// `view` is `full` or `compact`, and `group` is `day`, `week`, `month` or
+/// `none`. Both are remembered for later visits.
+#[get("/feed/<feed_id>?<sort>&<view>&<group>")]
+pub async fn feed(
+    db: &State<SyncDatabase>,
+    feed_id: String,
+    sort: Option<&str>,
+    view: Option<&str>,
+    group: Option<&str>,
+    flash: Option<FlashMessage<'_>>,
+) -> Option<Template> {
+    let view = view_mode(db, view).await;
+    let grouping = item_grouping(db, group).await;
     let db = db.read().await;
     let feed = db.get(&feed_id)?;
 
-    let tags: Vec<_> = feed.tags().collect();
+    let mut tags: Vec<_> = feed.tags().collect();
+    tags.sort_by_key(|tag| std::cmp::Reverse(db.tag_uses(tag)));
+
+    let mut children: Vec<_> = db
+        .children_of(&feed_id)
+        .into_iter()
+        .map(|(feed_id, feed)| ChildFeed {
+            feed_id,
+            feed_name: feed.display_name(),
+        })
+        .collect();
+    children.sort_by_key(|child| child.feed_name.to_ascii_lowercase());
 
-    let mut items = ItemBuilder::new(false);
+    let sort_by_episode = sort == Some("episode");
+    let has_episodes;
+    // Items of child feeds are rolled up, which needs their feed names
+    let mut items = ItemBuilder::new(!children.is_empty())
+        .with_view_mode(view)
+        .with_grouping(grouping)
+        .with_archive_links(&db);
+    let mut recent_items = Vec::new();
     {
-        let mut feeds: Vec<&FeedItemMeta> = feed.items().iter().collect();
-        FeedItem::sort(&mut feeds, |x| &x.item);
-        feeds.reverse();
-        feeds.dedup_by(|a, b| a.content_link() == b.content_link());
-        for item in feeds {
-            items.push_sorted(&item, &feed_id, feed, db.is_read(&feed_id, item.id()));
+        let mut feeds = FeedQuery::new();
+        for feed_id in db.with_descendants(&feed_id) {
+            feeds = feeds.feed(feed_id);
+        }
+        let query = ItemQuery::new()
+            .feeds(feeds)
+            .order(ItemOrder::NewestFirst)
+            .hidden(false)
+            .dedup_links();
+        let mut query_items = db.query_items_with_state(&query);
+        for (item_feed_id, _, item, _) in &query_items {
+            if **item_feed_id == feed_id && recent_items.len() < PREFIX_STATS_ITEMS {
+                recent_items.push(*item);
+            }
+        }
+        has_episodes = query_items
+            .iter()
+            .any(|(_, _, item, _)| item.item.episode().is_some());
+        if sort_by_episode {
+            // Items without an episode number go last
+            query_items.sort_by_key(|(_, _, item, _)| {
+                let episode = item.item.episode();
+                (episode.is_none(), item.item.season(), episode)
+            });
+        } else {
+            match feed.item_order() {
+                FeedItemOrder::Published => {}
+                FeedItemOrder::Updated => query_items
+                    .sort_by_key(|(_, _, item, _)| std::cmp::Reverse(item.last_change_date())),
+                FeedItemOrder::AsFetched => {
+                    query_items.sort_by_key(|(_, _, item, _)| std::cmp::Reverse(item.id()))
+                }
+            }
+        }
+        for (feed_id, feed, item, state) in query_items {
+            items.push_sorted(item, feed_id, feed, state);
         }
     }
     let items = items.into_groups();
+    let prefix_stats = PrefixStats::new(feed, &recent_items);
 
-    let known_tags: Vec<_> = db
+    let mut known_tags: Vec<_> = db
         .get_feeds()
         .into_iter()
         .map(|feed| feed.1.tags())
@@ -47,8 +269,37 @@ pub async fn feed(db: &State<SyncDatabase>, feed_id: String) -> Option<Template>
         .into_iter()
         .filter(|tag| !tags.contains(&tag))
         .collect();
+    known_tags.sort_by_key(|tag| std::cmp::Reverse(db.tag_uses(tag)));
 
     let title_aliases: Vec<_> = feed.title_aliases().iter().map(|s| &s[..]).collect();
+    let auto_title_aliases: Vec<_> = feed.auto_title_aliases().iter().map(|s| &s[..]).collect();
+
+    let other_view = match view {
+        ViewMode::Full => ViewMode::Compact,
+        ViewMode::Compact => ViewMode::Full,
+    };
+    let other_view_link = uri!(feed(&feed_id[..], sort, Some(other_view.as_str()), _)).to_string();
+    let grouping_links = grouping_links(grouping, |grouping| {
+        uri!(feed(&feed_id[..], sort, _, Some(grouping.as_str()))).to_string()
+    });
+
+    let feed_filter = format!("feed:{}", feed_id);
+    let categories = db
+        .categories(&FeedQuery::new().feed(&feed_id[..]))
+        .into_iter()
+        // Commas separate the terms of filters
+        .filter(|category| !category.name.contains(','))
+        .take(FEED_CATEGORIES)
+        .map(|category| CategoryRow {
+            link: category_link(Some(&feed_filter), &category.name),
+            name: category.name,
+            items: category.items,
+        })
+        .collect();
+
+    let today = Utc::now().date_naive();
+    let weekly_activity = feed.activity_histogram(ActivityPeriod::Week, ACTIVITY_WEEKS, today);
+    let monthly_activity = feed.activity_histogram(ActivityPeriod::Month, ACTIVITY_MONTHS, today);
 
     Some(Template::render(
         "pages/feed",
@@ -60,7 +311,45 @@ pub async fn feed(db: &State<SyncDatabase>, feed_id: String) -> Option<Template>
             original_title: feed.original_display_name(),
             feed_id: &feed_id,
             feed_url: feed.feed_url(),
+            previous_feed_urls: feed.previous_feed_urls().iter().map(|s| &s[..]).collect(),
+            notes: feed.notes(),
             title_aliases,
+            auto_title_aliases,
+            catch_up_keep: DEFAULT_CATCH_UP_KEEP,
+            prefix_stats,
+            has_episodes,
+            sort_by_episode,
+            item_order: feed.item_order().as_str(),
+            title_cleaning: feed.title_cleaning().as_str(),
+            title_cleaning_pattern: feed.title_cleaning().pattern(),
+            title_cleaning_error: flash
+                .as_ref()
+                .filter(|flash| flash.kind() == "title_cleaning_error")
+                .map(|flash| flash.message()),
+            source: feed.source().as_str(),
+            utc_offset_mins: feed.utc_offset_mins(),
+            language: feed.language(),
+            feed_url_error: flash
+                .as_ref()
+                .filter(|flash| flash.kind() == "error")
+                .map(|flash| flash.message()),
+            credentials_kind: db.credentials(&feed_id).map(FeedCredentials::kind),
+            credentials_name: db.credentials(&feed_id).map(FeedCredentials::name),
+            credentials_error: flash
+                .as_ref()
+                .filter(|flash| flash.kind() == "credentials_error")
+                .map(|flash| flash.message()),
+            weekly_activity,
+            monthly_activity,
+            compact: view == ViewMode::Compact,
+            other_view_link,
+            grouping_links,
+            children,
+            categories,
+            archive: feed.archive(),
+            cold_items: feed.cold_item_count(),
+            options: feed.options(),
+            settings: feed.effective_settings(&db),
         },
     ))
 }
@@ -73,29 +362,31 @@ pub struct NewTag<'r> {
 #[post("/feed/<feed_id>/tag/add", data = "<new_tag>")]
 pub async fn feed_add_tag(
     db: &State<SyncDatabase>,
+    _origin: SameOrigin,
     feed_id: String,
     new_tag: Form<NewTag<'_>>,
 ) -> Option<Redirect> {
     let mut db = db.write().await;
+    let names: Vec<String> = new_tag
+        .name
+        .split(',')
+        .filter_map(Tag::new)
+        .map(|name| db.tag_synonyms().canonical(name.as_str()).to_owned())
+        .collect();
     let feed = db.get_mut(&feed_id)?;
-    let mut is_new = false;
-
-    for name in new_tag.name.split(',') {
-        if let Some(name) = Tag::new(name) {
-            is_new |= feed.extend_tags([name.as_str()]);
-        }
-    }
+    let is_new = feed.extend_tags(names.iter().map(|name| name.as_str()));
 
     if is_new {
         db.save();
     }
 
-    Some(Redirect::to(uri!(feed(feed_id))))
+    Some(Redirect::to(uri!(feed(feed_id, _, _, _))))
 }
 
-#[get("/feed/<feed_id>/tag/remove/<tag>")]
+#[post("/feed/<feed_id>/tag/remove/<tag>")]
 pub async fn feed_remove_tag(
     db: &State<SyncDatabase>,
+    _origin: SameOrigin,
     feed_id: String,
     tag: &str,
 ) -> Option<Redirect> {
@@ -106,7 +397,7 @@ pub async fn feed_remove_tag(
         db.save_shrunk();
     }
 
-    Some(Redirect::to(uri!(feed(feed_id))))
+    Some(Redirect::to(uri!(feed(feed_id, _, _, _))))
 }
 
 #[derive(FromForm)]
@@ -117,39 +408,41 @@ pub struct NewTitle<'r> {
 #[post("/feed/<feed_id>/alias/add", data = "<new_title>")]
 pub async fn feed_add_alias(
     db: &State<SyncDatabase>,
+    _origin: SameOrigin,
     feed_id: String,
     new_title: Form<NewTitle<'_>>,
 ) -> Option<Redirect> {
     let mut db = db.write().await;
-    let feed = db.get_mut(&feed_id)?;
-    let is_new = feed.title_alias_insert(&new_title.name);
+    db.get(&feed_id)?;
 
-    if is_new {
+    if db.add_title_alias(&feed_id, new_title.name) {
         db.save();
     }
 
-    Some(Redirect::to(uri!(feed(feed_id))))
+    Some(Redirect::to(uri!(feed(feed_id, _, _, _))))
 }
 
-#[get("/feed/<feed_id>/alias/remove/<title>")]
+#[post("/feed/<feed_id>/alias/remove/<title>")]
 pub async fn feed_remove_alias(
     db: &State<SyncDatabase>,
+    _origin: SameOrigin,
     feed_id: String,
     title: &str,
 ) -> Option<Redirect> {
     let mut db = db.write().await;
-    let feed = db.get_mut(&feed_id)?;
+    db.get(&feed_id)?;
 
-    if feed.title_alias_remove(title) {
+    if db.remove_title_alias(&feed_id, title) {
         db.save_shrunk();
     }
 
-    Some(Redirect::to(uri!(feed(feed_id))))
+    Some(Redirect::to(uri!(feed(feed_id, _, _, _))))
 }
 
-#[get("/feed/<feed_id>/display/set/<title>")]
+#[post("/feed/<feed_id>/display/set/<title>")]
 pub async fn feed_set_display(
     db: &State<SyncDatabase>,
+    _origin: SameOrigin,
     feed_id: String,
     title: &str,
 ) -> Option<Redirect> {
@@ -159,5 +452,428 @@ pub async fn feed_set_display(
     feed.set_display_name(title.to_owned());
     db.save_shrunk();
 
-    Some(Redirect::to(uri!(feed(feed_id))))
+    Some(Redirect::to(uri!(feed(feed_id, _, _, _))))
+}
+
+#[derive(FromForm)]
+pub struct NewName<'r> {
+    name: &'r str,
+}
+
+#[post("/feed/<feed_id>/rename", data = "<new_name>")]
+pub async fn feed_rename(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    feed_id: String,
+    new_name: Form<NewName<'_>>,
+) -> Option<Redirect> {
+    let mut db = db.write().await;
+    db.get(&feed_id)?;
+
+    if db.rename_feed(&feed_id, new_name.name) {
+        db.save_shrunk();
+    }
+
+    Some(Redirect::to(uri!(feed(feed_id, _, _, _))))
+}
+
+#[derive(FromForm)]
+pub struct NewFeedUrl<'r> {
+    url: &'r str,
+}
+
+/// Downloads the new url first, and only keeps it if it serves a feed
+#[post("/feed/<feed_id>/feed_url", data = "<new_url>")]
+pub async fn feed_set_url(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    feed_id: String,
+    new_url: Form<NewFeedUrl<'_>>,
+) -> Option<Flash<Redirect>> {
+    // Download while the lock is not held
+    let check = {
+        let db = db.read().await;
+        db.get(&feed_id)?;
+        db.feed_url_check(new_url.url)
+    };
+    let result = check.run().await;
+
+    let mut db = db.write().await;
+    let redirect = Redirect::to(uri!(feed(&feed_id[..], _, _, _)));
+    Some(match db.set_feed_url(&feed_id, result) {
+        Ok(()) => {
+            db.save();
+            Flash::success(redirect, "changed the feed url")
+        }
+        Err(e) => Flash::error(redirect, e.to_string()),
+    })
+}
+
+#[derive(FromForm)]
+pub struct Notes<'r> {
+    notes: &'r str,
+}
+
+#[post("/feed/<feed_id>/notes", data = "<notes>")]
+pub async fn feed_set_notes(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    feed_id: String,
+    notes: Form<Notes<'_>>,
+) -> Option<Redirect> {
+    let mut db = db.write().await;
+    let feed = db.get_mut(&feed_id)?;
+
+    if feed.set_notes(notes.notes.trim().to_owned()) {
+        db.save_shrunk();
+    }
+
+    Some(Redirect::to(uri!(feed(feed_id, _, _, _))))
+}
+
+#[derive(FromForm)]
+pub struct CatchUp {
+    /// How many of the newest items stay unread
+    keep: Option<usize>,
+}
+
+#[post("/feed/<feed_id>/catch_up", data = "<catch_up>")]
+pub async fn feed_catch_up(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    feed_id: String,
+    catch_up: Form<CatchUp>,
+) -> Option<Redirect> {
+    let mut db = db.write().await;
+    db.get(&feed_id)?;
+
+    let query = FeedQuery::new().feed(&feed_id[..]);
+    if db.catch_up(&query, catch_up.keep.unwrap_or(DEFAULT_CATCH_UP_KEEP)) > 0 {
+        db.save_user_data();
+    }
+
+    Some(Redirect::to(uri!(feed(feed_id, _, _, _))))
+}
+
+/// `order` is `published`, `updated` or `as_fetched`
+#[post("/feed/<feed_id>/item_order/<order>")]
+pub async fn feed_set_item_order(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    feed_id: String,
+    order: &str,
+) -> Option<Redirect> {
+    let order: FeedItemOrder = order.parse().ok()?;
+    let mut db = db.write().await;
+    if !db.set_item_order(&feed_id, order) {
+        return None;
+    }
+    db.save();
+
+    Some(Redirect::to(uri!(feed(feed_id, _, _, _))))
+}
+
+#[derive(FromForm)]
+pub struct CredentialsForm<'r> {
+    /// `basic`, `header`, `query` or `none`
+    kind: &'r str,
+    /// The user name, header name or query parameter
+    name: &'r str,
+    secret: &'r str,
+}
+
+#[post("/feed/<feed_id>/credentials", data = "<credentials>")]
+pub async fn feed_set_credentials(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    feed_id: String,
+    credentials: Form<CredentialsForm<'_>>,
+) -> Option<Flash<Redirect>> {
+    let name = credentials.name.trim().to_owned();
+    let secret = credentials.secret.to_owned();
+    let credentials = match credentials.kind {
+        "basic" => Some(FeedCredentials::Basic {
+            username: name,
+            password: secret,
+        }),
+        "header" => Some(FeedCredentials::Header {
+            name,
+            value: secret,
+        }),
+        "query" => Some(FeedCredentials::Query {
+            name,
+            value: secret,
+        }),
+        "none" => None,
+        _ => return None,
+    };
+    let redirect = Redirect::to(uri!(feed(&feed_id[..], _, _, _)));
+    let mut db = db.write().await;
+    db.get(&feed_id)?;
+    Some(match db.set_credentials(&feed_id, credentials) {
+        Ok(()) => Flash::success(redirect, "changed the credentials"),
+        Err(e) => Flash::new(redirect, "credentials_error", e.to_string()),
+    })
+}
+
+#[derive(FromForm)]
+pub struct TitleCleaningForm<'r> {
+    /// `off`, `strip_known_prefixes` or `regex`
+    strategy: &'r str,
+    /// Only used by `regex`
+    pattern: &'r str,
+}
+
+#[post("/feed/<feed_id>/title_cleaning", data = "<cleaning>")]
+pub async fn feed_set_title_cleaning(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    feed_id: String,
+    cleaning: Form<TitleCleaningForm<'_>>,
+) -> Option<Flash<Redirect>> {
+    let title_cleaning = match cleaning.strategy {
+        "off" => Ok(TitleCleaning::Off),
+        "strip_known_prefixes" => Ok(TitleCleaning::StripKnownPrefixes),
+        "regex" => TitleCleaning::regex(cleaning.pattern),
+        _ => return None,
+    };
+    let redirect = Redirect::to(uri!(feed(&feed_id[..], _, _, _)));
+    let mut db = db.write().await;
+    let feed = db.get_mut(&feed_id)?;
+    Some(match title_cleaning {
+        Ok(title_cleaning) => {
+            feed.set_title_cleaning(title_cleaning);
+            db.save();
+            Flash::success(redirect, "changed the title cleaning")
+        }
+        Err(e) => Flash::new(redirect, "title_cleaning_error", e),
+    })
+}
+
+/// `source` is `http` or `directory`
+#[post("/feed/<feed_id>/source/<source>")]
+pub async fn feed_set_source(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    feed_id: String,
+    source: &str,
+) -> Option<Redirect> {
+    let source: SourceKind = source.parse().ok()?;
+    let mut db = db.write().await;
+    db.get_mut(&feed_id)?.set_source(source);
+    db.save();
+
+    Some(Redirect::to(uri!(feed(feed_id, _, _, _))))
+}
+
+#[derive(FromForm)]
+pub struct UtcOffset {
+    /// Empty for UTC
+    mins: Option<i32>,
+}
+
+#[post("/feed/<feed_id>/utc_offset", data = "<offset>")]
+pub async fn feed_set_utc_offset(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    feed_id: String,
+    offset: Form<UtcOffset>,
+) -> Option<Redirect> {
+    // Offsets beyond a day are not valid
+    let mins = offset.mins.filter(|mins| mins.abs() < 24 * 60);
+    let mut db = db.write().await;
+    if !db.set_utc_offset(&feed_id, mins) {
+        return None;
+    }
+    db.save();
+
+    Some(Redirect::to(uri!(feed(feed_id, _, _, _))))
+}
+
+#[post("/feed/<feed_id>/archive/<enabled>")]
+pub async fn feed_set_archive(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    feed_id: String,
+    enabled: bool,
+) -> Option<Redirect> {
+    let mut db = db.write().await;
+    if !db.set_archive(&feed_id, enabled) {
+        return None;
+    }
+    db.save();
+
+    Some(Redirect::to(uri!(feed(feed_id, _, _, _))))
+}
+
+#[post("/feed/<feed_id>/settings", data = "<options>")]
+pub async fn feed_set_options(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    feed_id: String,
+    options: Form<OptionsForm<'_>>,
+) -> Option<Redirect> {
+    let mut db = db.write().await;
+    if !db.set_feed_options(&feed_id, options.to_options()) {
+        return None;
+    }
+    db.save();
+
+    Some(Redirect::to(uri!(feed(feed_id, _, _, _))))
+}
+
+#[derive(serde::Serialize)]
+struct HistoryContext<'a> {
+    feed_id: &'a str,
+    feed_name: &'a str,
+    items: ItemsGroups<'a>,
+}
+
+/// The old items of a feed that got moved to cold storage, which are only
+/// read from disk for this page
+#[get("/feed/<feed_id>/history")]
+pub async fn feed_history(db: &State<SyncDatabase>, feed_id: String) -> Option<Template> {
+    let db = db.read().await;
+    let feed = db.get(&feed_id)?;
+    let cold_items = db.cold_items(&feed_id);
+
+    let mut items = ItemBuilder::new(false).with_archive_links(&db);
+    for item in &cold_items {
+        items.push_sorted(item, &feed_id, feed, db.item_state(&feed_id, item.id()));
+    }
+
+    Some(Template::render(
+        "pages/feed_history",
+        &HistoryContext {
+            feed_id: &feed_id,
+            feed_name: feed.display_name(),
+            items: items.into_groups(),
+        },
+    ))
+}
+
+#[derive(serde::Serialize)]
+struct HeadersContext<'a> {
+    feed_id: &'a str,
+    feed_name: &'a str,
+    headers: Vec<HeaderEntry<'a>>,
+}
+
+#[derive(serde::Serialize)]
+struct HeaderEntry<'a> {
+    id: usize,
+    title: Option<&'a str>,
+    date: Option<String>,
+    /// The changes since the header before, empty for the first one
+    changes: Vec<HeaderChange>,
+}
+
+/// The stored headers of a feed, oldest first, with the fields that changed
+/// from one to the next
+#[get("/feed/<feed_id>/headers")]
+pub async fn feed_headers(db: &State<SyncDatabase>, feed_id: String) -> Option<Template> {
+    let db = db.read().await;
+    let feed = db.get(&feed_id)?;
+    let mut stored: Vec<_> = feed.feed_headers().iter().collect();
+    stored.sort_by_key(|header| header.id());
+
+    let mut headers = Vec::new();
+    let mut previous: Option<&FeedHeader> = None;
+    for meta in stored {
+        headers.push(HeaderEntry {
+            id: meta.id(),
+            title: meta.header.title(),
+            date: meta.header.date(),
+            changes: match previous {
+                Some(previous) => previous.diff(&meta.header),
+                None => Vec::new(),
+            },
+        });
+        previous = Some(&meta.header);
+    }
+
+    Some(Template::render(
+        "pages/feed_headers",
+        &HeadersContext {
+            feed_id: &feed_id,
+            feed_name: feed.display_name(),
+            headers,
+        },
+    ))
+}
+
+#[derive(serde::Serialize)]
+struct ArchivedContext<'a> {
+    feed_id: &'a str,
+    feed_name: &'a str,
+    item_name: &'a str,
+    content_link: Option<&'a str>,
+    content: String,
+}
+
+/// The copy of the linked article of an item, kept by the archiver. The
+/// reader view of the items of feeds with the `full_content` setting.
+#[get("/feed/<feed_id>/item/<item_id>/archived")]
+pub async fn item_archived(
+    db: &State<SyncDatabase>,
+    feed_id: String,
+    item_id: usize,
+) -> Option<Template> {
+    let db = db.read().await;
+    let feed = db.get(&feed_id)?;
+    let item = feed.items().iter().find(|item| item.id() == item_id)?;
+    let content = db.archived_content(&feed_id, item_id)?;
+
+    Some(Template::render(
+        "pages/archived",
+        &ArchivedContext {
+            feed_id: &feed_id,
+            feed_name: feed.display_name(),
+            item_name: item.display_title().unwrap_or("???"),
+            content_link: item.content_link(),
+            content: content.html,
+        },
+    ))
+}
+
+/// Why an item shows up, optionally for the filter of the current view
+#[get("/feed/<feed_id>/item/<item_id>/provenance?<filter>")]
+pub async fn item_provenance(
+    db: &State<SyncDatabase>,
+    feed_id: String,
+    item_id: usize,
+    filter: Option<String>,
+) -> Option<(ContentType, String)> {
+    let db = db.read().await;
+    let filter = Filter::new(filter, db.tag_synonyms());
+    let provenance = db.item_provenance(&feed_id, item_id, filter.query())?;
+
+    Some((
+        ContentType::JSON,
+        serde_json::to_string_pretty(&provenance).unwrap(),
+    ))
+}
+
+/// A link to an item that stays valid across fetches and restarts. Leads to
+/// the item on the page of its feed.
+#[get("/item/<slug>")]
+pub async fn item_permalink(db: &State<SyncDatabase>, slug: &str) -> Option<Redirect> {
+    let db = db.read().await;
+    if let Some((feed_id, _, item)) = db.item_by_slug(slug) {
+        let anchor = item_anchor(feed_id, item.id());
+        return Some(Redirect::to(format!(
+            "{}#{}",
+            uri!(feed(&feed_id[..], _, _, _)),
+            anchor
+        )));
+    }
+
+    // Items in cold storage are only shown in the history
+    let (feed_id, item_id) = db.cold_item_by_slug(slug)?;
+    let anchor = item_anchor(&feed_id, item_id);
+    Some(Redirect::to(format!(
+        "{}#{}",
+        uri!(feed_history(&feed_id[..])),
+        anchor
+    )))
 }
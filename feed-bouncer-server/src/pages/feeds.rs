@@ -1,52 +1,365 @@
-use rocket::State;
+use std::{
+    collections::{BTreeSet, HashMap},
+    str::FromStr,
+    sync::Arc,
+};
+
+use feed_bouncer_database::{FeedId, FeedOptions, UpdateProgress};
+use rocket::form::Form;
+use rocket::http::Status;
+use rocket::{response::Redirect, State};
 use rocket_dyn_templates::Template;
 
-use crate::common::{Filter, Nav, SyncDatabase};
+use crate::common::{Filter, Nav, SyncDatabase, Tag};
+use crate::pages::feed::DEFAULT_CATCH_UP_KEEP;
+use crate::triggers::SameOrigin;
 
 #[derive(serde::Serialize)]
 pub struct Feed<'a> {
     pub feed_name: &'a str,
     pub feed_id: &'a str,
     pub tags: String,
+    pub moved: bool,
+    pub last_item: Option<String>,
+    pub unread: usize,
+    pub items: usize,
+    /// Nesting level below OPML folders, 0 unless shown as a tree
+    pub depth: usize,
+}
+
+#[derive(serde::Serialize)]
+struct SortLink {
+    label: &'static str,
+    link: String,
+    active: bool,
+    descending: bool,
 }
 
 #[derive(serde::Serialize)]
 struct Feeds<'a> {
     feeds: Vec<Feed<'a>>,
+    /// Feeds matching the filter, and all subscribed feeds
+    matching_feeds: usize,
+    total_feeds: usize,
     nav: Nav<'a>,
+    sort_links: Vec<SortLink>,
+    catch_up_keep: usize,
+    tree: bool,
+    /// Link to the same page, with or without the tree
+    other_layout_link: String,
+    /// The authors of the matching feeds
+    authors_link: String,
 }
 
-#[get("/feeds?<filter>")]
-pub async fn feeds(db: &State<SyncDatabase>, filter: Option<String>) -> Template {
-    let filter = Filter::new(filter);
+#[derive(Clone, Copy, PartialEq)]
+enum FeedSort {
+    Name,
+    LastItem,
+    Unread,
+    Items,
+    Tags,
+}
+
+impl FeedSort {
+    const ALL: [FeedSort; 5] = [
+        FeedSort::Name,
+        FeedSort::LastItem,
+        FeedSort::Unread,
+        FeedSort::Items,
+        FeedSort::Tags,
+    ];
+
+    fn parse(s: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|sort| sort.as_str() == s)
+    }
+    fn as_str(self) -> &'static str {
+        match self {
+            FeedSort::Name => "name",
+            FeedSort::LastItem => "last_item",
+            FeedSort::Unread => "unread",
+            FeedSort::Items => "items",
+            FeedSort::Tags => "tags",
+        }
+    }
+    fn label(self) -> &'static str {
+        match self {
+            FeedSort::Name => "name",
+            FeedSort::LastItem => "last item",
+            FeedSort::Unread => "unread",
+            FeedSort::Items => "items",
+            FeedSort::Tags => "tags",
+        }
+    }
+}
+
+/// Orders feeds below their parents, keeping the order among siblings.
+/// Feeds whose parent is not part of `feeds` are shown at the top level.
+fn tree_order<'a>(
+    feeds: Vec<(&'a FeedId, &'a feed_bouncer_database::Feed)>,
+) -> Vec<(usize, &'a FeedId, &'a feed_bouncer_database::Feed)> {
+    let feed_ids: BTreeSet<&FeedId> = feeds.iter().map(|(feed_id, _)| *feed_id).collect();
+    let mut roots = Vec::new();
+    let mut children: HashMap<&FeedId, Vec<_>> = HashMap::new();
+    for (feed_id, feed) in feeds {
+        match feed.parent().filter(|parent| feed_ids.contains(parent)) {
+            Some(parent) => children.entry(parent).or_default().push((feed_id, feed)),
+            None => roots.push((feed_id, feed)),
+        }
+    }
+
+    let mut ordered = Vec::new();
+    let mut pending: Vec<_> = roots
+        .into_iter()
+        .rev()
+        .map(|(feed_id, feed)| (0, feed_id, feed))
+        .collect();
+    while let Some((depth, feed_id, feed)) = pending.pop() {
+        ordered.push((depth, feed_id, feed));
+        if let Some(children) = children.remove(feed_id) {
+            pending.extend(
+                children
+                    .into_iter()
+                    .rev()
+                    .map(|(feed_id, feed)| (depth + 1, feed_id, feed)),
+            );
+        }
+    }
+    // Cycles of broken parent links are never reached from a root
+    for (_, feeds) in children {
+        ordered.extend(feeds.into_iter().map(|(feed_id, feed)| (0, feed_id, feed)));
+    }
+    ordered
+}
+
+/// `sort` is one of `name` (default), `last_item`, `unread`, `items` or
+/// `tags`, `dir` is `asc` (default) or `desc`. With `tree`, feeds are nested
+/// below the OPML folders they got imported from.
+#[get("/feeds?<filter>&<sort>&<dir>&<tree>")]
+pub async fn feeds(
+    db: &State<SyncDatabase>,
+    progress: &State<Arc<UpdateProgress>>,
+    filter: Option<String>,
+    sort: Option<&str>,
+    dir: Option<&str>,
+    tree: Option<bool>,
+) -> Template {
+    let tree = tree.unwrap_or(false);
+    let filter = Filter::new(filter, db.read().await.tag_synonyms());
+    let sort = sort.and_then(FeedSort::parse).unwrap_or(FeedSort::Name);
+    let descending = dir == Some("desc");
     let mut feeds = Vec::new();
 
     let db = db.read().await;
+    db.record_filter_use(filter.query(), filter.raw());
 
-    let mut feeds_src = db.get_feeds();
+    let mut feeds_src = db.query_feeds(filter.query());
+    let matching_feeds = feeds_src.len();
     feeds_src.sort_by_key(|(_, feed)| {
         (
             feed.display_name().to_ascii_lowercase(),
             feed.display_name(),
         )
     });
+    // Stable sorts, so ties stay ordered by name
+    match sort {
+        FeedSort::Name => {}
+        FeedSort::LastItem => feeds_src.sort_by_key(|(_, feed)| feed.last_item_date()),
+        FeedSort::Unread => feeds_src.sort_by_cached_key(|(feed_id, _)| db.unread_count(feed_id)),
+        FeedSort::Items => feeds_src.sort_by_key(|(_, feed)| feed.item_count()),
+        FeedSort::Tags => feeds_src.sort_by_key(|(_, feed)| feed.tag_count()),
+    }
+    if descending {
+        feeds_src.reverse();
+    }
 
-    for (feed_id, feed) in feeds_src {
-        if !filter.matches(feed) {
-            continue;
-        }
+    let feeds_src = if tree {
+        tree_order(feeds_src)
+    } else {
+        feeds_src
+            .into_iter()
+            .map(|(feed_id, feed)| (0, feed_id, feed))
+            .collect()
+    };
+
+    for (depth, feed_id, feed) in feeds_src {
         feeds.push(Feed {
             feed_name: feed.display_name(),
             feed_id: &feed_id,
             tags: feed.tags().collect::<Vec<_>>().join(", "),
+            moved: !feed.previous_feed_urls().is_empty(),
+            last_item: feed
+                .last_item_date()
+                .map(|date| date.format("%Y-%m-%d").to_string()),
+            unread: db.unread_count(feed_id),
+            items: feed.item_count(),
+            depth,
         });
     }
 
+    let sort_links = FeedSort::ALL
+        .iter()
+        .map(|&option| {
+            let active = option == sort;
+            // Clicking the active option again flips the direction
+            let dir = if active && !descending { "desc" } else { "asc" };
+            SortLink {
+                label: option.label(),
+                link: uri!(feeds(
+                    filter.raw_opt(),
+                    Some(option.as_str()),
+                    Some(dir),
+                    Some(tree)
+                ))
+                .to_string(),
+                active,
+                descending: active && descending,
+            }
+        })
+        .collect();
+    let other_layout_link = uri!(feeds(
+        filter.raw_opt(),
+        Some(sort.as_str()),
+        Some(if descending { "desc" } else { "asc" }),
+        Some(!tree)
+    ))
+    .to_string();
+
     Template::render(
         "pages/feeds",
         &Feeds {
             feeds,
-            nav: Nav::new(&db, &filter),
+            matching_feeds,
+            total_feeds: db.feed_count(),
+            nav: Nav::new(&db, &filter).with_update_progress(progress),
+            sort_links,
+            catch_up_keep: DEFAULT_CATCH_UP_KEEP,
+            tree,
+            other_layout_link,
+            authors_link: uri!(crate::pages::authors::authors(filter.raw_opt())).to_string(),
         },
     )
 }
+
+/// Catches up on all feeds matching the filter at once.
+#[derive(FromForm)]
+pub struct CatchUpAll {
+    filter: Option<String>,
+    /// How many of the newest items of each feed stay unread
+    keep: Option<usize>,
+}
+
+#[post("/feeds/catch_up", data = "<catch_up>")]
+pub async fn feeds_catch_up(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    catch_up: Form<CatchUpAll>,
+) -> Redirect {
+    let catch_up = catch_up.into_inner();
+    let mut db = db.write().await;
+    let filter = Filter::new(catch_up.filter, db.tag_synonyms());
+
+    if db.catch_up(
+        filter.query(),
+        catch_up.keep.unwrap_or(DEFAULT_CATCH_UP_KEEP),
+    ) > 0
+    {
+        db.save_user_data();
+    }
+
+    Redirect::to(uri!(feeds(filter.raw_opt(), _, _, _)))
+}
+
+#[derive(FromForm)]
+pub struct BulkEdit<'r> {
+    /// The checked feeds
+    feed_ids: Vec<String>,
+    /// `add_tags`, `remove_tags`, `mute` or `delete`
+    action: &'r str,
+    /// Comma separated, for the tag actions
+    tags: &'r str,
+    /// Filter of the page to return to
+    filter: Option<String>,
+}
+
+enum BulkAction {
+    AddTags,
+    RemoveTags,
+    Mute,
+    Delete,
+}
+
+impl FromStr for BulkAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "add_tags" => Ok(Self::AddTags),
+            "remove_tags" => Ok(Self::RemoveTags),
+            "mute" => Ok(Self::Mute),
+            "delete" => Ok(Self::Delete),
+            _ => Err(format!("unknown bulk action {:?}", s)),
+        }
+    }
+}
+
+/// Applies one action to all checked feeds, with a single save.
+#[post("/feeds/bulk", data = "<edit>")]
+pub async fn feeds_bulk(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    edit: Form<BulkEdit<'_>>,
+) -> Result<Redirect, (Status, String)> {
+    let action: BulkAction = edit.action.parse().map_err(|e| (Status::BadRequest, e))?;
+    let mut db = db.write().await;
+    let tags: Vec<String> = edit
+        .tags
+        .split(',')
+        .filter_map(Tag::new)
+        .map(|tag| db.tag_synonyms().canonical(tag.as_str()).to_owned())
+        .collect();
+    let mut changed = false;
+    let mut shrunk = false;
+
+    for feed_id in &edit.feed_ids {
+        match action {
+            BulkAction::AddTags => {
+                if let Some(feed) = db.get_mut(feed_id) {
+                    changed |= feed.extend_tags(tags.iter().map(|tag| tag.as_str()));
+                }
+            }
+            BulkAction::RemoveTags => {
+                if let Some(feed) = db.get_mut(feed_id) {
+                    for tag in &tags {
+                        shrunk |= feed.remove_tag(tag.as_str());
+                    }
+                }
+            }
+            BulkAction::Mute => {
+                let options = match db.get(feed_id) {
+                    Some(feed) if feed.options().mute_from_index != Some(true) => FeedOptions {
+                        mute_from_index: Some(true),
+                        ..feed.options().clone()
+                    },
+                    _ => continue,
+                };
+                changed |= db.set_feed_options(feed_id, options);
+            }
+            BulkAction::Delete => {
+                shrunk |= db.remove_feed(feed_id).is_some();
+            }
+        }
+    }
+
+    // All checked feeds change together or not at all
+    let saved = if shrunk {
+        db.save_atomic_shrunk()
+    } else if changed {
+        db.save_atomic()
+    } else {
+        Ok(())
+    };
+    saved.map_err(|e| (Status::InternalServerError, e.to_string()))?;
+
+    let filter = Filter::new(edit.filter.clone(), db.tag_synonyms());
+    Ok(Redirect::to(uri!(feeds(filter.raw_opt(), _, _, _))))
+}
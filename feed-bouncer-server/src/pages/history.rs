@@ -0,0 +1,50 @@
+use rocket::State;
+use rocket_dyn_templates::Template;
+
+use crate::common::SyncDatabase;
+
+/// Number of reads shown when no limit is given
+const DEFAULT_LIMIT: usize = 200;
+
+#[derive(serde::Serialize)]
+struct Read<'a> {
+    read_at: String,
+    feed_id: &'a str,
+    feed_name: &'a str,
+    item_name: &'a str,
+    content_link: Option<&'a str>,
+    permalink: String,
+}
+
+#[derive(serde::Serialize)]
+struct Context<'a> {
+    reads: Vec<Read<'a>>,
+    read_today: u64,
+}
+
+/// Lists the recently read items, the most recent first.
+#[get("/history?<limit>")]
+pub async fn history(db: &State<SyncDatabase>, limit: Option<usize>) -> Template {
+    let db = db.read().await;
+
+    let reads = db
+        .recently_read(limit.unwrap_or(DEFAULT_LIMIT))
+        .into_iter()
+        .map(|(read_at, feed_id, feed, item)| Read {
+            read_at: read_at.format("%Y-%m-%d %H:%M").to_string(),
+            feed_id,
+            feed_name: feed.display_name(),
+            item_name: item.display_title_without_prefixes(feed).unwrap_or("???"),
+            content_link: item.content_link(),
+            permalink: uri!(crate::pages::feed::item_permalink(item.slug(feed_id))).to_string(),
+        })
+        .collect();
+
+    Template::render(
+        "pages/history",
+        &Context {
+            reads,
+            read_today: db.reads_today(),
+        },
+    )
+}
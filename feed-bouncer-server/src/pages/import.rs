@@ -1,35 +1,328 @@
+use std::collections::HashMap;
+
 use rocket::form::Form;
+use rocket::http::Status;
 use rocket::{response::Redirect, State};
 use rocket_dyn_templates::Template;
 
-use crate::common::SyncDatabase;
+use feed_bouncer_database::{Database, FeedUrlError, OpmlImportReport, PreviewTaskResult};
+
+use crate::common::{SyncDatabase, Tag};
+use crate::import_queue::{ImportJob, ImportQueue};
+use crate::pages::admin_api::same_token;
+use crate::triggers::SameOrigin;
 
 #[derive(serde::Serialize)]
-struct Context {}
+struct Context {
+    /// The configured age limit for the first fetch, shown as placeholder
+    max_initial_age_days: Option<u64>,
+}
 
 #[get("/import")]
-pub async fn import(_db: &State<SyncDatabase>) -> Option<Template> {
-    Some(Template::render("pages/import", &Context {}))
+pub async fn import(db: &State<SyncDatabase>) -> Option<Template> {
+    let db = db.read().await;
+    Some(Template::render(
+        "pages/import",
+        &Context {
+            max_initial_age_days: db.config().updates.max_initial_age_days,
+        },
+    ))
+}
+
+#[derive(serde::Serialize)]
+struct Overlap<'a> {
+    feed_name: &'a str,
+    feed_id: &'a str,
+    reasons: &'a [String],
+}
+
+#[derive(serde::Serialize)]
+struct Preview<'a> {
+    rss_url: &'a str,
+    title: &'a str,
+    site_link: Option<&'a str>,
+    item_count: usize,
+    overlaps: Vec<Overlap<'a>>,
+    max_initial_age_days: Option<u64>,
+}
+
+/// Shows what a feed contains before subscribing to it, together with feeds
+/// that probably already provide the same content.
+#[get("/import/preview?<rss_url>")]
+pub async fn import_preview(
+    db: &State<SyncDatabase>,
+    rss_url: &str,
+) -> Result<Template, (Status, String)> {
+    // Don't hold the lock during the download
+    let task = db.read().await.preview_task(rss_url);
+    let result = task.run().await;
+    render_preview(&*db.read().await, rss_url, result)
+}
+
+fn render_preview(
+    db: &Database,
+    rss_url: &str,
+    result: PreviewTaskResult,
+) -> Result<Template, (Status, String)> {
+    let preview = db.preview_import(result).map_err(|e| {
+        let status = match e {
+            FeedUrlError::Download(_) => Status::BadGateway,
+            _ => Status::UnprocessableEntity,
+        };
+        (status, e.to_string())
+    })?;
+
+    let overlaps = preview
+        .overlaps
+        .iter()
+        .map(|overlap| Overlap {
+            feed_name: overlap.feed.display_name(),
+            feed_id: overlap.feed_id,
+            reasons: &overlap.reasons,
+        })
+        .collect();
+
+    Ok(Template::render(
+        "pages/import_preview",
+        &Preview {
+            rss_url,
+            title: &preview.title,
+            site_link: preview.site_link.as_deref(),
+            item_count: preview.item_count,
+            overlaps,
+            max_initial_age_days: db.config().updates.max_initial_age_days,
+        },
+    ))
+}
+
+#[derive(serde::Serialize)]
+struct FeedCandidate {
+    url: String,
+    preview_link: String,
+}
+
+#[derive(serde::Serialize)]
+struct Subscribe<'a> {
+    url: &'a str,
+    feeds: Vec<FeedCandidate>,
+    error: Option<String>,
+}
+
+/// Finds the feeds of a web page and shows the preview to subscribe to them,
+/// for a bookmarklet or a share target of the browser. Pages with several
+/// feeds get a list to pick one from.
+#[get("/subscribe?<url>&<token>")]
+pub async fn subscribe(
+    db: &State<SyncDatabase>,
+    url: &str,
+    token: Option<&str>,
+) -> Result<Template, Status> {
+    // Don't hold the lock during the downloads
+    let task = {
+        let db = db.read().await;
+        if let Some(expected) = &db.config().ui.subscribe_token {
+            if !token.map_or(false, |token| same_token(token, expected)) {
+                return Err(Status::Forbidden);
+            }
+        }
+        db.discover_task(url)
+    };
+
+    let (feeds, error) = match task.run().await {
+        Ok(feeds) => (feeds, None),
+        Err(e) => (Vec::new(), Some(e.to_string())),
+    };
+    if let [feed] = &feeds[..] {
+        let task = db.read().await.preview_task(feed);
+        let result = task.run().await;
+        if let Ok(preview) = render_preview(&*db.read().await, feed, result) {
+            return Ok(preview);
+        }
+    }
+
+    let feeds = feeds
+        .into_iter()
+        .map(|feed| FeedCandidate {
+            preview_link: uri!(import_preview(&feed[..])).to_string(),
+            url: feed,
+        })
+        .collect();
+    Ok(Template::render(
+        "pages/subscribe",
+        &Subscribe { url, feeds, error },
+    ))
 }
 
 #[derive(FromForm)]
 pub struct NewRss<'r> {
     rss_url: &'r str,
+    /// Skip older items on the first fetch, empty for the configured default
+    max_initial_age_days: Option<u64>,
 }
 
 #[post("/import/rss", data = "<new_rss>")]
-pub async fn import_rss(db: &State<SyncDatabase>, new_rss: Form<NewRss<'_>>) -> Option<Redirect> {
-    let mut db = db.write().await;
-
-    // TODO: Do not await here blockingly
+pub async fn import_rss(
+    queue: &State<ImportQueue>,
+    _origin: SameOrigin,
+    new_rss: Form<NewRss<'_>>,
+) -> Redirect {
     // TODO: initial tags
-    if let Ok(feed_ids) = db.import_from_rss(new_rss.rss_url, &[]).await {
-        if let Some(feed_id) = feed_ids.iter().next() {
-            return Some(Redirect::to(uri!(crate::pages::feed::feed(feed_id))));
-        }
+    queue.enqueue(
+        new_rss.rss_url,
+        Vec::new(),
+        new_rss.max_initial_age_days,
+        None,
+    );
+
+    Redirect::to(uri!(imports(_)))
+}
+
+#[derive(FromForm)]
+pub struct BulkImport {
+    /// One url per line, optionally followed by tags
+    urls: String,
+    max_initial_age_days: Option<u64>,
+}
+
+#[derive(serde::Serialize)]
+struct BulkLine<'a> {
+    line: usize,
+    url: &'a str,
+    tags: Vec<String>,
+    /// Why the line was not queued
+    error: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct BulkImportResult<'a> {
+    batch: usize,
+    queued: usize,
+    lines: Vec<BulkLine<'a>>,
+}
+
+/// Parses `url tags...`, returns `None` for empty lines and `#` comments
+fn parse_bulk_line(line: &str) -> Option<(&str, Result<Vec<String>, String>)> {
+    let mut words = line.split_whitespace();
+    let url = words.next().filter(|url| !url.starts_with('#'))?;
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Some((url, Err("not a http or https url".to_owned())));
+    }
+    let tags = words
+        .map(|word| {
+            Tag::new(word)
+                .map(|tag| tag.as_str().to_owned())
+                .ok_or_else(|| format!("invalid tag {:?}", word))
+        })
+        .collect();
+    Some((url, tags))
+}
+
+/// Queues many feeds at once, one url per line
+#[post("/import/bulk", data = "<bulk>")]
+pub async fn import_bulk(
+    queue: &State<ImportQueue>,
+    _origin: SameOrigin,
+    bulk: Form<BulkImport>,
+) -> Template {
+    let batch = queue.new_batch();
+    let mut queued = 0;
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    let mut lines = Vec::new();
+    for (i, line) in bulk.urls.lines().enumerate() {
+        let (url, tags) = match parse_bulk_line(line) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+        let first = seen.get(url).copied();
+        let (tags, error) = match (tags, first) {
+            (Err(e), _) => (Vec::new(), Some(e)),
+            (Ok(tags), Some(first)) => (tags, Some(format!("duplicate of line {}", first))),
+            (Ok(tags), None) => {
+                seen.insert(url, i + 1);
+                queue.enqueue(url, tags.clone(), bulk.max_initial_age_days, Some(batch));
+                queued += 1;
+                (tags, None)
+            }
+        };
+        lines.push(BulkLine {
+            line: i + 1,
+            url,
+            tags,
+            error,
+        });
+    }
+
+    Template::render(
+        "pages/import_bulk",
+        &BulkImportResult {
+            batch,
+            queued,
+            lines,
+        },
+    )
+}
+
+#[derive(serde::Serialize)]
+struct Imports {
+    jobs: Vec<ImportJob>,
+    batch: Option<usize>,
+}
+
+/// The queued imports and their results since the server started, or only
+/// those of one bulk import.
+#[get("/imports?<batch>")]
+pub async fn imports(queue: &State<ImportQueue>, batch: Option<usize>) -> Template {
+    let mut jobs = queue.jobs();
+    if let Some(batch) = batch {
+        jobs.retain(|job| job.batch == Some(batch));
     }
+    Template::render("pages/imports", &Imports { jobs, batch })
+}
+
+#[derive(FromForm)]
+pub struct OpmlImport<'r> {
+    opml: String,
+    /// Comma separated tags added to all imported feeds
+    tags: &'r str,
+    /// Tag the feeds with the names of their folders
+    folder_tags: bool,
+}
+
+#[derive(serde::Serialize)]
+struct OpmlImportResult {
+    report: Option<OpmlImportReport>,
+    error: Option<String>,
+}
+
+/// Subscribes to the feeds of a pasted OPML document and shows what
+/// happened to each outline.
+#[post("/import/opml", data = "<import>")]
+pub async fn import_opml(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    import: Form<OpmlImport<'_>>,
+) -> Template {
+    let tags: Vec<String> = import
+        .tags
+        .split(',')
+        .filter_map(Tag::new)
+        .map(|tag| tag.as_str().to_owned())
+        .collect();
+
+    let mut db = db.write().await;
+    let result = match db.import_opml(&import.opml, &tags, import.folder_tags) {
+        Ok(report) => {
+            db.save();
+            OpmlImportResult {
+                report: Some(report),
+                error: None,
+            }
+        }
+        Err(e) => OpmlImportResult {
+            report: None,
+            error: Some(e.to_string()),
+        },
+    };
 
-    Some(Redirect::to(uri!(crate::pages::index::index(
-        None::<String>
-    ))))
+    Template::render("pages/import_opml", &result)
 }
@@ -1,40 +1,436 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone, Utc};
+use feed_bouncer_database::{
+    Database, ItemGrouping, ItemOrder, ItemQuery, ItemSortKey, UpdateProgress, ViewMode,
+};
 use rocket::State;
 use rocket_dyn_templates::Template;
 
-use crate::common::{Filter, ItemBuilder, ItemsGroups, Nav, SyncDatabase};
+use crate::common::{
+    grouping_links, item_grouping, view_mode, Filter, GroupingLink, ItemBuilder, ItemsGroups, Nav,
+    SyncDatabase,
+};
+use crate::pages::timeline::TimelineFeedLinks;
+
+/// Number of feeds suggested when everything is read
+const QUIET_FEED_SUGGESTIONS: usize = 5;
+
+/// Number of items shown per page
+const ITEMS_PER_PAGE: usize = 100;
+
+#[derive(serde::Serialize)]
+struct QuietFeed<'a> {
+    feed_id: &'a str,
+    feed_name: &'a str,
+    unread: usize,
+}
+
+/// Shown instead of an empty list when the filter has no unread items
+#[derive(serde::Serialize)]
+struct InboxZero<'a> {
+    read_today: u64,
+    quiet_feeds: Vec<QuietFeed<'a>>,
+}
 
 #[derive(serde::Serialize)]
 struct Index<'a> {
     items: ItemsGroups<'a>,
     nav: Nav<'a>,
+    order: &'static str,
+    sort: &'static str,
+    /// Link to the same page, sorted by the other date
+    other_sort_link: String,
+    /// Link to the same page in the other view mode
+    other_view_link: String,
+    /// Links to the same page with the other item groupings
+    grouping_links: Vec<GroupingLink>,
+    compact: bool,
+    unread_only: bool,
+    search: Option<&'a str>,
+    /// Link to the same page without the default filter, if it was applied
+    without_default_link: Option<String>,
+    inbox_zero: Option<InboxZero<'a>>,
+    /// Link to the items without the page around them, see [`index_partial`]
+    partial_link: String,
+    /// Only items first seen since the last visit are shown
+    since_last_visit: bool,
+    /// Link to the same page with and without `since_last_visit`
+    since_link: String,
+    /// The RSS and JSON feeds, if the filter is a single tag
+    tag_feeds: Option<TimelineFeedLinks>,
 }
 
-#[get("/?<filter>")]
-pub async fn index(db: &State<SyncDatabase>, filter: Option<String>) -> Template {
-    let filter = Filter::new(filter);
-    let mut items = ItemBuilder::new(true);
+/// `from` and `to` are dates like `2023-06-05`, both inclusive. `page`
+/// starts at 1. `since` is `last_visit` to only show the items that are new
+/// since the last visit.
+#[allow(clippy::too_many_arguments)]
+#[get("/?<filter>&<order>&<unread>&<q>&<sort>&<view>&<group>&<from>&<to>&<page>&<since>")]
+pub async fn index(
+    db: &State<SyncDatabase>,
+    progress: &State<Arc<UpdateProgress>>,
+    filter: Option<String>,
+    order: Option<&str>,
+    unread: Option<bool>,
+    q: Option<&str>,
+    sort: Option<&str>,
+    view: Option<&str>,
+    group: Option<&str>,
+    from: Option<&str>,
+    to: Option<&str>,
+    page: Option<usize>,
+    since: Option<&str>,
+) -> Template {
+    let filter = Filter::or_default(filter, &*db.read().await);
+    let order = order
+        .and_then(|order| order.parse().ok())
+        .unwrap_or(ItemOrder::NewestFirst);
+    let sort = sort
+        .and_then(|sort| sort.parse().ok())
+        .unwrap_or(ItemSortKey::Published);
+    let unread_only = unread.unwrap_or(false);
+    let search = q.filter(|q| !q.trim().is_empty());
+    let view = view_mode(db, view).await;
+    let grouping = item_grouping(db, group).await;
+    let from = from.and_then(parse_date);
+    let to = to.and_then(parse_date);
 
     let db = db.read().await;
+    db.record_visit();
+    if !filter.is_default() {
+        db.record_filter_use(filter.query(), filter.raw());
+    }
+    let items = page_items(
+        &db,
+        &filter,
+        order,
+        sort,
+        unread,
+        search,
+        view,
+        grouping,
+        from,
+        to,
+        page.unwrap_or(1),
+        since,
+    );
 
-    {
-        let mut feeds = db.get_items_ordered_by_time();
-        feeds.reverse();
-        feeds.dedup_by(|a, b| a.2.content_link() == b.2.content_link());
-        // let feeds = &feeds[0..(feeds.len().min(10))];
-        for (feed_id, feed, item) in &feeds[..] {
-            if !filter.matches(feed) {
-                continue;
-            }
-            items.push_sorted(&item, &feed_id, feed, db.is_read(&feed_id, item.id()));
+    let inbox_zero = if search.is_none() {
+        let mut unread = ItemQuery::new()
+            .feeds(filter.query().clone())
+            .read(false)
+            .hidden(false)
+            .limit(1);
+        unread = with_date_range(unread, from, to);
+        if filter.hides_muted() {
+            unread = unread.without_muted();
         }
-    }
-    let items = items.into_groups();
+        db.query_items(&unread).is_empty().then(|| InboxZero {
+            read_today: db.reads_today(),
+            quiet_feeds: db
+                .quiet_feeds(QUIET_FEED_SUGGESTIONS)
+                .into_iter()
+                .map(|(feed_id, feed, unread)| QuietFeed {
+                    feed_id,
+                    feed_name: feed.display_name(),
+                    unread,
+                })
+                .collect(),
+        })
+    } else {
+        None
+    };
+
+    let other_sort = match sort {
+        ItemSortKey::Published => ItemSortKey::Discovered,
+        ItemSortKey::Discovered => ItemSortKey::Published,
+    };
+    let other_sort_link = uri!(index(
+        filter.raw_opt(),
+        Some(order.as_str()),
+        unread,
+        search,
+        Some(other_sort.as_str()),
+        _,
+        _,
+        from.map(|date| date.to_string()),
+        to.map(|date| date.to_string()),
+        _,
+        since
+    ))
+    .to_string();
+    let other_view = match view {
+        ViewMode::Full => ViewMode::Compact,
+        ViewMode::Compact => ViewMode::Full,
+    };
+    let other_view_link = uri!(index(
+        filter.raw_opt(),
+        Some(order.as_str()),
+        unread,
+        search,
+        Some(sort.as_str()),
+        Some(other_view.as_str()),
+        _,
+        from.map(|date| date.to_string()),
+        to.map(|date| date.to_string()),
+        _,
+        since
+    ))
+    .to_string();
+    let grouping_links = grouping_links(grouping, |grouping| {
+        uri!(index(
+            filter.raw_opt(),
+            Some(order.as_str()),
+            unread,
+            search,
+            Some(sort.as_str()),
+            _,
+            Some(grouping.as_str()),
+            from.map(|date| date.to_string()),
+            to.map(|date| date.to_string()),
+            _,
+            since
+        ))
+        .to_string()
+    });
+    let without_default_link = filter.is_default().then(|| {
+        uri!(index(
+            Some(""),
+            Some(order.as_str()),
+            unread,
+            search,
+            Some(sort.as_str()),
+            _,
+            _,
+            from.map(|date| date.to_string()),
+            to.map(|date| date.to_string()),
+            _,
+            since
+        ))
+        .to_string()
+    });
+    let partial_link = uri!(index_partial(
+        filter.raw_opt(),
+        Some(order.as_str()),
+        unread,
+        search,
+        Some(sort.as_str()),
+        _,
+        _,
+        from.map(|date| date.to_string()),
+        to.map(|date| date.to_string()),
+        _,
+        since
+    ))
+    .to_string();
+    let since_last_visit = since == Some("last_visit");
+    let other_since = if since_last_visit {
+        None
+    } else {
+        Some("last_visit")
+    };
+    let since_link = uri!(index(
+        filter.raw_opt(),
+        Some(order.as_str()),
+        unread,
+        search,
+        Some(sort.as_str()),
+        _,
+        _,
+        from.map(|date| date.to_string()),
+        to.map(|date| date.to_string()),
+        _,
+        other_since
+    ))
+    .to_string();
+    let nav = Nav::new(&db, &filter)
+        .with_update_progress(progress)
+        .with_date_range(from, to, |from, to| {
+            uri!(index(
+                filter.raw_opt(),
+                Some(order.as_str()),
+                unread,
+                search,
+                Some(sort.as_str()),
+                _,
+                _,
+                from.map(|date| date.to_string()),
+                to.map(|date| date.to_string()),
+                _,
+                since
+            ))
+            .to_string()
+        });
 
     Template::render(
         "pages/index",
         &Index {
             items,
-            nav: Nav::new(&db, &filter),
+            nav,
+            order: order.as_str(),
+            sort: sort.as_str(),
+            other_sort_link,
+            other_view_link,
+            grouping_links,
+            compact: view == ViewMode::Compact,
+            unread_only,
+            search,
+            without_default_link,
+            inbox_zero,
+            partial_link,
+            since_last_visit,
+            since_link,
+            tag_feeds: TimelineFeedLinks::for_filter(&db, &filter),
         },
     )
 }
+
+/// Only the items of the index, rendered without the page around them.
+/// Scripts use this to load further pages and to refresh the items after
+/// an action, without reloading the whole page.
+#[allow(clippy::too_many_arguments)]
+#[get(
+    "/partial/items?<filter>&<order>&<unread>&<q>&<sort>&<view>&<group>&<from>&<to>&<page>&<since>"
+)]
+pub async fn index_partial(
+    db: &State<SyncDatabase>,
+    filter: Option<String>,
+    order: Option<&str>,
+    unread: Option<bool>,
+    q: Option<&str>,
+    sort: Option<&str>,
+    view: Option<&str>,
+    group: Option<&str>,
+    from: Option<&str>,
+    to: Option<&str>,
+    page: Option<usize>,
+    since: Option<&str>,
+) -> Template {
+    let filter = Filter::or_default(filter, &*db.read().await);
+    let order = order
+        .and_then(|order| order.parse().ok())
+        .unwrap_or(ItemOrder::NewestFirst);
+    let sort = sort
+        .and_then(|sort| sort.parse().ok())
+        .unwrap_or(ItemSortKey::Published);
+    let search = q.filter(|q| !q.trim().is_empty());
+    let view = view_mode(db, view).await;
+    let grouping = item_grouping(db, group).await;
+    let from = from.and_then(parse_date);
+    let to = to.and_then(parse_date);
+
+    let db = db.read().await;
+    let items = page_items(
+        &db,
+        &filter,
+        order,
+        sort,
+        unread,
+        search,
+        view,
+        grouping,
+        from,
+        to,
+        page.unwrap_or(1),
+        since,
+    );
+    Template::render("items", &items)
+}
+
+/// One page of the items shown by [`index`] and [`index_partial`]
+#[allow(clippy::too_many_arguments)]
+fn page_items<'a>(
+    db: &'a Database,
+    filter: &'a Filter,
+    order: ItemOrder,
+    sort: ItemSortKey,
+    unread: Option<bool>,
+    search: Option<&str>,
+    view: ViewMode,
+    grouping: ItemGrouping,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    page: usize,
+    since: Option<&str>,
+) -> ItemsGroups<'a> {
+    let since_last_visit = since == Some("last_visit");
+    let mut items = ItemBuilder::new(true)
+        .with_filter(filter)
+        .with_sort_key(sort)
+        .with_view_mode(view)
+        .with_grouping(grouping)
+        .with_archive_links(db);
+    // Markers newer than the items of later pages would pile up at their top
+    if order == ItemOrder::NewestFirst && search.is_none() && page <= 1 {
+        items = items.with_subscription_markers(db.query_feeds(filter.query()));
+    }
+    if let Some(last_visit) = db.last_visit() {
+        if order == ItemOrder::NewestFirst && search.is_none() && !since_last_visit {
+            items = items.with_last_visit(last_visit);
+        }
+    }
+
+    let mut query = ItemQuery::new()
+        .feeds(filter.query().clone())
+        .order(order)
+        .sort_by(sort)
+        .hidden(false)
+        .dedup_links();
+    if unread.unwrap_or(false) {
+        query = query.read(false);
+    }
+    if let Some(search) = search {
+        query = query.text(search);
+    }
+    query = with_date_range(query, from, to);
+    if filter.hides_muted() {
+        query = query.without_muted();
+    }
+    if since_last_visit {
+        if let Some(last_visit) = db.last_visit() {
+            query = query.first_seen_after(last_visit);
+        }
+    }
+    let (page_items, item_page) = db.query_items_page(&query, page, ITEMS_PER_PAGE);
+    for (feed_id, feed, item, state) in page_items {
+        items.push_sorted(item, feed_id, feed, state);
+    }
+    let link = uri!(index(
+        filter.raw_opt(),
+        Some(order.as_str()),
+        unread,
+        search,
+        Some(sort.as_str()),
+        _,
+        _,
+        from.map(|date| date.to_string()),
+        to.map(|date| date.to_string()),
+        _,
+        since
+    ))
+    .to_string();
+    items.with_page(item_page, link).into_groups()
+}
+
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok()
+}
+
+/// Limits the query to items published between the start of `from` and the
+/// end of `to`, in UTC
+fn with_date_range(
+    mut query: ItemQuery,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> ItemQuery {
+    let start_of_day = |date: NaiveDate| {
+        DateTime::<FixedOffset>::from(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()))
+    };
+    if let Some(from) = from {
+        query = query.published_from(start_of_day(from));
+    }
+    if let Some(to) = to.and_then(|to| to.succ_opt()) {
+        query = query.published_to(start_of_day(to));
+    }
+    query
+}
@@ -0,0 +1,86 @@
+use feed_bouncer_database::{Database, FeedQuery};
+use rocket::http::ContentType;
+use rocket::State;
+
+use crate::common::SyncDatabase;
+
+/// Paths of the pages that may be indexed: the pages of the public tags and
+/// of the feeds carrying one of them.
+fn public_paths(db: &Database) -> Vec<String> {
+    let tags = &db.config().public.tags;
+    let mut paths = Vec::new();
+    for tag in tags {
+        paths.push(
+            uri!(crate::pages::index::index(
+                Some(tag.as_str()),
+                _,
+                _,
+                _,
+                _,
+                _,
+                _,
+                _,
+                _,
+                _,
+                _
+            ))
+            .to_string(),
+        );
+    }
+    for tag in tags {
+        for (feed_id, _) in db.query_feeds(&FeedQuery::new().with_tag(tag)) {
+            let path = uri!(crate::pages::feed::feed(feed_id, _, _, _)).to_string();
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    paths
+}
+
+pub fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Allows crawlers only on the public pages. Without any public tags the
+/// whole instance is excluded.
+#[get("/robots.txt")]
+pub async fn robots(db: &State<SyncDatabase>) -> String {
+    let db = db.read().await;
+
+    let mut robots = String::from("User-agent: *\n");
+    for path in public_paths(&db) {
+        robots += &format!("Allow: {}$\n", path);
+    }
+    robots += "Disallow: /\n";
+    if let Some(base_url) = &db.config().public.base_url {
+        robots += &format!("Sitemap: {}/sitemap.xml\n", base_url.trim_end_matches('/'));
+    }
+    robots
+}
+
+/// Lists the public pages, only served when `public.base_url` is configured
+/// since sitemaps need absolute urls.
+#[get("/sitemap.xml")]
+pub async fn sitemap(db: &State<SyncDatabase>) -> Option<(ContentType, String)> {
+    let db = db.read().await;
+    let base_url = db.config().public.base_url.as_ref()?.trim_end_matches('/');
+
+    let mut sitemap = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for path in public_paths(&db) {
+        sitemap += &format!(
+            "  <url><loc>{}</loc></url>\n",
+            escape_xml(&format!("{}{}", base_url, path))
+        );
+    }
+    sitemap += "</urlset>\n";
+
+    Some((ContentType::XML, sitemap))
+}
@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+
+use feed_bouncer_database::{Blocklist, FeedCommitSummary, SettingsBundle};
+use rocket::form::Form;
+use rocket::http::ContentType;
+use rocket::{response::Redirect, State};
+use rocket_dyn_templates::Template;
+
+use crate::common::SyncDatabase;
+use crate::supervisor::{Supervisor, TaskHealth};
+use crate::triggers::{
+    update::{UpdateHistory, UpdateSchedule},
+    SameOrigin,
+};
+
+#[derive(serde::Serialize)]
+struct Context {
+    message: Option<String>,
+    updates_paused: bool,
+    update_interval_mins: u64,
+    tasks: BTreeMap<&'static str, TaskHealth>,
+    default_filter: String,
+    /// Passed on by the bookmarklet
+    subscribe_token: Option<String>,
+    /// The lists of the blocklist, one entry per line
+    blocked_feed_urls: String,
+    blocked_domains: String,
+    blocked_titles: String,
+    recent_updates: Vec<RecentUpdate>,
+}
+
+#[derive(serde::Serialize)]
+struct RecentUpdate {
+    committed_at: String,
+    summary: String,
+    feeds: Vec<FeedCommitSummary>,
+}
+
+#[get("/settings?<message>")]
+pub async fn settings(
+    db: &State<SyncDatabase>,
+    schedule: &State<UpdateSchedule>,
+    supervisor: &State<Supervisor>,
+    history: &State<UpdateHistory>,
+    message: Option<String>,
+) -> Template {
+    let db = db.read().await;
+    let blocklist = &db.config().import.blocklist;
+    Template::render(
+        "pages/settings",
+        &Context {
+            message,
+            updates_paused: schedule.is_paused(),
+            update_interval_mins: schedule.interval.as_secs() / 60,
+            tasks: supervisor.health(),
+            default_filter: db.default_filter().to_owned(),
+            subscribe_token: db.config().ui.subscribe_token.clone(),
+            blocked_feed_urls: blocklist.feed_urls.join("\n"),
+            blocked_domains: blocklist.domains.join("\n"),
+            blocked_titles: blocklist.titles.join("\n"),
+            recent_updates: history
+                .summaries()
+                .into_iter()
+                .map(|update| RecentUpdate {
+                    committed_at: update.committed_at.format("%Y-%m-%d %H:%M").to_string(),
+                    summary: update.summary(),
+                    feeds: update.feeds,
+                })
+                .collect(),
+        },
+    )
+}
+
+#[get("/settings/export")]
+pub async fn settings_export(db: &State<SyncDatabase>) -> (ContentType, String) {
+    let db = db.read().await;
+    let bundle = serde_json::to_string_pretty(&db.export_settings()).unwrap();
+    (ContentType::JSON, bundle)
+}
+
+#[derive(FromForm)]
+pub struct DefaultFilter {
+    filter: String,
+}
+
+/// Sets the filter of the index page when none is given, empty to show all
+/// items
+#[post("/settings/default_filter", data = "<default>")]
+pub async fn settings_default_filter(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    default: Form<DefaultFilter>,
+) -> Redirect {
+    let mut db = db.write().await;
+    db.set_default_filter(&default.filter);
+    db.save_user_data();
+
+    Redirect::to(uri!(settings(_)))
+}
+
+#[derive(FromForm)]
+pub struct BlocklistForm<'r> {
+    /// One entry per line in each list
+    feed_urls: &'r str,
+    domains: &'r str,
+    titles: &'r str,
+}
+
+/// Replaces the blocklist of imports
+#[post("/settings/blocklist", data = "<blocklist>")]
+pub async fn settings_blocklist(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    blocklist: Form<BlocklistForm<'_>>,
+) -> Redirect {
+    let lines = |list: &str| {
+        list.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+            .collect()
+    };
+    let blocklist = Blocklist {
+        feed_urls: lines(blocklist.feed_urls),
+        domains: lines(blocklist.domains),
+        titles: lines(blocklist.titles),
+    };
+    let message = match db.write().await.set_blocklist(blocklist) {
+        Ok(()) => None,
+        Err(e) => Some(format!("Could not save the blocklist: {}", e)),
+    };
+
+    Redirect::to(uri!(settings(message)))
+}
+
+#[derive(FromForm)]
+pub struct ImportSettings {
+    bundle: String,
+}
+
+#[post("/settings/import", data = "<import>")]
+pub async fn settings_import(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    import: Form<ImportSettings>,
+) -> Redirect {
+    let message = match serde_json::from_str::<SettingsBundle>(&import.bundle) {
+        Ok(bundle) => {
+            let mut db = db.write().await;
+            match db.import_settings(bundle) {
+                Ok(report) => {
+                    format!(
+                        "Imported settings: {} new feeds, {} merged feeds",
+                        report.new_feeds, report.merged_feeds
+                    )
+                }
+                Err(e) => e.to_string(),
+            }
+        }
+        Err(e) => format!("Could not parse settings bundle: {}", e),
+    };
+
+    Redirect::to(uri!(settings(Some(message))))
+}
@@ -0,0 +1,140 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use feed_bouncer_database::{ItemOrder, ItemQuery, ItemState};
+use rocket::{form::Form, http::Status, response::Redirect, State};
+use rocket_dyn_templates::Template;
+
+use crate::common::{Filter, ItemBuilder, ItemsGroups, SyncDatabase};
+use crate::triggers::SameOrigin;
+
+/// Number of newest items shown on a share page
+const SHARE_ITEMS: usize = 200;
+
+/// Counts requests to share pages per client within a minute, whether the
+/// token exists or not, so tokens can't be guessed quickly.
+#[derive(Default)]
+pub struct ShareLimiter {
+    windows: Mutex<HashMap<Option<IpAddr>, (Instant, u32)>>,
+}
+
+impl ShareLimiter {
+    /// Returns false if the client used up its requests for this minute
+    fn allow(&self, ip: Option<IpAddr>, per_minute: u32) -> bool {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        windows.retain(|_, (start, _)| now.duration_since(*start) < Duration::from_secs(60));
+        let (_, count) = windows.entry(ip).or_insert((now, 0));
+        *count += 1;
+        *count <= per_minute
+    }
+}
+
+#[derive(serde::Serialize)]
+struct SharePage<'a> {
+    name: &'a str,
+    items: ItemsGroups<'a>,
+}
+
+/// The items of a shared filter, without any controls that change state.
+#[get("/share/<token>")]
+pub async fn share(
+    db: &State<SyncDatabase>,
+    limiter: &State<ShareLimiter>,
+    ip: Option<IpAddr>,
+    token: &str,
+) -> Result<Template, Status> {
+    let db = db.read().await;
+    if !limiter.allow(ip, db.config().public.share_requests_per_minute) {
+        return Err(Status::TooManyRequests);
+    }
+    let share = db.shares().get(token).ok_or(Status::NotFound)?;
+
+    let filter = Filter::new(Some(share.filter.clone()), db.tag_synonyms());
+    let mut items = ItemBuilder::new(true).shared();
+    {
+        let query = ItemQuery::new()
+            .feeds(filter.query().clone())
+            .order(ItemOrder::NewestFirst)
+            .hidden(false)
+            .dedup_links()
+            .limit(SHARE_ITEMS);
+        for (feed_id, feed, item) in db.query_items(&query) {
+            items.push_sorted(item, feed_id, feed, ItemState::default());
+        }
+    }
+    let items = items.into_groups();
+
+    Ok(Template::render(
+        "pages/share",
+        &SharePage {
+            name: &share.name,
+            items,
+        },
+    ))
+}
+
+#[derive(serde::Serialize)]
+struct ShareLink<'a> {
+    token: &'a str,
+    name: &'a str,
+    filter: &'a str,
+    created_at: String,
+}
+
+#[derive(serde::Serialize)]
+struct Context<'a> {
+    shares: Vec<ShareLink<'a>>,
+}
+
+#[get("/shares")]
+pub async fn shares(db: &State<SyncDatabase>) -> Template {
+    let db = db.read().await;
+    let shares = db
+        .shares()
+        .iter()
+        .map(|(token, share)| ShareLink {
+            token,
+            name: &share.name,
+            filter: &share.filter,
+            created_at: share.created_at.format("%Y-%m-%d").to_string(),
+        })
+        .collect();
+
+    Template::render("pages/shares", &Context { shares })
+}
+
+#[derive(FromForm)]
+pub struct NewShare<'r> {
+    name: &'r str,
+    filter: &'r str,
+}
+
+#[post("/shares/create", data = "<new_share>")]
+pub async fn shares_create(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    new_share: Form<NewShare<'_>>,
+) -> Redirect {
+    if !new_share.name.trim().is_empty() {
+        let mut db = db.write().await;
+        db.create_share(new_share.name, new_share.filter);
+        db.save_user_data();
+    }
+
+    Redirect::to(uri!(shares))
+}
+
+#[post("/shares/<token>/revoke")]
+pub async fn shares_revoke(db: &State<SyncDatabase>, _origin: SameOrigin, token: &str) -> Redirect {
+    let mut db = db.write().await;
+    if db.revoke_share(token) {
+        db.save_user_data();
+    }
+
+    Redirect::to(uri!(shares))
+}
@@ -0,0 +1,82 @@
+use rocket::{response::Redirect, State};
+use rocket_dyn_templates::Template;
+
+use crate::common::SyncDatabase;
+use crate::triggers::SameOrigin;
+
+#[derive(serde::Serialize)]
+struct FeedRow<'a> {
+    feed_id: &'a str,
+    name: &'a str,
+    items: usize,
+    headers: usize,
+    file_size: String,
+    oldest_item: Option<String>,
+    newest_item: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct Context<'a> {
+    message: Option<String>,
+    feeds: Vec<FeedRow<'a>>,
+    feed_count: usize,
+    total_items: usize,
+    total_headers: usize,
+    total_file_size: String,
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Shows which feeds take up the most storage.
+#[get("/stats?<message>")]
+pub async fn stats(db: &State<SyncDatabase>, message: Option<String>) -> Template {
+    let db = db.read().await;
+    let stats = db.stats();
+
+    let feeds = stats
+        .feeds
+        .iter()
+        .map(|feed| FeedRow {
+            feed_id: &feed.feed_id,
+            name: &feed.name,
+            items: feed.items,
+            headers: feed.headers,
+            file_size: format_size(feed.file_size),
+            oldest_item: feed.oldest_item.map(|date| date.date_naive().to_string()),
+            newest_item: feed.newest_item.map(|date| date.date_naive().to_string()),
+        })
+        .collect();
+
+    Template::render(
+        "pages/stats",
+        &Context {
+            message,
+            feeds,
+            feed_count: stats.feeds.len(),
+            total_items: stats.total_items,
+            total_headers: stats.total_headers,
+            total_file_size: format_size(stats.total_file_size),
+        },
+    )
+}
+
+/// Migrates left over legacy entries, drops redundant headers and rewrites
+/// all feed files
+#[post("/admin/compact")]
+pub async fn stats_compact(db: &State<SyncDatabase>, _origin: SameOrigin) -> Redirect {
+    let report = db.write().await.compact();
+    Redirect::to(uri!(stats(Some(report.summary()))))
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeSet;
+
+use feed_bouncer_database::{FeedOptions, UrlTagRule};
+use rocket::{form::Form, response::Redirect, State};
+use rocket_dyn_templates::Template;
+
+use crate::{
+    common::{SyncDatabase, Tag},
+    triggers::SameOrigin,
+};
+
+/// Settings of a feed or tag, empty fields are inherited.
+#[derive(FromForm)]
+pub struct OptionsForm<'r> {
+    update_interval_mins: Option<u64>,
+    retention_days: Option<u64>,
+    /// `yes`, `no` or anything else to inherit
+    mute_from_index: &'r str,
+    /// `yes`, `no` or anything else to inherit
+    full_content: &'r str,
+    /// Empty to inherit
+    user_agent: &'r str,
+}
+
+impl OptionsForm<'_> {
+    pub fn to_options(&self) -> FeedOptions {
+        FeedOptions {
+            update_interval_mins: self.update_interval_mins,
+            retention_days: self.retention_days,
+            mute_from_index: yes_no(self.mute_from_index),
+            full_content: yes_no(self.full_content),
+            user_agent: Some(self.user_agent.trim())
+                .filter(|user_agent| !user_agent.is_empty())
+                .map(str::to_owned),
+        }
+    }
+}
+
+fn yes_no(value: &str) -> Option<bool> {
+    match value {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+#[derive(serde::Serialize)]
+struct TagSettings<'a> {
+    tag: &'a str,
+    feeds: usize,
+    options: Option<&'a FeedOptions>,
+    /// Other names that stand for the tag
+    synonyms: Vec<&'a str>,
+}
+
+#[derive(serde::Serialize)]
+struct Context<'a> {
+    tags: Vec<TagSettings<'a>>,
+    /// The url tag rules as edited in the form, one per line
+    url_rules: String,
+}
+
+#[derive(FromForm)]
+pub struct UrlRulesForm<'r> {
+    /// One rule per line, the url pattern followed by its tags
+    rules: &'r str,
+}
+
+impl UrlRulesForm<'_> {
+    /// Invalid tags are dropped, and with them rules without valid tags
+    fn to_rules(&self) -> Vec<UrlTagRule> {
+        self.rules
+            .lines()
+            .filter_map(|line| {
+                let mut words = line.split(|c: char| c.is_whitespace() || c == ',');
+                let pattern = words.find(|word| !word.is_empty())?.to_owned();
+                let tags: Vec<String> = words
+                    .filter_map(Tag::new)
+                    .map(|tag| tag.as_str().to_owned())
+                    .collect();
+                (!tags.is_empty()).then(|| UrlTagRule { pattern, tags })
+            })
+            .collect()
+    }
+}
+
+/// The settings of all tags in use, inherited by their feeds
+#[get("/tags")]
+pub async fn tags(db: &State<SyncDatabase>) -> Template {
+    let db = db.read().await;
+    let feeds = db.get_feeds();
+    let mut names: BTreeSet<&str> = feeds.iter().flat_map(|&(_, feed)| feed.tags()).collect();
+    names.extend(db.tag_settings().keys().map(|tag| &tag[..]));
+    names.extend(db.tag_synonyms().iter().map(|(_, canonical)| canonical));
+
+    let tags = names
+        .into_iter()
+        .map(|tag| TagSettings {
+            tag,
+            feeds: feeds
+                .iter()
+                .filter(|(_, feed)| feed.contains_tag(tag))
+                .count(),
+            options: db.tag_settings().get(tag),
+            synonyms: db.tag_synonyms().synonyms_of(tag).collect(),
+        })
+        .collect();
+
+    let url_rules = db
+        .config()
+        .import
+        .url_tags
+        .iter()
+        .map(|rule| format!("{} {}\n", rule.pattern, rule.tags.join(" ")))
+        .collect();
+
+    Template::render("pages/tags", &Context { tags, url_rules })
+}
+
+#[post("/tags/<tag>/settings", data = "<options>")]
+pub async fn tag_set_settings(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    tag: &str,
+    options: Form<OptionsForm<'_>>,
+) -> Option<Redirect> {
+    let tag = Tag::new(tag)?;
+    let mut db = db.write().await;
+    db.set_tag_settings(tag.as_str(), options.to_options());
+    db.save_user_data();
+
+    Some(Redirect::to(uri!(tags)))
+}
+
+#[derive(FromForm)]
+pub struct SynonymForm<'r> {
+    synonym: &'r str,
+    /// The tag the synonym stands for
+    canonical: &'r str,
+}
+
+/// Makes a tag a synonym of another one, and replaces it among the tags of
+/// all feeds
+#[post("/tags/synonyms", data = "<form>")]
+pub async fn tag_add_synonym(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    form: Form<SynonymForm<'_>>,
+) -> Redirect {
+    let (synonym, canonical) = match (Tag::new(form.synonym), Tag::new(form.canonical)) {
+        (Some(synonym), Some(canonical)) => (synonym, canonical),
+        _ => return Redirect::to(uri!(tags)),
+    };
+    let mut db = db.write().await;
+    match db.add_tag_synonym(synonym.as_str(), canonical.as_str()) {
+        Ok(()) => {
+            // The synonym and the feeds using it get saved together
+            db.merge_tag_synonyms();
+            if let Err(e) = db.save_atomic_shrunk() {
+                eprintln!("WARN: could not save the tag synonym: {}", e);
+            }
+        }
+        Err(e) => eprintln!("WARN: could not add the tag synonym: {}", e),
+    }
+
+    Redirect::to(uri!(tags))
+}
+
+#[derive(FromForm)]
+pub struct RemoveSynonymForm<'r> {
+    synonym: &'r str,
+}
+
+/// Turns a synonym back into a tag of its own. Feeds keep the canonical tag.
+#[post("/tags/synonyms/remove", data = "<form>")]
+pub async fn tag_remove_synonym(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    form: Form<RemoveSynonymForm<'_>>,
+) -> Redirect {
+    let mut db = db.write().await;
+    if db.remove_tag_synonym(form.synonym) {
+        db.save_user_data();
+    }
+
+    Redirect::to(uri!(tags))
+}
+
+/// Replaces the rules that tag feeds imported from matching urls
+#[post("/tags/url_rules", data = "<rules>")]
+pub async fn tag_set_url_rules(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    rules: Form<UrlRulesForm<'_>>,
+) -> Redirect {
+    let mut db = db.write().await;
+    if let Err(e) = db.set_url_tag_rules(rules.to_rules()) {
+        eprintln!("WARN: could not save the url tag rules: {}", e);
+    }
+
+    Redirect::to(uri!(tags))
+}
@@ -0,0 +1,251 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use chrono::{DateTime, FixedOffset};
+use feed_bouncer_database::{Database, ItemOrder, ItemQuery, UpdateProgress};
+use rocket::http::ContentType;
+use rocket::State;
+use rocket_dyn_templates::Template;
+
+use crate::common::{Filter, SyncDatabase, Tag};
+use crate::pages::index::index;
+use crate::pages::public::escape_xml;
+
+/// Number of items in the RSS and JSON feeds of a tag
+const TIMELINE_FEED_ITEMS: usize = 50;
+
+/// The tags of the feeds, most used first. Synonyms don't show up, feeds
+/// only carry canonical tags.
+pub fn known_tags(db: &Database) -> Vec<&str> {
+    let mut tags: Vec<_> = db
+        .get_feeds()
+        .into_iter()
+        .flat_map(|(_, feed)| feed.tags())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    tags.sort_by_key(|tag| std::cmp::Reverse(db.tag_uses(tag)));
+    tags
+}
+
+/// The canonical tag for `raw`, if any feed has it
+fn timeline_tag(db: &Database, raw: &str) -> Option<String> {
+    let tag = Tag::new(raw)?;
+    let tag = db.tag_synonyms().canonical(tag.as_str());
+    known_tags(db).contains(&tag).then(|| tag.to_owned())
+}
+
+/// The RSS and JSON feeds of a tag
+#[derive(serde::Serialize)]
+pub struct TimelineFeedLinks {
+    rss_link: String,
+    json_link: String,
+}
+
+impl TimelineFeedLinks {
+    /// Only for filters that are a single known tag
+    pub fn for_filter(db: &Database, filter: &Filter) -> Option<Self> {
+        let tag = timeline_tag(db, filter.raw_opt()?)?;
+        Some(Self {
+            rss_link: uri!(tag_timeline_rss(&tag[..])).to_string(),
+            json_link: uri!(tag_timeline_json(&tag[..])).to_string(),
+        })
+    }
+}
+
+/// The index for a single tag. Synonyms of the tag work as well, unknown
+/// tags are not found.
+#[allow(clippy::too_many_arguments)]
+#[get("/t/<tag>?<order>&<unread>&<sort>&<view>&<group>&<page>")]
+pub async fn tag_timeline(
+    db: &State<SyncDatabase>,
+    progress: &State<Arc<UpdateProgress>>,
+    tag: &str,
+    order: Option<&str>,
+    unread: Option<bool>,
+    sort: Option<&str>,
+    view: Option<&str>,
+    group: Option<&str>,
+    page: Option<usize>,
+) -> Option<Template> {
+    let tag = timeline_tag(&*db.read().await, tag)?;
+    Some(
+        index(
+            db,
+            progress,
+            Some(tag),
+            order,
+            unread,
+            None,
+            sort,
+            view,
+            group,
+            None,
+            None,
+            page,
+            None,
+        )
+        .await,
+    )
+}
+
+struct TimelineItem<'a> {
+    title: &'a str,
+    link: Option<&'a str>,
+    /// The permalink of the item
+    id: String,
+    summary: Option<&'a str>,
+    /// The sanitized HTML content, the linked article for feeds with the
+    /// `full_content` setting
+    content_html: Option<&'a str>,
+    feed_name: &'a str,
+    /// Unknown for undated items stored before first seen dates got tracked
+    date: Option<DateTime<FixedOffset>>,
+}
+
+struct Timeline<'a> {
+    tag: String,
+    /// `public.base_url` of the config, links are relative without it
+    base_url: &'a str,
+    items: Vec<TimelineItem<'a>>,
+}
+
+impl<'a> Timeline<'a> {
+    fn new(db: &'a Database, raw_tag: &str) -> Option<Self> {
+        let tag = timeline_tag(db, raw_tag)?;
+        let base_url = db
+            .config()
+            .public
+            .base_url
+            .as_deref()
+            .unwrap_or_default()
+            .trim_end_matches('/');
+
+        let filter = Filter::new(Some(tag.clone()), db.tag_synonyms());
+        let mut query = ItemQuery::new()
+            .feeds(filter.query().clone())
+            .order(ItemOrder::NewestFirst)
+            .hidden(false)
+            .dedup_links()
+            .limit(TIMELINE_FEED_ITEMS);
+        if filter.hides_muted() {
+            query = query.without_muted();
+        }
+        let items = db
+            .query_items(&query)
+            .into_iter()
+            .map(|(feed_id, feed, item)| TimelineItem {
+                title: item.display_title_without_prefixes(feed).unwrap_or("???"),
+                link: item.content_link(),
+                id: format!(
+                    "{}{}",
+                    base_url,
+                    uri!(crate::pages::feed::item_permalink(item.slug(feed_id)))
+                ),
+                summary: item
+                    .content()
+                    .map(|content| &content.summary[..])
+                    .filter(|summary| !summary.is_empty()),
+                content_html: item
+                    .content()
+                    .map(|content| &content.html[..])
+                    .filter(|html| !html.is_empty()),
+                feed_name: feed.display_name(),
+                date: item
+                    .publish_date()
+                    .or_else(|| item.first_seen().map(Into::into)),
+            })
+            .collect();
+
+        Some(Self {
+            tag,
+            base_url,
+            items,
+        })
+    }
+
+    fn page_link(&self) -> String {
+        format!(
+            "{}{}",
+            self.base_url,
+            uri!(tag_timeline(&self.tag[..], _, _, _, _, _, _))
+        )
+    }
+}
+
+/// The newest items of a tag as RSS
+#[get("/t/<tag>/rss")]
+pub async fn tag_timeline_rss(
+    db: &State<SyncDatabase>,
+    tag: &str,
+) -> Option<(ContentType, String)> {
+    let db = db.read().await;
+    let timeline = Timeline::new(&db, tag)?;
+
+    let mut rss = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <rss version=\"2.0\">\n<channel>\n\
+         <title>Feed Bouncer - {tag}</title>\n\
+         <link>{link}</link>\n\
+         <description>The items of the feeds tagged {tag}</description>\n",
+        tag = escape_xml(&timeline.tag),
+        link = escape_xml(&timeline.page_link()),
+    );
+    for item in &timeline.items {
+        rss += "<item>\n";
+        rss += &format!(
+            "  <title>{}</title>\n",
+            escape_xml(&format!("{}: {}", item.feed_name, item.title))
+        );
+        if let Some(link) = item.link {
+            rss += &format!("  <link>{}</link>\n", escape_xml(link));
+        }
+        rss += &format!(
+            "  <guid isPermaLink=\"false\">{}</guid>\n",
+            escape_xml(&item.id)
+        );
+        if let Some(date) = item.date {
+            rss += &format!("  <pubDate>{}</pubDate>\n", date.to_rfc2822());
+        }
+        if let Some(description) = item.content_html.or(item.summary) {
+            rss += &format!("  <description>{}</description>\n", escape_xml(description));
+        }
+        rss += "</item>\n";
+    }
+    rss += "</channel>\n</rss>\n";
+
+    Some((ContentType::new("application", "rss+xml"), rss))
+}
+
+/// The newest items of a tag as a JSON Feed
+#[get("/t/<tag>/json")]
+pub async fn tag_timeline_json(
+    db: &State<SyncDatabase>,
+    tag: &str,
+) -> Option<(ContentType, String)> {
+    let db = db.read().await;
+    let timeline = Timeline::new(&db, tag)?;
+
+    let items: Vec<_> = timeline
+        .items
+        .iter()
+        .map(|item| {
+            serde_json::json!({
+                "id": item.id,
+                "url": item.link,
+                "title": format!("{}: {}", item.feed_name, item.title),
+                "summary": item.summary,
+                "content_html": item.content_html,
+                "date_published": item.date.map(|date| date.to_rfc3339()),
+            })
+        })
+        .collect();
+    let feed = serde_json::json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": format!("Feed Bouncer - {}", timeline.tag),
+        "home_page_url": timeline.page_link(),
+        "items": items,
+    });
+
+    Some((ContentType::JSON, serde_json::to_string(&feed).unwrap()))
+}
@@ -0,0 +1,27 @@
+use feed_bouncer_database::{ItemOrder, ItemQuery};
+use rocket::State;
+use rocket_dyn_templates::Template;
+
+use crate::common::{ItemBuilder, ItemsGroups, SyncDatabase};
+
+#[derive(serde::Serialize)]
+struct Context<'a> {
+    items: ItemsGroups<'a>,
+}
+
+/// Lists the items that got hidden, so they can be restored.
+#[get("/trash")]
+pub async fn trash(db: &State<SyncDatabase>) -> Template {
+    let db = db.read().await;
+
+    let mut items = ItemBuilder::new(true).in_trash();
+    {
+        let query = ItemQuery::new().order(ItemOrder::NewestFirst).hidden(true);
+        for (feed_id, feed, item, state) in db.query_items_with_state(&query) {
+            items.push_sorted(item, feed_id, feed, state);
+        }
+    }
+    let items = items.into_groups();
+
+    Template::render("pages/trash", &Context { items })
+}
@@ -0,0 +1,93 @@
+use feed_bouncer_database::SavedView;
+use rocket::form::Form;
+use rocket::{response::Redirect, State};
+use rocket_dyn_templates::Template;
+
+use crate::common::SyncDatabase;
+use crate::triggers::SameOrigin;
+
+#[derive(serde::Serialize)]
+struct View<'a> {
+    name: &'a str,
+    link: String,
+    filter: &'a str,
+    order: &'static str,
+    unread_only: bool,
+}
+
+#[derive(serde::Serialize)]
+struct Context<'a> {
+    views: Vec<View<'a>>,
+}
+
+#[get("/views")]
+pub async fn views(db: &State<SyncDatabase>) -> Template {
+    let db = db.read().await;
+    let views = db
+        .saved_views()
+        .iter()
+        .map(|(name, view)| View {
+            name,
+            link: uri!(crate::pages::index::index(
+                Some(&view.filter[..]),
+                Some(view.order.as_str()),
+                Some(view.unread_only),
+                _,
+                _,
+                _,
+                _,
+                _,
+                _,
+                _,
+                _
+            ))
+            .to_string(),
+            filter: &view.filter,
+            order: view.order.as_str(),
+            unread_only: view.unread_only,
+        })
+        .collect();
+
+    Template::render("pages/views", &Context { views })
+}
+
+#[derive(FromForm)]
+pub struct NewView<'r> {
+    name: &'r str,
+    filter: &'r str,
+    order: &'r str,
+    unread_only: bool,
+}
+
+#[post("/views/save", data = "<new_view>")]
+pub async fn views_save(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    new_view: Form<NewView<'_>>,
+) -> Redirect {
+    let name = new_view.name.trim();
+    if !name.is_empty() {
+        let mut db = db.write().await;
+        db.save_view(
+            name.to_owned(),
+            SavedView {
+                filter: new_view.filter.to_owned(),
+                order: new_view.order.parse().unwrap_or_default(),
+                unread_only: new_view.unread_only,
+            },
+        );
+        db.save_user_data();
+    }
+
+    Redirect::to(uri!(views))
+}
+
+#[post("/views/<name>/remove")]
+pub async fn views_remove(db: &State<SyncDatabase>, _origin: SameOrigin, name: &str) -> Redirect {
+    let mut db = db.write().await;
+    if db.remove_view(name) {
+        db.save_user_data();
+    }
+
+    Redirect::to(uri!(views))
+}
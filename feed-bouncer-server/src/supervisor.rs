@@ -0,0 +1,143 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use rocket::{
+    http::{ContentType, Status},
+    State,
+};
+
+const MAX_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+#[derive(serde::Serialize, Clone, Default)]
+pub struct TaskHealth {
+    /// Loops are expected to run all the time, one-off tasks are not
+    periodic: bool,
+    running: bool,
+    restarts: u32,
+    last_panic: Option<String>,
+    last_panic_at: Option<DateTime<Utc>>,
+}
+
+/// Owns the background loops of the server, and restarts them with a backoff
+/// if they panic.
+#[derive(Clone, Default)]
+pub struct Supervisor {
+    tasks: Arc<Mutex<BTreeMap<&'static str, TaskHealth>>>,
+}
+
+impl Supervisor {
+    pub fn spawn<F, Fut>(&self, name: &'static str, task: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let supervisor = self.clone();
+        rocket::tokio::spawn(async move {
+            let mut backoff = Duration::from_secs(1);
+            loop {
+                supervisor.update(name, |health| {
+                    health.periodic = true;
+                    health.running = true;
+                });
+                let started = Instant::now();
+                let result = rocket::tokio::spawn(task()).await;
+                supervisor.update(name, |health| health.running = false);
+
+                let message = match panic_message(result) {
+                    Some(message) => message,
+                    None => break,
+                };
+                // A loop that ran fine for a while starts over with a short delay
+                if started.elapsed() > MAX_BACKOFF {
+                    backoff = Duration::from_secs(1);
+                }
+                eprintln!(
+                    "ERROR: task {} panicked, restarting in {:?}: {}",
+                    name, backoff, message
+                );
+                supervisor.record_panic(name, message);
+                supervisor.update(name, |health| health.restarts += 1);
+
+                rocket::tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        });
+    }
+
+    /// Runs a task once, like a manually triggered update. A panic is only
+    /// recorded, the task is not restarted.
+    pub fn spawn_once<Fut>(&self, name: &'static str, task: Fut)
+    where
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let supervisor = self.clone();
+        rocket::tokio::spawn(async move {
+            supervisor.update(name, |health| health.running = true);
+            let result = rocket::tokio::spawn(task).await;
+            supervisor.update(name, |health| health.running = false);
+
+            if let Some(message) = panic_message(result) {
+                eprintln!("ERROR: task {} panicked: {}", name, message);
+                supervisor.record_panic(name, message);
+            }
+        });
+    }
+
+    fn record_panic(&self, name: &'static str, message: String) {
+        self.update(name, |health| {
+            health.last_panic = Some(message);
+            health.last_panic_at = Some(Utc::now());
+        });
+    }
+
+    fn update(&self, name: &'static str, f: impl FnOnce(&mut TaskHealth)) {
+        let mut tasks = self.tasks.lock().unwrap();
+        f(tasks.entry(name).or_default());
+    }
+
+    pub fn health(&self) -> BTreeMap<&'static str, TaskHealth> {
+        self.tasks.lock().unwrap().clone()
+    }
+}
+
+/// The panic message if the task panicked
+fn panic_message(result: Result<(), rocket::tokio::task::JoinError>) -> Option<String> {
+    let error = match result {
+        Err(e) if e.is_panic() => e.into_panic(),
+        _ => return None,
+    };
+    Some(
+        error
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| error.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_owned()),
+    )
+}
+
+#[derive(serde::Serialize)]
+struct Health {
+    ok: bool,
+    tasks: BTreeMap<&'static str, TaskHealth>,
+}
+
+#[get("/health")]
+pub async fn health(supervisor: &State<Supervisor>) -> (Status, (ContentType, String)) {
+    let tasks = supervisor.health();
+    let ok = tasks
+        .values()
+        .filter(|task| task.periodic)
+        .all(|task| task.running);
+    let status = if ok {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+    let body = serde_json::to_string_pretty(&Health { ok, tasks }).unwrap();
+    (status, (ContentType::JSON, body))
+}
@@ -1,10 +1,17 @@
 use rocket::{
+    http::Status,
     request::{self, FromRequest},
     response::Redirect,
     Request,
 };
 
+use crate::common::SyncDatabase;
+
+pub mod archive;
+pub mod autosave;
+pub mod hide;
 pub mod mark_read;
+pub mod reload;
 pub mod update;
 
 pub struct GetHeaders {
@@ -22,6 +29,39 @@ impl<'r> FromRequest<'r> for GetHeaders {
     }
 }
 
+/// Rejects requests sent by other sites, so they can't trigger actions with
+/// forms of their own. Browsers that send neither header are let through.
+/// Only the request guard creates it.
+pub struct SameOrigin(());
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SameOrigin {
+    type Error = String;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let headers = req.headers();
+        let same_origin = match headers.get_one("Sec-Fetch-Site") {
+            Some(site) => matches!(site, "same-origin" | "none"),
+            None => match (headers.get_one("Origin"), headers.get_one("Host")) {
+                (Some(origin), Some(host)) => {
+                    origin.split_once("://").map(|(_, origin)| origin) == Some(host)
+                }
+                _ => true,
+            },
+        };
+        if same_origin {
+            request::Outcome::Success(SameOrigin(()))
+        } else {
+            request::Outcome::Failure((Status::Forbidden, "cross-site request".to_owned()))
+        }
+    }
+}
+
+/// Whether the deprecated GET routes of the actions are still served
+async fn legacy_get_actions(db: &SyncDatabase) -> bool {
+    db.read().await.config().ui.legacy_get_actions
+}
+
 fn redirect_back(headers: GetHeaders) -> Redirect {
     match headers.referer {
         Some(url) => Redirect::to(url),
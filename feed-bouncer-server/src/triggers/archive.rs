@@ -0,0 +1,15 @@
+use crate::common::SyncDatabase;
+
+/// Archives the linked articles of feeds with the `archive` flag or the
+/// `full_content` setting, a batch per call.
+pub async fn run_archive(db: SyncDatabase) {
+    let task = db.read().await.archive_task();
+    if task.is_empty() {
+        return;
+    }
+
+    // Download while the lock is not held
+    let result = task.run().await;
+
+    db.write().await.commit_archive(result);
+}
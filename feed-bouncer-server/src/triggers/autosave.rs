@@ -0,0 +1,22 @@
+use std::time::Duration;
+
+use crate::{common::SyncDatabase, supervisor::Supervisor};
+
+/// Saves the database periodically, so a crash loses at most the changes
+/// since the last save that are not covered by the journal. This also stores
+/// the usage that page loads noted, see `PendingUsage`.
+pub fn start_autosave(supervisor: &Supervisor, db: &SyncDatabase, interval_mins: u64) {
+    if interval_mins == 0 {
+        return;
+    }
+    let db: SyncDatabase = db.clone();
+    supervisor.spawn("autosave", move || {
+        let db = db.clone();
+        async move {
+            loop {
+                rocket::tokio::time::sleep(Duration::from_secs(interval_mins * 60)).await;
+                db.write().await.save();
+            }
+        }
+    });
+}
@@ -0,0 +1,67 @@
+use feed_bouncer_database::FeedId;
+use rocket::{response::Redirect, State};
+
+use crate::{
+    common::SyncDatabase,
+    triggers::{GetHeaders, SameOrigin},
+};
+
+#[post("/hide/<feed_id>/<item_id>")]
+pub async fn hide(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    headers: GetHeaders,
+    feed_id: FeedId,
+    item_id: usize,
+) -> Redirect {
+    let mut db = db.write().await;
+    db.hide_item(&feed_id, item_id);
+    db.save_user_data();
+
+    super::redirect_back(headers)
+}
+
+#[post("/unhide/<feed_id>/<item_id>")]
+pub async fn unhide(
+    db: &State<SyncDatabase>,
+    _origin: SameOrigin,
+    headers: GetHeaders,
+    feed_id: FeedId,
+    item_id: usize,
+) -> Redirect {
+    let mut db = db.write().await;
+    db.unhide_item(&feed_id, item_id);
+    db.save_user_data();
+
+    super::redirect_back(headers)
+}
+
+/// Deprecated, only served with the `legacy_get_actions` setting
+#[get("/hide/<feed_id>/<item_id>")]
+pub async fn hide_get(
+    db: &State<SyncDatabase>,
+    origin: SameOrigin,
+    headers: GetHeaders,
+    feed_id: FeedId,
+    item_id: usize,
+) -> Option<Redirect> {
+    if !super::legacy_get_actions(db).await {
+        return None;
+    }
+    Some(hide(db, origin, headers, feed_id, item_id).await)
+}
+
+/// Deprecated, only served with the `legacy_get_actions` setting
+#[get("/unhide/<feed_id>/<item_id>")]
+pub async fn unhide_get(
+    db: &State<SyncDatabase>,
+    origin: SameOrigin,
+    headers: GetHeaders,
+    feed_id: FeedId,
+    item_id: usize,
+) -> Option<Redirect> {
+    if !super::legacy_get_actions(db).await {
+        return None;
+    }
+    Some(unhide(db, origin, headers, feed_id, item_id).await)
+}
@@ -1,39 +1,53 @@
-use feed_bouncer_database::FeedId;
+use feed_bouncer_database::{FeedId, ItemSortKey};
 use rocket::{response::Redirect, State};
 
-use crate::{common::SyncDatabase, triggers::GetHeaders};
+use crate::{
+    common::SyncDatabase,
+    triggers::{GetHeaders, SameOrigin},
+};
 
-#[get("/mark_read/<feed_id>/<item_id>")]
+#[post("/mark_read/<feed_id>/<item_id>")]
 pub async fn mark_read(
     db: &State<SyncDatabase>,
+    _origin: SameOrigin,
     headers: GetHeaders,
     feed_id: FeedId,
     item_id: usize,
 ) -> Redirect {
     let mut db = db.write().await;
 
-    let opt = db
+    let date = db
         .get(&feed_id)
-        .map(|feed| {
-            let mut vec = Vec::new();
-            for item in feed.items() {
-                vec.push((item.id(), item.publish_date_or_old()));
-            }
-            vec
-        })
-        .and_then(|index| {
-            let date = index.iter().find(|v| v.0 == item_id)?.1;
-            Some((date, index))
-        });
+        .and_then(|feed| feed.items().iter().find(|item| item.id() == item_id))
+        .map(|item| item.sort_date(ItemSortKey::Published));
 
-    if let Some((date, index)) = opt {
-        for (id, id_date) in index {
-            if id_date <= date {
-                db.mark_read(&feed_id, id);
-            }
+    if let Some(date) = date {
+        // Also marks the older items of the feed
+        let to_read: Vec<usize> = db
+            .items_with_state(&feed_id)
+            .filter(|(item, state)| !state.read && item.sort_date(ItemSortKey::Published) <= date)
+            .map(|(item, _)| item.id())
+            .collect();
+        for id in to_read {
+            db.mark_read(&feed_id, id);
         }
         db.save_user_data();
     }
 
     super::redirect_back(headers)
 }
+
+/// Deprecated, only served with the `legacy_get_actions` setting
+#[get("/mark_read/<feed_id>/<item_id>")]
+pub async fn mark_read_get(
+    db: &State<SyncDatabase>,
+    origin: SameOrigin,
+    headers: GetHeaders,
+    feed_id: FeedId,
+    item_id: usize,
+) -> Option<Redirect> {
+    if !super::legacy_get_actions(db).await {
+        return None;
+    }
+    Some(mark_read(db, origin, headers, feed_id, item_id).await)
+}
@@ -0,0 +1,52 @@
+use feed_bouncer_database::Error;
+use rocket::{response::Redirect, State};
+
+use crate::{common::SyncDatabase, supervisor::Supervisor, triggers::SameOrigin};
+
+/// Re-reads the config and processes new entries of the import file, without
+/// restarting the server
+#[post("/admin/reload")]
+pub async fn reload(db: &State<SyncDatabase>, _origin: SameOrigin) -> Redirect {
+    let message = match reload_db(db).await {
+        Ok(()) => "Reloaded the config and the import file".to_owned(),
+        Err(e) => format!("Could not reload: {}", e),
+    };
+    Redirect::to(uri!(crate::pages::settings::settings(Some(message))))
+}
+
+/// A config that can't be parsed is reported and the current one kept, the
+/// import file is processed either way. Like the import queue, the feeds
+/// are downloaded while the lock is not held.
+async fn reload_db(db: &SyncDatabase) -> Result<(), Error> {
+    let result = db.write().await.reload_config();
+    let task = db.read().await.import_file_task();
+    if let Some(task) = task {
+        let imported = task.run().await;
+        let mut db = db.write().await;
+        db.commit_import_file(imported);
+    }
+    result
+}
+
+/// Reloads on SIGHUP, e.g. with `kill -HUP` after editing the import file
+#[cfg(unix)]
+pub fn start_reload_on_sighup(supervisor: &Supervisor, db: &SyncDatabase) {
+    use rocket::tokio::signal::unix::{signal, SignalKind};
+
+    let db: SyncDatabase = db.clone();
+    supervisor.spawn("reload_on_sighup", move || {
+        let db = db.clone();
+        async move {
+            let mut hangup = signal(SignalKind::hangup()).expect("could not listen for SIGHUP");
+            while hangup.recv().await.is_some() {
+                eprintln!("Reloading after SIGHUP");
+                if let Err(e) = reload_db(&db).await {
+                    eprintln!("WARN: could not reload the config: {}", e);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn start_reload_on_sighup(_supervisor: &Supervisor, _db: &SyncDatabase) {}
@@ -1,42 +1,429 @@
-use rocket::{response::Redirect, State};
+use feed_bouncer_database::{
+    CommitSummary, FeedId, UpdateProgress, FIRST_RETRY_DELAY, UPDATE_RETRY_ATTEMPTS,
+};
+use rocket::{http::Status, response::Redirect, State};
 
-use crate::{common::SyncDatabase, triggers::GetHeaders};
-use std::time::Duration;
+use crate::{
+    common::SyncDatabase,
+    supervisor::Supervisor,
+    triggers::{GetHeaders, SameOrigin},
+};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
 
 use super::redirect_back;
 
-#[get("/update")]
-pub async fn update(db: &State<SyncDatabase>, referer: GetHeaders) -> Redirect {
-    start_update(db);
+#[post("/update")]
+pub async fn update(
+    db: &State<SyncDatabase>,
+    supervisor: &State<Supervisor>,
+    progress: &State<Arc<UpdateProgress>>,
+    history: &State<UpdateHistory>,
+    retries: &State<RetryQueue>,
+    _origin: SameOrigin,
+    referer: GetHeaders,
+) -> Redirect {
+    spawn_update(
+        db.inner(),
+        supervisor.inner(),
+        progress.inner(),
+        history.inner(),
+        retries.inner(),
+    );
     redirect_back(referer)
 }
 
-pub fn start_update(db: &SyncDatabase) {
-    let db: SyncDatabase = db.clone();
+/// Deprecated, only served with the `legacy_get_actions` setting
+#[get("/update")]
+pub async fn update_get(
+    db: &State<SyncDatabase>,
+    supervisor: &State<Supervisor>,
+    progress: &State<Arc<UpdateProgress>>,
+    history: &State<UpdateHistory>,
+    retries: &State<RetryQueue>,
+    origin: SameOrigin,
+    referer: GetHeaders,
+) -> Option<Redirect> {
+    if !super::legacy_get_actions(db).await {
+        return None;
+    }
+    Some(update(db, supervisor, progress, history, retries, origin, referer).await)
+}
+
+/// Number of update summaries kept for the settings page
+const UPDATE_HISTORY_LEN: usize = 20;
+
+/// The summaries of the latest committed updates, newest first
+#[derive(Clone, Default)]
+pub struct UpdateHistory {
+    summaries: Arc<Mutex<VecDeque<CommitSummary>>>,
+}
+
+impl UpdateHistory {
+    fn push(&self, summary: CommitSummary) {
+        let mut summaries = self.summaries.lock().unwrap();
+        summaries.push_front(summary);
+        summaries.truncate(UPDATE_HISTORY_LEN);
+    }
+    pub fn summaries(&self) -> Vec<CommitSummary> {
+        self.summaries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// The feeds waiting for a retry. All updates share it, so only one retry
+/// loop runs, which picks up the failures of later updates as well.
+#[derive(Clone, Default)]
+pub struct RetryQueue {
+    state: Arc<Mutex<RetryState>>,
+}
+
+#[derive(Default)]
+struct RetryState {
+    feed_ids: BTreeSet<FeedId>,
+    running: bool,
+}
+
+impl RetryQueue {
+    /// Queues the feeds, returns true if no retry loop runs yet and the
+    /// caller needs to start one
+    fn add(&self, feed_ids: Vec<FeedId>) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.feed_ids.extend(feed_ids);
+        !std::mem::replace(&mut state.running, true)
+    }
+    fn take(&self) -> BTreeSet<FeedId> {
+        std::mem::take(&mut self.state.lock().unwrap().feed_ids)
+    }
+    /// Ends the retry loop, unless feeds got queued since the last `take`
+    fn finish(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.running = !state.feed_ids.is_empty();
+        !state.running
+    }
+}
+
+/// Lets a panicking retry loop be started again by the next update
+struct RetryLoop(RetryQueue);
+
+impl Drop for RetryLoop {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.0.state.lock().unwrap().running = false;
+        }
+    }
+}
+
+/// Ends the reservation of `UpdateProgress::queue_full_update`, also if the
+/// update panics
+struct QueuedUpdate(Arc<UpdateProgress>);
+
+impl Drop for QueuedUpdate {
+    fn drop(&mut self) {
+        self.0.end_full_update();
+    }
+}
+
+/// Starts a full update in the background. Returns false and does nothing
+/// if a full update runs or waits already.
+pub(crate) fn spawn_update(
+    db: &SyncDatabase,
+    supervisor: &Supervisor,
+    progress: &Arc<UpdateProgress>,
+    history: &UpdateHistory,
+    retries: &RetryQueue,
+) -> bool {
+    if !progress.queue_full_update() {
+        println!("Skipped the update, another one is running");
+        return false;
+    }
+    supervisor.spawn_once(
+        "manual_update",
+        run_update(
+            db.clone(),
+            supervisor.clone(),
+            progress.clone(),
+            history.clone(),
+            retries.clone(),
+        ),
+    );
+    true
+}
 
-    rocket::tokio::spawn(async move {
-        // get tasks during a temporary read lock
-        let tasks = {
-            let db = db.read().await;
-            db.update_feeds_task()
-        };
+/// Runs a full update, which needs to be reserved with
+/// `UpdateProgress::queue_full_update` before
+async fn run_update(
+    db: SyncDatabase,
+    supervisor: Supervisor,
+    progress: Arc<UpdateProgress>,
+    history: UpdateHistory,
+    retries: RetryQueue,
+) {
+    let queued = QueuedUpdate(progress.clone());
+    // get tasks during a temporary read lock
+    let tasks = {
+        let db = db.read().await;
+        db.update_feeds_task().with_progress(progress.clone())
+    };
+    // A retry round or single feed update may still run, new ones wait
+    while !progress.try_start(false) {
+        rocket::tokio::time::sleep(Duration::from_secs(1)).await;
+    }
 
-        // Run the task updates while the lock is not held
-        let results = tasks.run().await;
+    // Run the task updates while the lock is not held
+    let results = tasks.run().await;
+    drop(queued);
+    let failed = results.unavailable_feeds();
+    if !failed.is_empty() && retries.add(failed) {
+        supervisor.spawn_once(
+            "retry_failed_feeds",
+            run_retries(db.clone(), progress, retries, history.clone()),
+        );
+    }
 
-        // commit the updates
+    // commit the updates
+    {
         let mut db = db.write().await;
-        db.commit_from(results).await;
-        db.save();
-    });
+        history.push(db.commit_from(results).await);
+        match db.move_to_cold_storage() {
+            Ok(0) => db.save(),
+            Ok(_) => db.save_shrunk(),
+            Err(e) => {
+                // Items of other feeds may have moved before the error
+                eprintln!("WARN: could not move items to cold storage: {}", e);
+                db.save_shrunk();
+            }
+        }
+    }
+
+    super::archive::run_archive(db).await;
+}
+
+/// Retries the queued feeds with an exponential backoff. Feeds that still
+/// fail are escalated to the diagnostics page.
+async fn run_retries(
+    db: SyncDatabase,
+    progress: Arc<UpdateProgress>,
+    retries: RetryQueue,
+    history: UpdateHistory,
+) {
+    let retries = RetryLoop(retries);
+    loop {
+        let mut feed_ids = BTreeSet::new();
+        let mut delay = FIRST_RETRY_DELAY;
+        for _ in 0..UPDATE_RETRY_ATTEMPTS {
+            rocket::tokio::time::sleep(delay).await;
+            delay *= 2;
+            // Feeds that failed in updates since the last round join in
+            feed_ids.extend(retries.0.take());
+            if feed_ids.is_empty() {
+                break;
+            }
+
+            let feed_ids_vec: Vec<FeedId> = feed_ids.iter().cloned().collect();
+            let task = db
+                .read()
+                .await
+                .retry_feeds_task(&feed_ids_vec)
+                .with_progress(progress.clone());
+            // A running update fetches them as well, the next round retries
+            // them if it fails
+            if !progress.try_start(true) {
+                continue;
+            }
+            let results = task.run().await;
+            feed_ids = results.unavailable_feeds().into_iter().collect();
+
+            let mut db = db.write().await;
+            history.push(db.commit_from(results).await);
+            db.save();
+        }
+        if !feed_ids.is_empty() {
+            eprintln!(
+                "WARN: {} feeds still failed after {} retries",
+                feed_ids.len(),
+                UPDATE_RETRY_ATTEMPTS
+            );
+            let feed_ids: Vec<FeedId> = feed_ids.into_iter().collect();
+            db.write().await.escalate_fetch_failures(&feed_ids);
+        }
+        if retries.0.finish() {
+            return;
+        }
+    }
+}
+
+/// Updates only one feed, and waits for it so the redirect shows the result.
+/// Fails while another update is running.
+#[post("/feed/<feed_id>/update")]
+pub async fn update_feed(
+    db: &State<SyncDatabase>,
+    progress: &State<Arc<UpdateProgress>>,
+    history: &State<UpdateHistory>,
+    _origin: SameOrigin,
+    referer: GetHeaders,
+    feed_id: String,
+) -> Result<Redirect, (Status, String)> {
+    let task = db
+        .read()
+        .await
+        .update_single_feed_task(&feed_id)
+        .ok_or_else(|| (Status::NotFound, "unknown feed or no feed url".to_owned()))?
+        .with_progress(progress.inner().clone());
+    if !progress.try_start(true) {
+        return Err((Status::Conflict, "another update is running".to_owned()));
+    }
+    let results = task.run().await;
+
+    let mut db = db.write().await;
+    history.push(db.commit_from(results).await);
+    db.save();
+    Ok(redirect_back(referer))
+}
+
+/// Deprecated, only served with the `legacy_get_actions` setting
+#[get("/feed/<feed_id>/update")]
+pub async fn update_feed_get(
+    db: &State<SyncDatabase>,
+    progress: &State<Arc<UpdateProgress>>,
+    history: &State<UpdateHistory>,
+    origin: SameOrigin,
+    referer: GetHeaders,
+    feed_id: String,
+) -> Option<Result<Redirect, (Status, String)>> {
+    if !super::legacy_get_actions(db).await {
+        return None;
+    }
+    Some(update_feed(db, progress, history, origin, referer, feed_id).await)
+}
+
+/// Timing of the automatic updates, and whether they are paused.
+#[derive(Clone)]
+pub struct UpdateSchedule {
+    pub interval: Duration,
+    pub startup_jitter: Duration,
+    paused: Arc<AtomicBool>,
+}
+
+impl UpdateSchedule {
+    pub fn new(interval: Duration, startup_jitter: Duration) -> Self {
+        Self {
+            interval,
+            startup_jitter,
+            paused: Arc::default(),
+        }
+    }
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    /// A delay up to `startup_jitter`, different between instances
+    fn jitter(&self) -> Duration {
+        let max_millis = self.startup_jitter.as_millis() as u64;
+        if max_millis == 0 {
+            return Duration::ZERO;
+        }
+        let seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos() as u64
+            ^ std::process::id() as u64;
+        Duration::from_millis(seed % max_millis)
+    }
 }
 
-pub fn start_periodic_update(db: &SyncDatabase) {
+pub fn start_periodic_update(
+    supervisor: &Supervisor,
+    db: &SyncDatabase,
+    schedule: &UpdateSchedule,
+    progress: &Arc<UpdateProgress>,
+    history: &UpdateHistory,
+    retries: &RetryQueue,
+) {
     let db: SyncDatabase = db.clone();
-    rocket::tokio::spawn(async move {
-        loop {
-            start_update(&db);
-            rocket::tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+    let schedule = schedule.clone();
+    let progress = progress.clone();
+    let history = history.clone();
+    let retries = retries.clone();
+    let retry_supervisor = supervisor.clone();
+    supervisor.spawn("periodic_update", move || {
+        let db = db.clone();
+        let schedule = schedule.clone();
+        let progress = progress.clone();
+        let history = history.clone();
+        let retries = retries.clone();
+        let supervisor = retry_supervisor.clone();
+        async move {
+            rocket::tokio::time::sleep(schedule.jitter()).await;
+            loop {
+                if !schedule.is_paused() && progress.queue_full_update() {
+                    run_update(
+                        db.clone(),
+                        supervisor.clone(),
+                        progress.clone(),
+                        history.clone(),
+                        retries.clone(),
+                    )
+                    .await;
+                }
+                rocket::tokio::time::sleep(schedule.interval).await;
+            }
         }
     });
 }
+
+#[post("/updates/pause")]
+pub async fn updates_pause(
+    schedule: &State<UpdateSchedule>,
+    _origin: SameOrigin,
+    referer: GetHeaders,
+) -> Redirect {
+    schedule.set_paused(true);
+    redirect_back(referer)
+}
+
+#[post("/updates/resume")]
+pub async fn updates_resume(
+    schedule: &State<UpdateSchedule>,
+    _origin: SameOrigin,
+    referer: GetHeaders,
+) -> Redirect {
+    schedule.set_paused(false);
+    redirect_back(referer)
+}
+
+/// Deprecated, only served with the `legacy_get_actions` setting
+#[get("/updates/pause")]
+pub async fn updates_pause_get(
+    db: &State<SyncDatabase>,
+    schedule: &State<UpdateSchedule>,
+    origin: SameOrigin,
+    referer: GetHeaders,
+) -> Option<Redirect> {
+    if !super::legacy_get_actions(db).await {
+        return None;
+    }
+    Some(updates_pause(schedule, origin, referer).await)
+}
+
+/// Deprecated, only served with the `legacy_get_actions` setting
+#[get("/updates/resume")]
+pub async fn updates_resume_get(
+    db: &State<SyncDatabase>,
+    schedule: &State<UpdateSchedule>,
+    origin: SameOrigin,
+    referer: GetHeaders,
+) -> Option<Redirect> {
+    if !super::legacy_get_actions(db).await {
+        return None;
+    }
+    Some(updates_resume(schedule, origin, referer).await)
+}